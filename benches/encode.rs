@@ -0,0 +1,90 @@
+//! Measures the throughput of `Encoder` and checks that encoding a page doesn't
+//! allocate, with a global allocator counting allocations.
+//!
+//! Run with `cargo bench --bench encode`.
+
+use litetx::{Checksum, Encoder, Header, HeaderFlags, PageNum, PageSize, TXID};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    io,
+    sync::atomic::{AtomicUsize, Ordering},
+    time,
+};
+
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+const PAGE_SIZE: u32 = 4096;
+const PAGES: u32 = 10_000;
+
+fn main() {
+    for (name, flags) in [
+        ("uncompressed", HeaderFlags::empty()),
+        ("page checksums", HeaderFlags::PAGE_CHECKSUM),
+    ] {
+        bench(name, flags);
+    }
+}
+
+fn bench(name: &str, flags: HeaderFlags) {
+    let mut enc = Encoder::new(
+        io::sink(),
+        &Header {
+            flags,
+            page_size: PageSize::new(PAGE_SIZE).unwrap(),
+            commit: Some(PageNum::new(PAGES).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::UNIX_EPOCH,
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        },
+    )
+    .expect("failed to create encoder");
+    let page: Vec<u8> = (0..PAGE_SIZE).map(|n| n as u8).collect();
+
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed);
+    let start = time::Instant::now();
+    for n in 1..=PAGES {
+        enc.encode_page(PageNum::new(n).unwrap(), &page)
+            .expect("failed to encode page");
+    }
+    let elapsed = start.elapsed();
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations;
+
+    enc.finish(Checksum::new(1))
+        .expect("failed to finish encoder");
+
+    let bytes = PAGES as f64 * PAGE_SIZE as f64;
+    println!(
+        "{name}: {:.0} MiB/s, {:.3} allocations per page",
+        bytes / elapsed.as_secs_f64() / (1024. * 1024.),
+        allocations as f64 / PAGES as f64,
+    );
+    assert_eq!(0, allocations, "{name}: encoding pages allocated");
+}
@@ -0,0 +1,26 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use std::io;
+
+fuzz_target!(|data: &[u8]| {
+    // Decode the whole file sequentially.
+    if let Ok((mut dec, header)) = litetx::Decoder::new(data) {
+        let mut page = vec![0; header.page_size.into_inner() as usize];
+        while let Ok(Some(_)) = dec.decode_page(&mut page) {}
+        let _ = dec.finish();
+    }
+
+    // Read pages and Merkle proofs through the seekable APIs.
+    if let Ok((mut dec, header)) = litetx::Decoder::new(io::Cursor::new(data)) {
+        let mut page = vec![0; header.page_size.into_inner() as usize];
+        if let Some(commit) = header.commit {
+            for n in 1..=commit.into_inner().min(16) {
+                let page_num = litetx::PageNum::new(n).unwrap();
+                let _ = dec.seek_page(page_num, &mut page);
+                let _ = dec.verify_page_proof(page_num, &page);
+            }
+        }
+        let _ = dec.merkle_root();
+    }
+});
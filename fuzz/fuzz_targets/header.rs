@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = litetx::read_header(data);
+    let _ = litetx::Decoder::builder().lenient_flags(true).build(data);
+});
@@ -0,0 +1,155 @@
+#ifndef LITETX_H
+#define LITETX_H
+
+/* Generated by cbindgen from src/ffi.rs. Do not edit. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * An LTX file decoder.
+ */
+typedef struct LtxDecoder LtxDecoder;
+
+/**
+ * An LTX file encoder.
+ */
+typedef struct LtxEncoder LtxEncoder;
+
+/**
+ * Reads up to `len` bytes into `buf`, returning the number of bytes read, zero at the
+ * end of the input, or a negative value on error.
+ */
+typedef ptrdiff_t (*LtxReadFn)(void *ctx, uint8_t *buf, size_t len);
+
+/**
+ * The header of an LTX file. Unset optional fields are zero.
+ *
+ * Page filters aren't supported.
+ */
+typedef struct LtxHeader {
+  uint32_t flags;
+  uint32_t page_size;
+  uint32_t commit;
+  uint64_t min_txid;
+  uint64_t max_txid;
+  /**
+   * Milliseconds since the Unix epoch.
+   */
+  uint64_t timestamp;
+  uint64_t pre_apply_checksum;
+  uint64_t wal_offset;
+  uint64_t wal_size;
+  uint32_t wal_salt1;
+  uint32_t wal_salt2;
+  uint64_t node_id;
+  uint32_t key_id;
+  uint32_t dict_id;
+  uint8_t app_data[16];
+} LtxHeader;
+
+/**
+ * The trailer of an LTX file. The post-apply checksum is zero if unset.
+ */
+typedef struct LtxTrailer {
+  uint64_t post_apply_checksum;
+  uint64_t file_checksum;
+} LtxTrailer;
+
+/**
+ * Writes up to `len` bytes of `buf`, returning the number of bytes written or a
+ * negative value on error.
+ */
+typedef ptrdiff_t (*LtxWriteFn)(void *ctx, const uint8_t *buf, size_t len);
+
+/**
+ * Return the description of the last failure on the calling thread, or NULL if none.
+ *
+ * The string is valid until the next failure on the thread.
+ */
+const char *ltx_last_error(void);
+
+/**
+ * Create a decoder reading from `read` called with `ctx`, and store the header of the
+ * file in `header`. Return NULL on failure.
+ *
+ * # Safety
+ * `header` must be valid for writes, and `read` must follow [`LtxReadFn`] until the
+ * decoder is freed.
+ */
+struct LtxDecoder *ltx_decoder_new(LtxReadFn read, void *ctx, struct LtxHeader *header);
+
+/**
+ * Decode the next page into `buf` of `len` bytes, the page size of the file.
+ *
+ * Return the page number, zero once all pages have been decoded, or -1 on failure.
+ *
+ * # Safety
+ * `dec` must come from [`ltx_decoder_new`] and `buf` must be valid for writes of
+ * `len` bytes.
+ */
+int64_t ltx_decode_page(struct LtxDecoder *dec, uint8_t *buf, size_t len);
+
+/**
+ * Verify the trailer and the file checksum once all pages have been decoded, store the
+ * trailer in `trailer` and free the decoder. Return 0 on success or -1 on failure.
+ *
+ * # Safety
+ * `dec` must come from [`ltx_decoder_new`] and isn't valid afterwards. `trailer` must
+ * be valid for writes.
+ */
+int ltx_decoder_finish(struct LtxDecoder *dec, struct LtxTrailer *trailer);
+
+/**
+ * Free a decoder without finishing it.
+ *
+ * # Safety
+ * `dec` must be NULL or come from [`ltx_decoder_new`], and isn't valid afterwards.
+ */
+void ltx_decoder_free(struct LtxDecoder *dec);
+
+/**
+ * Create an encoder writing the file described by `header` to `write` called with
+ * `ctx`. Return NULL on failure.
+ *
+ * # Safety
+ * `header` must be valid for reads, and `write` must follow [`LtxWriteFn`] until the
+ * encoder is freed.
+ */
+struct LtxEncoder *ltx_encoder_new(LtxWriteFn write, void *ctx, const struct LtxHeader *header);
+
+/**
+ * Encode page `page_num` from `buf` of `len` bytes, the page size of the file.
+ * Return 0 on success or -1 on failure.
+ *
+ * # Safety
+ * `enc` must come from [`ltx_encoder_new`] and `buf` must be valid for reads of `len`
+ * bytes.
+ */
+int ltx_encoder_encode_page(struct LtxEncoder *enc,
+                            uint32_t page_num,
+                            const uint8_t *buf,
+                            size_t len);
+
+/**
+ * Write the trailer with `post_apply_checksum`, which is ignored for files without
+ * checksums, store it in `trailer` and free the encoder. Return 0 on success or -1 on
+ * failure.
+ *
+ * # Safety
+ * `enc` must come from [`ltx_encoder_new`] and isn't valid afterwards. `trailer` must
+ * be NULL or valid for writes.
+ */
+int ltx_encoder_finish(struct LtxEncoder *enc,
+                       uint64_t post_apply_checksum,
+                       struct LtxTrailer *trailer);
+
+/**
+ * Free an encoder without finishing the file.
+ *
+ * # Safety
+ * `enc` must be NULL or come from [`ltx_encoder_new`], and isn't valid afterwards.
+ */
+void ltx_encoder_free(struct LtxEncoder *enc);
+
+#endif  /* LITETX_H */
@@ -0,0 +1,1169 @@
+use crate::{
+    decoder::Error as DecodeError,
+    direct::{self, DirectFile},
+    file, journal, Checksum, DatabaseChecksum, Decoder, Header, PageNum, PageSize, Pos, SyncMode,
+    TXID,
+};
+use std::{
+    collections::BTreeSet,
+    fs,
+    io::{self, Read, Seek, Write},
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+/// An error that can be returned while applying an LTX file.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("decode")]
+    Decode(#[from] DecodeError),
+    #[error("pre-apply checksum mismatch: {0}, expected {1}")]
+    PreApplyChecksumMismatch(Checksum, Checksum),
+    #[error("post-apply checksum mismatch: {0}, expected {1}")]
+    PostApplyChecksumMismatch(Checksum, Checksum),
+    #[error("database io")]
+    Io(#[from] io::Error),
+}
+
+/// An error that can be returned by [`check`] and [`check_pos`].
+#[derive(thiserror::Error, Debug)]
+pub enum PreconditionError {
+    #[error("pre-apply checksum mismatch: {0}, expected {1}")]
+    ChecksumMismatch(Checksum, Checksum),
+    #[error("LTX file starts at TXID {0}, database is at {1}")]
+    TxidMismatch(TXID, TXID),
+    #[error("database size isn't a multiple of the page size {0}")]
+    PageSize(PageSize),
+    #[error("database io")]
+    Io(#[from] io::Error),
+}
+
+/// Check that the LTX file with `header` applies to the database read from `db`,
+/// without writing anything.
+///
+/// The checksum of the whole database is calculated and compared against the pre-apply
+/// checksum of the header. Snapshots replace the whole database and files with the
+/// [`HeaderFlags::NO_CHECKSUM`](crate::HeaderFlags::NO_CHECKSUM) flag can't be checked,
+/// so both always pass.
+///
+/// # Example
+/// ```no_run
+/// let ltx = std::fs::File::open("0000000000000002-0000000000000002.ltx").expect("open");
+/// let header = litetx::read_header(ltx).expect("read_header");
+/// let db = std::fs::File::open("db.sqlite").expect("open");
+/// litetx::apply::check(db, &header).expect("LTX file doesn't apply");
+/// ```
+pub fn check<D>(mut db: D, header: &Header) -> Result<(), PreconditionError>
+where
+    D: Read + Seek,
+{
+    let Some(expected) = header.pre_apply_checksum else {
+        return Ok(());
+    };
+
+    let page_size = header.page_size.into_inner() as u64;
+    let len = db.seek(io::SeekFrom::End(0))?;
+    if len % page_size != 0 {
+        return Err(PreconditionError::PageSize(header.page_size));
+    }
+
+    db.rewind()?;
+    let checksum = pages_checksum(db, header.page_size, 1..=len / page_size)?;
+    if checksum != expected {
+        return Err(PreconditionError::ChecksumMismatch(checksum, expected));
+    }
+
+    Ok(())
+}
+
+/// Check that the LTX file with `header` applies to a database at `pos`, e.g. as
+/// recorded after the last file was applied.
+///
+/// Unlike [`check`], the database isn't read, so this is only as reliable as `pos`.
+/// Deltas must start at the TXID following `pos` and their pre-apply checksum, if any,
+/// must match. Snapshots always pass.
+pub fn check_pos(pos: Pos, header: &Header) -> Result<(), PreconditionError> {
+    if header.is_snapshot() {
+        return Ok(());
+    }
+
+    if pos.txid.checked_add(1) != Some(header.min_txid) {
+        return Err(PreconditionError::TxidMismatch(header.min_txid, pos.txid));
+    }
+    match header.pre_apply_checksum {
+        Some(expected) if expected != pos.post_apply_checksum => Err(
+            PreconditionError::ChecksumMismatch(pos.post_apply_checksum, expected),
+        ),
+        _ => Ok(()),
+    }
+}
+
+/// Apply the LTX file read from `r` to the SQLite database file at `db_path`.
+///
+/// The database file is created if it doesn't exist. For non-snapshot LTX files the
+/// current database checksum is verified against the pre-apply checksum before
+/// any page is written. Once all the pages are written, the database is truncated
+/// to the `commit` size of the LTX file and its checksum is verified against the
+/// post-apply checksum from the LTX trailer.
+///
+/// Note that the database is left modified if the post-apply verification fails. Pages
+/// are also written as they are decoded, before the file checksum of the trailer is
+/// verified, so the database is left modified by any decode error, e.g. for a truncated
+/// or corrupt file. Check such files with [`verify`](crate::verify) first if they can
+/// be read twice, or apply them with [`chain`], which decodes every file before writing
+/// to the database. Once applied, the database file and its directory are synced to disk, see
+/// [`Options`] to relax this.
+///
+/// Files with the [`HeaderFlags::NO_CHECKSUM`](crate::HeaderFlags::NO_CHECKSUM) flag
+/// are applied without verification, and the checksum of the resulting database is
+/// calculated by reading it in full.
+///
+/// Returns the position of the database after the LTX file has been applied.
+pub fn apply_ltx<P, R>(db_path: P, r: R) -> Result<Pos, Error>
+where
+    P: AsRef<Path>,
+    R: io::Read,
+{
+    apply_ltx_with_options(db_path, r, Options::default())
+}
+
+/// Options of [`apply_ltx_with_options`].
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Whether pages of zeros are left as holes in the database file, see
+    /// [`PageWriter::sparse`].
+    pub sparse: bool,
+    /// How the database file is synced once the LTX file is applied.
+    pub sync: SyncMode,
+    /// Whether the directory of the database file is synced once the LTX file is
+    /// applied, which makes the creation of the database file durable.
+    pub sync_dir: bool,
+    /// Whether the database file is read and written around the page cache, see
+    /// [`DirectFile`].
+    pub direct: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            sparse: false,
+            sync: SyncMode::Full,
+            sync_dir: true,
+            direct: false,
+        }
+    }
+}
+
+/// Apply the LTX file read from `r` to the SQLite database file at `db_path` as
+/// [`apply_ltx`] does, with `opts`.
+///
+/// The database isn't synced if applying the LTX file fails.
+pub fn apply_ltx_with_options<P, R>(db_path: P, r: R, opts: Options) -> Result<Pos, Error>
+where
+    P: AsRef<Path>,
+    R: io::Read,
+{
+    let mut open = fs::OpenOptions::new();
+    open.read(true).write(true).create(true).truncate(false);
+
+    let pos = if opts.direct {
+        let mut db = DirectDatabase {
+            file: DirectFile::open_with(&open, db_path.as_ref())?,
+            sparse: opts.sparse,
+        };
+        let pos = apply(&mut db, r)?;
+        opts.sync.sync(db.file.get_ref())?;
+        pos
+    } else {
+        let mut db = open.open(db_path.as_ref())?;
+        let pos = if opts.sparse {
+            apply(&mut SparseFile(PageWriter::new(&db).sparse(true)), r)?
+        } else {
+            apply(&mut db, r)?
+        };
+        opts.sync.sync(&db)?;
+        pos
+    };
+
+    if opts.sync_dir {
+        file::sync_parent_dir(db_path.as_ref())?;
+    }
+
+    Ok(pos)
+}
+
+/// An error that can be returned by [`chain`].
+#[derive(thiserror::Error, Debug)]
+pub enum ChainError {
+    #[error("no LTX files to apply")]
+    Empty,
+    #[error("file {0}: decode")]
+    Decode(PathBuf, #[source] DecodeError),
+    #[error("file {0}: page size differs from the first file")]
+    PageSize(PathBuf),
+    #[error("file {0} doesn't apply")]
+    Precondition(PathBuf, #[source] PreconditionError),
+    #[error("file {0}: apply")]
+    Apply(PathBuf, #[source] Error),
+    #[error("database io")]
+    Io(#[from] io::Error),
+}
+
+/// Apply the LTX files at `files`, in order, to the SQLite database file at `db_path`
+/// as a single unit.
+///
+/// All files are decoded and verified up front, and every file must follow the previous
+/// one, i.e. start at the next TXID with a pre-apply checksum matching the post-apply
+/// checksum of the previous file, unless it's a snapshot. The first file is checked
+/// against the database with [`check`]. The files are kept open and applied through the
+/// same handles, so replacing a file after it was verified has no effect.
+///
+/// The original contents of the pages the files write or truncate are then saved in a
+/// rollback journal next to the database, at `<db_path>-ltxjournal`, and the files are
+/// applied with [`apply_ltx`]. If applying any of them fails, the database is rolled
+/// back from the journal. If the process crashes instead, the journal is left behind
+/// and the database must be rolled back with [`recover`] before it's used again, which
+/// `chain` does on its own. The journal is removed once the database is synced.
+///
+/// Returns the position of the database after the last file has been applied.
+///
+/// # Example
+/// ```no_run
+/// let files = [
+///     "0000000000000002-0000000000000002.ltx".into(),
+///     "0000000000000003-0000000000000005.ltx".into(),
+/// ];
+/// let pos = litetx::apply::chain("db.sqlite", &files).expect("chain");
+/// ```
+pub fn chain<P>(db_path: P, files: &[PathBuf]) -> Result<Pos, ChainError>
+where
+    P: AsRef<Path>,
+{
+    let db_path = db_path.as_ref();
+    recover(db_path)?;
+
+    // Decode every file, verifying its file checksum and the continuity of the chain.
+    let mut first: Option<Header> = None;
+    let mut prev: Option<(TXID, Option<Checksum>)> = None;
+    let mut pages = BTreeSet::new();
+    let mut min_commit = u32::MAX;
+    // The files are applied through the handles they were verified through, so a file
+    // replaced in the meantime isn't applied unverified.
+    let mut handles = Vec::with_capacity(files.len());
+    for path in files {
+        let f = fs::File::open(path)?;
+        let (mut dec, header) = Decoder::new(io::BufReader::new(&f))
+            .map_err(|e| ChainError::Decode(path.clone(), e))?;
+        while let Some((page_num, _)) = dec
+            .decode_page_ref()
+            .map_err(|e| ChainError::Decode(path.clone(), e))?
+        {
+            pages.insert(page_num.into_inner());
+        }
+        let trailer = dec
+            .finish()
+            .map_err(|e| ChainError::Decode(path.clone(), e))?;
+
+        if let Some((txid, checksum)) = prev.filter(|_| !header.is_snapshot()) {
+            let pos = Pos {
+                txid,
+                // Only the TXID can be checked after files without a post-apply checksum.
+                post_apply_checksum: checksum
+                    .or(header.pre_apply_checksum)
+                    .unwrap_or(Checksum::new(0)),
+            };
+            check_pos(pos, &header).map_err(|e| ChainError::Precondition(path.clone(), e))?;
+        }
+        match &first {
+            Some(first) if first.page_size != header.page_size => {
+                return Err(ChainError::PageSize(path.clone()));
+            }
+            Some(_) => (),
+            None => first = Some(header.clone()),
+        }
+
+        min_commit = min_commit.min(header.commit.map_or(0, |c| c.into_inner()));
+        prev = Some((header.max_txid, trailer.post_apply_checksum));
+        handles.push(f);
+    }
+    let first = first.ok_or(ChainError::Empty)?;
+
+    let mut db = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(db_path)?;
+    check(&mut db, &first).map_err(|e| ChainError::Precondition(files[0].clone(), e))?;
+
+    // Pages past the smallest commit may be truncated along the way.
+    let db_pages = db.metadata()?.len() / first.page_size.into_inner() as u64;
+    let truncated = min_commit.saturating_add(1)..=db_pages.min(u32::MAX as u64) as u32;
+    let journal_path = journal::path(db_path);
+    journal::write(
+        &journal_path,
+        &mut db,
+        first.page_size,
+        pages.into_iter().chain(truncated),
+    )?;
+
+    let mut pos = None;
+    for (path, mut f) in files.iter().zip(handles) {
+        let result = f.rewind().map_err(ChainError::from).and_then(|_| {
+            apply(&mut db, io::BufReader::new(f)).map_err(|e| ChainError::Apply(path.clone(), e))
+        });
+        match result {
+            Ok(p) => pos = Some(p),
+            Err(e) => {
+                journal::rollback(&journal_path, &mut db)?;
+                return Err(e);
+            }
+        }
+    }
+
+    db.sync_all()?;
+    fs::remove_file(&journal_path)?;
+    // A journal coming back after a crash would roll the applied files back.
+    file::sync_parent_dir(&journal_path)?;
+
+    Ok(pos.unwrap())
+}
+
+/// Roll back the database file at `db_path` if [`chain`] was interrupted while applying
+/// LTX files to it, returning whether it was rolled back.
+///
+/// Must be called before the database is opened after a crash, unless the next call on
+/// it is to `chain`.
+pub fn recover<P>(db_path: P) -> io::Result<bool>
+where
+    P: AsRef<Path>,
+{
+    let journal_path = journal::path(db_path.as_ref());
+    if !journal_path.try_exists()? {
+        return Ok(false);
+    }
+
+    let mut db = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(db_path)?;
+    journal::rollback(&journal_path, &mut db)
+}
+
+/// Writes pages into a database file at their position, without moving its cursor.
+///
+/// This is the primitive [`apply_ltx`] writes pages with. Page `n` of a page of size
+/// `s` is written at offset `(n - 1) * s`, and the file is extended as needed.
+///
+/// In sparse mode, pages of zeros aren't written. Past the end of the file, the file is
+/// extended instead, which leaves a hole on file systems supporting them. Inside the
+/// file, on Linux, the range of the page is deallocated with
+/// `fallocate(FALLOC_FL_PUNCH_HOLE)` if the file system supports it. Holes read back
+/// as zeros, so the contents of the file are the same either way.
+#[derive(Debug)]
+pub struct PageWriter<'a> {
+    file: &'a fs::File,
+    sparse: bool,
+}
+
+impl<'a> PageWriter<'a> {
+    /// Create a new writer of the pages of `file`.
+    pub fn new(file: &'a fs::File) -> PageWriter<'a> {
+        PageWriter {
+            file,
+            sparse: false,
+        }
+    }
+
+    /// Set whether pages of zeros are left as holes. Defaults to `false`.
+    pub fn sparse(mut self, sparse: bool) -> Self {
+        self.sparse = sparse;
+        self
+    }
+
+    /// Write `page` as page `page_num`, the page size being the length of `page`.
+    pub fn write_page(&self, page_num: PageNum, page: &[u8]) -> io::Result<()> {
+        let offset = (page_num.into_inner() as u64 - 1) * page.len() as u64;
+        if self.sparse && leave_hole(self.file, offset, page)? {
+            return Ok(());
+        }
+
+        direct::write_all_at(self.file, page, offset)
+    }
+
+    /// Truncate or extend the file to `commit` pages of `page_size`, or to zero if
+    /// `commit` is `None`.
+    pub fn truncate(&self, page_size: PageSize, commit: Option<PageNum>) -> io::Result<()> {
+        let pages = commit.map_or(0, |c| c.into_inner() as u64);
+        self.file.set_len(pages * page_size.into_inner() as u64)
+    }
+}
+
+/// Leave the range of `page` at `offset` in `file` as a hole if `page` is all zeros,
+/// returning `false` if it must be written.
+fn leave_hole(file: &fs::File, offset: u64, page: &[u8]) -> io::Result<bool> {
+    if page.iter().any(|&b| b != 0) {
+        return Ok(false);
+    }
+
+    let end = offset + page.len() as u64;
+    let len = file.metadata()?.len();
+    if end > len {
+        file.set_len(end)?;
+    }
+    Ok(offset >= len || punch_hole(file, offset, len.min(end) - offset)?)
+}
+
+/// Deallocate `len` bytes of `file` at `offset`, returning `false` if holes aren't
+/// supported.
+#[cfg(target_os = "linux")]
+fn punch_hole(file: &fs::File, offset: u64, len: u64) -> io::Result<bool> {
+    use std::os::fd::AsRawFd;
+
+    // SAFETY: The file descriptor is valid for the lifetime of `file`.
+    let rc = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            offset as libc::off_t,
+            len as libc::off_t,
+        )
+    };
+    if rc == 0 {
+        return Ok(true);
+    }
+
+    let err = io::Error::last_os_error();
+    match err.raw_os_error() {
+        Some(libc::EOPNOTSUPP) => Ok(false),
+        _ => Err(err),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn punch_hole(_file: &fs::File, _offset: u64, _len: u64) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// A database file that LTX files can be applied to.
+pub(crate) trait Database: Read + Seek {
+    /// Return the size of the database in bytes.
+    fn len(&mut self) -> io::Result<u64>;
+
+    /// Truncate or extend the database to `len` bytes.
+    fn set_len(&mut self, len: u64) -> io::Result<()>;
+
+    /// Write `page` as page `page_num`.
+    fn write_page(&mut self, page_num: PageNum, page: &[u8]) -> io::Result<()>;
+}
+
+impl Database for fs::File {
+    fn len(&mut self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        fs::File::set_len(self, len)
+    }
+
+    fn write_page(&mut self, page_num: PageNum, page: &[u8]) -> io::Result<()> {
+        PageWriter::new(self).write_page(page_num, page)
+    }
+}
+
+/// A database file written through a [`PageWriter`] in sparse mode.
+struct SparseFile<'a>(PageWriter<'a>);
+
+impl Read for SparseFile<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.file.read(buf)
+    }
+}
+
+impl Seek for SparseFile<'_> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.0.file.seek(pos)
+    }
+}
+
+impl Database for SparseFile<'_> {
+    fn len(&mut self) -> io::Result<u64> {
+        Ok(self.0.file.metadata()?.len())
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.0.file.set_len(len)
+    }
+
+    fn write_page(&mut self, page_num: PageNum, page: &[u8]) -> io::Result<()> {
+        self.0.write_page(page_num, page)
+    }
+}
+
+/// A database file opened with [`DirectFile`].
+struct DirectDatabase {
+    file: DirectFile,
+    sparse: bool,
+}
+
+impl Read for DirectDatabase {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.file.read(buf)
+    }
+}
+
+impl Seek for DirectDatabase {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+impl Database for DirectDatabase {
+    fn len(&mut self) -> io::Result<u64> {
+        Ok(self.file.get_ref().metadata()?.len())
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.file.get_ref().set_len(len)
+    }
+
+    fn write_page(&mut self, page_num: PageNum, page: &[u8]) -> io::Result<()> {
+        let offset = (page_num.into_inner() as u64 - 1) * page.len() as u64;
+        if self.sparse && leave_hole(self.file.get_ref(), offset, page)? {
+            return Ok(());
+        }
+
+        self.file.write_all_at(page, offset)
+    }
+}
+
+impl Database for io::Cursor<Vec<u8>> {
+    fn len(&mut self) -> io::Result<u64> {
+        Ok(self.get_ref().len() as u64)
+    }
+
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.get_mut().resize(len as usize, 0);
+        Ok(())
+    }
+
+    fn write_page(&mut self, page_num: PageNum, page: &[u8]) -> io::Result<()> {
+        self.seek(io::SeekFrom::Start(
+            (page_num.into_inner() as u64 - 1) * page.len() as u64,
+        ))?;
+        self.write_all(page)
+    }
+}
+
+pub(crate) fn apply<D, R>(db: &mut D, r: R) -> Result<Pos, Error>
+where
+    D: Database,
+    R: io::Read,
+{
+    let (mut dec, header) = Decoder::new(r)?;
+    let page_size = header.page_size.into_inner() as u64;
+    let db_pages = db.len()? / page_size;
+
+    let mut checksum = match header.pre_apply_checksum {
+        Some(expected) => {
+            db.rewind()?;
+            let checksum = pages_checksum(&mut *db, header.page_size, 1..=db_pages)?;
+            if checksum != expected {
+                return Err(Error::PreApplyChecksumMismatch(checksum, expected));
+            }
+            DatabaseChecksum::from(checksum)
+        }
+        None => DatabaseChecksum::new(),
+    };
+
+    let mut page = vec![0; page_size as usize];
+    let mut old_page = vec![0; page_size as usize];
+    while let Some(page_num) = dec.decode_page(&mut page)? {
+        let offset = (page_num.into_inner() as u64 - 1) * page_size;
+
+        // Snapshots replace the whole database, so only deltas account for the old contents.
+        if header.pre_apply_checksum.is_some() && (page_num.into_inner() as u64) <= db_pages {
+            db.seek(io::SeekFrom::Start(offset))?;
+            db.read_exact(&mut old_page)?;
+            checksum.replace_page(page_num, &old_page, &page);
+        } else {
+            checksum.add_page(page_num, &page);
+        }
+
+        db.write_page(page_num, &page)?;
+    }
+
+    let trailer = dec.finish()?;
+
+    let commit = header.commit.map_or(0, |c| c.into_inner() as u64);
+    if header.pre_apply_checksum.is_some() && commit < db_pages {
+        db.seek(io::SeekFrom::Start(commit * page_size))?;
+        read_pages(
+            &mut *db,
+            header.page_size,
+            commit + 1..=db_pages,
+            |pgno, page| checksum.remove_page(pgno, page),
+        )?;
+    }
+    db.set_len(commit * page_size)?;
+
+    let checksum = checksum.finish();
+    let checksum = match trailer.post_apply_checksum {
+        // Files without checksums can't be verified, so the resulting checksum is
+        // calculated from the whole database.
+        None => {
+            db.rewind()?;
+            pages_checksum(&mut *db, header.page_size, 1..=commit)?
+        }
+        Some(expected) if checksum != expected => {
+            return Err(Error::PostApplyChecksumMismatch(checksum, expected));
+        }
+        Some(expected) => expected,
+    };
+
+    Ok(Pos {
+        txid: header.max_txid,
+        post_apply_checksum: checksum,
+    })
+}
+
+/// Calculate the checksum of the database pages in `range` read sequentially from `r`.
+pub(crate) fn pages_checksum<R>(
+    r: R,
+    page_size: PageSize,
+    range: RangeInclusive<u64>,
+) -> io::Result<Checksum>
+where
+    R: io::Read,
+{
+    let mut checksum = DatabaseChecksum::new();
+    read_pages(r, page_size, range, |pgno, page| {
+        checksum.add_page(pgno, page)
+    })?;
+
+    Ok(checksum.finish())
+}
+
+/// Call `f` with every database page in `range` read sequentially from `r`, skipping the
+/// lock page.
+pub(crate) fn read_pages<R, F>(
+    mut r: R,
+    page_size: PageSize,
+    range: RangeInclusive<u64>,
+    mut f: F,
+) -> io::Result<()>
+where
+    R: io::Read,
+    F: FnMut(PageNum, &[u8]),
+{
+    let lock_page = PageNum::lock_page(page_size);
+    let mut page = vec![0; page_size.into_inner() as usize];
+    for page_num in range {
+        r.read_exact(&mut page)?;
+
+        let page_num = PageNum::new(page_num as u32)?;
+        if page_num != lock_page {
+            f(page_num, &page);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        apply_ltx, apply_ltx_with_options, chain, check, check_pos, recover, ChainError, Error,
+        Options, PageWriter, PreconditionError,
+    };
+    use crate::{
+        journal, read_header, utils::TempDir, Checksum, DatabaseChecksum, Encoder, Header,
+        HeaderFlags, PageNum, PageSize, Pos, SyncMode, TXID,
+    };
+    use std::{
+        env, fs,
+        io::{self, Seek},
+        path::PathBuf,
+        time,
+    };
+
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new() -> TempFile {
+            TempFile(env::temp_dir().join(format!("litetx-{:016x}", rand::random::<u64>())))
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn db_checksum(pages: &[(u32, Vec<u8>)]) -> Checksum {
+        let mut checksum = DatabaseChecksum::new();
+        for (n, p) in pages {
+            checksum.add_page(PageNum::new(*n).unwrap(), p);
+        }
+        checksum.finish()
+    }
+
+    fn encode_file(
+        min_txid: u64,
+        commit: u32,
+        pre_apply_checksum: Option<Checksum>,
+        post_apply_checksum: Checksum,
+        pages: &[(u32, Vec<u8>)],
+    ) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(
+            &mut buf,
+            &Header {
+                flags: HeaderFlags::COMPRESS_LZ4,
+                page_size: PageSize::new(512).unwrap(),
+                commit: Some(PageNum::new(commit).unwrap()),
+                min_txid: TXID::new(min_txid).unwrap(),
+                max_txid: TXID::new(min_txid).unwrap(),
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder");
+
+        for (page_num, page) in pages {
+            enc.encode_page(PageNum::new(*page_num).unwrap(), page)
+                .expect("failed to encode page");
+        }
+        enc.finish(post_apply_checksum)
+            .expect("failed to finish encoder");
+
+        buf
+    }
+
+    fn contents(pages: &[(u32, Vec<u8>)]) -> Vec<u8> {
+        pages.iter().flat_map(|(_, p)| p.iter().copied()).collect()
+    }
+
+    fn random_page() -> Vec<u8> {
+        (0..512).map(|_| rand::random::<u8>()).collect()
+    }
+
+    #[test]
+    fn apply_snapshot_and_delta() {
+        let db = TempFile::new();
+
+        let mut pages = vec![(1, random_page()), (2, random_page()), (3, random_page())];
+        let checksum = db_checksum(&pages);
+        let snapshot = encode_file(1, 3, None, checksum, &pages);
+
+        let pos = apply_ltx(&db.0, snapshot.as_slice()).expect("failed to apply snapshot");
+        assert_eq!(
+            Pos {
+                txid: TXID::ONE,
+                post_apply_checksum: checksum,
+            },
+            pos
+        );
+        assert_eq!(contents(&pages), fs::read(&db.0).unwrap());
+
+        // Update the second page and shrink the database by one page.
+        let update = (2, random_page());
+        pages[1] = update.clone();
+        pages.truncate(2);
+        let delta = encode_file(2, 2, Some(checksum), db_checksum(&pages), &[update]);
+
+        let pos = apply_ltx(&db.0, delta.as_slice()).expect("failed to apply delta");
+        assert_eq!(
+            Pos {
+                txid: TXID::new(2).unwrap(),
+                post_apply_checksum: db_checksum(&pages),
+            },
+            pos
+        );
+        assert_eq!(contents(&pages), fs::read(&db.0).unwrap());
+    }
+
+    #[test]
+    fn apply_deletion() {
+        let db = TempFile::new();
+
+        let pages = vec![(1, random_page()), (2, random_page())];
+        let snapshot = encode_file(1, 2, None, db_checksum(&pages), &pages);
+        apply_ltx(&db.0, snapshot.as_slice()).expect("failed to apply snapshot");
+
+        let mut deletion = Vec::new();
+        Encoder::new(
+            &mut deletion,
+            &Header {
+                flags: HeaderFlags::empty(),
+                page_size: PageSize::new(512).unwrap(),
+                commit: None,
+                min_txid: TXID::new(2).unwrap(),
+                max_txid: TXID::new(2).unwrap(),
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum: Some(db_checksum(&pages)),
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder")
+        .finish(Checksum::new(0))
+        .expect("failed to finish encoder");
+
+        let pos = apply_ltx(&db.0, deletion.as_slice()).expect("failed to apply deletion");
+        assert_eq!(
+            Pos {
+                txid: TXID::new(2).unwrap(),
+                post_apply_checksum: Checksum::new(0),
+            },
+            pos
+        );
+        assert_eq!(0, fs::metadata(&db.0).unwrap().len());
+    }
+
+    #[test]
+    fn apply_no_checksum() {
+        let db = TempFile::new();
+
+        let mut pages = vec![(1, random_page()), (2, random_page())];
+        let snapshot = encode_file(1, 2, None, db_checksum(&pages), &pages);
+        apply_ltx(&db.0, snapshot.as_slice()).expect("failed to apply snapshot");
+
+        let update = (2, random_page());
+        pages[1] = update.clone();
+        let mut delta = Vec::new();
+        let mut enc = Encoder::new(
+            &mut delta,
+            &Header {
+                flags: HeaderFlags::NO_CHECKSUM,
+                page_size: PageSize::new(512).unwrap(),
+                commit: Some(PageNum::new(2).unwrap()),
+                min_txid: TXID::new(2).unwrap(),
+                max_txid: TXID::new(2).unwrap(),
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder");
+        enc.encode_page(PageNum::new(2).unwrap(), &update.1)
+            .expect("failed to encode page");
+        enc.finish(None).expect("failed to finish encoder");
+
+        let pos = apply_ltx(&db.0, delta.as_slice()).expect("failed to apply delta");
+        assert_eq!(
+            Pos {
+                txid: TXID::new(2).unwrap(),
+                post_apply_checksum: db_checksum(&pages),
+            },
+            pos
+        );
+        assert_eq!(contents(&pages), fs::read(&db.0).unwrap());
+    }
+
+    #[test]
+    fn apply_pre_apply_checksum_mismatch() {
+        let db = TempFile::new();
+
+        let pages = vec![(1, random_page())];
+        let snapshot = encode_file(1, 1, None, db_checksum(&pages), &pages);
+        apply_ltx(&db.0, snapshot.as_slice()).expect("failed to apply snapshot");
+
+        let delta = encode_file(
+            2,
+            1,
+            Some(Checksum::new(1)),
+            Checksum::new(2),
+            &[(1, random_page())],
+        );
+        assert!(matches!(
+            apply_ltx(&db.0, delta.as_slice()),
+            Err(Error::PreApplyChecksumMismatch(c, e))
+                if c == db_checksum(&pages) && e == Checksum::new(1)
+        ));
+        assert_eq!(contents(&pages), fs::read(&db.0).unwrap());
+    }
+
+    #[test]
+    fn apply_post_apply_checksum_mismatch() {
+        let db = TempFile::new();
+
+        let pages = vec![(1, random_page())];
+        let snapshot = encode_file(1, 1, None, Checksum::new(1), &pages);
+
+        assert!(matches!(
+            apply_ltx(&db.0, snapshot.as_slice()),
+            Err(Error::PostApplyChecksumMismatch(c, e))
+                if c == db_checksum(&pages) && e == Checksum::new(1)
+        ));
+    }
+
+    #[test]
+    fn check_pre_apply_checksum() {
+        let pages = vec![(1, random_page()), (2, random_page())];
+        let checksum = db_checksum(&pages);
+        let db = contents(&pages);
+
+        let snapshot = encode_file(1, 2, None, checksum, &pages);
+        let header = read_header(snapshot.as_slice()).expect("failed to read header");
+        check(io::Cursor::new(Vec::new()), &header).expect("snapshot doesn't apply");
+
+        let delta = encode_file(2, 2, Some(checksum), Checksum::new(1), &pages[..1]);
+        let header = read_header(delta.as_slice()).expect("failed to read header");
+        check(io::Cursor::new(&db), &header).expect("delta doesn't apply");
+        assert!(matches!(
+            check(io::Cursor::new(&db[..512]), &header),
+            Err(PreconditionError::ChecksumMismatch(c, e))
+                if c == db_checksum(&pages[..1]) && e == checksum
+        ));
+        assert!(matches!(
+            check(io::Cursor::new(&db[..600]), &header),
+            Err(PreconditionError::PageSize(_))
+        ));
+    }
+
+    #[test]
+    fn check_against_pos() {
+        let delta = encode_file(5, 1, Some(Checksum::new(1)), Checksum::new(2), &[]);
+        let header = read_header(delta.as_slice()).expect("failed to read header");
+
+        let pos = |txid, checksum| Pos {
+            txid: TXID::new(txid).unwrap(),
+            post_apply_checksum: Checksum::new(checksum),
+        };
+        check_pos(pos(4, 1), &header).expect("delta doesn't apply");
+        assert!(matches!(
+            check_pos(pos(3, 1), &header),
+            Err(PreconditionError::TxidMismatch(expected, actual))
+                if expected == TXID::new(5).unwrap() && actual == TXID::new(3).unwrap()
+        ));
+        assert!(matches!(
+            check_pos(pos(4, 3), &header),
+            Err(PreconditionError::ChecksumMismatch(..))
+        ));
+
+        let snapshot = encode_file(1, 1, None, Checksum::new(2), &[(1, random_page())]);
+        let header = read_header(snapshot.as_slice()).expect("failed to read header");
+        check_pos(pos(9, 9), &header).expect("snapshot doesn't apply");
+    }
+
+    /// Write the LTX files of a snapshot of pages 1 to 3 followed by deltas updating page
+    /// 2 and shrinking the database to 2 pages, then growing it back to 4 pages, the last
+    /// one with a wrong post-apply checksum if `bad_last`.
+    fn write_chain(dir: &TempDir, bad_last: bool) -> (Vec<PathBuf>, Vec<u8>, Vec<u8>) {
+        let mut pages = vec![(1, random_page()), (2, random_page()), (3, random_page())];
+        let snapshot_checksum = db_checksum(&pages);
+        let snapshot = encode_file(1, 3, None, snapshot_checksum, &pages);
+        let snapshot_contents = contents(&pages);
+
+        pages[1].1 = random_page();
+        pages.truncate(2);
+        let delta1_checksum = db_checksum(&pages);
+        let delta1 = encode_file(2, 2, Some(snapshot_checksum), delta1_checksum, &pages[1..2]);
+
+        pages.push((3, random_page()));
+        pages.push((4, random_page()));
+        let delta2_checksum = if bad_last {
+            Checksum::new(1)
+        } else {
+            db_checksum(&pages)
+        };
+        let delta2 = encode_file(3, 4, Some(delta1_checksum), delta2_checksum, &pages[2..]);
+
+        let paths = [snapshot, delta1, delta2]
+            .into_iter()
+            .enumerate()
+            .map(|(i, data)| {
+                let path = dir.path().join(format!("{i}.ltx"));
+                fs::write(&path, data).expect("failed to write LTX file");
+                path
+            })
+            .collect();
+
+        (paths, snapshot_contents, contents(&pages))
+    }
+
+    #[test]
+    fn chain_apply() {
+        let dir = TempDir::new();
+        let db = dir.path().join("db");
+        let (files, _, expected) = write_chain(&dir, false);
+
+        let pos = chain(&db, &files).expect("failed to apply chain");
+        assert_eq!(TXID::new(3).unwrap(), pos.txid);
+        assert_eq!(expected, fs::read(&db).unwrap());
+        assert!(!journal::path(&db).exists());
+    }
+
+    #[test]
+    fn chain_discontinuity() {
+        let dir = TempDir::new();
+        let db = dir.path().join("db");
+        let (files, _, _) = write_chain(&dir, false);
+
+        assert!(matches!(
+            chain(&db, &[files[0].clone(), files[2].clone()]),
+            Err(ChainError::Precondition(path, PreconditionError::TxidMismatch(..)))
+                if path == files[2]
+        ));
+        assert!(!db.exists());
+        assert!(matches!(chain(&db, &[]), Err(ChainError::Empty)));
+    }
+
+    #[test]
+    fn chain_rollback() {
+        let dir = TempDir::new();
+        let db = dir.path().join("db");
+        let (files, snapshot, _) = write_chain(&dir, true);
+        apply_ltx(&db, fs::File::open(&files[0]).unwrap()).expect("failed to apply snapshot");
+
+        assert!(matches!(
+            chain(&db, &files[1..]),
+            Err(ChainError::Apply(path, Error::PostApplyChecksumMismatch(..))) if path == files[2]
+        ));
+        assert_eq!(snapshot, fs::read(&db).unwrap());
+        assert!(!journal::path(&db).exists());
+    }
+
+    #[test]
+    fn chain_recover() {
+        let dir = TempDir::new();
+        let db = dir.path().join("db");
+        let original = contents(&[(1, random_page()), (2, random_page())]);
+        fs::write(&db, &original).unwrap();
+        assert!(!recover(&db).expect("failed to recover"));
+
+        // A crash after the journal was written.
+        let journal_path = journal::path(&db);
+        let mut f = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&db)
+            .unwrap();
+        journal::write(&journal_path, &mut f, PageSize::new(512).unwrap(), [1, 2]).unwrap();
+        fs::write(&db, random_page()).unwrap();
+        assert!(recover(&db).expect("failed to recover"));
+        assert_eq!(original, fs::read(&db).unwrap());
+        assert!(!journal_path.exists());
+
+        // A crash while the journal was written, before the database was touched.
+        journal::write(&journal_path, &mut f, PageSize::new(512).unwrap(), [1, 2]).unwrap();
+        let journal = fs::read(&journal_path).unwrap();
+        fs::write(&journal_path, &journal[..journal.len() - 1]).unwrap();
+        assert!(!recover(&db).expect("failed to recover"));
+        assert_eq!(original, fs::read(&db).unwrap());
+        assert!(!journal_path.exists());
+    }
+
+    #[test]
+    fn page_writer() {
+        let db = TempFile::new();
+        let f = fs::File::create(&db.0).unwrap();
+        let page = random_page();
+
+        for sparse in [false, true] {
+            f.set_len(0).unwrap();
+            let w = PageWriter::new(&f).sparse(sparse);
+            w.write_page(PageNum::new(3).unwrap(), &page).unwrap();
+            w.write_page(PageNum::new(1).unwrap(), &page).unwrap();
+            w.write_page(PageNum::new(5).unwrap(), &[0; 512]).unwrap();
+            assert_eq!(0, (&f).stream_position().unwrap());
+
+            let mut expected = [page.clone(), vec![0; 512], page.clone(), vec![0; 1024]].concat();
+            assert_eq!(expected, fs::read(&db.0).unwrap());
+
+            // Zero pages inside the file are written or punched out.
+            w.write_page(PageNum::new(3).unwrap(), &[0; 512]).unwrap();
+            expected[1024..1536].fill(0);
+            assert_eq!(expected, fs::read(&db.0).unwrap());
+
+            w.truncate(PageSize::new(512).unwrap(), PageNum::new(2).ok())
+                .unwrap();
+            assert_eq!(&expected[..1024], fs::read(&db.0).unwrap());
+            w.truncate(PageSize::new(512).unwrap(), None).unwrap();
+            assert_eq!(0, fs::metadata(&db.0).unwrap().len());
+        }
+    }
+
+    #[test]
+    fn apply_sparse() {
+        let pages = vec![(1, random_page()), (2, vec![0; 512]), (3, vec![0; 512])];
+        let snapshot = encode_file(1, 3, None, db_checksum(&pages), &pages);
+
+        let db = TempFile::new();
+        let opts = Options {
+            sparse: true,
+            ..Default::default()
+        };
+        let pos = apply_ltx_with_options(&db.0, snapshot.as_slice(), opts)
+            .expect("failed to apply snapshot");
+        assert_eq!(db_checksum(&pages), pos.post_apply_checksum);
+        assert_eq!(contents(&pages), fs::read(&db.0).unwrap());
+    }
+
+    #[test]
+    fn apply_direct() {
+        let pages = vec![(1, random_page()), (2, vec![0; 512]), (3, random_page())];
+        let snapshot = encode_file(1, 3, None, db_checksum(&pages), &pages);
+        let mut new_pages = pages.clone();
+        new_pages[2].1 = random_page();
+        let delta = encode_file(
+            2,
+            3,
+            Some(db_checksum(&pages)),
+            db_checksum(&new_pages),
+            &new_pages[2..],
+        );
+
+        for sparse in [false, true] {
+            let db = TempFile::new();
+            let opts = Options {
+                direct: true,
+                sparse,
+                ..Default::default()
+            };
+            apply_ltx_with_options(&db.0, snapshot.as_slice(), opts.clone())
+                .expect("failed to apply snapshot");
+            let pos = apply_ltx_with_options(&db.0, delta.as_slice(), opts)
+                .expect("failed to apply delta");
+            assert_eq!(db_checksum(&new_pages), pos.post_apply_checksum);
+            assert_eq!(contents(&new_pages), fs::read(&db.0).unwrap());
+        }
+    }
+
+    #[test]
+    fn apply_sync_modes() {
+        let pages = vec![(1, random_page()), (2, random_page())];
+        let snapshot = encode_file(1, 2, None, db_checksum(&pages), &pages);
+
+        for sync in [SyncMode::None, SyncMode::Data, SyncMode::Full] {
+            let db = TempFile::new();
+            let opts = Options {
+                sync,
+                sync_dir: sync != SyncMode::None,
+                ..Default::default()
+            };
+            apply_ltx_with_options(&db.0, snapshot.as_slice(), opts)
+                .expect("failed to apply snapshot");
+            assert_eq!(contents(&pages), fs::read(&db.0).unwrap());
+        }
+    }
+}
@@ -0,0 +1,655 @@
+use crate::{
+    compression,
+    decoder::{DecodeOptions, Error as DecodeError, PageValidator},
+    encoder::Error as EncodeError,
+    ltx::{Crc64Digest, PageHeader, CRC64, HEADER_SIZE, PAGE_HEADER_SIZE, TRAILER_SIZE},
+    lz4::{self, BlockDecoder, BlockHeader, FrameDescriptor},
+    Checksum, Encoder, Header, HeaderFlags, PageNum, PageSize, Trailer,
+};
+use std::{hash::Hasher, io};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use twox_hash::XxHash32;
+
+/// An LTX file encoder writing to an [`AsyncWrite`].
+///
+/// Pages are encoded in memory and the encoded bytes are written to the underlying
+/// writer before each method returns.
+///
+/// # Example
+/// ```no_run
+/// # use std::time::SystemTime;
+/// # use litetx::PageChecksum;
+/// # async fn example() {
+/// # let mut w = Vec::new();
+/// # let page = vec![0; 4096];
+/// #
+/// let mut enc = litetx::AsyncEncoder::new(&mut w, &litetx::Header{
+///     flags: litetx::HeaderFlags::empty(),
+///     page_size: litetx::PageSize::new(4096).unwrap(),
+///     commit: Some(litetx::PageNum::new(1).unwrap()),
+///     min_txid: litetx::TXID::ONE,
+///     max_txid: litetx::TXID::ONE,
+///     timestamp: SystemTime::now(),
+///     pre_apply_checksum: None,
+///     node_id: 0,
+///     wal: None,
+///     app_data: None,
+///     page_filter: None,
+///     key_id: None,
+///     dict_id: None,
+/// }).await.expect("encoder");
+///
+/// let page_num = litetx::PageNum::new(1).unwrap();
+/// enc.encode_page(page_num, &page).await.expect("encode_page");
+///
+/// enc.finish(page.page_checksum(page_num)).await.expect("finish");
+/// # }
+/// ```
+pub struct AsyncEncoder<'a, W>
+where
+    W: AsyncWrite + Unpin,
+{
+    enc: Encoder<'a, Vec<u8>>,
+    w: W,
+}
+
+impl<'a, W> AsyncEncoder<'a, W>
+where
+    W: AsyncWrite + Unpin,
+{
+    /// Create a new [`AsyncEncoder`] that writes to `w`.
+    ///
+    /// See [`Encoder::new`] for details.
+    pub async fn new(w: W, hdr: &Header) -> Result<AsyncEncoder<'a, W>, EncodeError> {
+        let mut enc = AsyncEncoder {
+            enc: Encoder::new(Vec::new(), hdr)?,
+            w,
+        };
+        enc.flush().await?;
+
+        Ok(enc)
+    }
+
+    /// Encode a page with the given `page_num` and `data`.
+    ///
+    /// See [`Encoder::encode_page`] for details.
+    pub async fn encode_page(&mut self, page_num: PageNum, data: &[u8]) -> Result<(), EncodeError> {
+        self.enc.encode_page(page_num, data)?;
+        self.flush().await?;
+
+        Ok(())
+    }
+
+    /// Consume the encoder and write LTX trailer into the output.
+    ///
+    /// See [`Encoder::finish`](crate::Encoder::finish) for details.
+    pub async fn finish<C>(mut self, post_apply_checksum: C) -> Result<Trailer, EncodeError>
+    where
+        C: Into<Option<Checksum>>,
+    {
+        let (trailer, buf) = self.enc.finish_into_inner(post_apply_checksum.into())?;
+        self.w.write_all(&buf).await?;
+        self.w.flush().await?;
+
+        Ok(trailer)
+    }
+
+    async fn flush(&mut self) -> io::Result<()> {
+        let buf = self.enc.get_mut();
+        if !buf.is_empty() {
+            self.w.write_all(buf).await?;
+            buf.clear();
+        }
+
+        Ok(())
+    }
+}
+
+/// An LTX file decoder reading from an [`AsyncRead`].
+///
+/// The decoder never reads past the end of the LTX file, so the reader can be
+/// reused for the data that follows it. Page numbers are checked as by [`Decoder`](crate::Decoder),
+/// and so are the block and content checksums of LZ4 frames. Zstd-compressed files
+/// are not supported.
+///
+/// # Example
+/// ```no_run
+/// # async fn example() {
+/// # let v = Vec::new();
+/// # let r = &v[..];
+/// #
+/// let (mut dec, header) = litetx::AsyncDecoder::new(r).await.expect("decoder");
+///
+/// let mut buf = vec![0; header.page_size.into_inner() as usize];
+/// while let Some(page_num) = dec.decode_page(&mut buf).await.expect("decode_page") {
+///     // do something with the page
+/// }
+///
+/// let trailer = dec.finish().await.expect("finish");
+/// # }
+/// ```
+pub struct AsyncDecoder<'a, R>
+where
+    R: AsyncRead + Unpin,
+{
+    r: R,
+    digest: Crc64Digest<'a>,
+    flags: HeaderFlags,
+    page_size: PageSize,
+    validator: PageValidator,
+    last_page_num: Option<PageNum>,
+    options: DecodeOptions,
+    pages: u64,
+    frame: Option<BlockDecoder>,
+    content_hasher: XxHash32,
+    // Decoded bytes which haven't been consumed yet start at `pos`.
+    buf: Vec<u8>,
+    pos: usize,
+    block: Vec<u8>,
+    pages_done: bool,
+}
+
+impl<'a, R> AsyncDecoder<'a, R>
+where
+    R: AsyncRead + Unpin,
+{
+    /// Construct a new [`AsyncDecoder`] that reads from `r`.
+    pub async fn new(r: R) -> Result<(AsyncDecoder<'a, R>, Header), DecodeError> {
+        AsyncDecoder::new_with_options(r, DecodeOptions::default()).await
+    }
+
+    /// Construct a new [`AsyncDecoder`] that reads from `r` with `options`.
+    ///
+    /// Limits are enforced as by [`Decoder`](crate::Decoder), failing with
+    /// [`Error::LimitExceeded`](crate::DecodeError::LimitExceeded).
+    pub async fn new_with_options(
+        mut r: R,
+        options: DecodeOptions,
+    ) -> Result<(AsyncDecoder<'a, R>, Header), DecodeError> {
+        let mut buf = vec![0; HEADER_SIZE];
+        r.read_exact(&mut buf).await?;
+        buf.resize(Header::encoded_size(&buf), 0);
+        r.read_exact(&mut buf[HEADER_SIZE..]).await?;
+
+        let mut digest = CRC64.digest();
+        digest.update(&buf);
+        let hdr = Header::decode_from_with(buf.as_slice(), options.lenient_flags)?;
+        options.check_header(&hdr)?;
+        let unsupported = hdr.flags.intersection(
+            HeaderFlags::COMPRESS_ZSTD
+                | HeaderFlags::ENCRYPTED
+                | HeaderFlags::PAGE_INDEX
+                | HeaderFlags::MERKLE_TREE
+                | HeaderFlags::PAGE_CHECKSUM
+                | HeaderFlags::LZ4_DICTIONARY
+                | HeaderFlags::PER_PAGE_COMPRESSION
+                | compression::registered_flags(),
+        );
+        if !unsupported.is_empty() {
+            return Err(DecodeError::UnsupportedFlags(unsupported));
+        }
+
+        let frame = if hdr.flags.contains(HeaderFlags::COMPRESS_LZ4) {
+            let mut desc = vec![0; FrameDescriptor::PREFIX_SIZE];
+            r.read_exact(&mut desc).await?;
+            desc.resize(FrameDescriptor::size(&desc)?, 0);
+            r.read_exact(&mut desc[FrameDescriptor::PREFIX_SIZE..])
+                .await?;
+
+            Some(BlockDecoder::new(FrameDescriptor::parse(&desc)?))
+        } else {
+            None
+        };
+
+        Ok((
+            AsyncDecoder {
+                r,
+                digest,
+                flags: hdr.flags,
+                page_size: hdr.page_size,
+                validator: PageValidator::new(&hdr, false),
+                last_page_num: None,
+                options,
+                pages: 0,
+                frame,
+                content_hasher: XxHash32::with_seed(0),
+                buf: Vec::new(),
+                pos: 0,
+                block: Vec::new(),
+                pages_done: false,
+            },
+            hdr,
+        ))
+    }
+
+    /// Decode the next page from the LTX file.
+    ///
+    /// See [`Decoder::decode_page`](crate::Decoder::decode_page) for details.
+    pub async fn decode_page(&mut self, data: &mut [u8]) -> Result<Option<PageNum>, DecodeError> {
+        if self.pages_done {
+            return Ok(None);
+        };
+
+        if data.len() != self.page_size.into_inner() as usize {
+            return Err(DecodeError::InvalidBufferSize(data.len(), self.page_size));
+        }
+
+        let header = PageHeader::decode_from(self.consume(PAGE_HEADER_SIZE).await?)?;
+        let Some(page_num) = header.0 else {
+            self.pages_done = true;
+            return Ok(None);
+        };
+        self.validator
+            .validate_page_num(self.last_page_num, page_num)?;
+        self.options.check_pages(self.pages + 1, self.page_size)?;
+
+        data.copy_from_slice(self.consume(data.len()).await?);
+        self.last_page_num = Some(page_num);
+        self.pages += 1;
+
+        Ok(Some(page_num))
+    }
+
+    /// Consume the decoder and verify file checksum.
+    pub async fn finish(mut self) -> Result<Trailer, DecodeError> {
+        if !self.pages_done {
+            return Err(io::Error::other("expected end of pages").into());
+        }
+        if self.pos != self.buf.len() {
+            return Err(io::Error::other("expected lz4 end frame").into());
+        }
+        self.validator.validate_complete(self.last_page_num)?;
+
+        if let Some(frame) = &self.frame {
+            let mut buf = [0; lz4::BLOCK_HEADER_SIZE];
+            self.r.read_exact(&mut buf).await?;
+            if BlockHeader::parse(buf) != BlockHeader::EndMark {
+                return Err(io::Error::other("expected lz4 end frame").into());
+            }
+
+            if frame.descriptor().content_checksum {
+                let mut buf = [0; lz4::CHECKSUM_SIZE];
+                self.r.read_exact(&mut buf).await?;
+                if u32::from_le_bytes(buf) != self.content_hasher.finish_32() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "lz4 content checksum mismatch",
+                    )
+                    .into());
+                }
+            }
+        }
+
+        let mut buf = [0; TRAILER_SIZE];
+        self.r.read_exact(&mut buf).await?;
+        let trailer = Trailer::decode_from(buf.as_slice())?;
+        trailer.validate(self.flags)?;
+
+        self.digest.update(&trailer.post_apply_checksum_bytes());
+
+        if Checksum::new(self.digest.finalize()) != trailer.file_checksum {
+            return Err(DecodeError::FileChecksumMismatch);
+        }
+
+        Ok(trailer)
+    }
+
+    /// Return the next `n` decoded bytes, reading more data from the underlying reader
+    /// when needed.
+    async fn consume(&mut self, n: usize) -> io::Result<&[u8]> {
+        if self.buf.len() - self.pos < n {
+            self.buf.drain(..self.pos);
+            self.pos = 0;
+        }
+
+        while self.buf.len() - self.pos < n {
+            match &mut self.frame {
+                None => {
+                    let len = self.buf.len();
+                    self.buf.resize(self.pos + n, 0);
+                    self.r.read_exact(&mut self.buf[len..]).await?;
+                }
+                Some(frame) => {
+                    let mut header = [0; lz4::BLOCK_HEADER_SIZE];
+                    self.r.read_exact(&mut header).await?;
+                    let header = BlockHeader::parse(header);
+
+                    let size = match header {
+                        BlockHeader::EndMark => {
+                            return Err(io::Error::new(
+                                io::ErrorKind::UnexpectedEof,
+                                "unexpected lz4 end frame",
+                            ))
+                        }
+                        BlockHeader::Data { size, .. } => size,
+                    };
+                    if size > frame.descriptor().max_block_size {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "invalid lz4 block size",
+                        ));
+                    }
+
+                    self.block.resize(size, 0);
+                    self.r.read_exact(&mut self.block).await?;
+                    if frame.descriptor().block_checksums {
+                        let mut checksum = [0; lz4::CHECKSUM_SIZE];
+                        self.r.read_exact(&mut checksum).await?;
+                        if u32::from_le_bytes(checksum) != XxHash32::oneshot(0, &self.block) {
+                            return Err(io::Error::new(
+                                io::ErrorKind::InvalidData,
+                                "lz4 block checksum mismatch",
+                            ));
+                        }
+                    }
+
+                    let len = self.buf.len();
+                    frame.decode(header, &self.block, &mut self.buf)?;
+                    if frame.descriptor().content_checksum {
+                        self.content_hasher.write(&self.buf[len..]);
+                    }
+                }
+            }
+        }
+
+        let data = &self.buf[self.pos..self.pos + n];
+        self.pos += n;
+        self.digest.update(data);
+
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AsyncDecoder, AsyncEncoder};
+    use crate::{
+        ltx::{HEADER_SIZE, PAGE_HEADER_SIZE},
+        utils::TimeRound,
+        Checksum, DecodeError, DecodeLimit, DecodeOptions, Decoder, Encoder, Header, HeaderFlags,
+        PageNum, PageSize, TXID,
+    };
+    use std::{future::Future, pin::pin, task, time};
+
+    fn block_on<F: Future>(f: F) -> F::Output {
+        let mut f = pin!(f);
+        let mut cx = task::Context::from_waker(task::Waker::noop());
+        loop {
+            if let task::Poll::Ready(v) = f.as_mut().poll(&mut cx) {
+                return v;
+            }
+        }
+    }
+
+    fn test_header(flags: HeaderFlags) -> Header {
+        Header {
+            flags,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(40).unwrap()),
+            min_txid: TXID::new(5).unwrap(),
+            max_txid: TXID::new(6).unwrap(),
+            timestamp: time::SystemTime::now()
+                .round(time::Duration::from_millis(1))
+                .unwrap(),
+            pre_apply_checksum: Some(Checksum::new(5)),
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        }
+    }
+
+    fn test_pages() -> Vec<(PageNum, Vec<u8>)> {
+        (1..=40)
+            .map(|n| {
+                let page = (0..4096).map(|i| ((i + n) % 7) as u8).collect();
+                (PageNum::new(n).unwrap(), page)
+            })
+            .collect()
+    }
+
+    fn async_encode_sync_decode(flags: HeaderFlags) {
+        let header = test_header(flags);
+        let pages = test_pages();
+
+        let mut buf = Vec::new();
+        let trailer = block_on(async {
+            let mut enc = AsyncEncoder::new(&mut buf, &header)
+                .await
+                .expect("failed to create encoder");
+            for (page_num, page) in &pages {
+                enc.encode_page(*page_num, page)
+                    .await
+                    .expect("failed to encode page");
+            }
+            enc.finish(Checksum::new(6))
+                .await
+                .expect("failed to finish encoder")
+        });
+
+        let (mut dec, header_out) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        assert_eq!(header, header_out);
+
+        let mut page_out = vec![0; 4096];
+        for (page_num, page) in pages {
+            assert!(matches!(
+                dec.decode_page(&mut page_out),
+                Ok(Some(num)) if num == page_num
+            ));
+            assert_eq!(page, page_out);
+        }
+        assert!(matches!(dec.decode_page(&mut page_out), Ok(None)));
+        assert_eq!(trailer, dec.finish().expect("failed to finish decoder"));
+    }
+
+    fn sync_encode_async_decode(flags: HeaderFlags) {
+        let header = test_header(flags);
+        let pages = test_pages();
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &header).expect("failed to create encoder");
+        for (page_num, page) in &pages {
+            enc.encode_page(*page_num, page)
+                .expect("failed to encode page");
+        }
+        let trailer = enc
+            .finish(Checksum::new(6))
+            .expect("failed to finish encoder");
+
+        // Data following the LTX file must be left unread.
+        buf.extend_from_slice(b"next");
+        let mut r = buf.as_slice();
+
+        block_on(async {
+            let (mut dec, header_out) = AsyncDecoder::new(&mut r)
+                .await
+                .expect("failed to create decoder");
+            assert_eq!(header, header_out);
+
+            let mut page_out = vec![0; 4096];
+            for (page_num, page) in pages {
+                assert!(matches!(
+                    dec.decode_page(&mut page_out).await,
+                    Ok(Some(num)) if num == page_num
+                ));
+                assert_eq!(page, page_out);
+            }
+            assert!(matches!(dec.decode_page(&mut page_out).await, Ok(None)));
+            assert_eq!(
+                trailer,
+                dec.finish().await.expect("failed to finish decoder")
+            );
+        });
+
+        assert_eq!(b"next", r);
+    }
+
+    #[test]
+    fn async_encoder() {
+        async_encode_sync_decode(HeaderFlags::empty());
+    }
+
+    #[test]
+    fn async_encoder_compressed() {
+        async_encode_sync_decode(HeaderFlags::COMPRESS_LZ4);
+    }
+
+    #[test]
+    fn async_decoder() {
+        sync_encode_async_decode(HeaderFlags::empty());
+    }
+
+    #[test]
+    fn async_decoder_compressed() {
+        sync_encode_async_decode(HeaderFlags::COMPRESS_LZ4);
+    }
+
+    fn async_decode(buf: &[u8]) -> Result<(), DecodeError> {
+        block_on(async {
+            let (mut dec, _) = AsyncDecoder::new(buf).await?;
+            let mut page = vec![0; 4096];
+            while dec.decode_page(&mut page).await?.is_some() {}
+            dec.finish().await.map(|_| ())
+        })
+    }
+
+    #[test]
+    fn async_decoder_out_of_order() {
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &test_header(HeaderFlags::empty()))
+            .expect("failed to create encoder");
+        for (page_num, page) in &test_pages()[..3] {
+            enc.encode_page(*page_num, page)
+                .expect("failed to encode page");
+        }
+        enc.finish(Checksum::new(6))
+            .expect("failed to finish encoder");
+
+        // Swap the page numbers of the second and third pages.
+        let second = HEADER_SIZE + PAGE_HEADER_SIZE + 4096;
+        let third = second + PAGE_HEADER_SIZE + 4096;
+        buf[second + 3] = 3;
+        buf[third + 3] = 2;
+
+        assert!(matches!(
+            async_decode(&buf),
+            Err(DecodeError::OutOfOrderPage(last, page_num))
+                if last.into_inner() == 3 && page_num.into_inner() == 2
+        ));
+    }
+
+    #[test]
+    fn async_decoder_incomplete_snapshot() {
+        let header = Header {
+            commit: Some(PageNum::new(3).unwrap()),
+            min_txid: TXID::ONE,
+            pre_apply_checksum: None,
+            ..test_header(HeaderFlags::empty())
+        };
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &header)
+            .expect("failed to create encoder")
+            .allow_incomplete_snapshot();
+        for (page_num, page) in &test_pages()[..2] {
+            enc.encode_page(*page_num, page)
+                .expect("failed to encode page");
+        }
+        enc.finish(Checksum::new(6))
+            .expect("failed to finish encoder");
+
+        assert!(matches!(
+            async_decode(&buf),
+            Err(DecodeError::IncompleteSnapshot(Some(last), want))
+                if last.into_inner() == 2 && want.into_inner() == 3
+        ));
+    }
+
+    #[test]
+    fn async_decoder_lz4_checksums() {
+        let mut buf = Vec::new();
+        let mut enc = Encoder::builder()
+            .block_checksum(true)
+            .content_checksum(true)
+            .build(&mut buf, &test_header(HeaderFlags::COMPRESS_LZ4))
+            .expect("failed to create encoder");
+        for (page_num, page) in &test_pages() {
+            enc.encode_page(*page_num, page)
+                .expect("failed to encode page");
+        }
+        enc.finish(Checksum::new(6))
+            .expect("failed to finish encoder");
+        async_decode(&buf).expect("failed to decode");
+
+        // The content checksum precedes the trailer.
+        let mut corrupt = buf.clone();
+        let len = corrupt.len();
+        corrupt[len - 17] ^= 1;
+        assert!(matches!(
+            async_decode(&corrupt),
+            Err(DecodeError::Read(err)) if err.to_string() == "lz4 content checksum mismatch"
+        ));
+
+        // The checksum of the first block precedes the second block.
+        let block = HEADER_SIZE + 7;
+        let size = u32::from_le_bytes(buf[block..block + 4].try_into().unwrap()) & 0x7fff_ffff;
+        let mut corrupt = buf.clone();
+        corrupt[block + 4 + size as usize] ^= 1;
+        assert!(matches!(
+            async_decode(&corrupt),
+            Err(DecodeError::Read(err)) if err.to_string() == "lz4 block checksum mismatch"
+        ));
+    }
+
+    #[test]
+    fn async_decoder_limits() {
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &test_header(HeaderFlags::COMPRESS_LZ4))
+            .expect("failed to create encoder");
+        for (page_num, page) in &test_pages() {
+            enc.encode_page(*page_num, page)
+                .expect("failed to encode page");
+        }
+        enc.finish(Checksum::new(6))
+            .expect("failed to finish encoder");
+
+        let decode = |options| {
+            block_on(async {
+                let (mut dec, _) = AsyncDecoder::new_with_options(buf.as_slice(), options).await?;
+                let mut page = vec![0; 4096];
+                while dec.decode_page(&mut page).await?.is_some() {}
+                dec.finish().await.map(|_| ())
+            })
+        };
+
+        decode(DecodeOptions {
+            max_pages: Some(40),
+            max_decompressed_bytes: Some(40 * 4096),
+            max_page_size: PageSize::new(4096).ok(),
+            ..Default::default()
+        })
+        .expect("failed to decode");
+        assert!(matches!(
+            decode(DecodeOptions {
+                max_page_size: PageSize::new(1024).ok(),
+                ..Default::default()
+            }),
+            Err(DecodeError::LimitExceeded(DecodeLimit::PageSize))
+        ));
+        assert!(matches!(
+            decode(DecodeOptions {
+                max_pages: Some(39),
+                ..Default::default()
+            }),
+            Err(DecodeError::LimitExceeded(DecodeLimit::Pages))
+        ));
+        assert!(matches!(
+            decode(DecodeOptions {
+                max_decompressed_bytes: Some(40 * 4096 - 1),
+                ..Default::default()
+            }),
+            Err(DecodeError::LimitExceeded(DecodeLimit::DecompressedBytes))
+        ));
+    }
+}
@@ -0,0 +1,627 @@
+//! An LTX codec depending only on `core` and `alloc`.
+//!
+//! [`BareDecoder`] and [`BareEncoder`] read and write through the [`io`](crate::io)
+//! traits, so they build with `default-features = false`, e.g. to verify and repackage
+//! files inside a WASM sandbox. They support uncompressed and LZ4-compressed files
+//! with optional page checksums and page filters. Other flags fail with
+//! [`Error::UnsupportedFlags`].
+
+use crate::{
+    io::{self, Read, Write},
+    ltx::{
+        Crc64Digest, HeaderDecodeError, HeaderEncodeError, PageHeader, PageHeaderDecodeError,
+        PageHeaderEncodeError, TrailerDecodeError, TrailerEncodeError, CRC64, HEADER_SIZE,
+        PAGE_CHECKSUM_SIZE, PAGE_HEADER_SIZE,
+    },
+    lz4::{FrameDescriptor, ParallelFrameDecoder, ParallelFrameEncoder},
+    Checksum, Header, HeaderFlags, PageChecksum, PageFilter, PageNum, PageSize, Trailer,
+};
+use alloc::{vec, vec::Vec};
+
+/// The flags supported by [`BareDecoder`] and [`BareEncoder`].
+const SUPPORTED_FLAGS: HeaderFlags = HeaderFlags::COMPRESS_LZ4
+    .union(HeaderFlags::NO_CHECKSUM)
+    .union(HeaderFlags::PAGE_CHECKSUM)
+    .union(HeaderFlags::PAGE_FILTER);
+
+/// The size of the LZ4 blocks written by [`BareEncoder`], as by default in
+/// [`Encoder`](crate::Encoder).
+const LZ4_BLOCK_SIZE: usize = 64 * 1024;
+
+/// An error that can be returned by [`BareDecoder`] and [`BareEncoder`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("decode header")]
+    HeaderDecode(#[from] HeaderDecodeError),
+    #[error("encode header")]
+    HeaderEncode(#[from] HeaderEncodeError),
+    #[error("decode page header")]
+    PageHeaderDecode(#[from] PageHeaderDecodeError),
+    #[error("encode page header")]
+    PageHeaderEncode(#[from] PageHeaderEncodeError),
+    #[error("decode trailer")]
+    TrailerDecode(#[from] TrailerDecodeError),
+    #[error("encode trailer")]
+    TrailerEncode(#[from] TrailerEncodeError),
+    #[error("unsupported header flags: {0:?}")]
+    UnsupportedFlags(HeaderFlags),
+    #[error("invalid page buffer size: {0}, expected {1}")]
+    InvalidBufferSize(usize, PageSize),
+    #[error("invalid lock page: {0}")]
+    LockPage(PageNum),
+    #[error("page {0} in a file deleting the database")]
+    DeletedDatabase(PageNum),
+    #[error("page {0} beyond commit {1}")]
+    PageBeyondCommit(PageNum, PageNum),
+    #[error("out-of-order page numbers: {0}, {1}")]
+    OutOfOrderPage(PageNum, PageNum),
+    #[error("page {0} missing from the page filter")]
+    PageNotInFilter(PageNum),
+    #[error("snapshot must start with page number 1, got {0}")]
+    FirstSnapshotPage(PageNum),
+    #[error("nonsequential page numbers in snapshot: {0}, {1}")]
+    NonsequentialPages(PageNum, PageNum),
+    #[error("incomplete snapshot: last page {0:?}, expected {1}")]
+    IncompleteSnapshot(Option<PageNum>, PageNum),
+    #[error("page {0} checksum mismatch")]
+    PageChecksumMismatch(PageNum),
+    #[error("pages left before the trailer")]
+    PagesLeft,
+    #[error("file checksum mismatch")]
+    FileChecksumMismatch,
+    #[error("post-apply checksum required on files with checksums")]
+    NoPostApplyChecksum,
+    #[error("io")]
+    Io(#[from] io::Error),
+}
+
+/// An LTX file decoder reading from a [`Read`](crate::io::Read).
+///
+/// The decoder never reads past the end of the LTX file, and the file checksum is
+/// verified by [`BareDecoder::finish`].
+///
+/// # Example
+/// ```no_run
+/// # let v = Vec::new();
+/// # let r = &v[..];
+/// let (mut dec, header) = litetx::BareDecoder::new(r).expect("decoder");
+///
+/// let mut buf = vec![0; header.page_size.into_inner() as usize];
+/// while let Some(page_num) = dec.decode_page(&mut buf).expect("decode_page") {
+///     // do something with the page
+/// }
+///
+/// let trailer = dec.finish().expect("finish");
+/// ```
+pub struct BareDecoder<R>
+where
+    R: Read,
+{
+    r: Reader<R>,
+    digest: Crc64Digest<'static>,
+    pages: PageValidator,
+    flags: HeaderFlags,
+    pages_done: bool,
+}
+
+impl<R> BareDecoder<R>
+where
+    R: Read,
+{
+    /// Construct a new [`BareDecoder`] that reads from `r`.
+    pub fn new(mut r: R) -> Result<(BareDecoder<R>, Header), Error> {
+        let mut buf = vec![0; HEADER_SIZE];
+        r.read_exact(&mut buf)?;
+        buf.resize(Header::encoded_size(&buf), 0);
+        r.read_exact(&mut buf[HEADER_SIZE..])?;
+
+        let hdr = Header::decode_from(buf.as_slice())?;
+        check_flags(hdr.flags)?;
+        let mut digest = CRC64.digest();
+        digest.update(&buf);
+
+        let r = if hdr.flags.contains(HeaderFlags::COMPRESS_LZ4) {
+            Reader::Lz4(ParallelFrameDecoder::new(r, 1))
+        } else {
+            Reader::Uncompressed(r)
+        };
+
+        Ok((
+            BareDecoder {
+                r,
+                digest,
+                pages: PageValidator::new(&hdr),
+                flags: hdr.flags,
+                pages_done: false,
+            },
+            hdr,
+        ))
+    }
+
+    /// Decode the next page from the LTX file.
+    ///
+    /// See [`Decoder::decode_page`](crate::Decoder::decode_page) for details.
+    pub fn decode_page(&mut self, data: &mut [u8]) -> Result<Option<PageNum>, Error> {
+        if self.pages_done {
+            return Ok(None);
+        }
+
+        if data.len() != self.pages.page_size.into_inner() as usize {
+            return Err(Error::InvalidBufferSize(data.len(), self.pages.page_size));
+        }
+
+        let mut buf = [0; PAGE_HEADER_SIZE];
+        self.read(&mut buf)?;
+        let Some(page_num) = PageHeader::decode_from(buf.as_slice())?.0 else {
+            self.pages_done = true;
+            return Ok(None);
+        };
+        self.pages.push(page_num)?;
+
+        self.read(data)?;
+        if self.flags.contains(HeaderFlags::PAGE_CHECKSUM) {
+            let mut buf = [0; PAGE_CHECKSUM_SIZE];
+            self.read(&mut buf)?;
+            if data.page_checksum(page_num) != Checksum::new(u64::from_be_bytes(buf)) {
+                return Err(Error::PageChecksumMismatch(page_num));
+            }
+        }
+
+        Ok(Some(page_num))
+    }
+
+    /// Consume the decoder and verify the file checksum.
+    ///
+    /// All pages must have been decoded first, or this fails with [`Error::PagesLeft`].
+    pub fn finish(mut self) -> Result<Trailer, Error> {
+        if !self.pages_done {
+            return Err(Error::PagesLeft);
+        }
+        self.pages.finish()?;
+
+        let mut r = self.r.finish()?;
+        let trailer = Trailer::decode_from(&mut r)?;
+        trailer.validate(self.flags)?;
+
+        self.digest.update(&trailer.post_apply_checksum_bytes());
+        if Checksum::new(self.digest.finalize()) != trailer.file_checksum {
+            return Err(Error::FileChecksumMismatch);
+        }
+
+        Ok(trailer)
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<()> {
+        self.r.read_exact(buf)?;
+        self.digest.update(buf);
+        Ok(())
+    }
+}
+
+/// An LTX file encoder writing to a [`Write`](crate::io::Write).
+///
+/// # Example
+/// ```no_run
+/// # use litetx::{time::SystemTime, PageChecksum};
+/// # let mut w = Vec::new();
+/// # let page = vec![0; 4096];
+/// #
+/// let mut enc = litetx::BareEncoder::new(&mut w, &litetx::Header{
+///     flags: litetx::HeaderFlags::COMPRESS_LZ4,
+///     page_size: litetx::PageSize::new(4096).unwrap(),
+///     commit: Some(litetx::PageNum::new(1).unwrap()),
+///     min_txid: litetx::TXID::ONE,
+///     max_txid: litetx::TXID::ONE,
+///     timestamp: SystemTime::UNIX_EPOCH,
+///     pre_apply_checksum: None,
+///     node_id: 0,
+///     wal: None,
+///     app_data: None,
+///     page_filter: None,
+///     key_id: None,
+///     dict_id: None,
+/// }).expect("encoder");
+///
+/// let page_num = litetx::PageNum::new(1).unwrap();
+/// enc.encode_page(page_num, &page).expect("encode_page");
+///
+/// enc.finish(page.page_checksum(page_num)).expect("finish");
+/// ```
+pub struct BareEncoder<W>
+where
+    W: Write,
+{
+    w: Writer<W>,
+    digest: Crc64Digest<'static>,
+    pages: PageValidator,
+    flags: HeaderFlags,
+}
+
+impl<W> BareEncoder<W>
+where
+    W: Write,
+{
+    /// Create a new [`BareEncoder`] that writes to `w`.
+    pub fn new(mut w: W, hdr: &Header) -> Result<BareEncoder<W>, Error> {
+        check_flags(hdr.flags)?;
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        hdr.encode_into(&mut header)?;
+        w.write_all(&header)?;
+
+        let mut digest = CRC64.digest();
+        digest.update(&header);
+
+        let w = if hdr.flags.contains(HeaderFlags::COMPRESS_LZ4) {
+            let desc = FrameDescriptor {
+                independent_blocks: true,
+                block_checksums: false,
+                content_checksum: false,
+                max_block_size: LZ4_BLOCK_SIZE,
+                dict_id: None,
+            };
+            Writer::Lz4(ParallelFrameEncoder::new(w, desc, 1))
+        } else {
+            Writer::Uncompressed(w)
+        };
+
+        Ok(BareEncoder {
+            w,
+            digest,
+            pages: PageValidator::new(hdr),
+            flags: hdr.flags,
+        })
+    }
+
+    /// Encode a page with the given `page_num` and `data`.
+    ///
+    /// See [`Encoder::encode_page`](crate::Encoder::encode_page) for details.
+    pub fn encode_page(&mut self, page_num: PageNum, data: &[u8]) -> Result<(), Error> {
+        if data.len() != self.pages.page_size.into_inner() as usize {
+            return Err(Error::InvalidBufferSize(data.len(), self.pages.page_size));
+        }
+        self.pages.push(page_num)?;
+
+        let mut header = Vec::with_capacity(PAGE_HEADER_SIZE);
+        PageHeader(Some(page_num)).encode_into(&mut header)?;
+        self.write(&header)?;
+        self.write(data)?;
+        if self.flags.contains(HeaderFlags::PAGE_CHECKSUM) {
+            self.write(&data.page_checksum(page_num).into_inner().to_be_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Consume the encoder and write the LTX trailer into the output.
+    ///
+    /// See [`Encoder::finish`](crate::Encoder::finish) for details.
+    pub fn finish<C>(mut self, post_apply_checksum: C) -> Result<Trailer, Error>
+    where
+        C: Into<Option<Checksum>>,
+    {
+        let post_apply_checksum = match post_apply_checksum.into() {
+            _ if self.flags.contains(HeaderFlags::NO_CHECKSUM) => None,
+            None => return Err(Error::NoPostApplyChecksum),
+            checksum => checksum,
+        };
+        self.pages.finish()?;
+
+        let mut header = Vec::with_capacity(PAGE_HEADER_SIZE);
+        PageHeader(None).encode_into(&mut header)?;
+        self.write(&header)?;
+        let mut w = self.w.finish()?;
+
+        let mut trailer = Trailer {
+            post_apply_checksum,
+            file_checksum: Checksum::new(0),
+        };
+        self.digest.update(&trailer.post_apply_checksum_bytes());
+        trailer.file_checksum = Checksum::new(self.digest.finalize());
+        trailer.encode_into(&mut w)?;
+        w.flush()?;
+
+        Ok(trailer)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.w.write_all(buf)?;
+        self.digest.update(buf);
+        Ok(())
+    }
+}
+
+fn check_flags(flags: HeaderFlags) -> Result<(), Error> {
+    let unsupported = flags.difference(SUPPORTED_FLAGS);
+    if !unsupported.is_empty() {
+        return Err(Error::UnsupportedFlags(unsupported));
+    }
+
+    Ok(())
+}
+
+/// Checks that the page numbers of a file are valid and in increasing order, and that
+/// snapshots contain every page of the database.
+struct PageValidator {
+    page_size: PageSize,
+    commit: Option<PageNum>,
+    is_snapshot: bool,
+    page_filter: Option<PageFilter>,
+    last_page_num: Option<PageNum>,
+}
+
+impl PageValidator {
+    fn new(hdr: &Header) -> PageValidator {
+        PageValidator {
+            page_size: hdr.page_size,
+            commit: hdr.commit,
+            is_snapshot: hdr.is_snapshot(),
+            page_filter: hdr.page_filter,
+            last_page_num: None,
+        }
+    }
+
+    fn push(&mut self, page_num: PageNum) -> Result<(), Error> {
+        if page_num == PageNum::lock_page(self.page_size) {
+            return Err(Error::LockPage(page_num));
+        }
+        let Some(commit) = self.commit else {
+            return Err(Error::DeletedDatabase(page_num));
+        };
+        if page_num > commit {
+            return Err(Error::PageBeyondCommit(page_num, commit));
+        }
+        match self.last_page_num {
+            None if self.is_snapshot && page_num != PageNum::ONE => {
+                return Err(Error::FirstSnapshotPage(page_num));
+            }
+            Some(last)
+                if self.is_snapshot
+                    && last.next_snapshot_page(self.page_size) != Some(page_num) =>
+            {
+                return Err(Error::NonsequentialPages(last, page_num));
+            }
+            Some(last) if last >= page_num => return Err(Error::OutOfOrderPage(last, page_num)),
+            _ => (),
+        }
+        if self
+            .page_filter
+            .is_some_and(|filter| !filter.may_contain(page_num))
+        {
+            return Err(Error::PageNotInFilter(page_num));
+        }
+
+        self.last_page_num = Some(page_num);
+        Ok(())
+    }
+
+    fn finish(&self) -> Result<(), Error> {
+        let Some(commit) = self.commit.filter(|_| self.is_snapshot) else {
+            return Ok(());
+        };
+
+        let want = PageNum::last_snapshot_page(self.page_size, commit);
+        if self.last_page_num != Some(want) {
+            return Err(Error::IncompleteSnapshot(self.last_page_num, want));
+        }
+
+        Ok(())
+    }
+}
+
+enum Reader<R>
+where
+    R: Read,
+{
+    Uncompressed(R),
+    Lz4(ParallelFrameDecoder<R>),
+}
+
+impl<R> Reader<R>
+where
+    R: Read,
+{
+    /// Make sure the LZ4 frame has been read till its end and return the reader of the
+    /// data following it.
+    fn finish(self) -> io::Result<R> {
+        match self {
+            Reader::Uncompressed(r) => Ok(r),
+            Reader::Lz4(mut dec) => {
+                if dec.read(&mut [0; 1])? != 0 {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "expected lz4 end frame",
+                    ));
+                }
+                Ok(dec.into_inner())
+            }
+        }
+    }
+}
+
+impl<R> Read for Reader<R>
+where
+    R: Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Reader::Uncompressed(r) => r.read(buf),
+            Reader::Lz4(dec) => dec.read(buf),
+        }
+    }
+}
+
+enum Writer<W>
+where
+    W: Write,
+{
+    Uncompressed(W),
+    Lz4(ParallelFrameEncoder<W>),
+}
+
+impl<W> Writer<W>
+where
+    W: Write,
+{
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Writer::Uncompressed(w) => Ok(w),
+            Writer::Lz4(enc) => enc.finish(),
+        }
+    }
+}
+
+impl<W> Write for Writer<W>
+where
+    W: Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Writer::Uncompressed(w) => w.write(buf),
+            Writer::Lz4(enc) => enc.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Writer::Uncompressed(w) => w.flush(),
+            Writer::Lz4(enc) => enc.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BareDecoder, BareEncoder, Error};
+    use crate::{
+        Checksum, Decoder, Encoder, Header, HeaderFlags, PageFilter, PageNum, PageSize, TXID,
+    };
+    use std::time;
+
+    fn test_header(flags: HeaderFlags) -> Header {
+        let page_filter = flags.contains(HeaderFlags::PAGE_FILTER).then(|| {
+            let mut filter = PageFilter::new();
+            (1..=3).for_each(|n| filter.insert(PageNum::new(n * 10).unwrap()));
+            filter
+        });
+        Header {
+            flags,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(40).unwrap()),
+            min_txid: TXID::new(5).unwrap(),
+            max_txid: TXID::new(6).unwrap(),
+            timestamp: time::SystemTime::UNIX_EPOCH + time::Duration::from_millis(1234),
+            pre_apply_checksum: (!flags.contains(HeaderFlags::NO_CHECKSUM))
+                .then_some(Checksum::new(5)),
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter,
+            key_id: None,
+            dict_id: None,
+        }
+    }
+
+    fn test_pages() -> Vec<(PageNum, Vec<u8>)> {
+        (1..=3)
+            .map(|n| {
+                let page = (0..4096).map(|i| ((i + n) % 7) as u8).collect();
+                (PageNum::new(n * 10).unwrap(), page)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn bare_roundtrip() {
+        for flags in [
+            HeaderFlags::empty(),
+            HeaderFlags::COMPRESS_LZ4,
+            HeaderFlags::COMPRESS_LZ4 | HeaderFlags::PAGE_CHECKSUM | HeaderFlags::PAGE_FILTER,
+            HeaderFlags::NO_CHECKSUM | HeaderFlags::PAGE_CHECKSUM,
+        ] {
+            let header = test_header(flags);
+            let pages = test_pages();
+
+            let mut bare = Vec::new();
+            let mut enc = BareEncoder::new(&mut bare, &header).expect("failed to create encoder");
+            for (page_num, page) in &pages {
+                enc.encode_page(*page_num, page)
+                    .expect("failed to encode page");
+            }
+            let trailer = enc.finish(Checksum::new(6)).expect("failed to finish");
+
+            let mut std = Vec::new();
+            let mut enc = Encoder::new(&mut std, &header).expect("failed to create encoder");
+            for (page_num, page) in &pages {
+                enc.encode_page(*page_num, page)
+                    .expect("failed to encode page");
+            }
+            assert_eq!(trailer, enc.finish(Checksum::new(6)).unwrap());
+
+            let (mut dec, header_out) =
+                Decoder::new(bare.as_slice()).expect("failed to create decoder");
+            assert_eq!(header, header_out);
+            let mut page_out = vec![0; 4096];
+            for (page_num, page) in &pages {
+                assert_eq!(Some(*page_num), dec.decode_page(&mut page_out).unwrap());
+                assert_eq!(page, &page_out);
+            }
+            assert_eq!(None, dec.decode_page(&mut page_out).unwrap());
+            assert_eq!(trailer, dec.finish().expect("failed to finish decoder"));
+
+            let mut r = std.as_slice();
+            let (mut dec, header_out) = BareDecoder::new(&mut r).expect("failed to create decoder");
+            assert_eq!(header, header_out);
+            for (page_num, page) in &pages {
+                assert_eq!(Some(*page_num), dec.decode_page(&mut page_out).unwrap());
+                assert_eq!(page, &page_out);
+            }
+            assert_eq!(None, dec.decode_page(&mut page_out).unwrap());
+            assert_eq!(trailer, dec.finish().expect("failed to finish decoder"));
+            assert!(r.is_empty());
+        }
+    }
+
+    #[test]
+    fn bare_errors() {
+        let header = test_header(HeaderFlags::PAGE_INDEX);
+        assert!(matches!(
+            BareEncoder::new(Vec::new(), &header),
+            Err(Error::UnsupportedFlags(HeaderFlags::PAGE_INDEX))
+        ));
+
+        let header = test_header(HeaderFlags::COMPRESS_LZ4);
+        let mut enc = BareEncoder::new(Vec::new(), &header).unwrap();
+        let page = vec![0; 4096];
+        enc.encode_page(PageNum::new(2).unwrap(), &page).unwrap();
+        assert!(matches!(
+            enc.encode_page(PageNum::new(1).unwrap(), &page),
+            Err(Error::OutOfOrderPage(_, _))
+        ));
+
+        let mut buf = Vec::new();
+        let mut enc = BareEncoder::new(&mut buf, &header).unwrap();
+        enc.encode_page(PageNum::new(2).unwrap(), &page).unwrap();
+        enc.finish(Checksum::new(6)).unwrap();
+        let len = buf.len();
+        buf[len - 1] ^= 1;
+
+        let (mut dec, _) = BareDecoder::new(buf.as_slice()).unwrap();
+        let mut page_out = vec![0; 4096];
+        dec.decode_page(&mut page_out).unwrap();
+        assert!(matches!(dec.finish(), Err(Error::PagesLeft)));
+
+        let (mut dec, _) = BareDecoder::new(buf.as_slice()).unwrap();
+        while dec.decode_page(&mut page_out).unwrap().is_some() {}
+        assert!(matches!(dec.finish(), Err(Error::FileChecksumMismatch)));
+
+        let mut header = test_header(HeaderFlags::empty());
+        header.min_txid = TXID::ONE;
+        header.pre_apply_checksum = None;
+        let mut enc = BareEncoder::new(Vec::new(), &header).unwrap();
+        enc.encode_page(PageNum::ONE, &page).unwrap();
+        assert!(matches!(
+            enc.finish(Checksum::new(6)),
+            Err(Error::IncompleteSnapshot(Some(PageNum::ONE), _))
+        ));
+    }
+}
@@ -0,0 +1,268 @@
+//! Command line tool for inspecting and manipulating LTX files.
+
+use litetx::{self as ltx, Header, HeaderFlags, Trailer, TXID};
+use std::{
+    env,
+    error::Error,
+    fs,
+    io::{self, BufReader, BufWriter, Write},
+    process,
+};
+
+const USAGE: &str = "\
+usage: ltx <command> [arguments]
+
+commands:
+    info FILE...                              print the header and the trailer of files
+    verify FILE...                            verify the integrity of files
+    encode-db -o PATH [-c] [-txid TXID] [-direct] DB
+                                              encode a database as a snapshot
+    apply -db PATH [-direct] FILE...          apply files to a database
+    dump [-json] FILE                         print the contents of a file
+    compact -o PATH [-c] FILE...              merge a sequence of files";
+
+type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some((cmd, args)) = args.split_first() else {
+        eprintln!("{USAGE}");
+        process::exit(2);
+    };
+
+    let result = match cmd.as_str() {
+        "info" => info(args),
+        "verify" => verify(args),
+        "encode-db" => encode_db(args),
+        "apply" => apply(args),
+        "dump" => dump(args),
+        "compact" => compact(args),
+        "help" | "-h" | "--help" => {
+            println!("{USAGE}");
+            Ok(())
+        }
+        _ => Err(format!("unknown command: {cmd}").into()),
+    };
+
+    if let Err(e) = result {
+        let mut msg = e.to_string();
+        let mut source = e.source();
+        while let Some(e) = source {
+            msg.push_str(&format!(": {e}"));
+            source = e.source();
+        }
+        eprintln!("ltx: {msg}");
+        process::exit(1);
+    }
+}
+
+/// Command line arguments of a command.
+#[derive(Default)]
+struct Args {
+    output: Option<String>,
+    db: Option<String>,
+    txid: Option<TXID>,
+    compress: bool,
+    json: bool,
+    direct: bool,
+    files: Vec<String>,
+}
+
+/// Parse `args`, accepting only the flags in `allowed`.
+fn parse_args(args: &[String], allowed: &[&str]) -> Result<Args> {
+    let mut parsed = Args::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let flag = arg.trim_start_matches('-');
+        if flag.len() == arg.len() {
+            parsed.files.push(arg.clone());
+            continue;
+        }
+        if !allowed.contains(&flag) {
+            return Err(format!("unknown flag: {arg}").into());
+        }
+
+        let mut value = || args.next().ok_or(format!("missing value of {arg}"));
+        match flag {
+            "o" => parsed.output = Some(value()?.clone()),
+            "db" => parsed.db = Some(value()?.clone()),
+            "txid" => parsed.txid = Some(TXID::try_from(value()?.clone())?),
+            "c" => parsed.compress = true,
+            "json" => parsed.json = true,
+            "direct" => parsed.direct = true,
+            _ => unreachable!(),
+        }
+    }
+
+    Ok(parsed)
+}
+
+fn flags(compress: bool) -> HeaderFlags {
+    if compress {
+        HeaderFlags::COMPRESS_LZ4
+    } else {
+        HeaderFlags::empty()
+    }
+}
+
+fn open(path: &str) -> Result<BufReader<fs::File>> {
+    let file = fs::File::open(path).map_err(|e| format!("open {path}: {e}"))?;
+    Ok(BufReader::new(file))
+}
+
+fn print_header(w: &mut impl Write, header: &Header) -> io::Result<()> {
+    let timestamp = header.timestamp_millis().unwrap_or(0);
+
+    writeln!(w, "flags:      {:?}", header.flags)?;
+    writeln!(w, "page size:  {}", header.page_size)?;
+    match header.commit {
+        Some(commit) => writeln!(w, "commit:     {commit}")?,
+        None => writeln!(w, "commit:     deleted")?,
+    }
+    writeln!(w, "min txid:   {}", header.min_txid)?;
+    writeln!(w, "max txid:   {}", header.max_txid)?;
+    writeln!(w, "timestamp:  {timestamp} ms")?;
+    match header.pre_apply_checksum {
+        Some(checksum) => writeln!(w, "pre-apply:  {checksum}")?,
+        None => writeln!(w, "pre-apply:  -")?,
+    }
+    if header.node_id != 0 {
+        writeln!(w, "node id:    {:016x}", header.node_id)?;
+    }
+    if let Some(wal) = header.wal {
+        writeln!(
+            w,
+            "wal:        offset {}, size {}, salt {:08x}/{:08x}",
+            wal.offset, wal.size, wal.salt1, wal.salt2
+        )?;
+    }
+    if let Some(data) = header.app_data {
+        let hex: String = data.iter().map(|b| format!("{b:02x}")).collect();
+        writeln!(w, "app data:   {hex}")?;
+    }
+
+    Ok(())
+}
+
+fn print_trailer(w: &mut impl Write, trailer: &Trailer) -> io::Result<()> {
+    match trailer.post_apply_checksum {
+        Some(checksum) => writeln!(w, "post-apply: {checksum}")?,
+        None => writeln!(w, "post-apply: -")?,
+    }
+    writeln!(w, "file:       {}", trailer.file_checksum)
+}
+
+fn info(args: &[String]) -> Result<()> {
+    let args = parse_args(args, &[])?;
+    let mut w = io::stdout().lock();
+
+    for (i, path) in args.files.iter().enumerate() {
+        let mut r = open(path)?;
+        let header = ltx::read_header(&mut r)?;
+        let trailer = Trailer::read_from_end(&mut r)?;
+
+        if i > 0 {
+            writeln!(w)?;
+        }
+        writeln!(w, "{path}")?;
+        print_header(&mut w, &header)?;
+        print_trailer(&mut w, &trailer)?;
+    }
+
+    Ok(())
+}
+
+fn verify(args: &[String]) -> Result<()> {
+    let args = parse_args(args, &[])?;
+
+    let mut failed = false;
+    for path in &args.files {
+        match ltx::verify(open(path)?) {
+            Ok(_) => println!("{path}: ok"),
+            Err(e) => {
+                let source = e.source().map(|e| format!(": {e}")).unwrap_or_default();
+                println!("{path}: {e}{source}");
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        return Err("verification failed".into());
+    }
+    Ok(())
+}
+
+fn encode_db(args: &[String]) -> Result<()> {
+    let args = parse_args(args, &["o", "c", "txid", "direct"])?;
+    let output = args.output.ok_or("output path required")?;
+    let [db] = &args.files[..] else {
+        return Err("exactly one database required".into());
+    };
+
+    let opts = ltx::SnapshotOptions {
+        flags: flags(args.compress),
+        txid: args.txid.unwrap_or(TXID::ONE),
+        ..Default::default()
+    };
+    let mut w = BufWriter::new(fs::File::create(&output)?);
+    if args.direct {
+        let db = ltx::DirectFile::open(db).map_err(|e| format!("open {db}: {e}"))?;
+        ltx::encode_db_snapshot(db, &mut w, opts)?;
+    } else {
+        ltx::encode_db_snapshot(open(db)?, &mut w, opts)?;
+    }
+    w.into_inner()?.sync_all()?;
+
+    Ok(())
+}
+
+fn apply(args: &[String]) -> Result<()> {
+    let args = parse_args(args, &["db", "direct"])?;
+    let db = args.db.ok_or("database path required")?;
+
+    let opts = ltx::apply::Options {
+        direct: args.direct,
+        ..Default::default()
+    };
+    for path in &args.files {
+        let pos = ltx::apply::apply_ltx_with_options(&db, open(path)?, opts.clone())?;
+        println!("{path}: {pos}");
+    }
+
+    Ok(())
+}
+
+fn dump(args: &[String]) -> Result<()> {
+    let args = parse_args(args, &["json"])?;
+    let [path] = &args.files[..] else {
+        return Err("exactly one file required".into());
+    };
+
+    let format = if args.json {
+        ltx::DumpFormat::Json
+    } else {
+        ltx::DumpFormat::Text
+    };
+    ltx::dump(open(path)?, io::stdout().lock(), format)?;
+
+    Ok(())
+}
+
+fn compact(args: &[String]) -> Result<()> {
+    let args = parse_args(args, &["o", "c"])?;
+    let output = args.output.ok_or("output path required")?;
+
+    let inputs = args
+        .files
+        .iter()
+        .map(|path| Ok(ltx::Decoder::new(open(path)?)?))
+        .collect::<Result<Vec<_>>>()?;
+    let compactor = ltx::Compactor::new(inputs, flags(args.compress))?;
+
+    let mut w = BufWriter::new(fs::File::create(&output)?);
+    compactor.compact(&mut w)?;
+    w.into_inner()?.sync_all()?;
+
+    Ok(())
+}
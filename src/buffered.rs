@@ -0,0 +1,314 @@
+use crate::{
+    direct::{read_exact_at, write_all_at},
+    encoder::Error as EncodeError,
+    file::TempPath,
+    Checksum, Encoder, Header, PageNum, PageSize, Trailer,
+};
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// The default number of bytes of pages held in memory before spilling to disk.
+const DEFAULT_MEMORY_LIMIT: usize = 64 << 20;
+
+/// An error that can be returned by [`BufferedEncoder`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("invalid buffer size {0}, expected {1}")]
+    InvalidBufferSize(usize, PageSize),
+    #[error("encode")]
+    Encode(#[from] EncodeError),
+    #[error("io")]
+    Io(#[from] io::Error),
+}
+
+/// An LTX encoder accepting pages in any order.
+///
+/// Pages are buffered until [`BufferedEncoder::finish`], which passes them to an
+/// [`Encoder`] in increasing order. A page encoded more than once keeps its latest
+/// data, so dirty pages can be collected from a hash map or as they're written. Pages
+/// are held in memory up to [`BufferedEncoder::memory_limit`] bytes, after which they
+/// are all moved to a temporary file in [`BufferedEncoder::spill_dir`], removed once
+/// the encoder is finished or dropped.
+///
+/// Only the page size is checked as pages are buffered. The other constraints of
+/// [`Encoder::encode_page`] are checked by `finish`.
+///
+/// # Example
+/// ```no_run
+/// # use std::{collections::HashMap, time::SystemTime};
+/// # let dirty: HashMap<litetx::PageNum, Vec<u8>> = HashMap::new();
+/// let mut enc = litetx::BufferedEncoder::new(
+///     Vec::new(),
+///     &litetx::Header {
+///         flags: litetx::HeaderFlags::COMPRESS_LZ4,
+///         page_size: litetx::PageSize::new(4096).unwrap(),
+///         commit: Some(litetx::PageNum::new(1000).unwrap()),
+///         min_txid: litetx::TXID::new(2).unwrap(),
+///         max_txid: litetx::TXID::new(2).unwrap(),
+///         timestamp: SystemTime::now(),
+///         pre_apply_checksum: Some(litetx::Checksum::new(1)),
+///         node_id: 0,
+///         wal: None,
+///         app_data: None,
+///         page_filter: None,
+///         key_id: None,
+///         dict_id: None,
+///     },
+/// )
+/// .expect("buffered encoder");
+///
+/// for (page_num, page) in &dirty {
+///     enc.encode_page(*page_num, page).expect("encode_page");
+/// }
+/// enc.finish(litetx::Checksum::new(2)).expect("finish");
+/// ```
+pub struct BufferedEncoder<'a, W>
+where
+    W: io::Write,
+{
+    enc: Encoder<'a, W>,
+    page_size: PageSize,
+    /// The slot of every page, in `buf` or in the spill file.
+    slots: BTreeMap<PageNum, u64>,
+    buf: Vec<u8>,
+    memory_limit: usize,
+    spill_dir: PathBuf,
+    spill: Option<(fs::File, TempPath)>,
+}
+
+impl<'a, W> BufferedEncoder<'a, W>
+where
+    W: io::Write,
+{
+    /// Create a new [`BufferedEncoder`] writing to `w`.
+    ///
+    /// The header is encoded right away, so an invalid header fails here.
+    pub fn new(w: W, hdr: &Header) -> Result<Self, Error> {
+        Ok(BufferedEncoder {
+            enc: Encoder::new(w, hdr)?,
+            page_size: hdr.page_size,
+            slots: BTreeMap::new(),
+            buf: Vec::new(),
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            spill_dir: std::env::temp_dir(),
+            spill: None,
+        })
+    }
+
+    /// Set the number of bytes of pages held in memory before they are moved to a
+    /// temporary file. Defaults to 64 MiB.
+    pub fn memory_limit(mut self, bytes: usize) -> Self {
+        self.memory_limit = bytes;
+        self
+    }
+
+    /// Set the directory of the temporary file. Defaults to [`std::env::temp_dir`].
+    pub fn spill_dir<P>(mut self, dir: P) -> Self
+    where
+        P: AsRef<Path>,
+    {
+        self.spill_dir = dir.as_ref().to_path_buf();
+        self
+    }
+
+    /// Return the number of distinct pages buffered so far.
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// Return `true` if no page has been buffered.
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    /// Buffer a page with the given `page_num` and `data`, replacing any data
+    /// buffered for the same page.
+    pub fn encode_page(&mut self, page_num: PageNum, data: &[u8]) -> Result<(), Error> {
+        let page_size = self.page_size.into_inner() as usize;
+        if data.len() != page_size {
+            return Err(Error::InvalidBufferSize(data.len(), self.page_size));
+        }
+
+        let next = self.slots.len() as u64;
+        let slot = *self.slots.entry(page_num).or_insert(next);
+        let offset = slot * page_size as u64;
+
+        if self.spill.is_none() && offset as usize + page_size > self.memory_limit {
+            self.spill()?;
+        }
+
+        match &self.spill {
+            Some((file, _)) => write_all_at(file, data, offset)?,
+            None if slot == next => self.buf.extend_from_slice(data),
+            None => self.buf[offset as usize..][..page_size].copy_from_slice(data),
+        }
+
+        Ok(())
+    }
+
+    /// Encode the buffered pages in increasing order and write the LTX trailer.
+    ///
+    /// See [`Encoder::finish`] for details.
+    pub fn finish<C>(self, post_apply_checksum: C) -> Result<Trailer, Error>
+    where
+        C: Into<Option<Checksum>>,
+    {
+        Ok(self.encode_pages()?.finish(post_apply_checksum)?)
+    }
+
+    /// Like [`BufferedEncoder::finish`], but compute the post-apply checksum from the
+    /// pages as [`Encoder::finish_auto`] does.
+    pub fn finish_auto(self) -> Result<Trailer, Error> {
+        Ok(self.encode_pages()?.finish_auto()?)
+    }
+
+    /// Move the pages buffered in memory to a new temporary file.
+    fn spill(&mut self) -> Result<(), Error> {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+        let path = self.spill_dir.join(format!(
+            "ltx-buffered-{}-{}.tmp",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        let tmp = TempPath(Some(path));
+
+        write_all_at(&file, &self.buf, 0)?;
+        self.buf = Vec::new();
+        self.spill = Some((file, tmp));
+
+        Ok(())
+    }
+
+    /// Pass the buffered pages to the encoder in increasing order.
+    fn encode_pages(self) -> Result<Encoder<'a, W>, Error> {
+        let BufferedEncoder {
+            mut enc,
+            page_size,
+            slots,
+            buf,
+            spill,
+            ..
+        } = self;
+        let page_size = page_size.into_inner() as usize;
+
+        let mut page = match &spill {
+            Some(_) => vec![0; page_size],
+            None => Vec::new(),
+        };
+        for (page_num, slot) in slots {
+            let offset = slot * page_size as u64;
+            let data = match &spill {
+                Some((file, _)) => {
+                    read_exact_at(file, &mut page, offset)?;
+                    &page[..]
+                }
+                None => &buf[offset as usize..][..page_size],
+            };
+            enc.encode_page(page_num, data)?;
+        }
+
+        Ok(enc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BufferedEncoder, Error};
+    use crate::{utils::TempDir, Checksum, Decoder, Header, HeaderFlags, PageNum, PageSize, TXID};
+    use std::{fs, time};
+
+    fn header(commit: u32) -> Header {
+        Header {
+            flags: HeaderFlags::COMPRESS_LZ4,
+            page_size: PageSize::new(512).unwrap(),
+            commit: Some(PageNum::new(commit).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::UNIX_EPOCH,
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        }
+    }
+
+    fn decode(buf: &[u8]) -> Vec<(PageNum, Vec<u8>)> {
+        let (mut dec, _) = Decoder::new(buf).expect("failed to create decoder");
+        let mut pages = Vec::new();
+        while let Some((page_num, page)) = dec.decode_page_ref().expect("failed to decode page") {
+            pages.push((page_num, page.to_vec()));
+        }
+        dec.finish().expect("failed to finish decoder");
+        pages
+    }
+
+    #[test]
+    fn buffered_encoder() {
+        let dir = TempDir::new();
+        // Limits fitting all the pages, a few of them and none.
+        for memory_limit in [1 << 20, 2048, 0] {
+            let mut buf = Vec::new();
+            let mut enc = BufferedEncoder::new(&mut buf, &header(10))
+                .expect("failed to create encoder")
+                .memory_limit(memory_limit)
+                .spill_dir(dir.path());
+
+            for n in [7, 2, 10, 1, 5, 3, 9, 4, 8, 6, 2] {
+                enc.encode_page(PageNum::new(n).unwrap(), &[n as u8; 512])
+                    .expect("failed to encode page");
+            }
+            // Page 2 is encoded again with different data.
+            enc.encode_page(PageNum::new(2).unwrap(), &[0xff; 512])
+                .expect("failed to encode page");
+            assert_eq!(10, enc.len());
+            assert_eq!(memory_limit < 5120, enc.spill.is_some());
+
+            enc.finish_auto().expect("failed to finish encoder");
+            assert_eq!(0, fs::read_dir(dir.path()).unwrap().count());
+
+            let pages: Vec<_> = (1..=10)
+                .map(|n| {
+                    let data = if n == 2 { 0xff } else { n as u8 };
+                    (PageNum::new(n).unwrap(), vec![data; 512])
+                })
+                .collect();
+            assert_eq!(pages, decode(&buf));
+        }
+    }
+
+    #[test]
+    fn buffered_encoder_errors() {
+        let dir = TempDir::new();
+        let mut enc = BufferedEncoder::new(Vec::new(), &header(2))
+            .expect("failed to create encoder")
+            .memory_limit(0)
+            .spill_dir(dir.path());
+        assert!(matches!(
+            enc.encode_page(PageNum::ONE, &[0; 100]),
+            Err(Error::InvalidBufferSize(100, _))
+        ));
+
+        enc.encode_page(PageNum::new(2).unwrap(), &[0; 512])
+            .expect("failed to encode page");
+        assert_eq!(1, fs::read_dir(dir.path()).unwrap().count());
+        assert!(matches!(
+            enc.finish(Checksum::new(1)),
+            Err(Error::Encode(_))
+        ));
+        assert_eq!(0, fs::read_dir(dir.path()).unwrap().count());
+    }
+}
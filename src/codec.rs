@@ -0,0 +1,276 @@
+use crate::{
+    decoder::Error as DecodeError,
+    ltx::{HEADER_SIZE, TRAILER_SIZE},
+    Decoder, Header, Trailer,
+};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::io;
+
+const LENGTH_SIZE: usize = 8;
+const DEFAULT_MAX_LENGTH: u64 = 64 * 1024 * 1024;
+
+/// An error that can be returned by [`LtxCodec`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("frame length {0} exceeds the limit of {1} bytes")]
+    FrameTooLarge(u64, u64),
+    #[error("frame length {0} is too small for an LTX file")]
+    FrameTooSmall(u64),
+    #[error("decode")]
+    Decode(#[from] DecodeError),
+    #[error("io")]
+    Io(#[from] io::Error),
+}
+
+/// A [`tokio_util::codec`] framing LTX files with a length prefix.
+///
+/// Every frame is an 8-byte big-endian length followed by a whole LTX file. The
+/// decoder checks the header of a file as soon as it arrives, and once the whole file
+/// is received, decodes it to verify the file checksum. Decoded frames are returned
+/// along with their header and trailer and can be read again with [`Decoder`].
+///
+/// Encrypted files can't be verified without the key and are rejected.
+///
+/// # Example
+/// ```no_run
+/// use tokio_util::codec::{Decoder, Encoder};
+/// # let file = bytes::Bytes::new();
+///
+/// let mut codec = litetx::LtxCodec::new();
+/// let mut buf = bytes::BytesMut::new();
+/// codec.encode(file, &mut buf).expect("encode");
+///
+/// while let Some((header, trailer, data)) = codec.decode(&mut buf).expect("decode") {
+///     let (dec, _) = litetx::Decoder::new(&data[..]).expect("decoder");
+/// }
+/// ```
+///
+/// The codec is usually wrapped in [`tokio_util::codec::Framed`] to send and receive
+/// the files over a connection.
+pub struct LtxCodec {
+    max_length: u64,
+    frame: Option<Frame>,
+}
+
+/// The state of a partially received frame.
+struct Frame {
+    length: usize,
+    header: Option<Header>,
+}
+
+impl LtxCodec {
+    /// Create a new [`LtxCodec`] accepting frames of up to 64 MiB.
+    pub fn new() -> LtxCodec {
+        LtxCodec::with_max_length(DEFAULT_MAX_LENGTH)
+    }
+
+    /// Create a new [`LtxCodec`] accepting frames of up to `max_length` bytes,
+    /// excluding the length prefix.
+    pub fn with_max_length(max_length: u64) -> LtxCodec {
+        LtxCodec {
+            max_length,
+            frame: None,
+        }
+    }
+
+    /// Return the maximum length of a frame.
+    pub fn max_length(&self) -> u64 {
+        self.max_length
+    }
+
+    fn check_length(&self, length: u64) -> Result<usize, Error> {
+        if length > self.max_length || length > usize::MAX as u64 {
+            return Err(Error::FrameTooLarge(length, self.max_length));
+        }
+        if length < (HEADER_SIZE + TRAILER_SIZE) as u64 {
+            return Err(Error::FrameTooSmall(length));
+        }
+
+        Ok(length as usize)
+    }
+}
+
+impl Default for LtxCodec {
+    fn default() -> Self {
+        LtxCodec::new()
+    }
+}
+
+impl tokio_util::codec::Decoder for LtxCodec {
+    type Item = (Header, Trailer, Bytes);
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Error> {
+        let frame = match &mut self.frame {
+            Some(frame) => frame,
+            None => {
+                if src.len() < LENGTH_SIZE {
+                    return Ok(None);
+                }
+                let length =
+                    self.check_length(u64::from_be_bytes(src[..LENGTH_SIZE].try_into().unwrap()))?;
+                src.advance(LENGTH_SIZE);
+
+                self.frame.insert(Frame {
+                    length,
+                    header: None,
+                })
+            }
+        };
+
+        // Reject invalid files before the rest of the frame arrives.
+        if frame.header.is_none()
+            && src.len() >= HEADER_SIZE
+            && src.len() >= Header::encoded_size(&src[..HEADER_SIZE])
+        {
+            let header = Header::decode_from(&src[..]).map_err(DecodeError::from)?;
+            frame.header = Some(header);
+        }
+
+        if src.len() < frame.length {
+            src.reserve(frame.length - src.len());
+            return Ok(None);
+        }
+
+        let length = frame.length;
+        self.frame = None;
+        let data = src.split_to(length).freeze();
+
+        let (mut dec, header) = Decoder::new(&data[..])?;
+        while dec.skip_page()?.is_some() {}
+        let trailer = dec.finish()?;
+
+        Ok(Some((header, trailer, data)))
+    }
+}
+
+impl tokio_util::codec::Encoder<&[u8]> for LtxCodec {
+    type Error = Error;
+
+    fn encode(&mut self, item: &[u8], dst: &mut BytesMut) -> Result<(), Error> {
+        self.check_length(item.len() as u64)?;
+
+        dst.reserve(LENGTH_SIZE + item.len());
+        dst.put_u64(item.len() as u64);
+        dst.put_slice(item);
+
+        Ok(())
+    }
+}
+
+impl tokio_util::codec::Encoder<Bytes> for LtxCodec {
+    type Error = Error;
+
+    fn encode(&mut self, item: Bytes, dst: &mut BytesMut) -> Result<(), Error> {
+        self.encode(&item[..], dst)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, LtxCodec};
+    use crate::{
+        decoder::Error as DecodeError, ltx::TRAILER_SIZE, Checksum, Encoder, Header, HeaderFlags,
+        PageNum, PageSize, TXID,
+    };
+    use bytes::BytesMut;
+    use std::time;
+    use tokio_util::codec::{Decoder, Encoder as _};
+
+    fn encode_file() -> (Header, Vec<u8>) {
+        let header = Header {
+            flags: HeaderFlags::COMPRESS_LZ4,
+            page_size: PageSize::new(512).unwrap(),
+            commit: Some(PageNum::ONE),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::UNIX_EPOCH,
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &header).expect("failed to create encoder");
+        enc.encode_page(PageNum::ONE, &[1; 512])
+            .expect("failed to encode page");
+        enc.finish(Checksum::new(1))
+            .expect("failed to finish encoder");
+
+        (header, buf)
+    }
+
+    #[test]
+    fn codec() {
+        let (header, file) = encode_file();
+        let mut codec = LtxCodec::new();
+        let mut framed = BytesMut::new();
+        codec
+            .encode(&file[..], &mut framed)
+            .expect("failed to encode frame");
+        codec
+            .encode(&file[..], &mut framed)
+            .expect("failed to encode frame");
+
+        // Feed the frames a few bytes at a time.
+        let mut src = BytesMut::new();
+        let mut frames = Vec::new();
+        for chunk in framed.chunks(7) {
+            src.extend_from_slice(chunk);
+            while let Some(frame) = codec.decode(&mut src).expect("failed to decode frame") {
+                frames.push(frame);
+            }
+        }
+
+        assert_eq!(2, frames.len());
+        for (h, trailer, data) in frames {
+            assert_eq!(header, h);
+            assert_eq!(Some(Checksum::new(1)), trailer.post_apply_checksum);
+            assert_eq!(file, data);
+        }
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn codec_checksum_mismatch() {
+        let (_, mut file) = encode_file();
+        let len = file.len();
+        file[len - TRAILER_SIZE] ^= 1;
+
+        let mut codec = LtxCodec::new();
+        let mut src = BytesMut::new();
+        codec.encode(&file[..], &mut src).unwrap();
+        assert!(matches!(
+            codec.decode(&mut src),
+            Err(Error::Decode(e)) if matches!(e.inner(), DecodeError::FileChecksumMismatch)
+        ));
+    }
+
+    #[test]
+    fn codec_frame_length() {
+        let (_, file) = encode_file();
+        let mut codec = LtxCodec::with_max_length(16);
+        let mut dst = BytesMut::new();
+        assert!(matches!(
+            codec.encode(&file[..], &mut dst),
+            Err(Error::FrameTooLarge(_, 16))
+        ));
+
+        let mut src = BytesMut::from(&(1u64 << 40).to_be_bytes()[..]);
+        assert!(matches!(
+            codec.decode(&mut src),
+            Err(Error::FrameTooLarge(_, 16))
+        ));
+
+        let mut codec = LtxCodec::new();
+        let mut src = BytesMut::from(&10u64.to_be_bytes()[..]);
+        assert!(matches!(
+            codec.decode(&mut src),
+            Err(Error::FrameTooSmall(10))
+        ));
+    }
+}
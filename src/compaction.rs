@@ -0,0 +1,344 @@
+//! Planning of LTX file compaction according to a retention policy.
+
+use crate::{name, LtxFile, TXID};
+use std::{path::PathBuf, time};
+
+/// A compaction level of a [`Planner`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Level {
+    interval: time::Duration,
+    retention: time::Duration,
+    snapshot: bool,
+}
+
+/// A compaction job planned by a [`Planner`].
+///
+/// The inputs are meant to be merged with [`Compactor`](crate::Compactor) into a single
+/// file covering `min_txid..=max_txid`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Job {
+    /// The paths of the input files, ordered by transaction ID.
+    pub inputs: Vec<PathBuf>,
+    /// The first transaction of the output file.
+    pub min_txid: TXID,
+    /// The last transaction of the output file.
+    pub max_txid: TXID,
+}
+
+impl Job {
+    /// Return `true` if the output file is a snapshot.
+    pub fn is_snapshot(&self) -> bool {
+        self.min_txid == TXID::ONE
+    }
+
+    /// Return the name of the output file.
+    pub fn filename(&self) -> String {
+        name::format_filename(self.min_txid, self.max_txid)
+    }
+}
+
+/// Plans compaction jobs for a set of LTX files according to a retention policy.
+///
+/// The policy is a list of levels ordered by retention. A file belongs to the first
+/// level whose retention is longer than the age of the file, based on its header
+/// timestamp. Files older than the retention of every level are left alone, they are
+/// expected to be garbage-collected.
+///
+/// Delta levels merge the contiguous deltas of every elapsed interval into a single
+/// delta. Snapshot levels produce a snapshot at the last transaction of every elapsed
+/// interval by merging the latest earlier snapshot with the deltas following it.
+///
+/// The planner only decides what to compact, the merging itself is done by
+/// [`Compactor`](crate::Compactor).
+///
+/// # Example
+/// ```no_run
+/// use std::time::{Duration, SystemTime};
+///
+/// let planner = litetx::compaction::Planner::new()
+///     .level(Duration::from_secs(60), Duration::from_secs(3600))
+///     .snapshot_level(Duration::from_secs(3600), Duration::from_secs(30 * 86400));
+///
+/// let dir = litetx::LtxDirectory::open("/var/lib/ltx").expect("open");
+/// for job in planner.plan(dir.files(), SystemTime::now()) {
+///     println!("{} <- {:?}", job.filename(), job.inputs);
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Planner {
+    levels: Vec<Level>,
+}
+
+impl Planner {
+    /// Create a new [`Planner`] without levels.
+    pub fn new() -> Planner {
+        Planner::default()
+    }
+
+    /// Add a level merging the deltas of every `interval` into one, applied to files
+    /// younger than `retention`.
+    pub fn level(self, interval: time::Duration, retention: time::Duration) -> Self {
+        self.push_level(interval, retention, false)
+    }
+
+    /// Add a level keeping a snapshot for every `interval`, applied to files younger
+    /// than `retention`.
+    pub fn snapshot_level(self, interval: time::Duration, retention: time::Duration) -> Self {
+        self.push_level(interval, retention, true)
+    }
+
+    fn push_level(
+        mut self,
+        interval: time::Duration,
+        retention: time::Duration,
+        snapshot: bool,
+    ) -> Self {
+        self.levels.push(Level {
+            interval,
+            retention,
+            snapshot,
+        });
+        self.levels.sort_by_key(|level| level.retention);
+        self
+    }
+
+    /// Plan the compaction jobs for `files` at the time `now`.
+    ///
+    /// The files must be ordered by transaction ID, as returned by
+    /// [`LtxDirectory::files`](crate::LtxDirectory::files). Jobs are independent of each
+    /// other and are returned ordered by their last transaction.
+    pub fn plan(&self, files: &[LtxFile], now: time::SystemTime) -> Vec<Job> {
+        let chain = delta_chain(files);
+
+        // Contiguous runs of deltas in the same level and the same elapsed interval.
+        let mut groups: Vec<(&Level, &[&LtxFile])> = Vec::new();
+        let mut start = 0;
+        for end in 1..=chain.len() {
+            let key = |file: &LtxFile| {
+                let level = self.level_of(file, now)?;
+                let bucket = bucket(file.header.timestamp, level.interval)?;
+                Some((level, bucket))
+            };
+            let first = key(chain[start]);
+            if end < chain.len()
+                && first.is_some()
+                && key(chain[end]) == first
+                && chain[end - 1].header.max_txid.checked_add(1) == Some(chain[end].header.min_txid)
+            {
+                continue;
+            }
+
+            if let Some((level, bucket)) = first {
+                if is_elapsed(bucket, level.interval, now) {
+                    groups.push((level, &chain[start..end]));
+                }
+            }
+            start = end;
+        }
+
+        let mut jobs = Vec::new();
+        for (level, group) in groups {
+            let max_txid = group[group.len() - 1].header.max_txid;
+            let job = if level.snapshot {
+                snapshot_job(files, &chain, max_txid)
+            } else if group.len() > 1 {
+                Some(Job {
+                    inputs: group.iter().map(|f| f.path.clone()).collect(),
+                    min_txid: group[0].header.min_txid,
+                    max_txid,
+                })
+            } else {
+                None
+            };
+            jobs.extend(job);
+        }
+
+        jobs
+    }
+
+    fn level_of(&self, file: &LtxFile, now: time::SystemTime) -> Option<&Level> {
+        let age = now
+            .duration_since(file.header.timestamp)
+            .unwrap_or_default();
+        self.levels.iter().find(|level| age < level.retention)
+    }
+}
+
+/// Return the deltas in `files` forming the longest sequence of transactions, preferring
+/// the widest of the files starting at the same transaction.
+fn delta_chain(files: &[LtxFile]) -> Vec<&LtxFile> {
+    let mut chain: Vec<&LtxFile> = Vec::new();
+    for file in files.iter().filter(|f| !f.header.is_snapshot()) {
+        if chain
+            .last()
+            .is_some_and(|last| last.header.min_txid == file.header.min_txid)
+        {
+            chain.pop();
+        }
+        if chain
+            .last()
+            .is_some_and(|last| file.header.min_txid <= last.header.max_txid)
+        {
+            continue;
+        }
+        chain.push(file);
+    }
+
+    chain
+}
+
+/// Plan a snapshot at `max_txid` from the latest earlier snapshot and the deltas of
+/// `chain` following it, unless such a snapshot already exists.
+fn snapshot_job(files: &[LtxFile], chain: &[&LtxFile], max_txid: TXID) -> Option<Job> {
+    let snapshots = files.iter().filter(|f| f.header.is_snapshot());
+    if snapshots.clone().any(|f| f.header.max_txid == max_txid) {
+        return None;
+    }
+    let base = snapshots
+        .filter(|f| f.header.max_txid < max_txid)
+        .max_by_key(|f| f.header.max_txid)?;
+
+    let mut inputs = vec![base.path.clone()];
+    let mut txid = base.header.max_txid;
+    for file in chain
+        .iter()
+        .skip_while(|f| f.header.min_txid <= base.header.max_txid)
+        .take_while(|f| f.header.max_txid <= max_txid)
+    {
+        if txid.checked_add(1) != Some(file.header.min_txid) {
+            return None;
+        }
+        inputs.push(file.path.clone());
+        txid = file.header.max_txid;
+    }
+
+    (txid == max_txid).then_some(Job {
+        inputs,
+        min_txid: TXID::ONE,
+        max_txid,
+    })
+}
+
+/// Return the index of the `interval` containing `timestamp`.
+fn bucket(timestamp: time::SystemTime, interval: time::Duration) -> Option<u128> {
+    let since_epoch = timestamp.duration_since(time::UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_nanos() / interval.as_nanos().max(1))
+}
+
+/// Return `true` if the `interval` with index `bucket` has ended by `now`.
+fn is_elapsed(bucket: u128, interval: time::Duration, now: time::SystemTime) -> bool {
+    now.duration_since(time::UNIX_EPOCH)
+        .is_ok_and(|now| (bucket + 1) * interval.as_nanos().max(1) <= now.as_nanos())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Job, Planner};
+    use crate::{Checksum, Header, HeaderFlags, LtxFile, PageNum, PageSize, Trailer, TXID};
+    use std::{path::PathBuf, time};
+
+    fn file(min_txid: u64, max_txid: u64, secs: u64) -> LtxFile {
+        let (min_txid, max_txid) = (TXID::new(min_txid).unwrap(), TXID::new(max_txid).unwrap());
+        LtxFile {
+            path: PathBuf::from(crate::name::format_filename(min_txid, max_txid)),
+            header: Header {
+                flags: HeaderFlags::empty(),
+                page_size: PageSize::new(512).unwrap(),
+                commit: Some(PageNum::ONE),
+                min_txid,
+                max_txid,
+                timestamp: time::UNIX_EPOCH + time::Duration::from_secs(secs),
+                pre_apply_checksum: (min_txid > TXID::ONE).then_some(Checksum::new(1)),
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+            trailer: Trailer {
+                post_apply_checksum: Some(Checksum::new(1)),
+                file_checksum: Checksum::new(0),
+            },
+        }
+    }
+
+    fn job(inputs: &[&LtxFile], min_txid: u64, max_txid: u64) -> Job {
+        Job {
+            inputs: inputs.iter().map(|f| f.path.clone()).collect(),
+            min_txid: TXID::new(min_txid).unwrap(),
+            max_txid: TXID::new(max_txid).unwrap(),
+        }
+    }
+
+    #[test]
+    fn plan_levels() {
+        let planner = Planner::new()
+            .snapshot_level(
+                time::Duration::from_secs(100),
+                time::Duration::from_secs(1000),
+            )
+            .level(
+                time::Duration::from_secs(10),
+                time::Duration::from_secs(100),
+            );
+        let files = [
+            file(1, 1, 500),
+            file(2, 2, 510),
+            file(3, 3, 590),
+            file(4, 4, 610),
+            file(5, 5, 905),
+            file(6, 6, 907),
+            file(7, 7, 915),
+            file(8, 8, 921),
+            file(9, 9, 922),
+        ];
+        let now = time::UNIX_EPOCH + time::Duration::from_secs(925);
+
+        let jobs = planner.plan(&files, now);
+        assert_eq!(
+            vec![
+                job(&[&files[0], &files[1], &files[2]], 1, 3),
+                job(&[&files[0], &files[1], &files[2], &files[3]], 1, 4),
+                job(&[&files[4], &files[5]], 5, 6),
+            ],
+            jobs
+        );
+        assert!(jobs[0].is_snapshot());
+        assert_eq!("0000000000000005-0000000000000006.ltx", jobs[2].filename());
+    }
+
+    #[test]
+    fn plan_compacted() {
+        let planner = Planner::new()
+            .level(
+                time::Duration::from_secs(10),
+                time::Duration::from_secs(100),
+            )
+            .snapshot_level(
+                time::Duration::from_secs(100),
+                time::Duration::from_secs(1000),
+            );
+        let files = [
+            file(1, 1, 500),
+            file(1, 3, 500),
+            file(2, 2, 510),
+            file(2, 3, 510),
+            file(3, 3, 590),
+            file(4, 4, 850),
+        ];
+        let now = time::UNIX_EPOCH + time::Duration::from_secs(925);
+
+        // The snapshot and the merged delta already exist.
+        assert_eq!(Vec::<Job>::new(), planner.plan(&files, now));
+        assert_eq!(
+            vec![job(&[&files[1], &files[5]], 1, 4)],
+            planner.plan(&files, now + time::Duration::from_secs(100))
+        );
+        // Files past the retention of every level are left alone.
+        assert_eq!(
+            Vec::<Job>::new(),
+            planner.plan(&files, now + time::Duration::from_secs(1000))
+        );
+    }
+}
@@ -0,0 +1,466 @@
+use crate::{
+    decoder::Error as DecodeError, encoder::Error as EncodeError, Checksum, Decoder, Encoder,
+    Header, HeaderFlags, PageNum, PageSize, Trailer, TXID,
+};
+use std::io;
+
+/// An error that can be returned by [`Compactor`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("at least one input file required")]
+    NoInputs,
+    #[error("input page size mismatch: {0}, expected {1}")]
+    PageSizeMismatch(PageSize, PageSize),
+    #[error("non-contiguous transaction ids: ({0}, {1})")]
+    NonContiguousTXID(TXID, TXID),
+    #[error("input starting at transaction {0} has no checksums, but the output has")]
+    NoChecksum(TXID),
+    #[error("unsupported output flags: {0:?}")]
+    UnsupportedFlags(HeaderFlags),
+    #[error(
+        "post-apply checksum of transaction {0} doesn't match pre-apply checksum of the next input"
+    )]
+    ChecksumMismatch(TXID),
+    #[error("decode")]
+    Decode(#[from] DecodeError),
+    #[error("encode")]
+    Encode(#[from] EncodeError),
+}
+
+/// Merges a contiguous sequence of LTX files into a single LTX file.
+///
+/// The inputs must be ordered by transaction ID and must not have gaps between them.
+/// If the same page is present in several inputs, the version from the latest
+/// transaction wins. The first input can be a snapshot, in which case the output
+/// is a snapshot as well.
+///
+/// The headers of the inputs are checked when the compactor is created. The checksum
+/// continuity between inputs can only be checked once the trailer of each input is
+/// read, which happens as soon as all its pages are merged.
+///
+/// # Example
+/// ```no_run
+/// # let files: Vec<&[u8]> = Vec::new();
+/// # let mut w = Vec::new();
+/// let inputs = files
+///     .into_iter()
+///     .map(litetx::Decoder::new)
+///     .collect::<Result<Vec<_>, _>>()
+///     .expect("decoder");
+///
+/// let compactor = litetx::Compactor::new(inputs, litetx::HeaderFlags::COMPRESS_LZ4)
+///     .expect("compactor");
+/// let trailer = compactor.compact(&mut w).expect("compact");
+/// ```
+pub struct Compactor<'a, R>
+where
+    R: io::Read,
+{
+    inputs: Vec<Input<'a, R>>,
+    header: Header,
+    post_apply_checksum: Option<Checksum>,
+}
+
+struct Input<'a, R>
+where
+    R: io::Read,
+{
+    dec: Option<Decoder<'a, R>>,
+    header: Header,
+    buf: Vec<u8>,
+    page_num: Option<PageNum>,
+}
+
+impl<'a, R> Input<'a, R>
+where
+    R: io::Read,
+{
+    /// Decode the next page. Once all the pages are decoded, the decoder is finished
+    /// and its trailer returned.
+    fn advance(&mut self) -> Result<Option<Trailer>, DecodeError> {
+        let Some(dec) = &mut self.dec else {
+            return Ok(None);
+        };
+        self.page_num = dec.decode_page(&mut self.buf)?;
+        if self.page_num.is_some() {
+            return Ok(None);
+        }
+
+        self.dec.take().unwrap().finish().map(Some)
+    }
+}
+
+impl<'a, R> Compactor<'a, R>
+where
+    R: io::Read,
+{
+    /// Create a new [`Compactor`] merging the given decoders.
+    ///
+    /// The resulting file is encoded with the given `flags`, which can't contain
+    /// [`HeaderFlags::PAGE_FILTER`] or [`HeaderFlags::LZ4_DICTIONARY`]. Unless `flags`
+    /// contains [`HeaderFlags::NO_CHECKSUM`], all the inputs must have checksums.
+    pub fn new<I>(inputs: I, flags: HeaderFlags) -> Result<Compactor<'a, R>, Error>
+    where
+        I: IntoIterator<Item = (Decoder<'a, R>, Header)>,
+    {
+        // The page filter and dictionary of the inputs don't apply to the output.
+        let unsupported =
+            flags.intersection(HeaderFlags::PAGE_FILTER | HeaderFlags::LZ4_DICTIONARY);
+        if !unsupported.is_empty() {
+            return Err(Error::UnsupportedFlags(unsupported));
+        }
+
+        let inputs: Vec<_> = inputs
+            .into_iter()
+            .map(|(dec, header)| Input {
+                dec: Some(dec),
+                buf: vec![0; header.page_size.into_inner() as usize],
+                header,
+                page_num: None,
+            })
+            .collect();
+
+        let (first, last) = match (inputs.first(), inputs.last()) {
+            (Some(first), Some(last)) => (&first.header, &last.header),
+            _ => return Err(Error::NoInputs),
+        };
+
+        for pair in inputs.windows(2) {
+            let (prev, next) = (&pair[0].header, &pair[1].header);
+            if next.page_size != first.page_size {
+                return Err(Error::PageSizeMismatch(next.page_size, first.page_size));
+            }
+            if prev.max_txid.checked_add(1) != Some(next.min_txid) {
+                return Err(Error::NonContiguousTXID(prev.max_txid, next.min_txid));
+            }
+        }
+        if !flags.contains(HeaderFlags::NO_CHECKSUM) {
+            if let Some(input) = inputs
+                .iter()
+                .find(|i| i.header.flags.contains(HeaderFlags::NO_CHECKSUM))
+            {
+                return Err(Error::NoChecksum(input.header.min_txid));
+            }
+        }
+
+        let header = Header {
+            flags,
+            page_size: first.page_size,
+            commit: last.commit,
+            min_txid: first.min_txid,
+            max_txid: last.max_txid,
+            timestamp: first.timestamp,
+            pre_apply_checksum: first
+                .pre_apply_checksum
+                .filter(|_| !flags.contains(HeaderFlags::NO_CHECKSUM)),
+            node_id: last.node_id,
+            wal: None,
+            app_data: last.app_data,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        Ok(Compactor {
+            inputs,
+            header,
+            post_apply_checksum: None,
+        })
+    }
+
+    /// Return the header of the resulting LTX file.
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Consume the compactor and write the merged LTX file into `w`.
+    ///
+    /// All the inputs are fully decoded and their file checksums are verified.
+    pub fn compact<W>(mut self, w: W) -> Result<Trailer, Error>
+    where
+        W: io::Write,
+    {
+        let mut enc = Encoder::new(w, &self.header)?;
+
+        for i in 0..self.inputs.len() {
+            self.advance(i)?;
+        }
+
+        while let Some(page_num) = self.inputs.iter().filter_map(|i| i.page_num).min() {
+            // Inputs are ordered by TXID so the last one holding the page is the latest.
+            let latest = self
+                .inputs
+                .iter()
+                .rposition(|i| i.page_num == Some(page_num))
+                .unwrap();
+            if self.header.commit.is_some_and(|commit| page_num <= commit) {
+                enc.encode_page(page_num, &self.inputs[latest].buf)?;
+            }
+
+            for i in 0..self.inputs.len() {
+                if self.inputs[i].page_num == Some(page_num) {
+                    self.advance(i)?;
+                }
+            }
+        }
+
+        Ok(enc.finish(self.post_apply_checksum)?)
+    }
+
+    /// Decode the next page of the input at `index`. Once the input is finished, its
+    /// post-apply checksum is checked against the pre-apply checksum of the next input,
+    /// or kept for the output if it's the last one.
+    fn advance(&mut self, index: usize) -> Result<(), Error> {
+        let Some(trailer) = self.inputs[index].advance()? else {
+            return Ok(());
+        };
+
+        match self.inputs.get(index + 1) {
+            // Inputs without checksums can't be checked against the previous input.
+            Some(next)
+                if !next.header.flags.contains(HeaderFlags::NO_CHECKSUM)
+                    && next.header.pre_apply_checksum != trailer.post_apply_checksum =>
+            {
+                Err(Error::ChecksumMismatch(next.header.min_txid))
+            }
+            Some(_) => Ok(()),
+            None => {
+                self.post_apply_checksum = trailer.post_apply_checksum;
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Compactor, Error};
+    use crate::{
+        utils::TimeRound, Checksum, Decoder, Encoder, Header, HeaderFlags, PageNum, PageSize, TXID,
+    };
+    use std::time;
+
+    fn encode_file(
+        min_txid: u64,
+        max_txid: u64,
+        commit: u32,
+        pre_apply_checksum: Option<u64>,
+        post_apply_checksum: u64,
+        pages: &[(u32, u8)],
+    ) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(
+            &mut buf,
+            &Header {
+                flags: HeaderFlags::empty(),
+                page_size: PageSize::new(512).unwrap(),
+                commit: Some(PageNum::new(commit).unwrap()),
+                min_txid: TXID::new(min_txid).unwrap(),
+                max_txid: TXID::new(max_txid).unwrap(),
+                timestamp: time::SystemTime::now()
+                    .round(time::Duration::from_millis(1))
+                    .unwrap(),
+                pre_apply_checksum: pre_apply_checksum.map(Checksum::new),
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder");
+
+        for (page_num, fill) in pages {
+            enc.encode_page(PageNum::new(*page_num).unwrap(), &[*fill; 512])
+                .expect("failed to encode page");
+        }
+        enc.finish(Checksum::new(post_apply_checksum))
+            .expect("failed to finish encoder");
+
+        buf
+    }
+
+    fn decode_file(buf: &[u8]) -> (Header, Vec<(u32, u8)>, Option<Checksum>) {
+        let (mut dec, header) = Decoder::new(buf).expect("failed to create decoder");
+        let mut pages = Vec::new();
+        let mut page = vec![0; 512];
+        while let Some(page_num) = dec.decode_page(&mut page).expect("failed to decode page") {
+            pages.push((page_num.into_inner(), page[0]));
+        }
+        let trailer = dec.finish().expect("failed to finish decoder");
+
+        (header, pages, trailer.post_apply_checksum)
+    }
+
+    #[test]
+    fn compact_snapshot_and_deltas() {
+        let files = [
+            encode_file(1, 1, 3, None, 1, &[(1, 1), (2, 1), (3, 1)]),
+            encode_file(2, 3, 4, Some(1), 2, &[(2, 2), (4, 2)]),
+            encode_file(4, 4, 4, Some(2), 3, &[(2, 3), (3, 3)]),
+        ];
+
+        let inputs = files
+            .iter()
+            .map(|f| Decoder::new(f.as_slice()))
+            .collect::<Result<Vec<_>, _>>()
+            .expect("failed to create decoders");
+        let compactor =
+            Compactor::new(inputs, HeaderFlags::COMPRESS_LZ4).expect("failed to create compactor");
+        let expected_header = compactor.header().clone();
+
+        let mut buf = Vec::new();
+        let trailer = compactor.compact(&mut buf).expect("failed to compact");
+        assert_eq!(Some(Checksum::new(3)), trailer.post_apply_checksum);
+
+        let (header, pages, post_apply_checksum) = decode_file(&buf);
+        assert_eq!(expected_header, header);
+        assert_eq!(HeaderFlags::COMPRESS_LZ4, header.flags);
+        assert_eq!(TXID::new(1).unwrap(), header.min_txid);
+        assert_eq!(TXID::new(4).unwrap(), header.max_txid);
+        assert_eq!(Some(PageNum::new(4).unwrap()), header.commit);
+        assert_eq!(None, header.pre_apply_checksum);
+        assert_eq!(vec![(1, 1), (2, 3), (3, 3), (4, 2)], pages);
+        assert_eq!(Some(Checksum::new(3)), post_apply_checksum);
+    }
+
+    #[test]
+    fn compact_shrunk_database() {
+        let files = [
+            encode_file(2, 2, 4, Some(1), 2, &[(1, 2), (4, 2)]),
+            encode_file(3, 3, 2, Some(2), 3, &[(2, 3)]),
+        ];
+
+        let inputs = files
+            .iter()
+            .map(|f| Decoder::new(f.as_slice()))
+            .collect::<Result<Vec<_>, _>>()
+            .expect("failed to create decoders");
+        let mut buf = Vec::new();
+        Compactor::new(inputs, HeaderFlags::empty())
+            .expect("failed to create compactor")
+            .compact(&mut buf)
+            .expect("failed to compact");
+
+        let (header, pages, _) = decode_file(&buf);
+        assert_eq!(Some(Checksum::new(1)), header.pre_apply_checksum);
+        assert_eq!(Some(PageNum::new(2).unwrap()), header.commit);
+        assert_eq!(vec![(1, 2), (2, 3)], pages);
+    }
+
+    #[test]
+    fn compact_non_contiguous() {
+        let files = [
+            encode_file(2, 2, 1, Some(1), 2, &[(1, 2)]),
+            encode_file(4, 4, 1, Some(2), 3, &[(1, 3)]),
+        ];
+
+        let inputs = files
+            .iter()
+            .map(|f| Decoder::new(f.as_slice()))
+            .collect::<Result<Vec<_>, _>>()
+            .expect("failed to create decoders");
+        assert!(matches!(
+            Compactor::new(inputs, HeaderFlags::empty()),
+            Err(Error::NonContiguousTXID(a, b))
+                if a == TXID::new(2).unwrap() && b == TXID::new(4).unwrap()
+        ));
+    }
+
+    #[test]
+    fn compact_checksum_mismatch() {
+        let files = [
+            encode_file(2, 2, 1, Some(1), 2, &[(1, 2)]),
+            encode_file(3, 3, 1, Some(5), 3, &[(1, 3)]),
+        ];
+
+        let inputs = files
+            .iter()
+            .map(|f| Decoder::new(f.as_slice()))
+            .collect::<Result<Vec<_>, _>>()
+            .expect("failed to create decoders");
+        let compactor =
+            Compactor::new(inputs, HeaderFlags::empty()).expect("failed to create compactor");
+        assert!(matches!(
+            compactor.compact(Vec::new()),
+            Err(Error::ChecksumMismatch(txid)) if txid == TXID::new(3).unwrap()
+        ));
+    }
+
+    #[test]
+    fn compact_no_inputs() {
+        let inputs: Vec<(Decoder<&[u8]>, Header)> = Vec::new();
+        assert!(matches!(
+            Compactor::new(inputs, HeaderFlags::empty()),
+            Err(Error::NoInputs)
+        ));
+    }
+
+    #[test]
+    fn compact_no_checksum() {
+        let mut no_checksum = Vec::new();
+        let mut enc = Encoder::new(
+            &mut no_checksum,
+            &Header {
+                flags: HeaderFlags::NO_CHECKSUM,
+                page_size: PageSize::new(512).unwrap(),
+                commit: Some(PageNum::ONE),
+                min_txid: TXID::new(3).unwrap(),
+                max_txid: TXID::new(3).unwrap(),
+                timestamp: time::UNIX_EPOCH,
+                pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder");
+        enc.encode_page(PageNum::ONE, &[3; 512])
+            .expect("failed to encode page");
+        enc.finish(None).expect("failed to finish encoder");
+        let files = [encode_file(2, 2, 1, Some(1), 2, &[(1, 2)]), no_checksum];
+
+        let decoders = || {
+            files
+                .iter()
+                .map(|f| Decoder::new(f.as_slice()))
+                .collect::<Result<Vec<_>, _>>()
+                .expect("failed to create decoders")
+        };
+        assert!(matches!(
+            Compactor::new(decoders(), HeaderFlags::empty()),
+            Err(Error::NoChecksum(txid)) if txid == TXID::new(3).unwrap()
+        ));
+
+        let mut buf = Vec::new();
+        Compactor::new(decoders(), HeaderFlags::NO_CHECKSUM)
+            .expect("failed to create compactor")
+            .compact(&mut buf)
+            .expect("failed to compact");
+        let (header, pages, post_apply_checksum) = decode_file(&buf);
+        assert_eq!(None, header.pre_apply_checksum);
+        assert_eq!(vec![(1, 3)], pages);
+        assert_eq!(None, post_apply_checksum);
+    }
+
+    #[test]
+    fn compact_unsupported_flags() {
+        let files = [encode_file(2, 2, 1, Some(1), 2, &[(1, 2)])];
+
+        for flags in [HeaderFlags::PAGE_FILTER, HeaderFlags::LZ4_DICTIONARY] {
+            let inputs = files
+                .iter()
+                .map(|f| Decoder::new(f.as_slice()))
+                .collect::<Result<Vec<_>, _>>()
+                .expect("failed to create decoders");
+            assert!(matches!(
+                Compactor::new(inputs, flags | HeaderFlags::COMPRESS_LZ4),
+                Err(Error::UnsupportedFlags(unsupported)) if unsupported == flags
+            ));
+        }
+    }
+}
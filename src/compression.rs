@@ -0,0 +1,391 @@
+//! Pluggable compression of the LTX page block.
+//!
+//! LZ4 and Zstd compression are built in. Additional codecs implement [`Compression`]
+//! and are [`register`]ed under a header flag, after which [`Encoder`](crate::Encoder)
+//! and [`Decoder`](crate::Decoder) use them for files with that flag set.
+//!
+//! Codecs are driven by pushing data through them, so they never own the underlying
+//! reader or writer.
+
+use crate::HeaderFlags;
+use std::{
+    io,
+    sync::{Arc, RwLock},
+};
+
+/// A compression codec identified by a header flag.
+pub trait Compression: Send + Sync {
+    /// Return the header flag marking files compressed with this codec.
+    fn flag(&self) -> HeaderFlags;
+
+    /// Create a new compressor.
+    fn compressor(&self) -> Box<dyn Compress>;
+
+    /// Create a new decompressor.
+    fn decompressor(&self) -> Box<dyn Decompress>;
+}
+
+/// A streaming compressor.
+pub trait Compress: Send {
+    /// Compress `input`, appending any output to `output`.
+    fn compress(&mut self, input: &[u8], output: &mut Vec<u8>) -> io::Result<()>;
+
+    /// End the current frame, appending the rest of the output to `output`.
+    ///
+    /// Data compressed afterwards starts a new frame.
+    fn finish_frame(&mut self, output: &mut Vec<u8>) -> io::Result<()>;
+}
+
+/// A streaming decompressor.
+pub trait Decompress: Send {
+    /// Decompress a prefix of `input`, appending any output to `output`, and return the
+    /// number of consumed bytes.
+    ///
+    /// Data past the end of the current frame must not be consumed. Once a frame has
+    /// ended, the next call starts a new one.
+    fn decompress(&mut self, input: &[u8], output: &mut Vec<u8>) -> io::Result<usize>;
+
+    /// Return `true` if the end of the current frame has been decompressed.
+    fn frame_done(&self) -> bool;
+}
+
+/// An error that can be returned by [`register`].
+#[derive(thiserror::Error, Debug)]
+pub enum RegisterError {
+    #[error("compression flag must be a single bit of the upper 16 not used by this crate: {0:?}")]
+    Flag(HeaderFlags),
+    #[error("compression flag already registered: {0:?}")]
+    AlreadyRegistered(HeaderFlags),
+}
+
+/// The lowest header flag available to extensions of the format.
+const EXTENSION_FLAGS: u32 = 0x10000;
+
+static CODECS: RwLock<Vec<Arc<dyn Compression>>> = RwLock::new(Vec::new());
+
+/// Register an additional compression codec.
+///
+/// The flag of the codec must be a single bit that isn't used by this crate or by
+/// another registered codec. The lower 16 bits are reserved for flags of the Go
+/// implementation, so the bit must be one of the upper 16, like the other extensions
+/// of this crate.
+pub fn register<C>(codec: C) -> Result<(), RegisterError>
+where
+    C: Compression + 'static,
+{
+    let flag = codec.flag();
+    if flag.bits().count_ones() != 1
+        || flag.bits() < EXTENSION_FLAGS
+        || HeaderFlags::all().contains(flag)
+    {
+        return Err(RegisterError::Flag(flag));
+    }
+
+    let mut codecs = CODECS.write().unwrap_or_else(|e| e.into_inner());
+    if codecs.iter().any(|c| c.flag() == flag) {
+        return Err(RegisterError::AlreadyRegistered(flag));
+    }
+    codecs.push(Arc::new(codec));
+
+    Ok(())
+}
+
+/// Return the union of the flags of all registered codecs.
+pub(crate) fn registered_flags() -> HeaderFlags {
+    CODECS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .fold(HeaderFlags::empty(), |flags, c| flags | c.flag())
+}
+
+/// Return the registered codec whose flag is set in `flags`.
+pub(crate) fn find(flags: HeaderFlags) -> Option<Arc<dyn Compression>> {
+    CODECS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .iter()
+        .find(|c| flags.contains(c.flag()))
+        .cloned()
+}
+
+/// An [`io::Write`] compressing the data with a registered codec.
+pub(crate) struct CompressWriter<W>
+where
+    W: io::Write,
+{
+    inner: W,
+    compressor: Box<dyn Compress>,
+    buf: Vec<u8>,
+}
+
+impl<W> CompressWriter<W>
+where
+    W: io::Write,
+{
+    pub(crate) fn new(inner: W, codec: &dyn Compression) -> CompressWriter<W> {
+        CompressWriter {
+            inner,
+            compressor: codec.compressor(),
+            buf: Vec::new(),
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    pub(crate) fn end_frame(&mut self) -> io::Result<()> {
+        self.compressor.finish_frame(&mut self.buf)?;
+        self.flush_buf()
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        self.end_frame()?;
+        Ok(self.inner)
+    }
+
+    fn flush_buf(&mut self) -> io::Result<()> {
+        self.inner.write_all(&self.buf)?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+impl<W> io::Write for CompressWriter<W>
+where
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.compressor.compress(buf, &mut self.buf)?;
+        self.flush_buf()?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+const INPUT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// An [`io::Read`] decompressing the data with a registered codec.
+///
+/// Like LZ4 frame decoder, the reader returns end of file once at the end of every
+/// frame. The input is read in chunks, so some of the data following the compressed
+/// stream may be buffered.
+pub(crate) struct DecompressReader<R>
+where
+    R: io::Read,
+{
+    inner: R,
+    decompressor: Box<dyn Decompress>,
+    input: Vec<u8>,
+    input_pos: usize,
+    output: Vec<u8>,
+    output_pos: usize,
+    frame_reported: bool,
+}
+
+impl<R> DecompressReader<R>
+where
+    R: io::Read,
+{
+    pub(crate) fn new(inner: R, codec: &dyn Compression) -> DecompressReader<R> {
+        DecompressReader {
+            inner,
+            decompressor: codec.decompressor(),
+            input: Vec::new(),
+            input_pos: 0,
+            output: Vec::new(),
+            output_pos: 0,
+            frame_reported: false,
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Return the underlying reader preceded by the buffered input.
+    pub(crate) fn into_inner(self) -> io::Chain<io::Cursor<Vec<u8>>, R> {
+        let mut input = self.input;
+        input.drain(..self.input_pos);
+        io::Read::chain(io::Cursor::new(input), self.inner)
+    }
+}
+
+impl<R> io::Read for DecompressReader<R>
+where
+    R: io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.output_pos == self.output.len() {
+            if self.decompressor.frame_done() && !self.frame_reported {
+                self.frame_reported = true;
+                return Ok(0);
+            }
+            self.frame_reported = false;
+
+            if self.input_pos == self.input.len() {
+                self.input.resize(INPUT_CHUNK_SIZE, 0);
+                let n = self.inner.read(&mut self.input)?;
+                self.input.truncate(n);
+                self.input_pos = 0;
+                if n == 0 {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+            }
+
+            self.output.clear();
+            self.output_pos = 0;
+            let n = self
+                .decompressor
+                .decompress(&self.input[self.input_pos..], &mut self.output)?;
+            if n == 0 && self.output.is_empty() && !self.decompressor.frame_done() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "decompressor made no progress",
+                ));
+            }
+            self.input_pos += n;
+        }
+
+        let n = buf.len().min(self.output.len() - self.output_pos);
+        buf[..n].copy_from_slice(&self.output[self.output_pos..self.output_pos + n]);
+        self.output_pos += n;
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::{register, Compress, Compression, Decompress, RegisterError};
+    use crate::HeaderFlags;
+    use std::io;
+
+    pub(crate) const XOR_FLAG: HeaderFlags = HeaderFlags::from_bits_retain(0x01000000);
+
+    /// A codec storing data in length-prefixed chunks XORed with a constant, with an
+    /// empty chunk ending the frame.
+    pub(crate) struct Xor;
+
+    struct XorCompress;
+
+    #[derive(Default)]
+    struct XorDecompress {
+        header: Vec<u8>,
+        remaining: usize,
+        done: bool,
+    }
+
+    impl Compression for Xor {
+        fn flag(&self) -> HeaderFlags {
+            XOR_FLAG
+        }
+
+        fn compressor(&self) -> Box<dyn Compress> {
+            Box::new(XorCompress)
+        }
+
+        fn decompressor(&self) -> Box<dyn Decompress> {
+            Box::<XorDecompress>::default()
+        }
+    }
+
+    impl Compress for XorCompress {
+        fn compress(&mut self, input: &[u8], output: &mut Vec<u8>) -> io::Result<()> {
+            if !input.is_empty() {
+                output.extend_from_slice(&(input.len() as u32).to_be_bytes());
+                output.extend(input.iter().map(|b| b ^ 0x5a));
+            }
+            Ok(())
+        }
+
+        fn finish_frame(&mut self, output: &mut Vec<u8>) -> io::Result<()> {
+            output.extend_from_slice(&[0; 4]);
+            Ok(())
+        }
+    }
+
+    impl Decompress for XorDecompress {
+        fn decompress(&mut self, input: &[u8], output: &mut Vec<u8>) -> io::Result<usize> {
+            if self.done {
+                *self = XorDecompress::default();
+            }
+
+            let mut pos = 0;
+            while pos < input.len() && !self.done {
+                if self.remaining == 0 {
+                    self.header.push(input[pos]);
+                    pos += 1;
+                    if self.header.len() == 4 {
+                        self.remaining =
+                            u32::from_be_bytes(self.header[..].try_into().unwrap()) as usize;
+                        self.header.clear();
+                        self.done = self.remaining == 0;
+                    }
+                } else {
+                    let n = self.remaining.min(input.len() - pos);
+                    output.extend(input[pos..pos + n].iter().map(|b| b ^ 0x5a));
+                    pos += n;
+                    self.remaining -= n;
+                }
+            }
+
+            Ok(pos)
+        }
+
+        fn frame_done(&self) -> bool {
+            self.done
+        }
+    }
+
+    /// Register the test codec, which may already be registered by another test.
+    pub(crate) fn register_xor() {
+        match register(Xor) {
+            Ok(()) | Err(RegisterError::AlreadyRegistered(_)) => (),
+            Err(e) => panic!("failed to register codec: {e}"),
+        }
+    }
+
+    #[test]
+    fn register_invalid_flag() {
+        struct Invalid(HeaderFlags);
+
+        impl Compression for Invalid {
+            fn flag(&self) -> HeaderFlags {
+                self.0
+            }
+
+            fn compressor(&self) -> Box<dyn Compress> {
+                unimplemented!()
+            }
+
+            fn decompressor(&self) -> Box<dyn Decompress> {
+                unimplemented!()
+            }
+        }
+
+        for flag in [
+            HeaderFlags::COMPRESS_LZ4,
+            HeaderFlags::from_bits_retain(0x03000000),
+            // Free, but reserved for the Go implementation.
+            HeaderFlags::from_bits_retain(0x00000004),
+            HeaderFlags::from_bits_retain(0x00008000),
+            HeaderFlags::empty(),
+        ] {
+            assert!(matches!(
+                register(Invalid(flag)),
+                Err(RegisterError::Flag(f)) if f == flag
+            ));
+        }
+
+        register_xor();
+        assert!(matches!(
+            register(Xor),
+            Err(RegisterError::AlreadyRegistered(f)) if f == XOR_FLAG
+        ));
+    }
+}
@@ -0,0 +1,345 @@
+//! Authenticated encryption of the LTX page block.
+//!
+//! The page block (after compression) is split into chunks of up to [`CHUNK_SIZE`]
+//! bytes, each sealed with ChaCha20-Poly1305. The encrypted stream is laid out as:
+//!
+//! | Size | Description                                             |
+//! | ---- | ------------------------------------------------------- |
+//! | 8    | Random nonce prefix.                                    |
+//! | 4    | Chunk length. The highest bit marks the final chunk.    |
+//! | N    | Encrypted chunk data followed by a 16 byte tag.         |
+//! | ...  | More chunks, up to and including the final one.         |
+//!
+//! The nonce of a chunk is the nonce prefix followed by the big-endian chunk index.
+//! The encoded LTX header together with the final chunk marker is authenticated as
+//! associated data, so chunks can't be reordered, truncated or moved between files.
+
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, AeadInPlace, KeyInit, OsRng},
+    ChaCha20Poly1305, Nonce,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+};
+
+/// The size of an encryption key, in bytes.
+pub(crate) const KEY_SIZE: usize = 32;
+
+/// A source of encryption keys by ID, for files with the
+/// [`Header::key_id`](crate::Header::key_id) set.
+///
+/// Files record the ID of the key they're encrypted with, so new files can be
+/// encrypted with a new key while earlier ones are still decrypted with theirs.
+///
+/// # Example
+/// ```
+/// let mut keys = std::collections::HashMap::new();
+/// keys.insert(1, [1; 32]);
+/// keys.insert(2, [2; 32]);
+///
+/// assert_eq!(Some([2; 32]), litetx::KeyProvider::key(&keys, 2));
+/// ```
+pub trait KeyProvider {
+    /// Return the key with the given `key_id`, `None` if it's unknown.
+    fn key(&self, key_id: u32) -> Option<[u8; KEY_SIZE]>;
+}
+
+impl KeyProvider for HashMap<u32, [u8; KEY_SIZE]> {
+    fn key(&self, key_id: u32) -> Option<[u8; KEY_SIZE]> {
+        self.get(&key_id).copied()
+    }
+}
+
+impl KeyProvider for BTreeMap<u32, [u8; KEY_SIZE]> {
+    fn key(&self, key_id: u32) -> Option<[u8; KEY_SIZE]> {
+        self.get(&key_id).copied()
+    }
+}
+
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_SIZE: usize = 16;
+const NONCE_PREFIX_SIZE: usize = 8;
+const FINAL_CHUNK: u32 = 1 << 31;
+
+fn nonce(prefix: &[u8; NONCE_PREFIX_SIZE], counter: u32) -> Nonce {
+    let mut nonce = Nonce::default();
+    nonce[..NONCE_PREFIX_SIZE].copy_from_slice(prefix);
+    nonce[NONCE_PREFIX_SIZE..].copy_from_slice(&counter.to_be_bytes());
+    nonce
+}
+
+fn associated_data(header: &[u8]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(header.len() + 1);
+    aad.extend_from_slice(header);
+    aad.push(0);
+    aad
+}
+
+/// An [`io::Write`] encrypting the data written into it.
+///
+/// [`EncryptWriter::finish`] must be called to write out the final chunk.
+pub(crate) struct EncryptWriter<W>
+where
+    W: io::Write,
+{
+    inner: W,
+    cipher: ChaCha20Poly1305,
+    aad: Vec<u8>,
+    nonce_prefix: [u8; NONCE_PREFIX_SIZE],
+    counter: u32,
+    buf: Vec<u8>,
+}
+
+impl<W> EncryptWriter<W>
+where
+    W: io::Write,
+{
+    pub(crate) fn new(inner: W, key: &[u8; KEY_SIZE], header: &[u8]) -> EncryptWriter<W> {
+        let mut nonce_prefix = [0; NONCE_PREFIX_SIZE];
+        OsRng.fill_bytes(&mut nonce_prefix);
+
+        EncryptWriter {
+            inner,
+            cipher: ChaCha20Poly1305::new(key.into()),
+            aad: associated_data(header),
+            nonce_prefix,
+            counter: 0,
+            buf: Vec::with_capacity(CHUNK_SIZE + TAG_SIZE),
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        &mut self.inner
+    }
+
+    /// Write out the final chunk and return the underlying writer.
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        self.seal(true)?;
+        Ok(self.inner)
+    }
+
+    fn seal(&mut self, last: bool) -> io::Result<()> {
+        if self.counter == 0 {
+            self.inner.write_all(&self.nonce_prefix)?;
+        }
+
+        *self.aad.last_mut().unwrap() = last as u8;
+        self.cipher
+            .encrypt_in_place(
+                &nonce(&self.nonce_prefix, self.counter),
+                &self.aad,
+                &mut self.buf,
+            )
+            .map_err(|_| io::Error::other("failed to encrypt chunk"))?;
+
+        let mut len = self.buf.len() as u32;
+        if last {
+            len |= FINAL_CHUNK;
+        }
+        self.inner.write_all(&len.to_be_bytes())?;
+        self.inner.write_all(&self.buf)?;
+        self.buf.clear();
+
+        self.counter = self
+            .counter
+            .checked_add(1)
+            .ok_or_else(|| io::Error::other("too many encrypted chunks"))?;
+
+        Ok(())
+    }
+}
+
+impl<W> io::Write for EncryptWriter<W>
+where
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // A full chunk is only sealed once more data arrives, so that the final
+        // chunk is never empty unless the whole stream is.
+        if self.buf.len() == CHUNK_SIZE {
+            self.seal(false)?;
+        }
+
+        let n = buf.len().min(CHUNK_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..n]);
+
+        Ok(n)
+    }
+
+    /// Flush the underlying writer. Data of an incomplete chunk stays buffered.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// An [`io::Read`] decrypting the data read from the underlying reader.
+///
+/// Once the final chunk has been read, the remaining data is passed through from the
+/// underlying reader as is.
+pub(crate) struct DecryptReader<R>
+where
+    R: io::Read,
+{
+    inner: R,
+    cipher: ChaCha20Poly1305,
+    aad: Vec<u8>,
+    nonce_prefix: [u8; NONCE_PREFIX_SIZE],
+    counter: u32,
+    buf: Vec<u8>,
+    pos: usize,
+    done: bool,
+}
+
+impl<R> DecryptReader<R>
+where
+    R: io::Read,
+{
+    pub(crate) fn new(inner: R, key: &[u8; KEY_SIZE], header: &[u8]) -> DecryptReader<R> {
+        DecryptReader {
+            inner,
+            cipher: ChaCha20Poly1305::new(key.into()),
+            aad: associated_data(header),
+            nonce_prefix: [0; NONCE_PREFIX_SIZE],
+            counter: 0,
+            buf: Vec::with_capacity(CHUNK_SIZE + TAG_SIZE),
+            pos: 0,
+            done: false,
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    pub(crate) fn into_inner(self) -> R {
+        self.inner
+    }
+
+    fn open(&mut self) -> io::Result<()> {
+        if self.counter == 0 {
+            self.inner.read_exact(&mut self.nonce_prefix)?;
+        }
+
+        let mut len = [0; 4];
+        self.inner.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len);
+        let last = len & FINAL_CHUNK != 0;
+        let size = (len & !FINAL_CHUNK) as usize;
+        if !(TAG_SIZE..=CHUNK_SIZE + TAG_SIZE).contains(&size) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid encrypted chunk size: {size}"),
+            ));
+        }
+
+        self.buf.resize(size, 0);
+        self.inner.read_exact(&mut self.buf)?;
+
+        *self.aad.last_mut().unwrap() = last as u8;
+        self.cipher
+            .decrypt_in_place(
+                &nonce(&self.nonce_prefix, self.counter),
+                &self.aad,
+                &mut self.buf,
+            )
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "encrypted chunk authentication failed",
+                )
+            })?;
+
+        self.counter = self.counter.checked_add(1).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidData, "too many encrypted chunks")
+        })?;
+        self.pos = 0;
+        self.done = last;
+
+        Ok(())
+    }
+}
+
+impl<R> io::Read for DecryptReader<R>
+where
+    R: io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.buf.len() {
+            if self.done {
+                return self.inner.read(buf);
+            }
+            self.open()?;
+        }
+
+        let n = buf.len().min(self.buf.len() - self.pos);
+        buf[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DecryptReader, EncryptWriter, CHUNK_SIZE};
+    use std::io::{self, Read, Write};
+
+    fn encrypt(data: &[u8], key: &[u8; 32], header: &[u8]) -> Vec<u8> {
+        let mut enc = EncryptWriter::new(Vec::new(), key, header);
+        enc.write_all(data).expect("failed to write");
+        let mut buf = enc.finish().expect("failed to finish");
+        buf.extend_from_slice(b"tail");
+        buf
+    }
+
+    fn decrypt(buf: &[u8], key: &[u8; 32], header: &[u8]) -> io::Result<Vec<u8>> {
+        let mut out = Vec::new();
+        DecryptReader::new(buf, key, header).read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn encrypt_decrypt() {
+        for size in [0, 1, CHUNK_SIZE, CHUNK_SIZE + 1, 3 * CHUNK_SIZE + 7] {
+            let data: Vec<u8> = (0..size).map(|_| rand::random::<u8>()).collect();
+            let buf = encrypt(&data, &[1; 32], b"header");
+
+            let mut expected = data.clone();
+            expected.extend_from_slice(b"tail");
+            assert_eq!(
+                expected,
+                decrypt(&buf, &[1; 32], b"header").expect("failed to decrypt")
+            );
+        }
+    }
+
+    #[test]
+    fn decrypt_tampered() {
+        let data = vec![7; 2 * CHUNK_SIZE];
+        let buf = encrypt(&data, &[1; 32], b"header");
+
+        let mut tampered = buf.clone();
+        tampered[20] ^= 1;
+
+        for (buf, key, header) in [
+            (&tampered, [1; 32], &b"header"[..]),
+            (&buf, [2; 32], b"header"),
+            (&buf, [1; 32], b"HEADER"),
+        ] {
+            let err = decrypt(buf, &key, header).expect_err("decryption must fail");
+            assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        }
+    }
+
+    #[test]
+    fn decrypt_truncated() {
+        let data = vec![7; 2 * CHUNK_SIZE];
+        let buf = encrypt(&data, &[1; 32], b"header");
+
+        // Cut off the final chunk along with the tail.
+        let len = 8 + 4 + CHUNK_SIZE + 16;
+        let err = decrypt(&buf[..len], &[1; 32], b"header").expect_err("decryption must fail");
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
+}
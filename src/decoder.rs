@@ -1,9 +1,28 @@
+#[cfg(feature = "encryption")]
+use crate::crypto::{DecryptReader, KeyProvider};
 use crate::{
-    ltx::{HeaderDecodeError, PageHeader, PageHeaderDecodeError, TrailerDecodeError, CRC64},
-    Checksum, Header, HeaderFlags, PageNum, PageSize, Trailer,
+    compression::{self, DecompressReader},
+    dictionary::{self, Dictionary},
+    ltx::{
+        Crc64Digest, HeaderDecodeError, PageHeader, PageHeaderDecodeError, PageIndex,
+        PageIndexDecodeError, TrailerDecodeError, CRC64, HEADER_SIZE, PAGE_CHECKSUM_SIZE,
+        PAGE_INDEX_COUNT_SIZE, TRAILER_SIZE,
+    },
+    lz4::ParallelFrameDecoder,
+    merkle::{MerkleDecodeError, MerkleLayout, MerkleTree},
+    progress::{self, ProgressEvent, ProgressFn, Stats, Stopwatch},
+    Checksum, Header, HeaderFlags, PageChecksum, PageFilter, PageHasher, PageNum, PagePool,
+    PageSize, PooledPage, Pos, StreamDecoder, Trailer, TXID,
 };
 use lz4_flex::frame::FrameDecoder;
-use std::io::{self, Read};
+use std::{
+    fmt,
+    io::{self, Read},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+};
 
 /// An error that can be returned by [`Decoder`].
 #[derive(thiserror::Error, Debug)]
@@ -14,12 +33,508 @@ pub enum Error {
     PageHeader(#[from] PageHeaderDecodeError),
     #[error("trailer")]
     Trailer(#[from] TrailerDecodeError),
+    #[error("page index")]
+    PageIndex(#[from] PageIndexDecodeError),
+    #[error("file has no page index")]
+    NoPageIndex,
+    #[error("merkle tree")]
+    Merkle(#[from] MerkleDecodeError),
+    #[error("file has no merkle tree")]
+    NoMerkleTree,
     #[error("invalid page buffer size: {0}, expected {1}")]
     InvalidBufferSize(usize, PageSize),
     #[error("file checksum mismatch")]
     FileChecksumMismatch,
+    #[error("page {0} checksum mismatch")]
+    PageChecksumMismatch(PageNum),
+    #[error("unsupported header flags: {0:?}")]
+    UnsupportedFlags(HeaderFlags),
+    #[error("encryption key required for encrypted files")]
+    KeyRequired,
+    #[error("encryption key given for unencrypted file")]
+    UnexpectedKey,
+    #[error("encrypted file has no key id")]
+    NoKeyId,
+    #[error("unknown encryption key: {0}")]
+    UnknownKey(u32),
+    #[error("unknown dictionary: {0}")]
+    UnknownDictionary(u32),
+    #[error("decoding cancelled")]
+    Cancelled,
+    #[error("file has no checksums")]
+    NoChecksum,
+    #[error("file contains lock page: {0}")]
+    LockPage(PageNum),
+    #[error("out-of-order page numbers: {0}, {1}")]
+    OutOfOrderPage(PageNum, PageNum),
+    #[error("page {0} missing from the page filter")]
+    PageNotInFilter(PageNum),
+    #[error("page {0} in a file deleting the database")]
+    DeletedDatabase(PageNum),
+    #[error("page {0} beyond commit {1}")]
+    PageBeyondCommit(PageNum, PageNum),
+    #[error("snapshot must start with page number 1, got {0}")]
+    FirstSnapshotPage(PageNum),
+    #[error("nonsequential page numbers in snapshot: {0}, {1}")]
+    NonsequentialPages(PageNum, PageNum),
+    #[error("incomplete snapshot: last page {0:?}, expected {1}")]
+    IncompleteSnapshot(Option<PageNum>, PageNum),
+    #[error("{0} limit exceeded")]
+    LimitExceeded(DecodeLimit),
+    #[error("trailing data after the trailer at offset {0}")]
+    TrailingData(u64),
     #[error("read")]
     Read(#[from] io::Error),
+    #[error("file truncated at offset {offset}, expected {expected}")]
+    TruncatedFile {
+        /// The section of the file being read when the input ended.
+        expected: FileSection,
+        /// The number of bytes read from the underlying reader.
+        offset: u64,
+    },
+    #[error("corrupt file at offset {offset}, last page {last_page_num:?}")]
+    Corrupt {
+        /// The number of bytes read from the underlying reader, which may include
+        /// data buffered ahead of the failure by decompressors.
+        offset: u64,
+        /// The last successfully decoded page.
+        last_page_num: Option<PageNum>,
+        #[source]
+        source: Box<Error>,
+    },
+    #[error("at offset {offset}, last page {last_page_num:?}")]
+    Position {
+        /// The number of bytes read from the underlying reader, which may include
+        /// data buffered ahead of the failure by decompressors.
+        offset: u64,
+        /// The last successfully decoded page.
+        last_page_num: Option<PageNum>,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+impl Error {
+    /// Return the error without the [`Error::Corrupt`] or [`Error::Position`] context
+    /// added by [`Decoder`].
+    pub fn inner(&self) -> &Error {
+        match self {
+            Error::Corrupt { source, .. } | Error::Position { source, .. } => source,
+            e => e,
+        }
+    }
+
+    /// Add the position of the decoder to an error, classifying it as a truncation or
+    /// a corruption of the file if possible.
+    fn at(self, offset: u64, last_page_num: Option<PageNum>) -> Error {
+        if let Some(expected) = self.truncated_section() {
+            return Error::TruncatedFile { expected, offset };
+        }
+
+        let source = Box::new(self);
+        if source.is_corrupt() {
+            Error::Corrupt {
+                offset,
+                last_page_num,
+                source,
+            }
+        } else {
+            Error::Position {
+                offset,
+                last_page_num,
+                source,
+            }
+        }
+    }
+
+    /// Return the section being read if the error is caused by the input ending early.
+    ///
+    /// A corrupt length may also make the decoder read past the end of the input, so
+    /// truncation can't be told apart from such corruption.
+    fn truncated_section(&self) -> Option<FileSection> {
+        let (expected, e) = match self {
+            Error::Header(HeaderDecodeError::Read(e)) => (FileSection::Header, e),
+            Error::PageHeader(PageHeaderDecodeError::Read(e)) | Error::Read(e) => {
+                (FileSection::PageBlock, e)
+            }
+            Error::Merkle(MerkleDecodeError::Read(e)) => (FileSection::MerkleTree, e),
+            Error::PageIndex(PageIndexDecodeError::Read(e)) => (FileSection::PageIndex, e),
+            Error::Trailer(TrailerDecodeError::Read(e)) => (FileSection::Trailer, e),
+            _ => return None,
+        };
+
+        (e.kind() == io::ErrorKind::UnexpectedEof).then_some(expected)
+    }
+
+    /// Return whether the error is caused by invalid file contents.
+    fn is_corrupt(&self) -> bool {
+        match self {
+            Error::Header(HeaderDecodeError::Read(_))
+            | Error::PageHeader(PageHeaderDecodeError::Read(_))
+            | Error::Merkle(MerkleDecodeError::Read(_))
+            | Error::PageIndex(PageIndexDecodeError::Read(_))
+            | Error::Trailer(TrailerDecodeError::Read(_)) => false,
+            Error::Read(e) => e.kind() == io::ErrorKind::InvalidData,
+            Error::Header(_)
+            | Error::PageHeader(_)
+            | Error::Trailer(_)
+            | Error::PageIndex(_)
+            | Error::Merkle(_)
+            | Error::FileChecksumMismatch
+            | Error::PageChecksumMismatch(_)
+            | Error::LockPage(_)
+            | Error::OutOfOrderPage(_, _)
+            | Error::PageNotInFilter(_)
+            | Error::DeletedDatabase(_)
+            | Error::PageBeyondCommit(_, _)
+            | Error::FirstSnapshotPage(_)
+            | Error::NonsequentialPages(_, _)
+            | Error::IncompleteSnapshot(_, _)
+            | Error::TrailingData(_) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A section of an LTX file, returned in [`Error::TruncatedFile`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSection {
+    /// The header, along with the page filter and dictionary ID following it.
+    Header,
+    /// The page headers and data.
+    PageBlock,
+    /// The Merkle tree after the page block.
+    MerkleTree,
+    /// The page index after the page block.
+    PageIndex,
+    /// The trailer.
+    Trailer,
+}
+
+impl fmt::Display for FileSection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FileSection::Header => "header",
+            FileSection::PageBlock => "page block",
+            FileSection::MerkleTree => "merkle tree",
+            FileSection::PageIndex => "page index",
+            FileSection::Trailer => "trailer",
+        })
+    }
+}
+
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Read(ioe) => ioe,
+            Error::Cancelled => io::Error::other(e),
+            Error::TruncatedFile { .. } => io::Error::new(io::ErrorKind::UnexpectedEof, e),
+            // Keep the kind of read errors along with the position.
+            Error::Position { ref source, .. } => match source.as_ref() {
+                Error::Read(ioe) => io::Error::new(ioe.kind(), e),
+                _ => io::Error::new(io::ErrorKind::InvalidData, e),
+            },
+            _ => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+/// Options of [`Decoder::new_with_options`], [`DecoderBuilder::options`] and
+/// [`AsyncDecoder::new_with_options`](crate::AsyncDecoder::new_with_options): what files
+/// are accepted and the limits on the resources used to decode them.
+///
+/// Limits are unset by default. Files exceeding a limit fail with
+/// [`Error::LimitExceeded`] before the offending page is read, so untrusted input can't
+/// make the decoder produce more data than allowed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecodeOptions {
+    /// Accept files with header flags unknown to this crate, see
+    /// [`DecoderBuilder::lenient_flags`].
+    pub lenient_flags: bool,
+    /// The maximum number of pages in a file.
+    pub max_pages: Option<u64>,
+    /// The maximum number of bytes of page data decoded from a file.
+    pub max_decompressed_bytes: Option<u64>,
+    /// The maximum page size of a file, checked against the header.
+    pub max_page_size: Option<PageSize>,
+}
+
+impl DecodeOptions {
+    /// Check the page size of the header `hdr` against the limits.
+    pub(crate) fn check_header(&self, hdr: &Header) -> Result<(), Error> {
+        if self
+            .max_page_size
+            .is_some_and(|max| hdr.page_size.into_inner() > max.into_inner())
+        {
+            return Err(Error::LimitExceeded(DecodeLimit::PageSize));
+        }
+
+        Ok(())
+    }
+
+    /// Check that a file of `pages` pages of `page_size` bytes stays within the limits.
+    pub(crate) fn check_pages(&self, pages: u64, page_size: PageSize) -> Result<(), Error> {
+        if self.max_pages.is_some_and(|max| pages > max) {
+            return Err(Error::LimitExceeded(DecodeLimit::Pages));
+        }
+        let bytes = pages.saturating_mul(page_size.into_inner() as u64);
+        if self.max_decompressed_bytes.is_some_and(|max| bytes > max) {
+            return Err(Error::LimitExceeded(DecodeLimit::DecompressedBytes));
+        }
+
+        Ok(())
+    }
+}
+
+/// A limit of [`DecodeOptions`], returned in [`Error::LimitExceeded`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeLimit {
+    /// [`DecodeOptions::max_pages`].
+    Pages,
+    /// [`DecodeOptions::max_decompressed_bytes`].
+    DecompressedBytes,
+    /// [`DecodeOptions::max_page_size`].
+    PageSize,
+}
+
+impl fmt::Display for DecodeLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DecodeLimit::Pages => "page count",
+            DecodeLimit::DecompressedBytes => "decompressed size",
+            DecodeLimit::PageSize => "page size",
+        })
+    }
+}
+
+/// A builder of [`Decoder`] with decompression tuning options.
+///
+/// # Example
+/// ```no_run
+/// # let v = Vec::new();
+/// # let r = &v[..];
+/// let (mut dec, header) = litetx::Decoder::builder()
+///     .threads(4)
+///     .build(r)
+///     .expect("decoder");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecoderBuilder {
+    threads: usize,
+    incomplete_snapshots: bool,
+    reject_trailing_data: bool,
+    options: DecodeOptions,
+}
+
+impl Default for DecoderBuilder {
+    fn default() -> Self {
+        DecoderBuilder {
+            threads: 1,
+            incomplete_snapshots: false,
+            reject_trailing_data: false,
+            options: DecodeOptions::default(),
+        }
+    }
+}
+
+impl DecoderBuilder {
+    /// Set the number of threads decompressing LZ4 blocks, one by default.
+    ///
+    /// With several threads, batches of independent blocks are read ahead and
+    /// decompressed on scoped worker threads. Frames with linked blocks are still
+    /// decompressed on the calling thread.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Accept files with header flags unknown to this crate, disabled by default.
+    ///
+    /// Unknown flags are kept in [`Header::flags`] and returned by
+    /// [`Header::unknown_flags`]. Files whose unknown flags change the encoding of
+    /// the page block fail to decode later, but their header is still available.
+    ///
+    /// This sets [`DecodeOptions::lenient_flags`], so it's overridden by a later call to
+    /// [`DecoderBuilder::options`].
+    pub fn lenient_flags(mut self, enabled: bool) -> Self {
+        self.options.lenient_flags = enabled;
+        self
+    }
+
+    /// Accept snapshots ending before their `commit` page, disabled by default.
+    ///
+    /// The first file of a [`SplitEncoder`](crate::SplitEncoder) chain is such a
+    /// snapshot, completed by the following files.
+    pub fn incomplete_snapshots(mut self, enabled: bool) -> Self {
+        self.incomplete_snapshots = enabled;
+        self
+    }
+
+    /// Fail with [`Error::TrailingData`] in [`Decoder::finish`] if the input isn't
+    /// exhausted after the trailer, disabled by default.
+    ///
+    /// One more byte is read after the trailer, so this is meant for readers holding a
+    /// single file. Files read by a [`StreamDecoder`] are followed by the next file and
+    /// are never checked.
+    pub fn reject_trailing_data(mut self, enabled: bool) -> Self {
+        self.reject_trailing_data = enabled;
+        self
+    }
+
+    /// Enable strict mode, rejecting anything but a complete, single file with known
+    /// header flags.
+    ///
+    /// Disables [`DecoderBuilder::lenient_flags`] and
+    /// [`DecoderBuilder::incomplete_snapshots`] and enables
+    /// [`DecoderBuilder::reject_trailing_data`].
+    pub fn strict(self) -> Self {
+        self.lenient_flags(false)
+            .incomplete_snapshots(false)
+            .reject_trailing_data(true)
+    }
+
+    /// Set the [`DecodeOptions`], including the resource limits enforced while
+    /// decoding, none by default.
+    ///
+    /// Limits apply to every file, including each file read by a [`StreamDecoder`].
+    pub fn options(mut self, options: DecodeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Create a new [`Decoder`] that reads from `r`.
+    ///
+    /// See [`Decoder::new`] for details.
+    pub fn build<'a, R>(&self, r: R) -> Result<(Decoder<'a, R>, Header), Error>
+    where
+        R: io::Read,
+    {
+        Decoder::with_options(r, |_| Ok(None), self)
+    }
+
+    /// Create a new [`Decoder`] that reads from `r` and decrypts the page block with `key`.
+    ///
+    /// See [`Decoder::new_encrypted`] for details.
+    #[cfg(feature = "encryption")]
+    pub fn build_encrypted<'a, R>(
+        &self,
+        r: R,
+        key: &[u8; 32],
+    ) -> Result<(Decoder<'a, R>, Header), Error>
+    where
+        R: io::Read,
+    {
+        Decoder::with_options(r, |_| Ok(Some(*key)), self)
+    }
+
+    /// Create a new [`Decoder`] that reads from `r` and decrypts the page block with the
+    /// key of `keys` identified by the key ID of the header.
+    ///
+    /// See [`Decoder::new_with_keys`] for details.
+    #[cfg(feature = "encryption")]
+    pub fn build_with_keys<'a, R>(
+        &self,
+        r: R,
+        keys: &dyn KeyProvider,
+    ) -> Result<(Decoder<'a, R>, Header), Error>
+    where
+        R: io::Read,
+    {
+        Decoder::with_options(
+            r,
+            |hdr| {
+                if !hdr.flags.contains(HeaderFlags::ENCRYPTED) {
+                    return Ok(None);
+                }
+                let key_id = hdr.key_id.ok_or(Error::NoKeyId)?;
+                keys.key(key_id).map(Some).ok_or(Error::UnknownKey(key_id))
+            },
+            self,
+        )
+    }
+
+    /// Create a new [`StreamDecoder`] that reads concatenated LTX files from `r`.
+    ///
+    /// See [`StreamDecoder::new`] for details.
+    pub fn build_stream<R>(&self, r: R) -> StreamDecoder<R>
+    where
+        R: io::Read,
+    {
+        StreamDecoder::with_options(r, *self)
+    }
+}
+
+/// The constraints on the page numbers of a file, checked by [`Decoder`] and
+/// [`AsyncDecoder`](crate::AsyncDecoder).
+pub(crate) struct PageValidator {
+    page_size: PageSize,
+    commit: Option<PageNum>,
+    is_snapshot: bool,
+    incomplete_snapshots: bool,
+    page_filter: Option<PageFilter>,
+}
+
+impl PageValidator {
+    pub(crate) fn new(hdr: &Header, incomplete_snapshots: bool) -> PageValidator {
+        PageValidator {
+            page_size: hdr.page_size,
+            commit: hdr.commit,
+            is_snapshot: hdr.is_snapshot(),
+            incomplete_snapshots,
+            page_filter: hdr.page_filter,
+        }
+    }
+
+    /// Check that `page_num` may follow `last_page_num`, the previous page of the file.
+    pub(crate) fn validate_page_num(
+        &self,
+        last_page_num: Option<PageNum>,
+        page_num: PageNum,
+    ) -> Result<(), Error> {
+        if page_num == PageNum::lock_page(self.page_size) {
+            return Err(Error::LockPage(page_num));
+        }
+        let Some(commit) = self.commit else {
+            return Err(Error::DeletedDatabase(page_num));
+        };
+        if page_num > commit {
+            return Err(Error::PageBeyondCommit(page_num, commit));
+        }
+        if self
+            .page_filter
+            .is_some_and(|filter| !filter.may_contain(page_num))
+        {
+            return Err(Error::PageNotInFilter(page_num));
+        }
+
+        match last_page_num {
+            None if self.is_snapshot && page_num != PageNum::ONE => {
+                Err(Error::FirstSnapshotPage(page_num))
+            }
+            Some(last)
+                if self.is_snapshot
+                    && last.next_snapshot_page(self.page_size) != Some(page_num) =>
+            {
+                Err(Error::NonsequentialPages(last, page_num))
+            }
+            Some(last) if last >= page_num => Err(Error::OutOfOrderPage(last, page_num)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that a snapshot ending with `last_page_num` contains all its pages.
+    pub(crate) fn validate_complete(&self, last_page_num: Option<PageNum>) -> Result<(), Error> {
+        let Some(commit) = self.commit.filter(|_| self.is_snapshot) else {
+            return Ok(());
+        };
+
+        let want = PageNum::last_snapshot_page(self.page_size, commit);
+        match last_page_num {
+            Some(last) if last == want => Ok(()),
+            _ if self.incomplete_snapshots => Ok(()),
+            last => Err(Error::IncompleteSnapshot(last, want)),
+        }
+    }
 }
 
 /// An LTX file decoder.
@@ -43,10 +558,39 @@ pub struct Decoder<'a, R>
 where
     R: io::Read,
 {
-    r: LTXReader<R>,
-    digest: crc::Digest<'a, u64>,
+    r: LTXReader<Input<CountRead<R>>>,
+    offset: Arc<AtomicU64>,
+    last_page_num: Option<PageNum>,
+    digest: Crc64Digest<'a>,
+    flags: HeaderFlags,
     page_size: PageSize,
+    validator: PageValidator,
+    reject_trailing_data: bool,
+    options: DecodeOptions,
+    max_txid: TXID,
     pages_done: bool,
+    indexed_pages: Option<usize>,
+    index: Option<PageIndex>,
+    tree: Option<MerkleTree>,
+    merkle: Option<MerkleLayout>,
+    dict: Option<Dictionary>,
+    page: Vec<u8>,
+    pages: u64,
+    progress: Option<ProgressFn<'a>>,
+    cancel: Option<Arc<AtomicBool>>,
+    started: Stopwatch,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+// The builder doesn't depend on the reader type, which is only known in
+// `DecoderBuilder::build`. A concrete type keeps `Decoder::builder()` from needing
+// type annotations.
+impl Decoder<'static, io::Empty> {
+    /// Return a builder of [`Decoder`] with decompression tuning options.
+    pub fn builder() -> DecoderBuilder {
+        DecoderBuilder::default()
+    }
 }
 
 impl<'a, R> Decoder<'a, R>
@@ -54,19 +598,146 @@ where
     R: io::Read,
 {
     /// Construct a new [`Decoder`] that reads from `r`.
-    pub fn new(mut r: R) -> Result<(Decoder<'a, R>, Header), Error> {
+    ///
+    /// Use [`Decoder::new_encrypted`] for files with the [`HeaderFlags::ENCRYPTED`] flag.
+    pub fn new(r: R) -> Result<(Decoder<'a, R>, Header), Error> {
+        DecoderBuilder::default().build(r)
+    }
+
+    /// Construct a new [`Decoder`] that reads from `r` with `options`.
+    ///
+    /// See [`Decoder::builder`] for the other settings of the decoder.
+    pub fn new_with_options(
+        r: R,
+        options: DecodeOptions,
+    ) -> Result<(Decoder<'a, R>, Header), Error> {
+        DecoderBuilder::default().options(options).build(r)
+    }
+
+    /// Construct a new [`Decoder`] that reads from `r` and decrypts the page block with `key`.
+    ///
+    /// The file must have the [`HeaderFlags::ENCRYPTED`] flag set. Every chunk of the
+    /// page block is authenticated before its pages are returned.
+    #[cfg(feature = "encryption")]
+    pub fn new_encrypted(r: R, key: &[u8; 32]) -> Result<(Decoder<'a, R>, Header), Error> {
+        DecoderBuilder::default().build_encrypted(r, key)
+    }
+
+    /// Construct a new [`Decoder`] that reads from `r` and decrypts the page block with
+    /// the key of `keys` identified by [`Header::key_id`].
+    ///
+    /// Files without the [`HeaderFlags::ENCRYPTED`] flag are read as is, so a directory
+    /// of files encrypted with different keys, or not at all, can be read with the same
+    /// provider. Fails with [`Error::NoKeyId`] for encrypted files without a key ID and
+    /// with [`Error::UnknownKey`] if the provider doesn't have the key.
+    #[cfg(feature = "encryption")]
+    pub fn new_with_keys(r: R, keys: &dyn KeyProvider) -> Result<(Decoder<'a, R>, Header), Error> {
+        DecoderBuilder::default().build_with_keys(r, keys)
+    }
+
+    fn with_options<K>(
+        r: R,
+        key: K,
+        opts: &DecoderBuilder,
+    ) -> Result<(Decoder<'a, R>, Header), Error>
+    where
+        K: FnOnce(&Header) -> Result<Option<[u8; 32]>, Error>,
+    {
+        let offset = Arc::new(AtomicU64::new(0));
+        let mut r = CountRead {
+            inner: r,
+            count: offset.clone(),
+        };
+        let read_error = |e: io::Error| {
+            let e = Error::Header(HeaderDecodeError::Read(e));
+            match e.truncated_section() {
+                Some(expected) => Error::TruncatedFile {
+                    expected,
+                    offset: offset.load(Ordering::Relaxed),
+                },
+                None => e,
+            }
+        };
+
         let mut digest = CRC64.digest();
-        let hdr = {
-            let reader = CrcDigestRead::new(&mut r, &mut digest);
-            Header::decode_from(reader)?
+        let mut header = vec![0; HEADER_SIZE];
+        let mut reader = CrcDigestRead::new(&mut r, &mut digest);
+        reader.read_exact(&mut header).map_err(read_error)?;
+        header.resize(Header::encoded_size(&header), 0);
+        reader
+            .read_exact(&mut header[HEADER_SIZE..])
+            .map_err(read_error)?;
+        let hdr = Header::decode_from_with(header.as_slice(), opts.options.lenient_flags)?;
+        opts.options.check_header(&hdr)?;
+        let key = key(&hdr)?;
+        let dict = match hdr.dict_id {
+            Some(id) => Some(dictionary::find(id).ok_or(Error::UnknownDictionary(id))?),
+            None => None,
+        };
+
+        let indexed = hdr.flags.contains(HeaderFlags::PAGE_INDEX);
+        if indexed {
+            let unsupported = hdr.flags.intersection(
+                HeaderFlags::COMPRESS_ZSTD
+                    | HeaderFlags::ENCRYPTED
+                    | compression::registered_flags(),
+            );
+            if !unsupported.is_empty() {
+                return Err(Error::UnsupportedFlags(unsupported));
+            }
+        }
+        if hdr
+            .flags
+            .contains(HeaderFlags::MERKLE_TREE | HeaderFlags::ENCRYPTED)
+        {
+            return Err(Error::UnsupportedFlags(HeaderFlags::MERKLE_TREE));
+        }
+
+        #[cfg(feature = "tracing")]
+        let span = {
+            let span = tracing::debug_span!(
+                "ltx_decode",
+                min_txid = %hdr.min_txid,
+                max_txid = %hdr.max_txid,
+                commit = hdr.commit.map_or(0, |c| c.into_inner()),
+            );
+            tracing::debug!(parent: &span, flags = ?hdr.flags, page_size = %hdr.page_size, "header decoded");
+            span
         };
 
         Ok((
             Decoder {
-                r: LTXReader::new(r, hdr.flags.contains(HeaderFlags::COMPRESS_LZ4)),
+                r: LTXReader::new(
+                    Input::new(r, hdr.flags, key.as_ref(), &header)?,
+                    hdr.flags,
+                    dict.as_ref(),
+                    opts.threads,
+                )?,
+                offset,
+                last_page_num: None,
                 digest,
+                flags: hdr.flags,
                 page_size: hdr.page_size,
+                validator: PageValidator::new(&hdr, opts.incomplete_snapshots),
+                reject_trailing_data: opts.reject_trailing_data,
+                options: opts.options,
+                max_txid: hdr.max_txid,
                 pages_done: false,
+                indexed_pages: indexed.then_some(0),
+                index: None,
+                tree: hdr
+                    .flags
+                    .contains(HeaderFlags::MERKLE_TREE)
+                    .then(MerkleTree::default),
+                merkle: None,
+                dict,
+                page: Vec::new(),
+                pages: 0,
+                progress: None,
+                cancel: None,
+                started: Stopwatch::start(),
+                #[cfg(feature = "tracing")]
+                span,
             },
             hdr,
         ))
@@ -76,6 +747,9 @@ where
     ///
     /// Returns `Ok(Some(page_num))` if a page has been successfully decoded.
     /// Return `Ok(None)` if the LTX file doesn't have any more pages.
+    ///
+    /// Pages of files with the [`HeaderFlags::PAGE_CHECKSUM`] flag are checked against
+    /// their stored checksum, failing with [`Error::PageChecksumMismatch`].
     pub fn decode_page(&mut self, data: &mut [u8]) -> Result<Option<PageNum>, Error> {
         if self.pages_done {
             return Ok(None);
@@ -84,127 +758,919 @@ where
         if data.len() != self.page_size.into_inner() as usize {
             return Err(Error::InvalidBufferSize(data.len(), self.page_size));
         }
+        self.check_cancelled()?;
+
+        self.next_page(Some(data))
+            .map_err(|e| self.with_position(e))
+    }
+
+    /// Skip the next page of the LTX file.
+    ///
+    /// The page is still decompressed and covered by the file checksum, but isn't
+    /// copied anywhere. Returns `Ok(None)` if the LTX file doesn't have any more pages.
+    pub fn skip_page(&mut self) -> Result<Option<PageNum>, Error> {
+        if self.pages_done {
+            return Ok(None);
+        };
+        self.check_cancelled()?;
+
+        self.next_page(None).map_err(|e| self.with_position(e))
+    }
 
-        let mut reader = CrcDigestRead::new(&mut self.r, &mut self.digest);
-        let header = PageHeader::decode_from(&mut reader)?;
-        if header.0.is_none() {
+    /// Read the next page into `data`, or discard it if `data` is `None`.
+    fn next_page(&mut self, data: Option<&mut [u8]>) -> Result<Option<PageNum>, Error> {
+        let (header, framed) = decode_page_header(
+            CrcDigestRead::new(&mut self.r, &mut self.digest),
+            self.flags,
+        )?;
+        let Some(page_num) = header.0 else {
             self.pages_done = true;
             return Ok(None);
         };
+        self.validator
+            .validate_page_num(self.last_page_num, page_num)?;
+        self.options.check_pages(self.pages + 1, self.page_size)?;
 
-        reader.read_exact(data)?;
+        let page_checksums = self.flags.contains(HeaderFlags::PAGE_CHECKSUM);
+        let want_checksum = self.tree.is_some();
+        let checksum = with_page_reader(&mut self.r, framed, self.dict.as_ref(), |r| {
+            read_page_data(
+                CrcDigestRead::new(r, &mut self.digest),
+                page_num,
+                self.page_size,
+                data,
+                page_checksums,
+                want_checksum,
+            )
+        })?;
+        if let (Some(tree), Some(checksum)) = (&mut self.tree, checksum) {
+            tree.push(page_num, checksum);
+        }
+
+        if let Some(n) = &mut self.indexed_pages {
+            self.r.end_frame()?;
+            *n += 1;
+        }
+
+        self.last_page_num = header.0;
+        self.pages += 1;
+        self.report_progress();
 
         Ok(header.0)
     }
 
-    /// Consume the decoder and verify file checksum.
-    pub fn finish(mut self) -> Result<Trailer, Error> {
-        let reader = self.r.finish()?;
-        let trailer = Trailer::decode_from(reader)?;
+    /// Call `f` with the progress of the decoder after every page and when the file is
+    /// finished.
+    ///
+    /// Pages read with [`Decoder::seek_page`] aren't reported.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # let v = Vec::new();
+    /// # let r = &v[..];
+    /// let (dec, header) = litetx::Decoder::new(r).expect("decoder");
+    /// let total = header.commit.map_or(0, |c| c.into_inner());
+    /// let mut dec = dec.with_progress(|e| eprintln!("{}/{total} pages", e.pages));
+    /// ```
+    pub fn with_progress<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(ProgressEvent) + Send + 'a,
+    {
+        self.progress = Some(Box::new(f));
+        self
+    }
 
-        self.digest
-            .update(&trailer.post_apply_checksum.into_inner().to_be_bytes());
+    /// Abort decoding once `cancel` is set.
+    ///
+    /// The flag is checked before every page and before the trailer is verified, which
+    /// then fail with [`Error::Cancelled`].
+    pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
 
-        if Checksum::new(self.digest.finalize()) != trailer.file_checksum {
-            return Err(Error::FileChecksumMismatch);
+    fn check_cancelled(&self) -> Result<(), Error> {
+        match &self.cancel {
+            Some(cancel) if cancel.load(Ordering::Relaxed) => Err(Error::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    fn report_progress(&mut self) {
+        #[cfg(feature = "tracing")]
+        if self.pages.is_multiple_of(progress::TRACE_PAGES) {
+            tracing::trace!(
+                parent: &self.span,
+                pages = self.pages,
+                bytes_in = self.offset.load(Ordering::Relaxed),
+                "pages decoded"
+            );
+        }
+        if let Some(progress) = &mut self.progress {
+            progress(ProgressEvent {
+                pages: self.pages,
+                bytes_in: self.offset.load(Ordering::Relaxed),
+                bytes_out: progress::uncompressed_size(self.pages, self.page_size.into_inner()),
+                elapsed: self.started.elapsed(),
+            });
+        }
+    }
+
+    fn with_position(&self, e: Error) -> Error {
+        e.at(self.offset.load(Ordering::Relaxed), self.last_page_num)
+    }
+
+    /// Decode the next page from the LTX file into a buffer owned by the decoder.
+    ///
+    /// Like [`Decoder::decode_page`], but returns a borrow of the page data, which is
+    /// valid until the next call.
+    pub fn decode_page_ref(&mut self) -> Result<Option<(PageNum, &[u8])>, Error> {
+        let mut page = std::mem::take(&mut self.page);
+        page.resize(self.page_size.into_inner() as usize, 0);
+        let result = self.decode_page(&mut page);
+        self.page = page;
+
+        Ok(result?.map(|page_num| (page_num, self.page.as_slice())))
+    }
+
+    /// Decode the next page from the LTX file into a new [`Bytes`](bytes::Bytes) buffer.
+    ///
+    /// Like [`Decoder::decode_page`], but the page can be handed over, e.g. to an
+    /// uploader or an applier, and shared without being copied again.
+    #[cfg(feature = "bytes")]
+    pub fn decode_page_bytes(&mut self) -> Result<Option<(PageNum, bytes::Bytes)>, Error> {
+        let mut page = bytes::BytesMut::zeroed(self.page_size.into_inner() as usize);
+        let page_num = self.decode_page(&mut page)?;
+
+        Ok(page_num.map(|page_num| (page_num, page.freeze())))
+    }
+
+    /// Return an iterator decoding the remaining pages into buffers taken from `pool`.
+    ///
+    /// The iterator stops after the last page or the first error.
+    pub fn pages<'d>(&'d mut self, pool: &PagePool) -> Pages<'d, 'a, R> {
+        Pages {
+            dec: self,
+            pool: pool.clone(),
+            done: false,
         }
+    }
 
+    /// Consume the decoder and verify file checksum.
+    ///
+    /// The post-apply checksum of the trailer is `None` if the file has the
+    /// [`HeaderFlags::NO_CHECKSUM`] flag set. With
+    /// [`DecoderBuilder::reject_trailing_data`], fails with [`Error::TrailingData`] if
+    /// the input continues after the trailer.
+    pub fn finish(self) -> Result<Trailer, Error> {
+        let (trailer, _) = self.finish_exhausted()?;
         Ok(trailer)
     }
-}
 
-struct LTXReader<R>
-where
-    R: io::Read,
-{
-    dec: FrameDecoder<R>,
-    compressed: bool,
-}
+    /// Like [`Decoder::finish`], but return the position of the database after the file
+    /// is applied.
+    ///
+    /// Fails with [`Error::NoChecksum`] if the file has the [`HeaderFlags::NO_CHECKSUM`]
+    /// flag set.
+    pub fn finish_pos(self) -> Result<Pos, Error> {
+        let txid = self.max_txid;
+        let trailer = self.finish()?;
 
-impl<R> LTXReader<R>
-where
-    R: io::Read,
-{
-    fn new(r: R, compressed: bool) -> LTXReader<R> {
-        LTXReader {
-            dec: FrameDecoder::new(r),
-            compressed,
+        Ok(Pos {
+            txid,
+            post_apply_checksum: trailer.post_apply_checksum.ok_or(Error::NoChecksum)?,
+        })
+    }
+
+    /// Like [`Decoder::finish`], but also return the [`Stats`] of the file.
+    pub fn finish_with_stats(self) -> Result<(Trailer, Stats), Error> {
+        let offset = self.offset.clone();
+        let uncompressed_bytes = progress::uncompressed_file_size(
+            self.pages,
+            self.page_size.into_inner(),
+            self.indexed_pages.is_some(),
+        );
+        let (pages, started) = (self.pages, self.started);
+
+        let (trailer, buf) = self.finish_exhausted()?;
+        let stats = Stats {
+            pages,
+            uncompressed_bytes,
+            compressed_bytes: offset.load(Ordering::Relaxed) - buf.len() as u64,
+            elapsed: started.elapsed(),
+        };
+
+        Ok((trailer, stats))
+    }
+
+    /// Like [`Decoder::finish_into_inner`], but check that the input is exhausted if
+    /// trailing data is rejected.
+    fn finish_exhausted(self) -> Result<(Trailer, Vec<u8>), Error> {
+        let (reject, offset) = (self.reject_trailing_data, self.offset.clone());
+        let (trailer, r, buf) = self.finish_into_inner()?;
+
+        if reject && (!buf.is_empty() || !is_exhausted(r)?) {
+            let end = offset.load(Ordering::Relaxed) - buf.len() as u64;
+            return Err(Error::TrailingData(end));
         }
+
+        Ok((trailer, buf))
     }
 
-    fn finish(mut self) -> io::Result<R> {
-        // Read lz4 trailer frame.
-        if self.compressed {
-            let mut buf = [0; 1];
-            match self.dec.read_exact(&mut buf) {
-                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => (),
-                Err(e) => return Err(e),
-                _ => {
-                    return Err(io::Error::new(
-                        io::ErrorKind::Other,
-                        "expected lz4 end frame",
-                    ))
-                }
-            }
+    /// Like [`Decoder::finish`], but also return the underlying reader along with the
+    /// data read ahead from it past the end of the file.
+    pub(crate) fn finish_into_inner(self) -> Result<(Trailer, R, Vec<u8>), Error> {
+        self.check_cancelled()?;
+        let (offset, last_page_num) = (self.offset.clone(), self.last_page_num);
+        self.finish_trailer()
+            .map_err(|e| e.at(offset.load(Ordering::Relaxed), last_page_num))
+    }
+
+    fn finish_trailer(mut self) -> Result<(Trailer, R, Vec<u8>), Error> {
+        self.validator.validate_complete(self.last_page_num)?;
+        let mut reader = self.r.finish()?;
+        #[cfg(feature = "tracing")]
+        let (span, pages) = (self.span, self.pages);
+        if let Some(tree) = &self.tree {
+            tree.verify_from(CrcDigestRead::new(&mut reader, &mut self.digest))?;
+        }
+        if let Some(n) = self.indexed_pages {
+            PageIndex::decode_from(CrcDigestRead::new(&mut reader, &mut self.digest), n)?;
+        }
+
+        let trailer = Trailer::decode_from(&mut reader)?;
+        trailer.validate(self.flags)?;
+
+        self.digest.update(&trailer.post_apply_checksum_bytes());
+
+        if Checksum::new(self.digest.finalize()) != trailer.file_checksum {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                parent: &span,
+                file_checksum = %trailer.file_checksum,
+                "file checksum mismatch"
+            );
+            return Err(Error::FileChecksumMismatch);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            parent: &span,
+            pages,
+            file_checksum = %trailer.file_checksum,
+            "trailer verified"
+        );
+
+        let (r, buf) = reader.into_inner();
+        if let Some(progress) = &mut self.progress {
+            progress(ProgressEvent {
+                pages: self.pages,
+                bytes_in: self.offset.load(Ordering::Relaxed) - buf.len() as u64,
+                bytes_out: progress::uncompressed_file_size(
+                    self.pages,
+                    self.page_size.into_inner(),
+                    self.indexed_pages.is_some(),
+                ),
+                elapsed: self.started.elapsed(),
+            });
         }
 
-        Ok(self.dec.into_inner())
+        Ok((trailer, r, buf))
     }
 }
 
-impl<R> io::Read for LTXReader<R>
+/// Return whether `r` has no more data, reading at most one byte.
+fn is_exhausted<R>(mut r: R) -> io::Result<bool>
 where
     R: io::Read,
 {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        if self.compressed {
-            self.dec.read(buf)
-        } else {
-            self.dec.get_mut().read(buf)
+    loop {
+        match r.read(&mut [0; 1]) {
+            Ok(n) => return Ok(n == 0),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
         }
     }
 }
 
-/// An [`io::Read`] computing a digest on the bytes read.
-struct CrcDigestRead<'a, 'b, R>
+/// An iterator over the pages of a [`Decoder`], returned by [`Decoder::pages`].
+pub struct Pages<'d, 'a, R>
 where
     R: io::Read,
 {
-    inner: R,
-    digest: &'a mut crc::Digest<'b, u64>,
+    dec: &'d mut Decoder<'a, R>,
+    pool: PagePool,
+    done: bool,
 }
 
-impl<'a, 'b, R> CrcDigestRead<'a, 'b, R>
+impl<'d, 'a, R> Iterator for Pages<'d, 'a, R>
 where
     R: io::Read,
 {
-    fn new(inner: R, digest: &'a mut crc::Digest<'b, u64>) -> Self {
-        CrcDigestRead { inner, digest }
+    type Item = Result<(PageNum, PooledPage), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let mut page = self.pool.get(self.dec.page_size.into_inner() as usize);
+        match self.dec.decode_page(&mut page) {
+            Ok(Some(page_num)) => Some(Ok((page_num, page))),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
     }
 }
 
-impl<'a, 'b, R> io::Read for CrcDigestRead<'a, 'b, R>
+impl<'d, 'a, R> std::iter::FusedIterator for Pages<'d, 'a, R> where R: io::Read {}
+
+impl<'a, R> Decoder<'a, R>
 where
-    R: io::Read,
+    R: io::Read + io::Seek,
 {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        let read = self.inner.read(buf)?;
-        self.digest.update(&buf[..read]);
-        Ok(read)
-    }
-}
+    /// Read the page with the given `page_num` into `data` using the page index.
+    ///
+    /// Returns `Ok(false)` if the file doesn't contain the page. The file must have been
+    /// written with the [`HeaderFlags::PAGE_INDEX`] flag. The index is loaded from the
+    /// end of the file on first use.
+    ///
+    /// Sequential decoding isn't affected, so [`Decoder::decode_page`] can be used
+    /// afterwards. Pages read this way are not covered by the file checksum verified in
+    /// [`Decoder::finish`].
+    pub fn seek_page(&mut self, page_num: PageNum, data: &mut [u8]) -> Result<bool, Error> {
+        if !self.flags.contains(HeaderFlags::PAGE_INDEX) {
+            return Err(Error::NoPageIndex);
+        }
+        if data.len() != self.page_size.into_inner() as usize {
+            return Err(Error::InvalidBufferSize(data.len(), self.page_size));
+        }
 
-#[cfg(test)]
-mod tests {
-    use super::{CrcDigestRead, Decoder};
-    use crate::{
-        ltx::CRC64, utils::TimeRound, Checksum, Encoder, Header, HeaderFlags, PageNum, PageSize,
-        TXID,
-    };
-    use std::{io::Read, time};
+        let r = &mut self.r.get_mut().get_mut().inner;
+        let pos = r.stream_position()?;
+        let result = read_indexed_page(
+            r,
+            &mut self.index,
+            self.flags,
+            self.dict.as_ref(),
+            self.page_size,
+            page_num,
+            data,
+        );
+        r.seek(io::SeekFrom::Start(pos))?;
 
-    #[test]
-    fn crc_digest_read() {
+        result
+    }
+
+    /// Return the root of the Merkle tree over the page checksums of the file.
+    ///
+    /// The file must have been written with the [`HeaderFlags::MERKLE_TREE`] flag. The
+    /// root can be compared with a trusted root, e.g. one received from the primary, to
+    /// trust the pages verified with [`Decoder::verify_page_proof`].
+    pub fn merkle_root(&mut self) -> Result<Checksum, Error> {
+        self.with_merkle_layout(|layout, r| Ok(layout.root(r)?))
+    }
+
+    /// Verify the contents `data` of the page with the given `page_num` against the
+    /// root of the Merkle tree of the file.
+    ///
+    /// Only the nodes on the path from the page to the root are read, so single pages
+    /// can be verified without reading the rest of the file. Returns `Ok(false)` if the
+    /// file doesn't contain the page or its contents don't match. The file must have
+    /// been written with the [`HeaderFlags::MERKLE_TREE`] flag.
+    ///
+    /// Like with [`Decoder::seek_page`], sequential decoding isn't affected.
+    ///
+    /// # Example
+    /// ```no_run
+    /// let f = std::fs::File::open("0000000000000001-0000000000000064.ltx").expect("open");
+    /// let (mut dec, header) = litetx::Decoder::new(f).expect("decoder");
+    ///
+    /// let page_num = litetx::PageNum::new(7).unwrap();
+    /// let mut page = vec![0; header.page_size.into_inner() as usize];
+    /// dec.seek_page(page_num, &mut page).expect("seek_page");
+    /// assert!(dec.verify_page_proof(page_num, &page).expect("verify_page_proof"));
+    /// ```
+    pub fn verify_page_proof(&mut self, page_num: PageNum, data: &[u8]) -> Result<bool, Error> {
+        let checksum = data.page_checksum(page_num);
+        self.with_merkle_layout(|layout, r| Ok(layout.verify(r, page_num, checksum)?))
+    }
+
+    fn with_merkle_layout<T, F>(&mut self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&MerkleLayout, &mut R) -> Result<T, Error>,
+    {
+        if !self.flags.contains(HeaderFlags::MERKLE_TREE) {
+            return Err(Error::NoMerkleTree);
+        }
+
+        let r = &mut self.r.get_mut().get_mut().inner;
+        let pos = r.stream_position()?;
+        let result = match &self.merkle {
+            Some(layout) => f(layout, r),
+            None => read_merkle_layout(r, self.flags)
+                .and_then(|layout| f(self.merkle.insert(layout), r)),
+        };
+        r.seek(io::SeekFrom::Start(pos))?;
+
+        result
+    }
+}
+
+fn read_page_index<R>(r: &mut R) -> Result<PageIndex, Error>
+where
+    R: io::Read + io::Seek,
+{
+    let (offset, count) = page_index_location(r)?;
+    r.seek(io::SeekFrom::Start(offset))?;
+
+    Ok(PageIndex::decode_from(r, count as usize)?)
+}
+
+/// Return the offset and the number of entries of the page index.
+fn page_index_location<R>(r: &mut R) -> Result<(u64, u32), Error>
+where
+    R: io::Read + io::Seek,
+{
+    let end = r.seek(io::SeekFrom::End(0))?;
+    let count_offset = end
+        .checked_sub((TRAILER_SIZE + PAGE_INDEX_COUNT_SIZE) as u64)
+        .ok_or(PageIndexDecodeError::Size(0))?;
+
+    let mut count = [0; PAGE_INDEX_COUNT_SIZE];
+    r.seek(io::SeekFrom::Start(count_offset))?;
+    r.read_exact(&mut count)
+        .map_err(PageIndexDecodeError::from)?;
+    let count = u32::from_be_bytes(count);
+
+    let offset = (end - TRAILER_SIZE as u64)
+        .checked_sub(PageIndex::encoded_size(count as usize) as u64)
+        .filter(|offset| *offset >= HEADER_SIZE as u64)
+        .ok_or(PageIndexDecodeError::Size(count))?;
+
+    Ok((offset, count))
+}
+
+fn read_merkle_layout<R>(r: &mut R, flags: HeaderFlags) -> Result<MerkleLayout, Error>
+where
+    R: io::Read + io::Seek,
+{
+    // The tree is followed by the page index, if any, and the trailer.
+    let end = if flags.contains(HeaderFlags::PAGE_INDEX) {
+        page_index_location(r)?.0
+    } else {
+        r.seek(io::SeekFrom::End(0))?
+            .checked_sub(TRAILER_SIZE as u64)
+            .ok_or(MerkleDecodeError::Size(0))?
+    };
+
+    Ok(MerkleLayout::read_from(r, end)?)
+}
+
+fn read_indexed_page<R>(
+    r: &mut R,
+    index: &mut Option<PageIndex>,
+    flags: HeaderFlags,
+    dict: Option<&Dictionary>,
+    page_size: PageSize,
+    page_num: PageNum,
+    data: &mut [u8],
+) -> Result<bool, Error>
+where
+    R: io::Read + io::Seek,
+{
+    let index = match index {
+        Some(index) => index,
+        None => index.insert(read_page_index(r)?),
+    };
+    let Some(offset) = index.find(page_num) else {
+        return Ok(false);
+    };
+
+    r.seek(io::SeekFrom::Start(offset))?;
+    let mut reader = LTXReader::new(r, flags, dict, 1)?;
+    let (header, framed) = decode_page_header(&mut reader, flags)?;
+    if header.0 != Some(page_num) {
+        return Err(PageIndexDecodeError::Offset(page_num).into());
+    }
+    with_page_reader(&mut reader, framed, dict, |r| {
+        read_page_data(
+            r,
+            page_num,
+            page_size,
+            Some(data),
+            flags.contains(HeaderFlags::PAGE_CHECKSUM),
+            false,
+        )
+    })?;
+
+    Ok(true)
+}
+
+/// Decode a page header, along with whether the page is compressed in an LZ4 frame of
+/// its own, as in files with the [`HeaderFlags::PER_PAGE_COMPRESSION`] flag.
+fn decode_page_header<R>(r: R, flags: HeaderFlags) -> Result<(PageHeader, bool), Error>
+where
+    R: io::Read,
+{
+    if flags.contains(HeaderFlags::PER_PAGE_COMPRESSION) {
+        let (header, raw) = PageHeader::decode_raw_from(r)?;
+        let framed = header.0.is_some() && !raw;
+        Ok((header, framed))
+    } else {
+        Ok((PageHeader::decode_from(r)?, false))
+    }
+}
+
+/// Call `f` with a reader of the data following a page header, decompressing the LZ4
+/// frame of the page if `framed`.
+fn with_page_reader<R, T, F>(
+    r: R,
+    framed: bool,
+    dict: Option<&Dictionary>,
+    f: F,
+) -> Result<T, Error>
+where
+    R: io::Read,
+    F: FnOnce(&mut dyn io::Read) -> Result<T, Error>,
+{
+    let mut r = r;
+    if !framed {
+        return f(&mut r);
+    }
+
+    let mut frame = ParallelFrameDecoder::new(r, 1);
+    if let Some(dict) = dict {
+        frame = frame.with_dict(dict.id(), dict.data());
+    }
+    let result = f(&mut frame)?;
+    if frame.read(&mut [0; 1])? != 0 {
+        return Err(Error::Read(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "page frame longer than the page",
+        )));
+    }
+
+    Ok(result)
+}
+
+/// Read the data of page `page_num` into `data`, or discard it if `data` is `None`,
+/// followed by its checksum if `page_checksums`.
+///
+/// Return the page checksum if it's been computed, which it is if `page_checksums` or
+/// `want_checksum` are set or if the page is discarded.
+fn read_page_data<R>(
+    mut r: R,
+    page_num: PageNum,
+    page_size: PageSize,
+    data: Option<&mut [u8]>,
+    page_checksums: bool,
+    want_checksum: bool,
+) -> Result<Option<Checksum>, Error>
+where
+    R: io::Read,
+{
+    let checksum = match data {
+        Some(data) => {
+            r.read_exact(data)?;
+            (page_checksums || want_checksum).then(|| data.page_checksum(page_num))
+        }
+        None => {
+            let size = page_size.into_inner() as u64;
+            let mut hasher = PageHasher::new(page_num);
+            if io::copy(&mut (&mut r).take(size), &mut hasher)? != size {
+                return Err(Error::Read(io::ErrorKind::UnexpectedEof.into()));
+            }
+            Some(hasher.finalize_checksum())
+        }
+    };
+
+    if page_checksums {
+        let mut buf = [0; PAGE_CHECKSUM_SIZE];
+        r.read_exact(&mut buf)?;
+        if checksum != Some(Checksum::new(u64::from_be_bytes(buf))) {
+            return Err(Error::PageChecksumMismatch(page_num));
+        }
+    }
+
+    Ok(checksum)
+}
+
+enum LTXReader<R>
+where
+    R: io::Read,
+{
+    Uncompressed(R),
+    Lz4(FrameDecoder<R>),
+    Lz4Parallel(ParallelFrameDecoder<R>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::read::Decoder<'static, io::BufReader<R>>),
+    /// A codec registered with [`compression::register`].
+    Custom(DecompressReader<R>),
+}
+
+impl<R> LTXReader<R>
+where
+    R: io::Read,
+{
+    fn new(
+        r: R,
+        flags: HeaderFlags,
+        dict: Option<&Dictionary>,
+        threads: usize,
+    ) -> Result<LTXReader<R>, Error> {
+        // Pages compressed one by one are read from the page block as is.
+        if flags.contains(HeaderFlags::PER_PAGE_COMPRESSION) {
+            Ok(LTXReader::Uncompressed(r))
+        } else if let Some(dict) = dict.filter(|_| flags.contains(HeaderFlags::COMPRESS_LZ4)) {
+            Ok(LTXReader::Lz4Parallel(
+                ParallelFrameDecoder::new(r, threads).with_dict(dict.id(), dict.data()),
+            ))
+        } else if flags.contains(HeaderFlags::COMPRESS_LZ4) && threads > 1 {
+            Ok(LTXReader::Lz4Parallel(ParallelFrameDecoder::new(
+                r, threads,
+            )))
+        } else if flags.contains(HeaderFlags::COMPRESS_LZ4) {
+            Ok(LTXReader::Lz4(FrameDecoder::new(r)))
+        } else if flags.contains(HeaderFlags::COMPRESS_ZSTD) {
+            #[cfg(feature = "zstd")]
+            return Ok(LTXReader::Zstd(
+                zstd::stream::read::Decoder::with_buffer(io::BufReader::new(r))?.single_frame(),
+            ));
+            #[cfg(not(feature = "zstd"))]
+            return Err(Error::UnsupportedFlags(HeaderFlags::COMPRESS_ZSTD));
+        } else if let Some(codec) = compression::find(flags) {
+            Ok(LTXReader::Custom(DecompressReader::new(r, codec.as_ref())))
+        } else {
+            Ok(LTXReader::Uncompressed(r))
+        }
+    }
+
+    fn get_mut(&mut self) -> &mut R {
+        match self {
+            LTXReader::Uncompressed(r) => r,
+            LTXReader::Lz4(dec) => dec.get_mut(),
+            LTXReader::Lz4Parallel(dec) => dec.get_mut(),
+            #[cfg(feature = "zstd")]
+            LTXReader::Zstd(dec) => dec.get_mut().get_mut(),
+            LTXReader::Custom(dec) => dec.get_mut(),
+        }
+    }
+
+    /// Make sure the compressed stream has been read till the end frame.
+    fn end_frame(&mut self) -> io::Result<()> {
+        if !matches!(self, LTXReader::Uncompressed(_)) {
+            let mut buf = [0; 1];
+            match self.read_exact(&mut buf) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => (),
+                Err(e) => return Err(e),
+                _ => return Err(io::Error::other("expected compressed stream end frame")),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(mut self) -> io::Result<LTXReaderTail<R>> {
+        self.end_frame()?;
+
+        match self {
+            LTXReader::Uncompressed(r) => Ok(LTXReaderTail::Unbuffered(r)),
+            LTXReader::Lz4(dec) => Ok(LTXReaderTail::Unbuffered(dec.into_inner())),
+            LTXReader::Lz4Parallel(dec) => Ok(LTXReaderTail::Unbuffered(dec.into_inner())),
+            #[cfg(feature = "zstd")]
+            LTXReader::Zstd(dec) => Ok(LTXReaderTail::Buffered(dec.finish())),
+            LTXReader::Custom(dec) => Ok(LTXReaderTail::Prefixed(dec.into_inner())),
+        }
+    }
+}
+
+impl<R> io::Read for LTXReader<R>
+where
+    R: io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            LTXReader::Uncompressed(r) => r.read(buf),
+            LTXReader::Lz4(dec) => dec.read(buf),
+            LTXReader::Lz4Parallel(dec) => dec.read(buf),
+            #[cfg(feature = "zstd")]
+            LTXReader::Zstd(dec) => dec.read(buf),
+            LTXReader::Custom(dec) => dec.read(buf),
+        }
+    }
+}
+
+/// The reader below the compression layer, optionally decrypting the page block.
+///
+/// The encrypted page block is followed by the plain text trailer, which is passed
+/// through once the final encrypted chunk has been read.
+enum Input<R>
+where
+    R: io::Read,
+{
+    Plain(R),
+    #[cfg(feature = "encryption")]
+    Encrypted(DecryptReader<R>),
+}
+
+impl<R> Input<R>
+where
+    R: io::Read,
+{
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    fn new(
+        r: R,
+        flags: HeaderFlags,
+        key: Option<&[u8; 32]>,
+        header: &[u8],
+    ) -> Result<Input<R>, Error> {
+        match (flags.contains(HeaderFlags::ENCRYPTED), key) {
+            (false, None) => Ok(Input::Plain(r)),
+            #[cfg(feature = "encryption")]
+            (true, Some(key)) => Ok(Input::Encrypted(DecryptReader::new(r, key, header))),
+            #[cfg(feature = "encryption")]
+            (true, None) => Err(Error::KeyRequired),
+            #[cfg(feature = "encryption")]
+            (false, Some(_)) => Err(Error::UnexpectedKey),
+            #[cfg(not(feature = "encryption"))]
+            _ => Err(Error::UnsupportedFlags(HeaderFlags::ENCRYPTED)),
+        }
+    }
+}
+
+impl<R> Input<R>
+where
+    R: io::Read,
+{
+    fn get_mut(&mut self) -> &mut R {
+        match self {
+            Input::Plain(r) => r,
+            #[cfg(feature = "encryption")]
+            Input::Encrypted(dec) => dec.get_mut(),
+        }
+    }
+}
+
+impl<R> Input<CountRead<R>>
+where
+    R: io::Read,
+{
+    fn into_inner(self) -> R {
+        match self {
+            Input::Plain(r) => r.inner,
+            #[cfg(feature = "encryption")]
+            Input::Encrypted(dec) => dec.into_inner().inner,
+        }
+    }
+}
+
+impl<R> io::Read for Input<R>
+where
+    R: io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Input::Plain(r) => r.read(buf),
+            #[cfg(feature = "encryption")]
+            Input::Encrypted(dec) => dec.read(buf),
+        }
+    }
+}
+
+/// The remainder of the LTX file following the page block.
+enum LTXReaderTail<R>
+where
+    R: io::Read,
+{
+    Unbuffered(R),
+    // Zstd decoder reads the input in chunks, so some of the data following the
+    // compressed stream can be buffered.
+    #[cfg(feature = "zstd")]
+    Buffered(io::BufReader<R>),
+    // Registered codecs read the input in chunks as well, the buffered remainder is
+    // replayed before the rest of the input.
+    Prefixed(io::Chain<io::Cursor<Vec<u8>>, R>),
+}
+
+impl<R> LTXReaderTail<Input<CountRead<R>>>
+where
+    R: io::Read,
+{
+    /// Return the underlying reader along with the data buffered from it.
+    fn into_inner(self) -> (R, Vec<u8>) {
+        match self {
+            LTXReaderTail::Unbuffered(r) => (r.into_inner(), Vec::new()),
+            #[cfg(feature = "zstd")]
+            LTXReaderTail::Buffered(r) => {
+                let buf = r.buffer().to_vec();
+                (r.into_inner().into_inner(), buf)
+            }
+            LTXReaderTail::Prefixed(r) => {
+                let (buf, r) = r.into_inner();
+                let pos = buf.position() as usize;
+                (r.into_inner(), buf.into_inner().split_off(pos))
+            }
+        }
+    }
+}
+
+impl<R> io::Read for LTXReaderTail<R>
+where
+    R: io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            LTXReaderTail::Unbuffered(r) => r.read(buf),
+            #[cfg(feature = "zstd")]
+            LTXReaderTail::Buffered(r) => r.read(buf),
+            LTXReaderTail::Prefixed(r) => r.read(buf),
+        }
+    }
+}
+
+/// An [`io::Read`] counting the bytes read.
+///
+/// The count is shared with the [`Decoder`], which outlives the reader in
+/// [`Decoder::finish`].
+struct CountRead<R>
+where
+    R: io::Read,
+{
+    inner: R,
+    count: Arc<AtomicU64>,
+}
+
+impl<R> io::Read for CountRead<R>
+where
+    R: io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.fetch_add(n as u64, Ordering::Relaxed);
+        Ok(n)
+    }
+}
+
+/// An [`io::Read`] computing a digest on the bytes read.
+struct CrcDigestRead<'a, 'b, R>
+where
+    R: io::Read,
+{
+    inner: R,
+    digest: &'a mut Crc64Digest<'b>,
+}
+
+impl<'a, 'b, R> CrcDigestRead<'a, 'b, R>
+where
+    R: io::Read,
+{
+    fn new(inner: R, digest: &'a mut Crc64Digest<'b>) -> Self {
+        CrcDigestRead { inner, digest }
+    }
+}
+
+impl<'a, 'b, R> io::Read for CrcDigestRead<'a, 'b, R>
+where
+    R: io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.digest.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CrcDigestRead, DecodeLimit, DecodeOptions, Decoder, DecoderBuilder, Error, FileSection,
+    };
+    use crate::{
+        compression,
+        ltx::{
+            HeaderDecodeError, PageIndex, CRC64, HEADER_SIZE, PAGE_CHECKSUM_SIZE, PAGE_HEADER_SIZE,
+            TRAILER_SIZE,
+        },
+        utils::TimeRound,
+        Checksum, Encoder, Header, HeaderFlags, PageNum, PagePool, PageSize, Pos, Trailer, TXID,
+    };
+    use std::{
+        io::{self, Read},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time,
+    };
+
+    #[test]
+    fn crc_digest_read() {
         let buf_in = vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
         let mut digest = CRC64.digest();
         let mut reader = CrcDigestRead::new(buf_in.as_slice(), &mut digest);
@@ -221,25 +1687,32 @@ mod tests {
         let header = Header {
             flags,
             page_size: PageSize::new(4096).unwrap(),
-            commit: PageNum::new(3).unwrap(),
+            commit: Some(PageNum::new(6).unwrap()),
             min_txid: TXID::new(5).unwrap(),
             max_txid: TXID::new(6).unwrap(),
             timestamp: time::SystemTime::now()
                 .round(time::Duration::from_millis(1))
                 .unwrap(),
             pre_apply_checksum: Some(Checksum::new(5)),
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
         };
 
         let mut enc = Encoder::new(&mut buf, &header).expect("failed to create encoder");
-        let mut pages: Vec<(PageNum, Vec<_>)> = Vec::new();
-        pages.push((
-            PageNum::new(4).unwrap(),
-            (0..4096).map(|_| rand::random::<u8>()).collect::<Vec<_>>(),
-        ));
-        pages.push((
-            PageNum::new(6).unwrap(),
-            (0..4096).map(|_| rand::random::<u8>()).collect::<Vec<_>>(),
-        ));
+        let pages: Vec<(PageNum, Vec<_>)> = vec![
+            (
+                PageNum::new(4).unwrap(),
+                (0..4096).map(|_| rand::random::<u8>()).collect::<Vec<_>>(),
+            ),
+            (
+                PageNum::new(6).unwrap(),
+                (0..4096).map(|_| rand::random::<u8>()).collect::<Vec<_>>(),
+            ),
+        ];
 
         for (page_num, page) in &pages {
             enc.encode_page(*page_num, page.as_slice())
@@ -277,4 +1750,1199 @@ mod tests {
     fn decoder_compressed() {
         decoder_test(HeaderFlags::COMPRESS_LZ4);
     }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn decoder_zstd() {
+        decoder_test(HeaderFlags::COMPRESS_ZSTD);
+    }
+
+    #[test]
+    fn decoder_custom_compression() {
+        compression::tests::register_xor();
+        decoder_test(compression::tests::XOR_FLAG);
+    }
+
+    #[test]
+    fn decoder_parallel() {
+        let header = Header {
+            flags: HeaderFlags::COMPRESS_LZ4,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(200).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+        let pages: Vec<Vec<u8>> = (0..200)
+            .map(|i| (0..4096).map(|j| ((i * j) % 7) as u8).collect())
+            .collect();
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &header).expect("failed to create encoder");
+        for (page_num, page) in (1..).zip(&pages) {
+            enc.encode_page(PageNum::new(page_num).unwrap(), page)
+                .expect("failed to encode page");
+        }
+        let trailer = enc
+            .finish(Checksum::new(1))
+            .expect("failed to finish encoder");
+
+        let (mut dec, _) = Decoder::builder()
+            .threads(4)
+            .build(buf.as_slice())
+            .expect("failed to create decoder");
+        let mut page = vec![0; 4096];
+        for expected in &pages {
+            dec.decode_page(&mut page)
+                .expect("failed to decode page")
+                .expect("missing page");
+            assert_eq!(expected, &page);
+        }
+        assert!(matches!(dec.decode_page(&mut page), Ok(None)));
+        assert_eq!(trailer, dec.finish().expect("failed to finish decoder"));
+    }
+
+    #[test]
+    fn decoder_page_ref() {
+        let header = Header {
+            flags: HeaderFlags::COMPRESS_LZ4,
+            page_size: PageSize::new(512).unwrap(),
+            commit: Some(PageNum::new(3).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &header).expect("failed to create encoder");
+        for page_num in 1..=3 {
+            enc.encode_page(PageNum::new(page_num).unwrap(), &[page_num as u8; 512])
+                .expect("failed to encode page");
+        }
+        let trailer = enc
+            .finish(Checksum::new(1))
+            .expect("failed to finish encoder");
+
+        let (mut dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        for page_num in 1..=3 {
+            let (num, page) = dec
+                .decode_page_ref()
+                .expect("failed to decode page")
+                .expect("missing page");
+            assert_eq!(PageNum::new(page_num).unwrap(), num);
+            assert_eq!(&[page_num as u8; 512], page);
+        }
+        assert!(matches!(dec.decode_page_ref(), Ok(None)));
+        assert_eq!(trailer, dec.finish().expect("failed to finish decoder"));
+    }
+
+    #[test]
+    #[cfg(feature = "bytes")]
+    fn decoder_page_bytes() {
+        let header = Header {
+            flags: HeaderFlags::COMPRESS_LZ4,
+            page_size: PageSize::new(512).unwrap(),
+            commit: Some(PageNum::new(3).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &header).expect("failed to create encoder");
+        for page_num in 1..=3 {
+            enc.encode_page_bytes(
+                PageNum::new(page_num).unwrap(),
+                bytes::Bytes::from(vec![page_num as u8; 512]),
+            )
+            .expect("failed to encode page");
+        }
+        let trailer = enc.finish_auto().expect("failed to finish encoder");
+
+        let (mut dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        let mut pages = Vec::new();
+        while let Some(page) = dec.decode_page_bytes().expect("failed to decode page") {
+            pages.push(page);
+        }
+        assert_eq!(trailer, dec.finish().expect("failed to finish decoder"));
+        assert_eq!(
+            (1..=3)
+                .map(|n| (PageNum::new(n).unwrap(), vec![n as u8; 512]))
+                .collect::<Vec<_>>(),
+            pages
+                .into_iter()
+                .map(|(n, page)| (n, page.to_vec()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn decoder_lenient_flags() {
+        let unknown = HeaderFlags::from_bits_retain(0x40000000);
+        let header = Header {
+            flags: HeaderFlags::COMPRESS_LZ4 | unknown,
+            page_size: PageSize::new(512).unwrap(),
+            commit: Some(PageNum::ONE),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::now()
+                .round(time::Duration::from_millis(1))
+                .unwrap(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &header).expect("failed to create encoder");
+        enc.encode_page(PageNum::ONE, &[1; 512])
+            .expect("failed to encode page");
+        enc.finish(Checksum::new(1))
+            .expect("failed to finish encoder");
+
+        assert!(matches!(
+            Decoder::new(buf.as_slice()),
+            Err(Error::Header(HeaderDecodeError::Flags(0x40000001)))
+        ));
+
+        let (mut dec, header_out) = Decoder::new_with_options(
+            buf.as_slice(),
+            DecodeOptions {
+                lenient_flags: true,
+                ..Default::default()
+            },
+        )
+        .expect("failed to create decoder");
+        assert_eq!(header, header_out);
+        assert_eq!(unknown, header_out.unknown_flags());
+
+        let mut page = vec![0; 512];
+        assert!(matches!(dec.decode_page(&mut page), Ok(Some(PageNum::ONE))));
+        assert!(matches!(dec.decode_page(&mut page), Ok(None)));
+        dec.finish().expect("failed to finish decoder");
+    }
+
+    #[test]
+    fn decoder_error_position() {
+        let header = Header {
+            flags: HeaderFlags::empty(),
+            page_size: PageSize::new(512).unwrap(),
+            commit: Some(PageNum::new(3).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &header).expect("failed to create encoder");
+        for page_num in 1..=3 {
+            enc.encode_page(PageNum::new(page_num).unwrap(), &[page_num as u8; 512])
+                .expect("failed to encode page");
+        }
+        enc.finish(Checksum::new(1))
+            .expect("failed to finish encoder");
+
+        // Truncated in the middle of the second page.
+        let truncated = &buf[..HEADER_SIZE + 2 * PAGE_HEADER_SIZE + 512 + 100];
+        let (mut dec, _) = Decoder::new(truncated).expect("failed to create decoder");
+        let mut page = vec![0; 512];
+        dec.decode_page(&mut page).expect("failed to decode page");
+        let err = dec.decode_page(&mut page).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::TruncatedFile { expected: FileSection::PageBlock, offset }
+                if offset == truncated.len() as u64
+        ));
+        assert_eq!(io::ErrorKind::UnexpectedEof, io::Error::from(err).kind());
+
+        // Truncated in the header and in the trailer.
+        assert!(matches!(
+            Decoder::new(&buf[..50]),
+            Err(Error::TruncatedFile {
+                expected: FileSection::Header,
+                offset: 50
+            })
+        ));
+        let truncated = &buf[..buf.len() - 4];
+        let (mut dec, _) = Decoder::new(truncated).expect("failed to create decoder");
+        while dec
+            .decode_page(&mut page)
+            .expect("failed to decode page")
+            .is_some()
+        {}
+        assert!(matches!(
+            dec.finish(),
+            Err(Error::TruncatedFile { expected: FileSection::Trailer, offset })
+                if offset == truncated.len() as u64
+        ));
+
+        // Corrupted file checksum.
+        let len = buf.len();
+        buf[len - 1] ^= 1;
+        let (mut dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        while dec
+            .decode_page(&mut page)
+            .expect("failed to decode page")
+            .is_some()
+        {}
+        assert!(matches!(
+            dec.finish(),
+            Err(Error::Corrupt { offset, last_page_num: Some(num), source })
+                if offset == len as u64
+                    && num == PageNum::new(3).unwrap()
+                    && matches!(*source, Error::FileChecksumMismatch)
+        ));
+    }
+
+    #[test]
+    fn decoder_invalid_page_num() {
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(
+            &mut buf,
+            &Header {
+                flags: HeaderFlags::empty(),
+                page_size: PageSize::new(512).unwrap(),
+                commit: Some(PageNum::new(3).unwrap()),
+                min_txid: TXID::new(2).unwrap(),
+                max_txid: TXID::new(2).unwrap(),
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum: Some(Checksum::new(1)),
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder");
+        for page_num in 2..=3 {
+            enc.encode_page(PageNum::new(page_num).unwrap(), &[page_num as u8; 512])
+                .expect("failed to encode page");
+        }
+        enc.finish(Checksum::new(2))
+            .expect("failed to finish encoder");
+
+        let set_page_num = |buf: &mut [u8], i: usize, page_num: PageNum| {
+            let offset = HEADER_SIZE + i * (PAGE_HEADER_SIZE + 512);
+            buf[offset..offset + 4].copy_from_slice(&page_num.into_inner().to_be_bytes());
+        };
+        let mut page = vec![0; 512];
+
+        let mut corrupted = buf.clone();
+        set_page_num(&mut corrupted, 1, PageNum::new(2).unwrap());
+        let (mut dec, _) = Decoder::new(corrupted.as_slice()).expect("failed to create decoder");
+        dec.decode_page(&mut page).expect("failed to decode page");
+        assert!(matches!(
+            dec.decode_page(&mut page).as_ref().map_err(Error::inner),
+            Err(Error::OutOfOrderPage(last, p)) if *last == *p && *p == PageNum::new(2).unwrap()
+        ));
+
+        let lock_page = PageNum::lock_page(PageSize::new(512).unwrap());
+        set_page_num(&mut buf, 0, lock_page);
+        let (mut dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        assert!(matches!(
+            dec.decode_page(&mut page).as_ref().map_err(Error::inner),
+            Err(Error::LockPage(p)) if *p == lock_page
+        ));
+    }
+
+    #[test]
+    fn decoder_commit_checks() {
+        let header = Header {
+            flags: HeaderFlags::empty(),
+            page_size: PageSize::new(512).unwrap(),
+            commit: Some(PageNum::new(3).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+        let encode = |header: &Header, pages: u32| {
+            let mut buf = Vec::new();
+            let mut enc = Encoder::new(&mut buf, header)
+                .expect("failed to create encoder")
+                .allow_incomplete_snapshot();
+            for page_num in 4 - pages..=3 {
+                enc.encode_page(PageNum::new(page_num).unwrap(), &[page_num as u8; 512])
+                    .expect("failed to encode page");
+            }
+            enc.finish(Checksum::new(1))
+                .expect("failed to finish encoder");
+            buf
+        };
+        let set_page_num = |buf: &mut [u8], i: usize, page_num: u32| {
+            let offset = HEADER_SIZE + i * (PAGE_HEADER_SIZE + 512);
+            buf[offset..offset + 4].copy_from_slice(&page_num.to_be_bytes());
+        };
+        let decode = |buf: &[u8]| -> Result<Trailer, Error> {
+            let (mut dec, _) = Decoder::new(buf)?;
+            while dec.skip_page()?.is_some() {}
+            dec.finish()
+        };
+        let page = |n| PageNum::new(n).unwrap();
+
+        let snapshot = encode(&header, 3);
+        let mut buf = snapshot.clone();
+        set_page_num(&mut buf, 0, 2);
+        assert!(matches!(
+            decode(&buf).as_ref().map_err(Error::inner),
+            Err(Error::FirstSnapshotPage(p)) if *p == page(2)
+        ));
+
+        let mut buf = snapshot.clone();
+        set_page_num(&mut buf, 1, 3);
+        assert!(matches!(
+            decode(&buf).as_ref().map_err(Error::inner),
+            Err(Error::NonsequentialPages(last, p)) if *last == page(1) && *p == page(3)
+        ));
+
+        let mut buf = snapshot;
+        set_page_num(&mut buf, 2, 4);
+        assert!(matches!(
+            decode(&buf).as_ref().map_err(Error::inner),
+            Err(Error::PageBeyondCommit(p, commit)) if *p == page(4) && *commit == page(3)
+        ));
+
+        // Snapshot without its last page.
+        let buf = encode(
+            &Header {
+                commit: Some(page(4)),
+                ..header.clone()
+            },
+            3,
+        );
+        assert!(matches!(
+            decode(&buf).as_ref().map_err(Error::inner),
+            Err(Error::IncompleteSnapshot(Some(last), want)) if *last == page(3) && *want == page(4)
+        ));
+        let (mut dec, _) = Decoder::builder()
+            .incomplete_snapshots(true)
+            .build(buf.as_slice())
+            .expect("failed to create decoder");
+        while dec.skip_page().expect("failed to skip page").is_some() {}
+        dec.finish().expect("failed to finish decoder");
+
+        // Delta with the commit cleared in the header.
+        let mut buf = encode(
+            &Header {
+                min_txid: TXID::new(2).unwrap(),
+                max_txid: TXID::new(2).unwrap(),
+                pre_apply_checksum: Some(Checksum::new(1)),
+                ..header
+            },
+            2,
+        );
+        buf[12..16].copy_from_slice(&[0; 4]);
+        assert!(matches!(
+            decode(&buf).as_ref().map_err(Error::inner),
+            Err(Error::DeletedDatabase(p)) if *p == page(2)
+        ));
+    }
+
+    #[test]
+    fn decoder_error_into_io_error() {
+        let e = io::Error::from(Error::Read(io::ErrorKind::UnexpectedEof.into()));
+        assert_eq!(io::ErrorKind::UnexpectedEof, e.kind());
+        assert!(e.get_ref().is_none());
+
+        let e = io::Error::from(Error::Position {
+            offset: 10,
+            last_page_num: None,
+            source: Box::new(Error::Read(io::ErrorKind::UnexpectedEof.into())),
+        });
+        assert_eq!(io::ErrorKind::UnexpectedEof, e.kind());
+        assert!(matches!(
+            e.into_inner().map(|e| e.downcast::<Error>()),
+            Some(Ok(e)) if matches!(*e, Error::Position { offset: 10, .. })
+        ));
+
+        let e = io::Error::from(Error::FileChecksumMismatch);
+        assert_eq!(io::ErrorKind::InvalidData, e.kind());
+    }
+
+    #[test]
+    fn decoder_skip_page() {
+        for flags in [HeaderFlags::empty(), HeaderFlags::COMPRESS_LZ4] {
+            let buf = encode_indexed(flags);
+            let (mut dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+
+            let mut page = vec![0; 4096];
+            for page_num in (2..=40).step_by(2) {
+                let num = if page_num % 4 == 0 {
+                    dec.skip_page()
+                } else {
+                    dec.decode_page(&mut page)
+                };
+                assert!(matches!(num, Ok(Some(num)) if num == PageNum::new(page_num).unwrap()));
+            }
+            assert!(matches!(dec.skip_page(), Ok(None)));
+
+            // Skipped pages are still covered by the file checksum.
+            dec.finish().expect("failed to finish decoder");
+        }
+    }
+
+    #[test]
+    fn decoder_progress() {
+        let buf = encode_indexed(HeaderFlags::COMPRESS_LZ4);
+        let mut events = Vec::new();
+        let (dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        let mut dec = dec.with_progress(|e| events.push(e));
+
+        while dec.skip_page().expect("failed to skip page").is_some() {}
+        dec.finish().expect("failed to finish decoder");
+
+        assert_eq!(21, events.len());
+        for (i, e) in events.iter().take(20).enumerate() {
+            assert_eq!(i as u64 + 1, e.pages);
+            assert_eq!(
+                (HEADER_SIZE + (4096 + PAGE_HEADER_SIZE) * (i + 1)) as u64,
+                e.bytes_out
+            );
+        }
+        assert!(events.windows(2).all(|w| w[0].bytes_in <= w[1].bytes_in));
+        assert_eq!(buf.len() as u64, events[20].bytes_in);
+        assert_eq!(
+            (HEADER_SIZE
+                + (4096 + PAGE_HEADER_SIZE) * 20
+                + PAGE_HEADER_SIZE
+                + PageIndex::encoded_size(20)
+                + TRAILER_SIZE) as u64,
+            events[20].bytes_out
+        );
+    }
+
+    #[test]
+    fn decoder_cancel() {
+        let buf = encode_indexed(HeaderFlags::COMPRESS_LZ4);
+        let cancel = Arc::new(AtomicBool::new(false));
+        let (dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        let mut dec = dec.with_cancel(cancel.clone());
+        assert!(matches!(dec.skip_page(), Ok(Some(_))));
+
+        cancel.store(true, Ordering::Relaxed);
+        assert!(matches!(dec.skip_page(), Err(Error::Cancelled)));
+        let mut page = vec![0; 4096];
+        assert!(matches!(dec.decode_page(&mut page), Err(Error::Cancelled)));
+
+        // Nothing is consumed by cancelled calls, so decoding can resume.
+        cancel.store(false, Ordering::Relaxed);
+        assert!(matches!(dec.decode_page(&mut page), Ok(Some(n)) if n == PageNum::new(4).unwrap()));
+
+        cancel.store(true, Ordering::Relaxed);
+        assert!(matches!(dec.finish(), Err(Error::Cancelled)));
+    }
+
+    #[test]
+    #[cfg(feature = "tracing")]
+    fn decoder_tracing() {
+        use std::sync::Mutex;
+
+        /// A subscriber recording the messages of events.
+        #[derive(Default)]
+        struct Recorder(Mutex<Vec<String>>);
+
+        impl tracing::field::Visit for &Recorder {
+            fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+                if field.name() == "message" {
+                    self.0.lock().unwrap().push(format!("{value:?}"));
+                }
+            }
+        }
+
+        impl tracing::Subscriber for Recorder {
+            fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, _: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+                tracing::span::Id::from_u64(1)
+            }
+            fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+            fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+            fn event(&self, event: &tracing::Event<'_>) {
+                event.record(&mut &*self);
+            }
+            fn enter(&self, _: &tracing::span::Id) {}
+            fn exit(&self, _: &tracing::span::Id) {}
+        }
+
+        let buf = encode_indexed(HeaderFlags::COMPRESS_LZ4);
+        let recorder = Arc::new(Recorder::default());
+        tracing::subscriber::with_default(recorder.clone(), || {
+            let (mut dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+            while dec.skip_page().expect("failed to skip page").is_some() {}
+            dec.finish().expect("failed to finish decoder");
+        });
+
+        assert_eq!(
+            vec!["header decoded", "trailer verified"],
+            *recorder.0.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn decoder_finish_pos() {
+        let buf = encode_indexed(HeaderFlags::empty());
+        let (mut dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        while dec.skip_page().expect("failed to skip page").is_some() {}
+        assert_eq!(
+            Pos {
+                txid: TXID::new(6).unwrap(),
+                post_apply_checksum: Checksum::new(6),
+            },
+            dec.finish_pos().expect("failed to finish decoder")
+        );
+
+        let mut buf = Vec::new();
+        let enc = Encoder::new(
+            &mut buf,
+            &Header {
+                flags: HeaderFlags::NO_CHECKSUM,
+                page_size: PageSize::new(4096).unwrap(),
+                commit: Some(PageNum::new(40).unwrap()),
+                min_txid: TXID::new(5).unwrap(),
+                max_txid: TXID::new(6).unwrap(),
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder");
+        enc.finish(None).expect("failed to finish encoder");
+
+        let (mut dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        assert!(matches!(dec.skip_page(), Ok(None)));
+        assert!(matches!(dec.finish_pos(), Err(Error::NoChecksum)));
+    }
+
+    #[test]
+    fn decoder_pages() {
+        let header = Header {
+            flags: HeaderFlags::COMPRESS_LZ4,
+            page_size: PageSize::new(512).unwrap(),
+            commit: Some(PageNum::new(3).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &header).expect("failed to create encoder");
+        for page_num in 1..=3 {
+            enc.encode_page(PageNum::new(page_num).unwrap(), &[page_num as u8; 512])
+                .expect("failed to encode page");
+        }
+        let trailer = enc
+            .finish(Checksum::new(1))
+            .expect("failed to finish encoder");
+
+        let pool = PagePool::new();
+        let (mut dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        let pages = dec
+            .pages(&pool)
+            .collect::<Result<Vec<_>, _>>()
+            .expect("failed to decode pages");
+        for (page_num, (num, page)) in (1..=3).zip(&pages) {
+            assert_eq!(PageNum::new(page_num).unwrap(), *num);
+            assert_eq!(&[page_num as u8; 512], &page[..]);
+        }
+        assert_eq!(trailer, dec.finish().expect("failed to finish decoder"));
+
+        // One more buffer was taken for the end of the page block.
+        assert_eq!(1, pool.len());
+        drop(pages);
+        assert_eq!(4, pool.len());
+    }
+
+    fn encode_indexed(flags: HeaderFlags) -> Vec<u8> {
+        encode_even_pages(flags | HeaderFlags::PAGE_INDEX)
+    }
+
+    /// Encode a delta with the even pages up to 40, each filled with its page number.
+    fn encode_even_pages(flags: HeaderFlags) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(
+            &mut buf,
+            &Header {
+                flags,
+                page_size: PageSize::new(4096).unwrap(),
+                commit: Some(PageNum::new(40).unwrap()),
+                min_txid: TXID::new(5).unwrap(),
+                max_txid: TXID::new(6).unwrap(),
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum: Some(Checksum::new(5)),
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder");
+        for page_num in (2..=40).step_by(2) {
+            enc.encode_page(PageNum::new(page_num).unwrap(), &[page_num as u8; 4096])
+                .expect("failed to encode page");
+        }
+        enc.finish(Checksum::new(6))
+            .expect("failed to finish encoder");
+
+        buf
+    }
+
+    #[test]
+    fn decoder_merkle_tree() {
+        for flags in [
+            HeaderFlags::empty(),
+            HeaderFlags::COMPRESS_LZ4,
+            HeaderFlags::COMPRESS_LZ4 | HeaderFlags::PAGE_INDEX,
+        ] {
+            let mut buf = encode_even_pages(flags | HeaderFlags::MERKLE_TREE);
+            let (mut dec, _) =
+                Decoder::new(io::Cursor::new(&buf)).expect("failed to create decoder");
+            let page_num = PageNum::new(30).unwrap();
+            assert!(dec.verify_page_proof(page_num, &[30; 4096]).unwrap());
+            assert!(!dec.verify_page_proof(page_num, &[31; 4096]).unwrap());
+            assert!(!dec
+                .verify_page_proof(PageNum::new(3).unwrap(), &[3; 4096])
+                .unwrap());
+            let root = dec.merkle_root().expect("failed to read root");
+
+            // Sequential decoding verifies the tree against the pages.
+            let mut page = vec![0; 4096];
+            while dec.skip_page().unwrap().is_some() {}
+            dec.finish().expect("failed to finish decoder");
+
+            // The root is followed by the leaf count, the page index of 20 entries and
+            // the trailer. A tampered tree is detected before the file checksum.
+            let index_size = if flags.contains(HeaderFlags::PAGE_INDEX) {
+                20 * 12 + 4
+            } else {
+                0
+            };
+            let root_offset = buf.len() - TRAILER_SIZE - index_size - 4 - 8;
+            buf[root_offset] ^= 1;
+            let (mut dec, _) =
+                Decoder::new(io::Cursor::new(&buf)).expect("failed to create decoder");
+            assert_ne!(root, dec.merkle_root().unwrap());
+            while dec.decode_page(&mut page).unwrap().is_some() {}
+            let err = dec.finish().expect_err("tampered tree");
+            assert!(matches!(err.inner(), Error::Merkle(_)));
+        }
+    }
+
+    #[test]
+    fn decoder_page_checksum() {
+        for flags in [
+            HeaderFlags::empty(),
+            HeaderFlags::PAGE_INDEX,
+            HeaderFlags::COMPRESS_LZ4 | HeaderFlags::PAGE_INDEX,
+        ] {
+            let mut buf = encode_even_pages(flags | HeaderFlags::PAGE_CHECKSUM);
+            let mut page = vec![0; 4096];
+            let (mut dec, _) =
+                Decoder::new(io::Cursor::new(&buf)).expect("failed to create decoder");
+            while dec.decode_page(&mut page).unwrap().is_some() {}
+            dec.finish().expect("failed to finish decoder");
+
+            if flags.contains(HeaderFlags::COMPRESS_LZ4) {
+                continue;
+            }
+
+            // Corrupt the data of the second page, page 4.
+            buf[HEADER_SIZE + 2 * PAGE_HEADER_SIZE + 4096 + PAGE_CHECKSUM_SIZE] ^= 1;
+            let page_num = PageNum::new(4).unwrap();
+            for skip in [false, true] {
+                let (mut dec, _) =
+                    Decoder::new(io::Cursor::new(&buf)).expect("failed to create decoder");
+                dec.decode_page(&mut page).expect("failed to decode page");
+                let err = if skip {
+                    dec.skip_page().expect_err("corrupt page")
+                } else {
+                    dec.decode_page(&mut page).expect_err("corrupt page")
+                };
+                assert!(matches!(err.inner(), Error::PageChecksumMismatch(n) if *n == page_num));
+            }
+
+            if flags.contains(HeaderFlags::PAGE_INDEX) {
+                let (mut dec, _) =
+                    Decoder::new(io::Cursor::new(&buf)).expect("failed to create decoder");
+                assert!(matches!(
+                    dec.seek_page(page_num, &mut page),
+                    Err(Error::PageChecksumMismatch(_))
+                ));
+                assert!(dec.seek_page(PageNum::new(6).unwrap(), &mut page).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn decoder_seek_page() {
+        for (flags, threads) in [
+            (HeaderFlags::empty(), 1),
+            (HeaderFlags::COMPRESS_LZ4, 1),
+            (HeaderFlags::COMPRESS_LZ4, 4),
+        ] {
+            let buf = encode_indexed(flags);
+            let (mut dec, _) = Decoder::builder()
+                .threads(threads)
+                .build(io::Cursor::new(buf))
+                .expect("failed to create decoder");
+
+            let mut page = vec![0; 4096];
+            for page_num in [30, 2, 40] {
+                assert!(dec
+                    .seek_page(PageNum::new(page_num).unwrap(), &mut page)
+                    .expect("failed to seek page"));
+                assert_eq!(vec![page_num as u8; 4096], page);
+            }
+            assert!(!dec
+                .seek_page(PageNum::new(3).unwrap(), &mut page)
+                .expect("failed to seek page"));
+
+            // Sequential decoding is not affected by seeking.
+            for page_num in (2..=40).step_by(2) {
+                assert!(matches!(
+                    dec.decode_page(&mut page),
+                    Ok(Some(num)) if num == PageNum::new(page_num).unwrap()
+                ));
+                assert_eq!(vec![page_num as u8; 4096], page);
+                dec.seek_page(PageNum::new(10).unwrap(), &mut page)
+                    .expect("failed to seek page");
+            }
+            assert!(matches!(dec.decode_page(&mut page), Ok(None)));
+
+            let trailer = dec.finish().expect("failed to finish decoder");
+            assert_eq!(Some(Checksum::new(6)), trailer.post_apply_checksum);
+        }
+    }
+
+    #[test]
+    fn decoder_no_page_index() {
+        let mut buf = Vec::new();
+        Encoder::new(
+            &mut buf,
+            &Header {
+                flags: HeaderFlags::empty(),
+                page_size: PageSize::new(4096).unwrap(),
+                commit: None,
+                min_txid: TXID::ONE,
+                max_txid: TXID::ONE,
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder")
+        .finish(Checksum::new(0))
+        .expect("failed to finish encoder");
+
+        let (mut dec, _) = Decoder::new(io::Cursor::new(buf)).expect("failed to create decoder");
+        assert!(matches!(
+            dec.seek_page(PageNum::ONE, &mut [0; 4096]),
+            Err(Error::NoPageIndex)
+        ));
+    }
+
+    #[cfg(feature = "encryption")]
+    fn encode_encrypted(flags: HeaderFlags, key: &[u8; 32]) -> (Header, Vec<u8>) {
+        let header = Header {
+            flags: flags | HeaderFlags::ENCRYPTED,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(20).unwrap()),
+            min_txid: TXID::new(5).unwrap(),
+            max_txid: TXID::new(6).unwrap(),
+            timestamp: time::SystemTime::now()
+                .round(time::Duration::from_millis(1))
+                .unwrap(),
+            pre_apply_checksum: Some(Checksum::new(5)),
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let mut buf = Vec::new();
+        let mut enc =
+            Encoder::new_encrypted(&mut buf, &header, key).expect("failed to create encoder");
+        for page_num in 1..=20 {
+            enc.encode_page(PageNum::new(page_num).unwrap(), &[page_num as u8; 4096])
+                .expect("failed to encode page");
+        }
+        enc.finish(Checksum::new(6))
+            .expect("failed to finish encoder");
+
+        (header, buf)
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn decoder_encrypted() {
+        for flags in [HeaderFlags::empty(), HeaderFlags::COMPRESS_LZ4] {
+            let (header, buf) = encode_encrypted(flags, &[1; 32]);
+
+            let (mut dec, header_out) =
+                Decoder::new_encrypted(buf.as_slice(), &[1; 32]).expect("failed to create decoder");
+            assert_eq!(header, header_out);
+
+            let mut page = vec![0; 4096];
+            for page_num in 1..=20 {
+                assert!(matches!(
+                    dec.decode_page(&mut page),
+                    Ok(Some(num)) if num == PageNum::new(page_num).unwrap()
+                ));
+                assert_eq!(vec![page_num as u8; 4096], page);
+            }
+            assert!(matches!(dec.decode_page(&mut page), Ok(None)));
+
+            let trailer = dec.finish().expect("failed to finish decoder");
+            assert_eq!(Some(Checksum::new(6)), trailer.post_apply_checksum);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn decoder_encrypted_wrong_key() {
+        let (_, buf) = encode_encrypted(HeaderFlags::empty(), &[1; 32]);
+
+        let (mut dec, _) =
+            Decoder::new_encrypted(buf.as_slice(), &[2; 32]).expect("failed to create decoder");
+        let mut page = vec![0; 4096];
+        assert!(matches!(
+            dec.decode_page(&mut page),
+            Err(e) if matches!(e.inner(), Error::PageHeader(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn decoder_key_provider() {
+        use crate::encoder::Error as EncodeError;
+        use std::collections::BTreeMap;
+
+        let keys = BTreeMap::from([(1, [1; 32]), (2, [2; 32])]);
+        let (mut header, _) = encode_encrypted(HeaderFlags::COMPRESS_LZ4, &[1; 32]);
+        let mut files = Vec::new();
+        for key_id in [1, 2] {
+            header.key_id = Some(key_id);
+            let mut buf = Vec::new();
+            let mut enc =
+                Encoder::new_with_keys(&mut buf, &header, &keys).expect("failed to create encoder");
+            enc.encode_page(PageNum::ONE, &[key_id as u8; 4096])
+                .expect("failed to encode page");
+            enc.finish(Checksum::new(6))
+                .expect("failed to finish encoder");
+            files.push(buf);
+        }
+
+        let mut page = vec![0; 4096];
+        for (key_id, buf) in [1, 2].into_iter().zip(&files) {
+            let (mut dec, header_out) =
+                Decoder::new_with_keys(buf.as_slice(), &keys).expect("failed to create decoder");
+            assert_eq!(Some(key_id), header_out.key_id);
+            assert_eq!(Some(PageNum::ONE), dec.decode_page(&mut page).unwrap());
+            assert_eq!(vec![key_id as u8; 4096], page);
+            assert_eq!(None, dec.decode_page(&mut page).unwrap());
+            dec.finish().expect("failed to finish decoder");
+        }
+
+        // Files keep decrypting with their key after new files moved on to another one.
+        let rotated = BTreeMap::from([(2, [2; 32])]);
+        Decoder::new_with_keys(files[1].as_slice(), &rotated).expect("failed to create decoder");
+        assert!(matches!(
+            Decoder::new_with_keys(files[0].as_slice(), &rotated),
+            Err(Error::UnknownKey(1))
+        ));
+
+        let (_, buf) = encode_encrypted(HeaderFlags::empty(), &[1; 32]);
+        assert!(matches!(
+            Decoder::new_with_keys(buf.as_slice(), &keys),
+            Err(Error::NoKeyId)
+        ));
+
+        header.key_id = Some(3);
+        assert!(matches!(
+            Encoder::new_with_keys(Vec::new(), &header, &keys),
+            Err(EncodeError::UnknownKey(3))
+        ));
+        header.key_id = None;
+        assert!(matches!(
+            Encoder::new_with_keys(Vec::new(), &header, &keys),
+            Err(EncodeError::NoKeyId)
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn decoder_encrypted_key_mismatch() {
+        let (_, buf) = encode_encrypted(HeaderFlags::empty(), &[1; 32]);
+        assert!(matches!(
+            Decoder::new(buf.as_slice()),
+            Err(Error::KeyRequired)
+        ));
+
+        let mut buf = Vec::new();
+        Encoder::new(
+            &mut buf,
+            &Header {
+                flags: HeaderFlags::empty(),
+                page_size: PageSize::new(4096).unwrap(),
+                commit: None,
+                min_txid: TXID::ONE,
+                max_txid: TXID::ONE,
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder")
+        .finish(Checksum::new(0))
+        .expect("failed to finish encoder");
+        assert!(matches!(
+            Decoder::new_encrypted(buf.as_slice(), &[1; 32]),
+            Err(Error::UnexpectedKey)
+        ));
+    }
+
+    #[test]
+    fn decoder_trailing_data() {
+        for flags in [HeaderFlags::empty(), HeaderFlags::COMPRESS_LZ4] {
+            let mut buf = Vec::new();
+            let mut enc = Encoder::new(
+                &mut buf,
+                &Header {
+                    flags,
+                    page_size: PageSize::new(512).unwrap(),
+                    commit: Some(PageNum::ONE),
+                    min_txid: TXID::ONE,
+                    max_txid: TXID::ONE,
+                    timestamp: time::SystemTime::now(),
+                    pre_apply_checksum: None,
+                    node_id: 0,
+                    wal: None,
+                    app_data: None,
+                    page_filter: None,
+                    key_id: None,
+                    dict_id: None,
+                },
+            )
+            .expect("failed to create encoder");
+            enc.encode_page(PageNum::ONE, &[1; 512])
+                .expect("failed to encode page");
+            enc.finish_auto().expect("failed to finish encoder");
+            let len = buf.len();
+
+            let finish = |data: &[u8], builder: DecoderBuilder| {
+                let (mut dec, _) = builder.build(data).expect("failed to create decoder");
+                while dec
+                    .decode_page(&mut [0; 512])
+                    .expect("failed to decode page")
+                    .is_some()
+                {}
+                dec.finish()
+            };
+
+            let strict = Decoder::builder().strict();
+            assert!(finish(&buf, strict).is_ok());
+
+            buf.extend_from_slice(b"garbage");
+            assert!(finish(&buf, Decoder::builder()).is_ok());
+            assert!(matches!(
+                finish(&buf, strict),
+                Err(Error::TrailingData(offset)) if offset == len as u64
+            ));
+        }
+    }
+
+    #[test]
+    fn decoder_limits() {
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(
+            &mut buf,
+            &Header {
+                flags: HeaderFlags::COMPRESS_LZ4,
+                page_size: PageSize::new(1024).unwrap(),
+                commit: Some(PageNum::new(3).unwrap()),
+                min_txid: TXID::ONE,
+                max_txid: TXID::ONE,
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder");
+        for n in 1..=3 {
+            enc.encode_page(PageNum::new(n).unwrap(), &[n as u8; 1024])
+                .expect("failed to encode page");
+        }
+        enc.finish_auto().expect("failed to finish encoder");
+
+        let decode = |options: DecodeOptions| -> Result<u64, Error> {
+            let (mut dec, _) = Decoder::builder().options(options).build(buf.as_slice())?;
+            let mut page = vec![0; 1024];
+            let mut pages = 0;
+            while dec.decode_page(&mut page)?.is_some() {
+                pages += 1;
+            }
+            dec.finish()?;
+            Ok(pages)
+        };
+
+        assert_eq!(3, decode(DecodeOptions::default()).unwrap());
+        assert_eq!(
+            3,
+            decode(DecodeOptions {
+                max_pages: Some(3),
+                max_decompressed_bytes: Some(3 * 1024),
+                max_page_size: PageSize::new(1024).ok(),
+                ..Default::default()
+            })
+            .unwrap()
+        );
+
+        assert!(matches!(
+            decode(DecodeOptions {
+                max_page_size: PageSize::new(512).ok(),
+                ..Default::default()
+            }),
+            Err(Error::LimitExceeded(DecodeLimit::PageSize))
+        ));
+        let err = decode(DecodeOptions {
+            max_pages: Some(2),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(matches!(
+            err.inner(),
+            Error::LimitExceeded(DecodeLimit::Pages)
+        ));
+        let err = decode(DecodeOptions {
+            max_decompressed_bytes: Some(3 * 1024 - 1),
+            ..Default::default()
+        })
+        .unwrap_err();
+        assert!(matches!(
+            err.inner(),
+            Error::LimitExceeded(DecodeLimit::DecompressedBytes)
+        ));
+    }
+
+    #[test]
+    fn decoder_corrupt_input() {
+        for flags in [
+            HeaderFlags::empty(),
+            HeaderFlags::COMPRESS_LZ4,
+            HeaderFlags::COMPRESS_LZ4 | HeaderFlags::PAGE_INDEX | HeaderFlags::MERKLE_TREE,
+            HeaderFlags::COMPRESS_LZ4 | HeaderFlags::PER_PAGE_COMPRESSION,
+            HeaderFlags::PAGE_CHECKSUM | HeaderFlags::NO_CHECKSUM,
+        ] {
+            let header = Header {
+                flags,
+                page_size: PageSize::new(512).unwrap(),
+                commit: Some(PageNum::new(3).unwrap()),
+                min_txid: TXID::ONE,
+                max_txid: TXID::ONE,
+                timestamp: time::SystemTime::UNIX_EPOCH,
+                pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            };
+            let mut buf = Vec::new();
+            let mut enc = Encoder::new(&mut buf, &header).expect("failed to create encoder");
+            for n in 1..=3 {
+                enc.encode_page(PageNum::new(n).unwrap(), &[n as u8; 512])
+                    .expect("failed to encode page");
+            }
+            enc.finish_auto().expect("failed to finish encoder");
+
+            let decode = |data: &[u8]| -> Result<Trailer, Error> {
+                let (mut dec, _) = Decoder::new(io::Cursor::new(data))?;
+                let mut page = vec![0; 512];
+                let _ = dec.seek_page(PageNum::new(2).unwrap(), &mut page);
+                let _ = dec.merkle_root();
+                while dec.decode_page(&mut page)?.is_some() {}
+                dec.finish()
+            };
+
+            // Every corruption and truncation must fail with an error, never panic.
+            for i in 0..buf.len() {
+                let mut corrupt = buf.clone();
+                corrupt[i] ^= 0xff;
+                assert!(decode(&corrupt).is_err(), "byte {i} corrupted");
+                assert!(decode(&buf[..i]).is_err(), "truncated at {i}");
+            }
+        }
+    }
 }
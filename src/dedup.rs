@@ -0,0 +1,247 @@
+//! Content-addressed deduplication of the pages of LTX files.
+//!
+//! [`Exporter`] decodes LTX files and stores every distinct page content once as a blob
+//! named by its SHA-256 hash. The [`Manifest`] maps the pages of every file to their
+//! blobs, so hot pages repeated across a long chain of files are only stored once.
+//!
+//! # Example
+//! ```no_run
+//! let mut exporter = litetx::dedup::Exporter::new("/var/lib/blobs");
+//! let dir = litetx::LtxDirectory::open("/var/lib/ltx").expect("open");
+//! for file in dir.files() {
+//!     let r = std::fs::File::open(&file.path).expect("open");
+//!     exporter.add_file(r).expect("add_file");
+//! }
+//!
+//! let manifest = exporter.finish();
+//! manifest
+//!     .write_to(std::fs::File::create("manifest.txt").expect("create"))
+//!     .expect("write_to");
+//! ```
+
+use crate::{decoder::Error as DecodeError, Decoder, Header, PageNum, TXID};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashSet,
+    fmt, fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// An error that can be returned by [`Exporter`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("decode")]
+    Decode(#[from] DecodeError),
+    #[error("blob io")]
+    Io(#[from] io::Error),
+}
+
+/// The SHA-256 hash of a page, naming its blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ContentHash([u8; 32]);
+
+impl ContentHash {
+    /// Hash the contents of a page.
+    pub fn of(data: &[u8]) -> ContentHash {
+        ContentHash(Sha256::digest(data).into())
+    }
+
+    /// Return the bytes of the hash.
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+}
+
+impl fmt::Display for ContentHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+/// A page of an LTX file stored as a blob.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    /// The last transaction of the file containing the page.
+    pub txid: TXID,
+    pub page_num: PageNum,
+    pub hash: ContentHash,
+}
+
+/// The mapping of pages to blobs produced by an [`Exporter`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Manifest {
+    /// The pages of all the files, in the order they were added.
+    pub entries: Vec<Entry>,
+}
+
+impl Manifest {
+    /// Return the number of distinct blobs referenced by the manifest.
+    pub fn blobs(&self) -> usize {
+        self.entries
+            .iter()
+            .map(|entry| entry.hash)
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Write the manifest as text into `w`, one `<txid> <page number> <hash>` line per
+    /// page.
+    pub fn write_to<W>(&self, w: W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        let mut w = io::BufWriter::new(w);
+        for entry in &self.entries {
+            writeln!(w, "{} {} {}", entry.txid, entry.page_num, entry.hash)?;
+        }
+
+        w.flush()
+    }
+}
+
+/// Stores the pages of LTX files as content-addressed blobs in a directory.
+///
+/// Blobs already present in the directory, e.g. from an earlier export, aren't
+/// rewritten.
+#[derive(Debug)]
+pub struct Exporter {
+    dir: PathBuf,
+    stored: HashSet<ContentHash>,
+    manifest: Manifest,
+    written: usize,
+}
+
+impl Exporter {
+    /// Create a new [`Exporter`] storing blobs in `dir`.
+    ///
+    /// The directory is created when the first blob is written.
+    pub fn new<P>(dir: P) -> Exporter
+    where
+        P: AsRef<Path>,
+    {
+        Exporter {
+            dir: dir.as_ref().to_path_buf(),
+            stored: HashSet::new(),
+            manifest: Manifest::default(),
+            written: 0,
+        }
+    }
+
+    /// Return the path of the blob with the given `hash`.
+    pub fn blob_path(&self, hash: &ContentHash) -> PathBuf {
+        self.dir.join(hash.to_string())
+    }
+
+    /// Return the number of blobs written so far.
+    pub fn blobs_written(&self) -> usize {
+        self.written
+    }
+
+    /// Decode the LTX file read from `r` and store its pages.
+    ///
+    /// The file is fully decoded and its file checksum is verified. Blobs are written
+    /// as the pages are decoded, so some may be left behind if the file is invalid.
+    pub fn add_file<R>(&mut self, r: R) -> Result<Header, Error>
+    where
+        R: io::Read,
+    {
+        let (mut dec, header) = Decoder::new(r)?;
+
+        let mut page = vec![0; header.page_size.into_inner() as usize];
+        while let Some(page_num) = dec.decode_page(&mut page)? {
+            let hash = ContentHash::of(&page);
+            if self.stored.insert(hash) {
+                self.write_blob(&hash, &page)?;
+            }
+            self.manifest.entries.push(Entry {
+                txid: header.max_txid,
+                page_num,
+                hash,
+            });
+        }
+        dec.finish()?;
+
+        Ok(header)
+    }
+
+    /// Consume the exporter and return the manifest of all the added files.
+    pub fn finish(self) -> Manifest {
+        self.manifest
+    }
+
+    fn write_blob(&mut self, hash: &ContentHash, data: &[u8]) -> Result<(), Error> {
+        let path = self.blob_path(hash);
+        if path.exists() {
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        // Blobs are written under a temporary name, so an interrupted export never
+        // leaves a truncated blob under its final name.
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, data)?;
+        fs::rename(tmp, path)?;
+        self.written += 1;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ContentHash, Exporter};
+    use crate::{
+        test_util::{encode_ltx, test_header},
+        utils::TempDir,
+        Checksum, HeaderFlags, TXID,
+    };
+    use std::fs;
+
+    fn encode(txid: u64, pages: &[(u32, u8)]) -> Vec<u8> {
+        let header = test_header(
+            HeaderFlags::COMPRESS_LZ4,
+            txid..=txid,
+            3,
+            Some(Checksum::new(1)),
+        );
+        encode_ltx(&header, pages, Some(Checksum::new(1)))
+    }
+
+    #[test]
+    fn dedup_export() {
+        let dir = TempDir::new();
+        let mut exporter = Exporter::new(dir.path());
+        exporter
+            .add_file(encode(2, &[(1, 1), (2, 2), (3, 1)]).as_slice())
+            .expect("failed to add file");
+        exporter
+            .add_file(encode(3, &[(2, 2), (3, 3)]).as_slice())
+            .expect("failed to add file");
+        assert_eq!(3, exporter.blobs_written());
+
+        let hash = ContentHash::of(&[1; 512]);
+        assert_eq!(vec![1; 512], fs::read(exporter.blob_path(&hash)).unwrap());
+
+        let manifest = exporter.finish();
+        assert_eq!(5, manifest.entries.len());
+        assert_eq!(3, manifest.blobs());
+        assert_eq!(hash, manifest.entries[2].hash);
+        assert_eq!(TXID::new(3).unwrap(), manifest.entries[3].txid);
+
+        let mut text = Vec::new();
+        manifest.write_to(&mut text).unwrap();
+        let text = String::from_utf8(text).unwrap();
+        assert_eq!(
+            format!("0000000000000002 1 {hash}"),
+            text.lines().next().unwrap()
+        );
+
+        // Blobs of an earlier export are reused.
+        let mut exporter = Exporter::new(dir.path());
+        exporter
+            .add_file(encode(4, &[(1, 1)]).as_slice())
+            .expect("failed to add file");
+        assert_eq!(0, exporter.blobs_written());
+    }
+}
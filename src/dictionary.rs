@@ -0,0 +1,197 @@
+//! Shared LZ4 compression dictionaries.
+//!
+//! SQLite pages of 512 to 4096 bytes compress poorly in isolation. A dictionary holds
+//! content common to many pages, e.g. [`train`]ed on representative files, and is used
+//! as the history of every compressed LZ4 block. Dictionaries are [`register`]ed under
+//! their ID, and files compressed with one record it in
+//! [`Header::dict_id`](crate::Header::dict_id), after which [`Encoder`](crate::Encoder)
+//! and [`Decoder`](crate::Decoder) look it up here.
+
+use crate::{lz4::MAX_DICT_SIZE, DecodeError, Decoder};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io,
+    sync::{Arc, RwLock},
+};
+
+/// The size of the page segments counted by [`train`].
+const SEGMENT_SIZE: usize = 64;
+
+/// An error that can be returned by this module.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("dictionary of {0} bytes exceeds {MAX_DICT_SIZE} bytes")]
+    Size(usize),
+    #[error("dictionary already registered: {0}")]
+    AlreadyRegistered(u32),
+    #[error("decode sample")]
+    Decode(#[from] DecodeError),
+}
+
+/// An LZ4 dictionary identified by an ID.
+///
+/// Cloning a dictionary is cheap, the data is shared.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Dictionary {
+    id: u32,
+    data: Arc<[u8]>,
+}
+
+impl Dictionary {
+    /// Create a dictionary of at most 64 KB, the size of the LZ4 window.
+    pub fn new<D>(id: u32, data: D) -> Result<Dictionary, Error>
+    where
+        D: Into<Vec<u8>>,
+    {
+        let data = data.into();
+        if data.len() > MAX_DICT_SIZE {
+            return Err(Error::Size(data.len()));
+        }
+
+        Ok(Dictionary {
+            id,
+            data: data.into(),
+        })
+    }
+
+    /// Return the ID of the dictionary.
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    /// Return the dictionary data.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub(crate) fn data(&self) -> Arc<[u8]> {
+        self.data.clone()
+    }
+}
+
+static DICTIONARIES: RwLock<BTreeMap<u32, Dictionary>> = RwLock::new(BTreeMap::new());
+
+/// Register a dictionary under its ID.
+///
+/// Dictionaries can't be replaced, since files already compressed with a dictionary
+/// can only be decompressed with the same one.
+pub fn register(dict: Dictionary) -> Result<(), Error> {
+    let mut dicts = DICTIONARIES.write().unwrap_or_else(|e| e.into_inner());
+    match dicts.get(&dict.id) {
+        Some(registered) if *registered == dict => Ok(()),
+        Some(_) => Err(Error::AlreadyRegistered(dict.id)),
+        None => {
+            dicts.insert(dict.id, dict);
+            Ok(())
+        }
+    }
+}
+
+/// Return the registered dictionary `id`.
+pub(crate) fn find(id: u32) -> Option<Dictionary> {
+    DICTIONARIES
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(&id)
+        .cloned()
+}
+
+/// Build a dictionary of at most `size` bytes from the pages of the LTX files `samples`.
+///
+/// Pages are split into aligned 64-byte segments, and the segments found most often
+/// across the samples make up the dictionary, the most frequent last. Segments seen
+/// only once or made of zeros are ignored, so the dictionary may be smaller than `size`.
+///
+/// # Example
+/// ```no_run
+/// # use litetx::dictionary::{self, Dictionary};
+/// let samples = ["a.ltx", "b.ltx"].map(|path| std::fs::File::open(path).unwrap());
+/// let dict = dictionary::train(1, samples, 16 * 1024).expect("train");
+/// dictionary::register(dict).expect("register");
+/// ```
+pub fn train<I, R>(id: u32, samples: I, size: usize) -> Result<Dictionary, Error>
+where
+    I: IntoIterator<Item = R>,
+    R: io::Read,
+{
+    let mut counts: HashMap<Vec<u8>, usize> = HashMap::new();
+    for sample in samples {
+        let (mut dec, header) = Decoder::new(sample)?;
+        let mut page = vec![0; header.page_size.into_inner() as usize];
+        while dec.decode_page(&mut page)?.is_some() {
+            for segment in page.chunks_exact(SEGMENT_SIZE) {
+                if segment.iter().any(|&b| b != 0) {
+                    *counts.entry(segment.to_vec()).or_default() += 1;
+                }
+            }
+        }
+        dec.finish()?;
+    }
+
+    let mut segments: Vec<_> = counts.into_iter().filter(|(_, n)| *n > 1).collect();
+    segments.sort_unstable_by(|(a, n), (b, m)| m.cmp(n).then_with(|| a.cmp(b)));
+    segments.truncate(size.min(MAX_DICT_SIZE) / SEGMENT_SIZE);
+
+    let data: Vec<u8> = segments.into_iter().rev().flat_map(|(s, _)| s).collect();
+    Dictionary::new(id, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{register, train, Dictionary, Error};
+    use crate::{Encoder, Header, HeaderFlags, PageNum, PageSize, TXID};
+    use std::time;
+
+    #[test]
+    fn register_dictionary() {
+        let dict = Dictionary::new(0xd1c7, vec![1; 100]).unwrap();
+        register(dict.clone()).expect("failed to register dictionary");
+        register(dict).expect("failed to register the same dictionary again");
+
+        let other = Dictionary::new(0xd1c7, vec![2; 100]).unwrap();
+        assert!(matches!(
+            register(other),
+            Err(Error::AlreadyRegistered(0xd1c7))
+        ));
+        assert!(matches!(
+            Dictionary::new(1, vec![0; 64 * 1024 + 1]),
+            Err(Error::Size(_))
+        ));
+    }
+
+    #[test]
+    fn train_dictionary() {
+        let mut sample = Vec::new();
+        let mut enc = Encoder::new(
+            &mut sample,
+            &Header {
+                flags: HeaderFlags::empty(),
+                page_size: PageSize::new(512).unwrap(),
+                commit: Some(PageNum::new(4).unwrap()),
+                min_txid: TXID::ONE,
+                max_txid: TXID::ONE,
+                timestamp: time::SystemTime::UNIX_EPOCH,
+                pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder");
+        for n in 1..=4u32 {
+            let mut page = vec![0; 512];
+            page[..64].fill(0xaa);
+            page[64..128].fill(n as u8);
+            enc.encode_page(PageNum::new(n).unwrap(), &page)
+                .expect("failed to encode page");
+        }
+        enc.finish_auto().expect("failed to finish encoder");
+
+        let dict = train(7, [sample.as_slice()], 1024).expect("failed to train dictionary");
+        assert_eq!(7, dict.id());
+        assert_eq!(&[0xaa; 64], dict.as_bytes());
+    }
+}
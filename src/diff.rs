@@ -0,0 +1,194 @@
+use crate::{
+    decoder::Error as DecodeError, Checksum, Decoder, Header, PageChecksum, PageNum, Trailer,
+};
+use std::io;
+
+/// An error that can be returned by [`diff`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("decode")]
+    Decode(#[from] DecodeError),
+}
+
+/// The differences between two LTX files, returned by [`diff`].
+#[derive(Debug)]
+pub struct DiffReport {
+    /// The names of the header and trailer fields that differ, e.g. `"max_txid"`.
+    pub fields: Vec<&'static str>,
+    /// The headers of the first and the second file.
+    pub headers: (Header, Header),
+    /// The trailers of the first and the second file.
+    pub trailers: (Trailer, Trailer),
+    /// The pages present only in the first file.
+    pub only_in_a: Vec<PageNum>,
+    /// The pages present only in the second file.
+    pub only_in_b: Vec<PageNum>,
+    /// The pages present in both files with different contents, along with their
+    /// checksums in the first and the second file.
+    pub changed_pages: Vec<(PageNum, Checksum, Checksum)>,
+}
+
+impl DiffReport {
+    /// Return `true` if the files differ in neither fields nor pages.
+    pub fn is_identical(&self) -> bool {
+        self.fields.is_empty()
+            && self.only_in_a.is_empty()
+            && self.only_in_b.is_empty()
+            && self.changed_pages.is_empty()
+    }
+}
+
+/// Decode the LTX files read from `a` and `b` and report their differences.
+///
+/// Pages are compared by their checksums, so the files may use different compression.
+/// Both files are fully decoded and their file checksums are verified.
+///
+/// # Example
+/// ```no_run
+/// let a = std::fs::File::open("primary.ltx").expect("open");
+/// let b = std::fs::File::open("replica.ltx").expect("open");
+/// let report = litetx::diff(a, b).expect("diff");
+/// for (page_num, _, _) in &report.changed_pages {
+///     println!("page {page_num} differs");
+/// }
+/// ```
+pub fn diff<A, B>(a: A, b: B) -> Result<DiffReport, Error>
+where
+    A: io::Read,
+    B: io::Read,
+{
+    let (mut dec_a, header_a) = Decoder::new(a)?;
+    let (mut dec_b, header_b) = Decoder::new(b)?;
+
+    let mut only_in_a = Vec::new();
+    let mut only_in_b = Vec::new();
+    let mut changed_pages = Vec::new();
+
+    // Pages are sorted by page number, so both files are walked in lockstep.
+    let mut page_a = next_page(&mut dec_a)?;
+    let mut page_b = next_page(&mut dec_b)?;
+    loop {
+        match (page_a, page_b) {
+            (None, None) => break,
+            (Some((num_a, _)), Some((num_b, _))) if num_a < num_b => {
+                only_in_a.push(num_a);
+                page_a = next_page(&mut dec_a)?;
+            }
+            (Some((num_a, _)), None) => {
+                only_in_a.push(num_a);
+                page_a = next_page(&mut dec_a)?;
+            }
+            (Some((num_a, sum_a)), Some((num_b, sum_b))) if num_a == num_b => {
+                if sum_a != sum_b {
+                    changed_pages.push((num_a, sum_a, sum_b));
+                }
+                page_a = next_page(&mut dec_a)?;
+                page_b = next_page(&mut dec_b)?;
+            }
+            (_, Some((num_b, _))) => {
+                only_in_b.push(num_b);
+                page_b = next_page(&mut dec_b)?;
+            }
+        }
+    }
+
+    let trailer_a = dec_a.finish()?;
+    let trailer_b = dec_b.finish()?;
+
+    let fields = [
+        ("flags", header_a.flags != header_b.flags),
+        ("page_size", header_a.page_size != header_b.page_size),
+        ("commit", header_a.commit != header_b.commit),
+        ("min_txid", header_a.min_txid != header_b.min_txid),
+        ("max_txid", header_a.max_txid != header_b.max_txid),
+        ("timestamp", header_a.timestamp != header_b.timestamp),
+        (
+            "pre_apply_checksum",
+            header_a.pre_apply_checksum != header_b.pre_apply_checksum,
+        ),
+        ("node_id", header_a.node_id != header_b.node_id),
+        ("wal", header_a.wal != header_b.wal),
+        ("app_data", header_a.app_data != header_b.app_data),
+        ("page_filter", header_a.page_filter != header_b.page_filter),
+        ("dict_id", header_a.dict_id != header_b.dict_id),
+        (
+            "post_apply_checksum",
+            trailer_a.post_apply_checksum != trailer_b.post_apply_checksum,
+        ),
+        (
+            "file_checksum",
+            trailer_a.file_checksum != trailer_b.file_checksum,
+        ),
+    ]
+    .into_iter()
+    .filter_map(|(field, differs)| differs.then_some(field))
+    .collect();
+
+    Ok(DiffReport {
+        fields,
+        headers: (header_a, header_b),
+        trailers: (trailer_a, trailer_b),
+        only_in_a,
+        only_in_b,
+        changed_pages,
+    })
+}
+
+/// Decode the next page and return its number and checksum.
+fn next_page<R>(dec: &mut Decoder<R>) -> Result<Option<(PageNum, Checksum)>, DecodeError>
+where
+    R: io::Read,
+{
+    Ok(dec
+        .decode_page_ref()?
+        .map(|(page_num, page)| (page_num, page.page_checksum(page_num))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff;
+    use crate::{
+        test_util::{encode_ltx, test_header},
+        Checksum, HeaderFlags, PageChecksum, PageNum,
+    };
+
+    fn encode(flags: HeaderFlags, max_txid: u64, pages: &[(u32, u8)]) -> Vec<u8> {
+        let header = test_header(flags, 2..=max_txid, 10, Some(Checksum::new(1)));
+        encode_ltx(&header, pages, Some(Checksum::new(2)))
+    }
+
+    #[test]
+    fn diff_identical() {
+        let a = encode(HeaderFlags::empty(), 2, &[(1, 1), (3, 3)]);
+        let b = encode(HeaderFlags::empty(), 2, &[(1, 1), (3, 3)]);
+
+        let report = diff(a.as_slice(), b.as_slice()).expect("failed to diff");
+        assert!(report.is_identical());
+    }
+
+    #[test]
+    fn diff_pages() {
+        let a = encode(HeaderFlags::empty(), 2, &[(1, 1), (2, 2), (4, 4), (5, 5)]);
+        let b = encode(
+            HeaderFlags::COMPRESS_LZ4,
+            3,
+            &[(2, 2), (3, 3), (4, 9), (7, 7)],
+        );
+
+        let report = diff(a.as_slice(), b.as_slice()).expect("failed to diff");
+        assert!(!report.is_identical());
+        assert_eq!(vec!["flags", "max_txid", "file_checksum"], report.fields);
+
+        let page = |n| PageNum::new(n).unwrap();
+        assert_eq!(vec![page(1), page(5)], report.only_in_a);
+        assert_eq!(vec![page(3), page(7)], report.only_in_b);
+        assert_eq!(
+            vec![(
+                page(4),
+                [4; 512].page_checksum(page(4)),
+                [9; 512].page_checksum(page(4))
+            )],
+            report.changed_pages
+        );
+    }
+}
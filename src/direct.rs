@@ -0,0 +1,384 @@
+use std::{
+    alloc::{self, Layout},
+    fmt, fs,
+    io::{self, Read, Seek},
+    ops,
+    path::Path,
+    ptr::NonNull,
+    slice,
+};
+
+/// The alignment of [`AlignedBuf`], which satisfies the `O_DIRECT` requirements of all
+/// common devices and file systems.
+pub const DIRECT_ALIGN: usize = 4096;
+
+/// The maximum number of bytes read by a single call to [`DirectFile::read_at`].
+const MAX_READ: usize = 1 << 20;
+
+/// A zeroed heap buffer whose start is aligned to [`DIRECT_ALIGN`] bytes, as unbuffered
+/// I/O requires.
+pub struct AlignedBuf {
+    ptr: NonNull<u8>,
+    len: usize,
+}
+
+// SAFETY: The buffer is uniquely owned, like a `Vec<u8>`.
+unsafe impl Send for AlignedBuf {}
+// SAFETY: The buffer is only mutated through `&mut self`.
+unsafe impl Sync for AlignedBuf {}
+
+impl AlignedBuf {
+    /// Allocate a zeroed buffer of `len` bytes.
+    pub fn new(len: usize) -> AlignedBuf {
+        let layout = Self::layout(len);
+        // SAFETY: The layout has a non-zero size.
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let Some(ptr) = NonNull::new(ptr) else {
+            alloc::handle_alloc_error(layout);
+        };
+
+        AlignedBuf { ptr, len }
+    }
+
+    fn layout(len: usize) -> Layout {
+        Layout::from_size_align(len.max(1), DIRECT_ALIGN).expect("buffer too large")
+    }
+}
+
+impl Drop for AlignedBuf {
+    fn drop(&mut self) {
+        // SAFETY: The pointer was allocated with the same layout.
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), Self::layout(self.len)) }
+    }
+}
+
+impl ops::Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        // SAFETY: The buffer holds `len` initialized bytes.
+        unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl ops::DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        // SAFETY: The buffer holds `len` initialized bytes, borrowed uniquely.
+        unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+    }
+}
+
+impl fmt::Debug for AlignedBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AlignedBuf")
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+/// A file read and written around the page cache, for restoring or snapshotting large
+/// databases without evicting everything else from the cache.
+///
+/// On Linux, the file is opened with `O_DIRECT` and all I/O goes through an internal
+/// [`AlignedBuf`]. If the file system doesn't support `O_DIRECT`, or a request is
+/// rejected because of its alignment, e.g. pages smaller than the logical block size
+/// of the device, the file falls back to buffered I/O. On other platforms, I/O is
+/// always buffered. [`DirectFile::is_direct`] tells which is in use.
+///
+/// Reads at any offset and of any length are supported, but writes bypass the cache
+/// only if their offset and length are multiples of the logical block size.
+///
+/// # Example
+/// ```no_run
+/// # let mut w = Vec::new();
+/// let db = litetx::DirectFile::open("db.sqlite").expect("open");
+/// let trailer = litetx::encode_db_snapshot(db, &mut w, Default::default())
+///     .expect("encode_db_snapshot");
+/// ```
+#[derive(Debug)]
+pub struct DirectFile {
+    file: fs::File,
+    direct: bool,
+    buf: AlignedBuf,
+    pos: u64,
+}
+
+impl DirectFile {
+    /// Open the file at `path` for reading.
+    pub fn open<P>(path: P) -> io::Result<DirectFile>
+    where
+        P: AsRef<Path>,
+    {
+        DirectFile::open_with(fs::OpenOptions::new().read(true), path)
+    }
+
+    /// Open the file at `path` with `opts`.
+    pub fn open_with<P>(opts: &fs::OpenOptions, path: P) -> io::Result<DirectFile>
+    where
+        P: AsRef<Path>,
+    {
+        let (file, direct) = open_direct(opts, path.as_ref())?;
+        Ok(DirectFile {
+            file,
+            direct,
+            buf: AlignedBuf::new(0),
+            pos: 0,
+        })
+    }
+
+    /// Return whether I/O currently bypasses the page cache.
+    pub fn is_direct(&self) -> bool {
+        self.direct
+    }
+
+    /// Return a reference to the underlying file.
+    pub fn get_ref(&self) -> &fs::File {
+        &self.file
+    }
+
+    /// Return the underlying file.
+    pub fn into_inner(self) -> fs::File {
+        self.file
+    }
+
+    /// Read up to `buf.len()` bytes at `offset` into `buf`, without moving the cursor.
+    pub fn read_at(&mut self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        if self.direct {
+            match self.read_at_direct(buf, offset) {
+                Err(e) if is_alignment_error(&e) => self.fallback()?,
+                result => return result,
+            }
+        }
+
+        read_at(&self.file, buf, offset)
+    }
+
+    /// Write all of `buf` at `offset`, without moving the cursor.
+    pub fn write_all_at(&mut self, buf: &[u8], offset: u64) -> io::Result<()> {
+        if self.direct {
+            self.reserve(buf.len());
+            self.buf[..buf.len()].copy_from_slice(buf);
+            match write_all_at(&self.file, &self.buf[..buf.len()], offset) {
+                Err(e) if is_alignment_error(&e) => self.fallback()?,
+                result => return result,
+            }
+        }
+
+        write_all_at(&self.file, buf, offset)
+    }
+
+    /// Read the aligned blocks covering the range of `buf` at `offset` into the
+    /// internal buffer, and copy the range into `buf`.
+    fn read_at_direct(&mut self, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+        let len = buf.len().min(MAX_READ);
+        let buf = &mut buf[..len];
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        let start = offset - offset % DIRECT_ALIGN as u64;
+        let skip = (offset - start) as usize;
+        let len = (skip + buf.len()).next_multiple_of(DIRECT_ALIGN);
+        self.reserve(len);
+
+        let n = read_at(&self.file, &mut self.buf[..len], start)?;
+        let n = n.saturating_sub(skip).min(buf.len());
+        buf[..n].copy_from_slice(&self.buf[skip..skip + n]);
+        Ok(n)
+    }
+
+    fn reserve(&mut self, len: usize) {
+        if self.buf.len() < len {
+            self.buf = AlignedBuf::new(len);
+        }
+    }
+
+    fn fallback(&mut self) -> io::Result<()> {
+        disable_direct(&self.file)?;
+        self.direct = false;
+        Ok(())
+    }
+}
+
+impl Read for DirectFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.read_at(buf, self.pos)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl Seek for DirectFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            io::SeekFrom::Start(pos) => Some(pos),
+            io::SeekFrom::End(delta) => self.file.metadata()?.len().checked_add_signed(delta),
+            io::SeekFrom::Current(delta) => self.pos.checked_add_signed(delta),
+        };
+
+        self.pos = pos.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative offset",
+            )
+        })?;
+        Ok(self.pos)
+    }
+}
+
+/// Open the file at `path` with `opts` and `O_DIRECT`, or without it if the file system
+/// doesn't support it. Returns whether `O_DIRECT` is set.
+#[cfg(target_os = "linux")]
+fn open_direct(opts: &fs::OpenOptions, path: &Path) -> io::Result<(fs::File, bool)> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut direct = opts.clone();
+    direct.custom_flags(libc::O_DIRECT);
+    match direct.open(path) {
+        Ok(file) => Ok((file, true)),
+        Err(e) if e.raw_os_error() == Some(libc::EINVAL) => Ok((opts.open(path)?, false)),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_direct(opts: &fs::OpenOptions, path: &Path) -> io::Result<(fs::File, bool)> {
+    Ok((opts.open(path)?, false))
+}
+
+/// Clear the `O_DIRECT` flag of `file`.
+#[cfg(target_os = "linux")]
+fn disable_direct(file: &fs::File) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let fd = file.as_raw_fd();
+    // SAFETY: The file descriptor is valid for the lifetime of `file`.
+    let rc = unsafe {
+        let flags = libc::fcntl(fd, libc::F_GETFL);
+        if flags < 0 {
+            flags
+        } else {
+            libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_DIRECT)
+        }
+    };
+    if rc < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn disable_direct(_file: &fs::File) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn is_alignment_error(err: &io::Error) -> bool {
+    err.raw_os_error() == Some(libc::EINVAL)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_alignment_error(_err: &io::Error) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn read_at(file: &fs::File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(not(unix))]
+fn read_at(mut file: &fs::File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    file.seek(io::SeekFrom::Start(offset))?;
+    file.read(buf)
+}
+
+/// Read exactly `buf.len()` bytes at `offset` of `file`.
+pub(crate) fn read_exact_at(
+    file: &fs::File,
+    mut buf: &mut [u8],
+    mut offset: u64,
+) -> io::Result<()> {
+    while !buf.is_empty() {
+        match read_at(file, buf, offset) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => {
+                buf = &mut buf[n..];
+                offset += n as u64;
+            }
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+pub(crate) fn write_all_at(file: &fs::File, buf: &[u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, buf, offset)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn write_all_at(mut file: &fs::File, buf: &[u8], offset: u64) -> io::Result<()> {
+    use std::io::Write;
+
+    file.seek(io::SeekFrom::Start(offset))?;
+    file.write_all(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AlignedBuf, DirectFile, DIRECT_ALIGN};
+    use crate::utils::TempDir;
+    use std::{
+        fs,
+        io::{Read, Seek, SeekFrom},
+    };
+
+    #[test]
+    fn aligned_buf() {
+        for len in [0, 1, 512, 4096, 10000] {
+            let mut buf = AlignedBuf::new(len);
+            assert_eq!(0, buf.as_ptr() as usize % DIRECT_ALIGN);
+            assert_eq!(vec![0; len], &buf[..]);
+            buf.fill(1);
+            assert_eq!(vec![1; len], &buf[..]);
+        }
+    }
+
+    #[test]
+    fn direct_file() {
+        let dir = TempDir::new();
+        let path = dir.path().join("db");
+        let mut opts = fs::OpenOptions::new();
+        opts.read(true).write(true).create(true).truncate(false);
+        let mut f = DirectFile::open_with(&opts, &path).expect("failed to open file");
+
+        let data: Vec<u8> = (0..3 * 4096).map(|_| rand::random()).collect();
+        for (i, block) in data.chunks(4096).enumerate() {
+            f.write_all_at(block, i as u64 * 4096)
+                .expect("failed to write block");
+        }
+        assert_eq!(data, fs::read(&path).unwrap());
+
+        // Unaligned reads, and a read past the end.
+        let mut buf = vec![0; 5000];
+        assert_eq!(5000, f.read_at(&mut buf, 100).unwrap());
+        assert_eq!(&data[100..5100], &buf);
+        assert_eq!(12, f.read_at(&mut buf, data.len() as u64 - 12).unwrap());
+        assert_eq!(0, f.read_at(&mut buf, data.len() as u64).unwrap());
+
+        f.seek(SeekFrom::Start(7)).unwrap();
+        let mut read = Vec::new();
+        f.read_to_end(&mut read).unwrap();
+        assert_eq!(&data[7..], &read);
+        assert_eq!(data.len() as u64, f.stream_position().unwrap());
+
+        // Unaligned writes fall back to buffered I/O if `O_DIRECT` rejects them.
+        f.write_all_at(&[9; 10], 3).expect("failed to write");
+        let mut expected = data;
+        expected[3..13].fill(9);
+        assert_eq!(expected, fs::read(&path).unwrap());
+    }
+}
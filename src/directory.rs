@@ -0,0 +1,268 @@
+use crate::{
+    ltx::{HeaderDecodeError, TrailerDecodeError},
+    name, Header, HeaderFlags, Pos, Trailer, TXID,
+};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// An error that can be returned by [`LtxDirectory`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("io")]
+    Io(#[from] io::Error),
+    #[error("file {0}")]
+    Name(PathBuf, #[source] name::Error),
+    #[error("file {0}: header")]
+    Header(PathBuf, #[source] HeaderDecodeError),
+    #[error("file {0}: trailer")]
+    Trailer(PathBuf, #[source] TrailerDecodeError),
+    #[error("gap between transaction ids: ({0}, {1})")]
+    Gap(TXID, TXID),
+    #[error("overlapping transaction ids: ({0}, {1})")]
+    Overlap(TXID, TXID),
+    #[error(
+        "post-apply checksum of transaction {0} doesn't match pre-apply checksum of the next file"
+    )]
+    ChecksumMismatch(TXID),
+}
+
+/// An LTX file found in an [`LtxDirectory`].
+#[derive(Debug)]
+pub struct LtxFile {
+    /// Path of the file.
+    pub path: PathBuf,
+    /// Header of the file.
+    pub header: Header,
+    /// Trailer of the file. The file checksum is not verified.
+    pub trailer: Trailer,
+}
+
+impl LtxFile {
+    fn open(path: PathBuf) -> Result<LtxFile, Error> {
+        let mut file = fs::File::open(&path)?;
+        let header = Header::decode_from(&mut file).map_err(|e| Error::Header(path.clone(), e))?;
+        let trailer = Trailer::read_from_end(&mut file)
+            .and_then(|trailer| trailer.validate(header.flags).map(|_| trailer))
+            .map_err(|e| Error::Trailer(path.clone(), e))?;
+
+        // The file name was already parsed, so only the match with the header is left.
+        let file_name = path.file_name().unwrap().to_string_lossy();
+        name::validate_filename(&file_name, &header).map_err(|e| Error::Name(path.clone(), e))?;
+
+        Ok(LtxFile {
+            path,
+            header,
+            trailer,
+        })
+    }
+
+    /// Return the database position after this file is applied. `None` if the file has
+    /// no checksums.
+    pub fn pos(&self) -> Option<Pos> {
+        Some(Pos {
+            txid: self.header.max_txid,
+            post_apply_checksum: self.trailer.post_apply_checksum?,
+        })
+    }
+}
+
+/// A directory of LTX files ordered by transaction ID.
+///
+/// Only headers and trailers of the files are read, so scanning is cheap even for large
+/// files. Files not named according to the [`name`] convention are ignored.
+///
+/// # Example
+/// ```no_run
+/// let dir = litetx::LtxDirectory::open("/var/lib/ltx").expect("open");
+/// dir.validate().expect("validate");
+/// println!("{:?}", dir.pos());
+/// ```
+#[derive(Debug)]
+pub struct LtxDirectory {
+    files: Vec<LtxFile>,
+}
+
+impl LtxDirectory {
+    /// Scan the directory at `path` for LTX files.
+    pub fn open<P>(path: P) -> Result<LtxDirectory, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(path)? {
+            let entry = entry?;
+            let file_name = entry.file_name();
+            let Some(file_name) = file_name.to_str() else {
+                continue;
+            };
+            if name::parse_filename(file_name).is_err() || !entry.file_type()?.is_file() {
+                continue;
+            }
+
+            files.push(LtxFile::open(entry.path())?);
+        }
+
+        files.sort_by_key(|f| (f.header.min_txid, f.header.max_txid));
+
+        Ok(LtxDirectory { files })
+    }
+
+    /// Return the files ordered by transaction ID.
+    pub fn files(&self) -> &[LtxFile] {
+        &self.files
+    }
+
+    /// Return the database position after all the files are applied.
+    pub fn pos(&self) -> Option<Pos> {
+        self.files.last().and_then(LtxFile::pos)
+    }
+
+    /// Check that the files form a contiguous sequence of transactions.
+    ///
+    /// Every file must start at the transaction following the last transaction of the
+    /// previous file, and its pre-apply checksum must match the post-apply checksum of
+    /// the previous file unless it has the [`HeaderFlags::NO_CHECKSUM`] flag set.
+    pub fn validate(&self) -> Result<(), Error> {
+        for pair in self.files.windows(2) {
+            let (prev, next) = (&pair[0], &pair[1]);
+            let (prev_max, next_min) = (prev.header.max_txid, next.header.min_txid);
+
+            if next_min <= prev_max {
+                return Err(Error::Overlap(prev_max, next_min));
+            }
+            if prev_max.checked_add(1) != Some(next_min) {
+                return Err(Error::Gap(prev_max, next_min));
+            }
+            if !next.header.flags.contains(HeaderFlags::NO_CHECKSUM)
+                && next.header.pre_apply_checksum != prev.trailer.post_apply_checksum
+            {
+                return Err(Error::ChecksumMismatch(prev_max));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, LtxDirectory};
+    use crate::{
+        name, utils::TempDir, Checksum, Encoder, Header, HeaderFlags, PageNum, PageSize, Pos, TXID,
+    };
+    use std::{fs, time};
+
+    impl TempDir {
+        fn write_file(&self, min_txid: u64, max_txid: u64, pre: Option<u64>, post: u64) {
+            let (min_txid, max_txid) = (TXID::new(min_txid).unwrap(), TXID::new(max_txid).unwrap());
+            let path = self.path().join(name::format_filename(min_txid, max_txid));
+            let mut enc = Encoder::new(
+                fs::File::create(path).expect("failed to create file"),
+                &Header {
+                    flags: HeaderFlags::COMPRESS_LZ4,
+                    page_size: PageSize::new(512).unwrap(),
+                    commit: Some(PageNum::new(1).unwrap()),
+                    min_txid,
+                    max_txid,
+                    timestamp: time::SystemTime::now(),
+                    pre_apply_checksum: pre.map(Checksum::new),
+                    node_id: 0,
+                    wal: None,
+                    app_data: None,
+                    page_filter: None,
+                    key_id: None,
+                    dict_id: None,
+                },
+            )
+            .expect("failed to create encoder");
+            enc.encode_page(PageNum::ONE, &[0; 512])
+                .expect("failed to encode page");
+            enc.finish(Checksum::new(post))
+                .expect("failed to finish encoder");
+        }
+    }
+
+    #[test]
+    fn directory() {
+        let dir = TempDir::new();
+        dir.write_file(4, 4, Some(2), 3);
+        dir.write_file(1, 1, None, 1);
+        dir.write_file(2, 3, Some(1), 2);
+        fs::write(dir.path().join("notes.txt"), "not an LTX file").unwrap();
+
+        let ltx_dir = LtxDirectory::open(dir.path()).expect("failed to open directory");
+        let ranges: Vec<_> = ltx_dir
+            .files()
+            .iter()
+            .map(|f| {
+                (
+                    f.header.min_txid.into_inner(),
+                    f.header.max_txid.into_inner(),
+                )
+            })
+            .collect();
+        assert_eq!(vec![(1, 1), (2, 3), (4, 4)], ranges);
+        assert_eq!(
+            Some(Pos {
+                txid: TXID::new(4).unwrap(),
+                post_apply_checksum: Checksum::new(3),
+            }),
+            ltx_dir.pos()
+        );
+        ltx_dir.validate().expect("failed to validate directory");
+    }
+
+    #[test]
+    fn directory_gap() {
+        let dir = TempDir::new();
+        dir.write_file(1, 1, None, 1);
+        dir.write_file(3, 3, Some(1), 2);
+
+        let ltx_dir = LtxDirectory::open(dir.path()).expect("failed to open directory");
+        assert!(matches!(
+            ltx_dir.validate(),
+            Err(Error::Gap(a, b)) if a == TXID::ONE && b == TXID::new(3).unwrap()
+        ));
+    }
+
+    #[test]
+    fn directory_overlap() {
+        let dir = TempDir::new();
+        dir.write_file(1, 2, None, 1);
+        dir.write_file(2, 3, Some(1), 2);
+
+        let ltx_dir = LtxDirectory::open(dir.path()).expect("failed to open directory");
+        assert!(matches!(ltx_dir.validate(), Err(Error::Overlap(_, _))));
+    }
+
+    #[test]
+    fn directory_checksum_mismatch() {
+        let dir = TempDir::new();
+        dir.write_file(1, 1, None, 1);
+        dir.write_file(2, 2, Some(5), 2);
+
+        let ltx_dir = LtxDirectory::open(dir.path()).expect("failed to open directory");
+        assert!(matches!(
+            ltx_dir.validate(),
+            Err(Error::ChecksumMismatch(txid)) if txid == TXID::ONE
+        ));
+    }
+
+    #[test]
+    fn directory_name_mismatch() {
+        let dir = TempDir::new();
+        dir.write_file(1, 1, None, 1);
+        fs::rename(
+            dir.path().join("0000000000000001-0000000000000001.ltx"),
+            dir.path().join("0000000000000001-0000000000000002.ltx"),
+        )
+        .unwrap();
+
+        assert!(matches!(
+            LtxDirectory::open(dir.path()),
+            Err(Error::Name(_, name::Error::HeaderMismatch(_, _)))
+        ));
+    }
+}
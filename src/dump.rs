@@ -0,0 +1,260 @@
+use crate::{
+    decoder::Error as DecodeError,
+    ltx::{DICT_ID_SIZE, HEADER_SIZE, PAGE_CHECKSUM_SIZE, PAGE_FILTER_SIZE, PAGE_HEADER_SIZE},
+    Checksum, Decoder, Header, HeaderFlags, PageChecksum, Trailer,
+};
+use std::io;
+
+/// An error that can be returned by [`dump`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("decode")]
+    Decode(#[from] DecodeError),
+    #[error("write")]
+    Write(#[from] io::Error),
+}
+
+/// The output format of [`dump`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpFormat {
+    /// A JSON object with `header`, `pages` and `trailer` fields.
+    Json,
+    /// Human readable text with the pages in a table.
+    Text,
+}
+
+/// Decode the LTX file read from `r` and write a description of it into `w`.
+///
+/// The output lists the header, the number and checksum of every page along with its
+/// offset, and the trailer. Offsets refer to the uncompressed file, so they only match
+/// the file offsets of uncompressed files. Pages are written as they are decoded, and
+/// the file checksum is verified at the end.
+///
+/// # Example
+/// ```no_run
+/// # let v = Vec::new();
+/// # let r = &v[..];
+/// litetx::dump(r, std::io::stdout(), litetx::DumpFormat::Json).expect("dump");
+/// ```
+pub fn dump<R, W>(r: R, mut w: W, format: DumpFormat) -> Result<Trailer, Error>
+where
+    R: io::Read,
+    W: io::Write,
+{
+    let (mut dec, header) = Decoder::new(r)?;
+    let mut frame_size = PAGE_HEADER_SIZE as u64 + header.page_size.into_inner() as u64;
+    if header.flags.contains(HeaderFlags::PAGE_CHECKSUM) {
+        frame_size += PAGE_CHECKSUM_SIZE as u64;
+    }
+
+    match format {
+        DumpFormat::Json => write_json_header(&mut w, &header)?,
+        DumpFormat::Text => write_text_header(&mut w, &header)?,
+    }
+
+    let mut offset = HEADER_SIZE as u64;
+    if header.page_filter.is_some() {
+        offset += PAGE_FILTER_SIZE as u64;
+    }
+    if header.dict_id.is_some() {
+        offset += DICT_ID_SIZE as u64;
+    }
+    let mut first = true;
+    while let Some((page_num, page)) = dec.decode_page_ref()? {
+        let checksum = page.page_checksum(page_num);
+        match format {
+            DumpFormat::Json => {
+                let sep = if first { "" } else { "," };
+                write!(
+                    w,
+                    "{sep}\n    {{\"pgno\": {page_num}, \"offset\": {offset}, \"checksum\": \"{checksum}\"}}"
+                )?;
+            }
+            DumpFormat::Text => {
+                writeln!(w, "{:<10} {offset:<12} {checksum}", page_num.into_inner())?
+            }
+        }
+
+        offset += frame_size;
+        first = false;
+    }
+
+    let trailer = dec.finish()?;
+    match format {
+        DumpFormat::Json => {
+            let sep = if first { "" } else { "\n  " };
+            writeln!(w, "{sep}],")?;
+            writeln!(w, "  \"trailer\": {{")?;
+            writeln!(
+                w,
+                "    \"postApplyChecksum\": {},",
+                json_checksum(trailer.post_apply_checksum)
+            )?;
+            writeln!(w, "    \"fileChecksum\": \"{}\"", trailer.file_checksum)?;
+            writeln!(w, "  }}")?;
+            writeln!(w, "}}")?;
+        }
+        DumpFormat::Text => {
+            writeln!(w)?;
+            writeln!(
+                w,
+                "post-apply checksum: {}",
+                text_checksum(trailer.post_apply_checksum)
+            )?;
+            writeln!(w, "file checksum:       {}", trailer.file_checksum)?;
+        }
+    }
+
+    Ok(trailer)
+}
+
+fn json_checksum(checksum: Option<Checksum>) -> String {
+    checksum.map_or("null".to_string(), |c| format!("\"{c}\""))
+}
+
+fn text_checksum(checksum: Option<Checksum>) -> String {
+    checksum.map_or("-".to_string(), |c| c.to_string())
+}
+
+fn write_json_header<W>(w: &mut W, header: &Header) -> io::Result<()>
+where
+    W: io::Write,
+{
+    let commit = header.commit.map_or("null".to_string(), |c| c.to_string());
+    let wal = header.wal.map_or("null".to_string(), |wal| {
+        format!(
+            "{{\"offset\": {}, \"size\": {}, \"salt1\": {}, \"salt2\": {}}}",
+            wal.offset, wal.size, wal.salt1, wal.salt2
+        )
+    });
+
+    // Flags are named as in the serde representation of the header.
+    let mut flags = String::new();
+    bitflags::parser::to_writer(&header.flags, &mut flags).map_err(io::Error::other)?;
+
+    writeln!(w, "{{")?;
+    writeln!(w, "  \"header\": {{")?;
+    writeln!(w, "    \"flags\": \"{flags}\",")?;
+    writeln!(w, "    \"pageSize\": {},", header.page_size)?;
+    writeln!(w, "    \"commit\": {commit},")?;
+    writeln!(w, "    \"minTXID\": \"{}\",", header.min_txid)?;
+    writeln!(w, "    \"maxTXID\": \"{}\",", header.max_txid)?;
+    writeln!(
+        w,
+        "    \"timestamp\": {},",
+        header.timestamp_millis().unwrap_or(0)
+    )?;
+    writeln!(
+        w,
+        "    \"preApplyChecksum\": {},",
+        json_checksum(header.pre_apply_checksum)
+    )?;
+    writeln!(w, "    \"nodeID\": {},", header.node_id)?;
+    writeln!(w, "    \"wal\": {wal}")?;
+    writeln!(w, "  }},")?;
+    write!(w, "  \"pages\": [")
+}
+
+fn write_text_header<W>(w: &mut W, header: &Header) -> io::Result<()>
+where
+    W: io::Write,
+{
+    let commit = header
+        .commit
+        .map_or("deleted".to_string(), |c| c.to_string());
+
+    writeln!(w, "flags:               {:?}", header.flags)?;
+    writeln!(w, "page size:           {}", header.page_size)?;
+    writeln!(w, "commit:              {commit}")?;
+    writeln!(w, "min txid:            {}", header.min_txid)?;
+    writeln!(w, "max txid:            {}", header.max_txid)?;
+    writeln!(
+        w,
+        "timestamp:           {} ms",
+        header.timestamp_millis().unwrap_or(0)
+    )?;
+    writeln!(
+        w,
+        "pre-apply checksum:  {}",
+        text_checksum(header.pre_apply_checksum)
+    )?;
+    writeln!(w, "node id:             {}", header.node_id)?;
+    if let Some(wal) = header.wal {
+        writeln!(
+            w,
+            "wal:                 offset {}, size {}, salt {:08x}/{:08x}",
+            wal.offset, wal.size, wal.salt1, wal.salt2
+        )?;
+    }
+    writeln!(w)?;
+    writeln!(w, "{:<10} {:<12} CHECKSUM", "PGNO", "OFFSET")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dump, DumpFormat};
+    use crate::{
+        test_util::{encode_ltx, test_header},
+        Checksum, Header, HeaderFlags, PageChecksum, PageNum,
+    };
+    use std::time;
+
+    fn encode_file() -> Vec<u8> {
+        let header = Header {
+            timestamp: time::UNIX_EPOCH + time::Duration::from_millis(1000),
+            ..test_header(HeaderFlags::empty(), 2..=2, 2, Some(Checksum::new(1)))
+        };
+        encode_ltx(&header, &[(1, 1), (2, 2)], Some(Checksum::new(2)))
+    }
+
+    #[test]
+    fn dump_json() {
+        let file = encode_file();
+        let mut out = Vec::new();
+        let trailer = dump(&file[..], &mut out, DumpFormat::Json).expect("failed to dump");
+
+        let checksum = |n: u32| [n as u8; 512].page_checksum(PageNum::new(n).unwrap());
+        let expected = format!(
+            r#"{{
+  "header": {{
+    "flags": "",
+    "pageSize": 512,
+    "commit": 2,
+    "minTXID": "0000000000000002",
+    "maxTXID": "0000000000000002",
+    "timestamp": 1000,
+    "preApplyChecksum": "{}",
+    "nodeID": 0,
+    "wal": null
+  }},
+  "pages": [
+    {{"pgno": 1, "offset": 100, "checksum": "{}"}},
+    {{"pgno": 2, "offset": 616, "checksum": "{}"}}
+  ],
+  "trailer": {{
+    "postApplyChecksum": "{}",
+    "fileChecksum": "{}"
+  }}
+}}
+"#,
+            Checksum::new(1),
+            checksum(1),
+            checksum(2),
+            Checksum::new(2),
+            trailer.file_checksum
+        );
+        assert_eq!(expected, String::from_utf8(out).unwrap());
+    }
+
+    #[test]
+    fn dump_text() {
+        let file = encode_file();
+        let mut out = Vec::new();
+        dump(&file[..], &mut out, DumpFormat::Text).expect("failed to dump");
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("PGNO       OFFSET       CHECKSUM\n1          100          "));
+        assert!(out.contains("\n2          616          "));
+        assert!(out.contains(&format!("post-apply checksum: {}\n", Checksum::new(2))));
+    }
+}
@@ -1,9 +1,29 @@
+#[cfg(feature = "encryption")]
+use crate::crypto::{EncryptWriter, KeyProvider};
 use crate::{
-    ltx::{HeaderEncodeError, PageHeader, PageHeaderEncodeError, TrailerEncodeError, CRC64},
-    Checksum, Header, HeaderFlags, PageNum, PageSize, Trailer,
+    compression::{self, CompressWriter},
+    dictionary::{self, Dictionary},
+    ltx::{
+        Crc64Digest, HeaderEncodeError, PageHeader, PageHeaderEncodeError, PageIndex,
+        PageIndexEncodeError, TrailerEncodeError, CRC64, HEADER_SIZE,
+    },
+    lz4::{FrameDescriptor, ParallelFrameEncoder},
+    merkle::MerkleTree,
+    progress::{self, ProgressEvent, ProgressFn, Stats, Stopwatch},
+    Checksum, DatabaseChecksum, Header, HeaderFlags, PageChecksum, PageFilter, PageNum, PageSize,
+    Pos, Trailer, TXID,
 };
 use lz4_flex::frame::{BlockSize, FrameEncoder, FrameInfo};
-use std::io::{self, Write};
+use std::{
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time,
+};
+
+const DEFAULT_COMPRESSION_LEVEL: i32 = 3;
 
 /// An error that can be returned by [`Encoder`].
 #[derive(thiserror::Error, Debug)]
@@ -14,16 +34,44 @@ pub enum Error {
     PageHeader(#[from] PageHeaderEncodeError),
     #[error("trailer")]
     Trailer(#[from] TrailerEncodeError),
+    #[error("page index")]
+    PageIndex(#[from] PageIndexEncodeError),
     #[error("cannot encode lock page: {0}")]
     LockPage(PageNum),
+    #[error("cannot encode page {0} into a file deleting the database")]
+    DeletedDatabase(PageNum),
     #[error("snapshot transaction file must start with page number 1")]
     FirstSnapshotPage,
     #[error("nonsequential page numbers in snapshot transaction: {0}, {1}")]
     NonsequentialPages(PageNum, PageNum),
     #[error("out-of-order page numbers: {0}, {1}")]
     OutOfOrderPage(PageNum, PageNum),
+    #[error("page {0} missing from the page filter")]
+    PageNotInFilter(PageNum),
+    #[error("incomplete snapshot: last page {0:?}, expected {1}")]
+    IncompleteSnapshot(Option<PageNum>, PageNum),
     #[error("invalid page buffer size: {0}, expected {1}")]
     InvalidBufferSize(usize, PageSize),
+    #[error("unsupported header flags: {0:?}")]
+    UnsupportedFlags(HeaderFlags),
+    #[error("encryption key required for encrypted files")]
+    KeyRequired,
+    #[error("encryption key given for unencrypted file")]
+    UnexpectedKey,
+    #[error("key id required to encrypt with a key provider")]
+    NoKeyId,
+    #[error("unknown encryption key: {0}")]
+    UnknownKey(u32),
+    #[error("unknown dictionary: {0}")]
+    UnknownDictionary(u32),
+    #[error("post-apply checksum required on files with checksums")]
+    NoPostApplyChecksum,
+    #[error("encoding cancelled")]
+    Cancelled,
+    #[error("no transaction ID after {0}")]
+    TXIDOverflow(TXID),
+    #[error("post-apply checksum can only be computed for snapshots")]
+    NotSnapshot,
     #[error("write")]
     Write(#[from] io::Error),
 }
@@ -32,44 +80,261 @@ impl From<Error> for io::Error {
     fn from(e: Error) -> Self {
         match e {
             Error::Write(ioe) => ioe,
-            _ => io::Error::new(io::ErrorKind::Other, e),
+            _ => io::Error::other(e),
+        }
+    }
+}
+
+/// The maximum size of LZ4 blocks. See [`EncoderBuilder::block_size`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Lz4BlockSize {
+    /// 64 KB blocks.
+    #[default]
+    Max64KB,
+    /// 256 KB blocks.
+    Max256KB,
+    /// 1 MB blocks.
+    Max1MB,
+    /// 4 MB blocks.
+    Max4MB,
+}
+
+impl From<Lz4BlockSize> for BlockSize {
+    fn from(size: Lz4BlockSize) -> Self {
+        match size {
+            Lz4BlockSize::Max64KB => BlockSize::Max64KB,
+            Lz4BlockSize::Max256KB => BlockSize::Max256KB,
+            Lz4BlockSize::Max1MB => BlockSize::Max1MB,
+            Lz4BlockSize::Max4MB => BlockSize::Max4MB,
+        }
+    }
+}
+
+impl Lz4BlockSize {
+    fn size(self) -> usize {
+        match self {
+            Lz4BlockSize::Max64KB => 64 * 1024,
+            Lz4BlockSize::Max256KB => 256 * 1024,
+            Lz4BlockSize::Max1MB => 1024 * 1024,
+            Lz4BlockSize::Max4MB => 4 * 1024 * 1024,
+        }
+    }
+}
+
+/// A builder of [`Encoder`] with compression tuning options.
+///
+/// # Example
+/// ```
+/// # use std::time::SystemTime;
+/// # let mut w = Vec::new();
+/// # let header = litetx::Header{
+/// #     flags: litetx::HeaderFlags::COMPRESS_LZ4,
+/// #     page_size: litetx::PageSize::new(65536).unwrap(),
+/// #     commit: Some(litetx::PageNum::new(1).unwrap()),
+/// #     min_txid: litetx::TXID::ONE,
+/// #     max_txid: litetx::TXID::ONE,
+/// #     timestamp: SystemTime::now(),
+/// #     pre_apply_checksum: None,
+/// #     node_id: 0,
+/// #     wal: None,
+/// #     app_data: None,
+/// #     page_filter: None,
+/// #     key_id: None,
+/// #     dict_id: None,
+/// # };
+/// let mut enc = litetx::Encoder::builder()
+///     .block_size(litetx::Lz4BlockSize::Max256KB)
+///     .block_checksum(true)
+///     .build(&mut w, &header)
+///     .expect("encoder");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderBuilder {
+    block_size: Lz4BlockSize,
+    block_checksum: bool,
+    content_checksum: bool,
+    compression_level: i32,
+    threads: usize,
+    page_filter: Option<PageFilter>,
+}
+
+impl Default for EncoderBuilder {
+    fn default() -> Self {
+        EncoderBuilder {
+            block_size: Lz4BlockSize::default(),
+            block_checksum: false,
+            content_checksum: false,
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
+            threads: 1,
+            page_filter: None,
         }
     }
 }
 
+impl EncoderBuilder {
+    /// Set the maximum size of LZ4 blocks.
+    ///
+    /// Blocks larger than the page size keep pages of large-page databases from being
+    /// split across blocks, at the cost of more memory.
+    pub fn block_size(mut self, block_size: Lz4BlockSize) -> Self {
+        self.block_size = block_size;
+        self
+    }
+
+    /// Enable checksums of individual LZ4 blocks.
+    pub fn block_checksum(mut self, enabled: bool) -> Self {
+        self.block_checksum = enabled;
+        self
+    }
+
+    /// Enable the checksum of the uncompressed contents of LZ4 and Zstd frames.
+    pub fn content_checksum(mut self, enabled: bool) -> Self {
+        self.content_checksum = enabled;
+        self
+    }
+
+    /// Set the Zstd compression level. LZ4 compression has no levels.
+    pub fn compression_level(mut self, level: i32) -> Self {
+        self.compression_level = level;
+        self
+    }
+
+    /// Set the number of threads compressing LZ4 blocks, one by default.
+    ///
+    /// With several threads, batches of independent blocks are compressed on scoped
+    /// worker threads and written in order. The output is the same LZ4 frame format,
+    /// and the file checksum doesn't depend on the compressed bytes.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Write the [`PageFilter`] of the pages that will be encoded after the header,
+    /// setting the [`HeaderFlags::PAGE_FILTER`] flag.
+    ///
+    /// The filter must be built from the page numbers up front since the header is
+    /// written first. Pages missing from it fail with [`Error::PageNotInFilter`].
+    pub fn page_filter(mut self, filter: PageFilter) -> Self {
+        self.page_filter = Some(filter);
+        self
+    }
+
+    /// Create a new [`Encoder`] that writes to `w`.
+    ///
+    /// See [`Encoder::new`] for details.
+    pub fn build<'a, W>(&self, w: W, hdr: &Header) -> Result<Encoder<'a, W>, Error>
+    where
+        W: io::Write,
+    {
+        Encoder::with_options(w, hdr, None, self)
+    }
+
+    /// Create a new [`Encoder`] that writes to `w` and encrypts the page block with `key`.
+    ///
+    /// See [`Encoder::new_encrypted`] for details.
+    #[cfg(feature = "encryption")]
+    pub fn build_encrypted<'a, W>(
+        &self,
+        w: W,
+        hdr: &Header,
+        key: &[u8; 32],
+    ) -> Result<Encoder<'a, W>, Error>
+    where
+        W: io::Write,
+    {
+        Encoder::with_options(w, hdr, Some(key), self)
+    }
+
+    /// Create a new [`Encoder`] that writes to `w` and encrypts the page block with the
+    /// key of `keys` identified by the key ID of `hdr`.
+    ///
+    /// See [`Encoder::new_with_keys`] for details.
+    #[cfg(feature = "encryption")]
+    pub fn build_with_keys<'a, W>(
+        &self,
+        w: W,
+        hdr: &Header,
+        keys: &dyn KeyProvider,
+    ) -> Result<Encoder<'a, W>, Error>
+    where
+        W: io::Write,
+    {
+        let key_id = hdr.key_id.ok_or(Error::NoKeyId)?;
+        let key = keys.key(key_id).ok_or(Error::UnknownKey(key_id))?;
+        Encoder::with_options(w, hdr, Some(&key), self)
+    }
+}
+
 /// An LTX file encoder.
 ///
+/// The header, data and checksum of uncompressed pages are passed to `w` in a single
+/// [`io::Write::write_vectored`] call where the writer supports it, so encoding into an
+/// unbuffered file or socket costs one system call per page. Compressed files are
+/// buffered by the compressor.
+///
 /// # Example
 /// ```
 /// # use std::time::SystemTime;
-/// # use litetx::PageChecksum;
 /// # let mut w = Vec::new();
 /// # let page = vec![0; 4096];
 /// #
 /// let mut enc = litetx::Encoder::new(&mut w, &litetx::Header{
 ///     flags: litetx::HeaderFlags::empty(),
 ///     page_size: litetx::PageSize::new(4096).unwrap(),
-///     commit: litetx::PageNum::new(1).unwrap(),
+///     commit: Some(litetx::PageNum::new(1).unwrap()),
 ///     min_txid: litetx::TXID::ONE,
 ///     max_txid: litetx::TXID::ONE,
 ///     timestamp: SystemTime::now(),
 ///     pre_apply_checksum: None,
+///     node_id: 0,
+///     wal: None,
+///     app_data: None,
+///     page_filter: None,
+///     key_id: None,
+///     dict_id: None,
 /// }).expect("encoder");
 ///
-/// let page_num = litetx::PageNum::new(1).unwrap();
-/// enc.encode_page(page_num, &page).expect("encode_page");
+/// enc.encode_page(litetx::PageNum::new(1).unwrap(), &page).expect("encode_page");
 ///
-/// enc.finish(page.page_checksum(page_num)).expect("finish");
+/// // The post-apply checksum of snapshots is computed from the encoded pages.
+/// enc.finish_auto().expect("finish");
 /// ```
 pub struct Encoder<'a, W>
 where
     W: io::Write,
 {
-    w: LTXWriter<W>,
-    digest: crc::Digest<'a, u64>,
+    w: LTXWriter<Output<CountWrite<W>>>,
+    digest: Crc64Digest<'a>,
     page_size: PageSize,
+    commit: Option<PageNum>,
     is_snapshot: bool,
+    incomplete_snapshot: bool,
+    no_checksum: bool,
     last_page_num: Option<PageNum>,
+    index: Option<PageIndex>,
+    tree: Option<MerkleTree>,
+    page_checksums: bool,
+    page_filter: Option<PageFilter>,
+    page_compressor: Option<PageCompressor>,
+    /// The page and its checksum, reused across pages compressed one by one.
+    scratch: Vec<u8>,
+    checksum: Option<DatabaseChecksum>,
+    pages: u64,
+    progress: Option<ProgressFn<'a>>,
+    cancel: Option<Arc<AtomicBool>>,
+    started: Stopwatch,
+    #[cfg(feature = "tracing")]
+    span: tracing::Span,
+}
+
+// The builder doesn't depend on the writer type, which is only known in
+// `EncoderBuilder::build`. A concrete type keeps `Encoder::builder()` from needing
+// type annotations.
+impl Encoder<'static, io::Sink> {
+    /// Return a builder of [`Encoder`] with compression tuning options.
+    pub fn builder() -> EncoderBuilder {
+        EncoderBuilder::default()
+    }
 }
 
 impl<'a, W> Encoder<'a, W>
@@ -79,34 +344,299 @@ where
     /// Create a new [`Encoder`] that writes to `w`.
     ///
     /// Depending on the `hdr` flags, the [`Encoder`] will produce either compressed or
-    /// uncompressed LTX file.
-    pub fn new(mut w: W, hdr: &Header) -> Result<Encoder<'a, W>, Error> {
+    /// uncompressed LTX file. Zstd compression requires the `zstd` feature.
+    ///
+    /// Use [`Encoder::new_encrypted`] for headers with the [`HeaderFlags::ENCRYPTED`] flag.
+    ///
+    /// If [`HeaderFlags::PAGE_INDEX`] is set, the offsets of all pages are written into
+    /// the file, allowing [`Decoder::seek_page`](crate::Decoder::seek_page) to read pages
+    /// at random. Compressed pages are then put into separate LZ4 frames. The page index
+    /// can't be combined with Zstd compression or encryption.
+    pub fn new(w: W, hdr: &Header) -> Result<Encoder<'a, W>, Error> {
+        EncoderBuilder::default().build(w, hdr)
+    }
+
+    /// Create a new [`Encoder`] that writes to `w` and encrypts the page block with `key`.
+    ///
+    /// The `hdr` must have the [`HeaderFlags::ENCRYPTED`] flag set. Pages are encrypted
+    /// with ChaCha20-Poly1305 after compression, while the header and the trailer are
+    /// written in plain text.
+    #[cfg(feature = "encryption")]
+    pub fn new_encrypted(w: W, hdr: &Header, key: &[u8; 32]) -> Result<Encoder<'a, W>, Error> {
+        EncoderBuilder::default().build_encrypted(w, hdr, key)
+    }
+
+    /// Create a new [`Encoder`] that writes to `w` and encrypts the page block with the
+    /// key of `keys` identified by [`Header::key_id`].
+    ///
+    /// Like [`Encoder::new_encrypted`], but the key ID is recorded in the header so the
+    /// file can be decrypted with [`Decoder::new_with_keys`](crate::Decoder::new_with_keys)
+    /// after newer files moved on to another key. Fails with [`Error::NoKeyId`] if the
+    /// header has no key ID.
+    #[cfg(feature = "encryption")]
+    pub fn new_with_keys(
+        w: W,
+        hdr: &Header,
+        keys: &dyn KeyProvider,
+    ) -> Result<Encoder<'a, W>, Error> {
+        EncoderBuilder::default().build_with_keys(w, hdr, keys)
+    }
+
+    /// Create a new [`Encoder`] for a delta file applied on top of the database at
+    /// position `prev` and containing transactions up to `max_txid`.
+    ///
+    /// The minimum transaction ID and the pre-apply checksum of the header are derived
+    /// from `prev`. The timestamp is set to the current time.
+    ///
+    /// # Example
+    /// ```
+    /// # let mut w = Vec::new();
+    /// let prev: litetx::Pos = "0000000000000005/8000000000000123".parse().unwrap();
+    /// let enc = litetx::Encoder::for_delta(
+    ///     &mut w,
+    ///     prev,
+    ///     litetx::TXID::new(6).unwrap(),
+    ///     litetx::PageNum::new(10).ok(),
+    ///     litetx::PageSize::new(4096).unwrap(),
+    ///     litetx::HeaderFlags::COMPRESS_LZ4,
+    /// )
+    /// .expect("encoder");
+    /// ```
+    pub fn for_delta(
+        w: W,
+        prev: Pos,
+        max_txid: TXID,
+        commit: Option<PageNum>,
+        page_size: PageSize,
+        flags: HeaderFlags,
+    ) -> Result<Encoder<'a, W>, Error> {
+        let min_txid = prev
+            .txid
+            .checked_add(1)
+            .ok_or(Error::TXIDOverflow(prev.txid))?;
+        let pre_apply_checksum =
+            (!flags.contains(HeaderFlags::NO_CHECKSUM)).then_some(prev.post_apply_checksum);
+
+        Encoder::new(
+            w,
+            &Header {
+                flags,
+                page_size,
+                commit,
+                min_txid,
+                max_txid,
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+    }
+
+    fn with_options(
+        w: W,
+        hdr: &Header,
+        key: Option<&[u8; 32]>,
+        opts: &EncoderBuilder,
+    ) -> Result<Encoder<'a, W>, Error> {
+        if hdr.flags.contains(HeaderFlags::PAGE_INDEX) {
+            let unsupported = hdr.flags.intersection(
+                HeaderFlags::COMPRESS_ZSTD
+                    | HeaderFlags::ENCRYPTED
+                    | compression::registered_flags(),
+            );
+            if !unsupported.is_empty() {
+                return Err(Error::UnsupportedFlags(unsupported));
+            }
+        }
+        // Page checksums in plain text would leak information about encrypted pages.
+        if hdr
+            .flags
+            .contains(HeaderFlags::MERKLE_TREE | HeaderFlags::ENCRYPTED)
+        {
+            return Err(Error::UnsupportedFlags(HeaderFlags::MERKLE_TREE));
+        }
+
+        let filtered;
+        let hdr = match opts.page_filter {
+            Some(filter) => {
+                filtered = Header {
+                    flags: hdr.flags | HeaderFlags::PAGE_FILTER,
+                    page_filter: Some(filter),
+                    ..hdr.clone()
+                };
+                &filtered
+            }
+            None => hdr,
+        };
+
+        let dict = match hdr.dict_id {
+            Some(id) => Some(dictionary::find(id).ok_or(Error::UnknownDictionary(id))?),
+            None => None,
+        };
+
+        let mut header = Vec::with_capacity(HEADER_SIZE);
+        hdr.encode_into(&mut header)?;
+
         let mut digest = CRC64.digest();
+        let w = Output::new(CountWrite::new(w), hdr.flags, key, &header)?;
+        let page_compressor = hdr
+            .flags
+            .contains(HeaderFlags::PER_PAGE_COMPRESSION)
+            .then(|| PageCompressor {
+                desc: FrameDescriptor {
+                    independent_blocks: true,
+                    block_checksums: opts.block_checksum,
+                    content_checksum: opts.content_checksum,
+                    max_block_size: opts.block_size.size(),
+                    dict_id: dict.as_ref().map(Dictionary::id),
+                },
+                dict: dict
+                    .as_ref()
+                    .map_or_else(|| Arc::from([]), Dictionary::data),
+            });
+        let mut w = LTXWriter::new(w, hdr.flags, dict.as_ref(), opts)?;
         {
-            let writer = CrcDigestWrite::new(&mut w, &mut digest);
-            hdr.encode_into(writer)?;
+            // Compressors and encryption don't output anything before the first write,
+            // so the header goes to the underlying writer as is.
+            let mut writer = CrcDigestWrite::new(w.get_mut().get_mut(), &mut digest);
+            writer.write_all(&header)?;
         }
 
+        #[cfg(feature = "tracing")]
+        let span = {
+            let span = tracing::debug_span!(
+                "ltx_encode",
+                min_txid = %hdr.min_txid,
+                max_txid = %hdr.max_txid,
+                commit = hdr.commit.map_or(0, |c| c.into_inner()),
+            );
+            tracing::debug!(parent: &span, flags = ?hdr.flags, page_size = %hdr.page_size, "header encoded");
+            span
+        };
+
         Ok(Encoder {
-            w: LTXWriter::new(w, hdr.flags.contains(HeaderFlags::COMPRESS_LZ4)),
+            w,
             digest,
             page_size: hdr.page_size,
+            commit: hdr.commit,
             is_snapshot: hdr.is_snapshot(),
+            incomplete_snapshot: false,
+            no_checksum: hdr.flags.contains(HeaderFlags::NO_CHECKSUM),
             last_page_num: None,
+            index: hdr
+                .flags
+                .contains(HeaderFlags::PAGE_INDEX)
+                .then(PageIndex::default),
+            tree: hdr
+                .flags
+                .contains(HeaderFlags::MERKLE_TREE)
+                .then(MerkleTree::default),
+            page_checksums: hdr.flags.contains(HeaderFlags::PAGE_CHECKSUM),
+            page_filter: hdr.page_filter,
+            page_compressor,
+            scratch: Vec::new(),
+            checksum: (hdr.is_snapshot() && !hdr.flags.contains(HeaderFlags::NO_CHECKSUM))
+                .then(DatabaseChecksum::new),
+            pages: 0,
+            progress: None,
+            cancel: None,
+            started: Stopwatch::start(),
+            #[cfg(feature = "tracing")]
+            span,
         })
     }
 
-    fn validate_page_num(&self, page_num: PageNum) -> Result<(), Error> {
-        let lock = PageNum::lock_page(self.page_size);
+    /// Abort encoding once `cancel` is set.
+    ///
+    /// The flag is checked before every page and before the trailer is written, which
+    /// then fail with [`Error::Cancelled`]. The output is left as it was after the last
+    /// page, so the encoder can be dropped or passed to [`Encoder::abort`].
+    pub fn with_cancel(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Allow finishing a snapshot before all pages up to `commit` have been encoded, for
+    /// snapshots continued by the following files of a chain.
+    pub(crate) fn allow_incomplete_snapshot(mut self) -> Self {
+        self.incomplete_snapshot = true;
+        self
+    }
+
+    fn check_cancelled(&self) -> Result<(), Error> {
+        match &self.cancel {
+            Some(cancel) if cancel.load(Ordering::Relaxed) => Err(Error::Cancelled),
+            _ => Ok(()),
+        }
+    }
+
+    /// Call `f` with the progress of the encoder after every page and when the file is
+    /// finished.
+    ///
+    /// # Example
+    /// ```
+    /// # use std::time::SystemTime;
+    /// # let mut w = Vec::new();
+    /// # let header = litetx::Header{
+    /// #     flags: litetx::HeaderFlags::COMPRESS_LZ4,
+    /// #     page_size: litetx::PageSize::new(4096).unwrap(),
+    /// #     commit: Some(litetx::PageNum::new(1).unwrap()),
+    /// #     min_txid: litetx::TXID::ONE,
+    /// #     max_txid: litetx::TXID::ONE,
+    /// #     timestamp: SystemTime::now(),
+    /// #     pre_apply_checksum: None,
+    /// #     node_id: 0,
+    /// #     wal: None,
+    /// #     app_data: None,
+    /// #     page_filter: None,
+    /// #     key_id: None,
+    /// #     dict_id: None,
+    /// # };
+    /// let enc = litetx::Encoder::new(&mut w, &header)
+    ///     .expect("encoder")
+    ///     .with_progress(|e| eprintln!("{} pages, {} bytes written", e.pages, e.bytes_out));
+    /// ```
+    pub fn with_progress<F>(mut self, f: F) -> Self
+    where
+        F: FnMut(ProgressEvent) + Send + 'a,
+    {
+        self.progress = Some(Box::new(f));
+        self
+    }
+
+    fn report_progress(&mut self) {
+        let bytes_out = self.bytes_written();
+        #[cfg(feature = "tracing")]
+        if self.pages.is_multiple_of(progress::TRACE_PAGES) {
+            tracing::trace!(parent: &self.span, pages = self.pages, bytes_out, "pages encoded");
+        }
+        if let Some(progress) = &mut self.progress {
+            progress(ProgressEvent {
+                pages: self.pages,
+                bytes_in: progress::uncompressed_size(self.pages, self.page_size.into_inner()),
+                bytes_out,
+                elapsed: self.started.elapsed(),
+            });
+        }
+    }
 
-        if page_num == lock {
+    fn validate_page_num(&self, page_num: PageNum) -> Result<(), Error> {
+        if page_num == PageNum::lock_page(self.page_size) {
             return Err(Error::LockPage(page_num));
         }
+        if self.commit.is_none() {
+            return Err(Error::DeletedDatabase(page_num));
+        }
         if self.is_snapshot {
             if self.last_page_num.is_none() && page_num != PageNum::ONE {
                 return Err(Error::FirstSnapshotPage);
             } else if let Some(last) = self.last_page_num {
-                if last + 1 != page_num || last + 1 == lock && last + 2 != page_num {
+                if last.next_snapshot_page(self.page_size) != Some(page_num) {
                     return Err(Error::NonsequentialPages(last, page_num));
                 }
             }
@@ -115,6 +645,28 @@ where
                 return Err(Error::OutOfOrderPage(last, page_num));
             }
         }
+        if self
+            .page_filter
+            .is_some_and(|filter| !filter.may_contain(page_num))
+        {
+            return Err(Error::PageNotInFilter(page_num));
+        }
+
+        Ok(())
+    }
+
+    fn validate_complete(&self) -> Result<(), Error> {
+        let Some(commit) = self
+            .commit
+            .filter(|_| self.is_snapshot && !self.incomplete_snapshot)
+        else {
+            return Ok(());
+        };
+
+        let want = PageNum::last_snapshot_page(self.page_size, commit);
+        if self.last_page_num != Some(want) {
+            return Err(Error::IncompleteSnapshot(self.last_page_num, want));
+        }
 
         Ok(())
     }
@@ -127,69 +679,338 @@ where
     ///    and must contain all pages from the first one up to `commit` in increasing oreder.
     ///  - if `min_txid` is greater than 1, the LTX file may contain a subset of database
     ///    pages in increasing order.
+    ///  - if `commit` is `None`, the LTX file deletes the database and can't contain any
+    ///    pages.
     pub fn encode_page(&mut self, page_num: PageNum, data: &[u8]) -> Result<(), Error> {
+        self.check_cancelled()?;
         self.validate_page_num(page_num)?;
         if data.len() != self.page_size.into_inner() as usize {
             return Err(Error::InvalidBufferSize(data.len(), self.page_size));
         }
 
-        {
-            let mut writer = CrcDigestWrite::new(&mut self.w, &mut self.digest);
-            PageHeader(Some(page_num)).encode_into(&mut writer)?;
-            writer.write_all(data)?;
+        if let Some(index) = &mut self.index {
+            index.0.push((page_num, self.w.get_mut().get_mut().count));
+        }
+
+        let page_checksum =
+            (self.page_checksums || self.tree.is_some()).then(|| data.page_checksum(page_num));
+        if let Some(compressor) = &self.page_compressor {
+            let page = &mut self.scratch;
+            page.clear();
+            page.extend_from_slice(data);
+            if let Some(checksum) = page_checksum.filter(|_| self.page_checksums) {
+                page.extend_from_slice(&checksum.into_inner().to_be_bytes());
+            }
+            let compressed = compressor.compress(page)?;
+            let raw = compressed.len() >= page.len();
+
+            let header = PageHeader(Some(page_num)).encode_raw(raw);
+            self.digest.update(&header);
+            self.digest.update(page);
+            write_all_vectored(
+                &mut self.w,
+                &mut [
+                    io::IoSlice::new(&header),
+                    io::IoSlice::new(if raw { page } else { &compressed }),
+                ],
+            )?;
+        } else {
+            let header = PageHeader(Some(page_num)).encode_raw(false);
+            let checksum = page_checksum
+                .filter(|_| self.page_checksums)
+                .map(|checksum| checksum.into_inner().to_be_bytes());
+            write_all_vectored(
+                CrcDigestWrite::new(&mut self.w, &mut self.digest),
+                &mut [
+                    io::IoSlice::new(&header),
+                    io::IoSlice::new(data),
+                    io::IoSlice::new(checksum.as_ref().map_or(&[], |c| c.as_slice())),
+                ],
+            )?;
+        }
+
+        // Indexed pages must be decodable on their own.
+        if self.index.is_some() {
+            self.w.end_frame()?;
+        }
+
+        if let Some(checksum) = &mut self.checksum {
+            checksum.add_page(page_num, data);
+        }
+        if let (Some(tree), Some(checksum)) = (&mut self.tree, page_checksum) {
+            tree.push(page_num, checksum);
         }
 
         self.last_page_num = Some(page_num);
+        self.pages += 1;
+        self.report_progress();
 
         Ok(())
     }
 
+    /// Encode a page held in a [`Bytes`](bytes::Bytes) buffer, e.g. one received from
+    /// the network or decoded by [`Decoder`](crate::Decoder), as [`Encoder::encode_page`]
+    /// does.
+    #[cfg(feature = "bytes")]
+    pub fn encode_page_bytes(
+        &mut self,
+        page_num: PageNum,
+        data: bytes::Bytes,
+    ) -> Result<(), Error> {
+        self.encode_page(page_num, &data)
+    }
+
     /// Consume the encoder and write LTX trailer into the output.
-    pub fn finish(mut self, post_apply_checksum: Checksum) -> Result<Trailer, Error> {
+    ///
+    /// The `post_apply_checksum` may only be omitted if the [`HeaderFlags::NO_CHECKSUM`]
+    /// flag is set, in which case it is ignored. Snapshots fail with
+    /// [`Error::IncompleteSnapshot`] unless all pages up to `commit` have been encoded.
+    pub fn finish<C>(self, post_apply_checksum: C) -> Result<Trailer, Error>
+    where
+        C: Into<Option<Checksum>>,
+    {
+        let (trailer, _) = self.finish_into_inner(post_apply_checksum.into())?;
+
+        Ok(trailer)
+    }
+
+    /// Like [`Encoder::finish`], but compute the post-apply checksum from the encoded
+    /// pages.
+    ///
+    /// Only snapshots contain all pages of the database, so other files fail with
+    /// [`Error::NotSnapshot`].
+    pub fn finish_auto(self) -> Result<Trailer, Error> {
+        if !self.is_snapshot {
+            return Err(Error::NotSnapshot);
+        }
+
+        let checksum = self.checksum.map(DatabaseChecksum::finish);
+        self.finish(checksum)
+    }
+
+    /// Like [`Encoder::finish`], but also return the [`Stats`] of the file.
+    pub fn finish_with_stats<C>(self, post_apply_checksum: C) -> Result<(Trailer, Stats), Error>
+    where
+        C: Into<Option<Checksum>>,
+    {
+        let (trailer, _, stats) = self.finish_file(post_apply_checksum.into())?;
+
+        Ok((trailer, stats))
+    }
+
+    /// Consume the encoder without finishing the file.
+    ///
+    /// Nothing more is written or flushed, so data buffered by the compressor is
+    /// discarded and the output lacks the end of the page block and the trailer.
+    /// Decoders reject such files. Use [`FileEncoder::abort`](crate::FileEncoder::abort)
+    /// to remove the partial output as well.
+    pub fn abort(self) {}
+
+    /// Return the number of bytes written to the underlying writer so far.
+    ///
+    /// Data buffered by the compressor isn't accounted for until it's flushed.
+    pub(crate) fn bytes_written(&mut self) -> u64 {
+        self.w.get_mut().get_mut().count
+    }
+
+    /// Return a mutable reference to the underlying writer.
+    ///
+    /// The writer only receives data once it has passed through the compressor.
+    #[cfg(feature = "async")]
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        &mut self.w.get_mut().get_mut().inner
+    }
+
+    /// Write LTX trailer into the output and return the trailer along with the underlying writer.
+    pub(crate) fn finish_into_inner(
+        self,
+        post_apply_checksum: Option<Checksum>,
+    ) -> Result<(Trailer, W), Error> {
+        let (trailer, w, _) = self.finish_file(post_apply_checksum)?;
+        Ok((trailer, w))
+    }
+
+    fn finish_file(
+        mut self,
+        post_apply_checksum: Option<Checksum>,
+    ) -> Result<(Trailer, W, Stats), Error> {
+        self.check_cancelled()?;
+        self.validate_complete()?;
+        let post_apply_checksum = match post_apply_checksum {
+            _ if self.no_checksum => None,
+            None => return Err(Error::NoPostApplyChecksum),
+            checksum => checksum,
+        };
+
         let mut writer = CrcDigestWrite::new(&mut self.w, &mut self.digest);
         PageHeader(None).encode_into(&mut writer)?;
 
-        let writer = self.w.finish()?;
-        self.digest
-            .update(&post_apply_checksum.into_inner().to_be_bytes());
+        let mut writer = self.w.finish()?.finish()?;
+        #[cfg(feature = "tracing")]
+        let (span, pages) = (self.span, self.pages);
+        #[cfg(feature = "tracing")]
+        tracing::debug!(parent: &span, pages, bytes_out = writer.count, "compression flushed");
+        if let Some(tree) = &self.tree {
+            CrcDigestWrite::new(&mut writer, &mut self.digest).write_all(&tree.encode())?;
+        }
+        if let Some(index) = &self.index {
+            index.encode_into(CrcDigestWrite::new(&mut writer, &mut self.digest))?;
+        }
 
-        let trailer = Trailer {
+        let mut trailer = Trailer {
             post_apply_checksum,
-            file_checksum: Checksum::new(self.digest.finalize()),
+            file_checksum: Checksum::new(0),
         };
+        self.digest.update(&trailer.post_apply_checksum_bytes());
+        trailer.file_checksum = Checksum::new(self.digest.finalize());
 
-        trailer.encode_into(writer)?;
+        trailer.encode_into(&mut writer)?;
+        #[cfg(feature = "tracing")]
+        tracing::debug!(
+            parent: &span,
+            file_checksum = %trailer.file_checksum,
+            bytes_out = writer.count,
+            "trailer written"
+        );
 
-        Ok(trailer)
+        let stats = Stats {
+            pages: self.pages,
+            uncompressed_bytes: progress::uncompressed_file_size(
+                self.pages,
+                self.page_size.into_inner(),
+                self.index.is_some(),
+            ),
+            compressed_bytes: writer.count,
+            elapsed: self.started.elapsed(),
+        };
+        if let Some(progress) = &mut self.progress {
+            progress(ProgressEvent {
+                pages: stats.pages,
+                bytes_in: stats.uncompressed_bytes,
+                bytes_out: stats.compressed_bytes,
+                elapsed: stats.elapsed,
+            });
+        }
+
+        Ok((trailer, writer.inner, stats))
+    }
+}
+
+/// Compresses the pages of files with the [`HeaderFlags::PER_PAGE_COMPRESSION`] flag into
+/// separate LZ4 frames.
+struct PageCompressor {
+    desc: FrameDescriptor,
+    dict: Arc<[u8]>,
+}
+
+impl PageCompressor {
+    fn compress(&self, page: &[u8]) -> io::Result<Vec<u8>> {
+        let mut enc =
+            ParallelFrameEncoder::new(Vec::new(), self.desc, 1).with_dict(self.dict.clone());
+        enc.write_all(page)?;
+        enc.finish()
     }
 }
 
-struct LTXWriter<W>
+enum LTXWriter<W>
 where
     W: io::Write,
 {
-    enc: FrameEncoder<W>,
-    compressed: bool,
+    Uncompressed(W),
+    Lz4(FrameEncoder<W>),
+    Lz4Parallel(ParallelFrameEncoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+    /// A codec registered with [`compression::register`].
+    Custom(CompressWriter<W>),
 }
 
 impl<W> LTXWriter<W>
 where
     W: io::Write,
 {
-    fn new(w: W, compressed: bool) -> LTXWriter<W> {
-        LTXWriter {
-            enc: FrameEncoder::with_frame_info(FrameInfo::new().block_size(BlockSize::Max64KB), w),
-            compressed,
+    #[cfg_attr(not(feature = "zstd"), allow(unused_variables))]
+    fn new(
+        w: W,
+        flags: HeaderFlags,
+        dict: Option<&Dictionary>,
+        opts: &EncoderBuilder,
+    ) -> Result<LTXWriter<W>, Error> {
+        // Pages compressed one by one are written to the page block as is.
+        if flags.contains(HeaderFlags::PER_PAGE_COMPRESSION) {
+            Ok(LTXWriter::Uncompressed(w))
+        } else if flags.contains(HeaderFlags::COMPRESS_LZ4) && (opts.threads > 1 || dict.is_some())
+        {
+            // `lz4_flex` frames don't support dictionaries, so they use the parallel
+            // encoder even on a single thread.
+            let desc = FrameDescriptor {
+                independent_blocks: true,
+                block_checksums: opts.block_checksum,
+                content_checksum: opts.content_checksum,
+                max_block_size: opts.block_size.size(),
+                dict_id: dict.map(Dictionary::id),
+            };
+            let enc = ParallelFrameEncoder::new(w, desc, opts.threads);
+            Ok(LTXWriter::Lz4Parallel(match dict {
+                Some(dict) => enc.with_dict(dict.data()),
+                None => enc,
+            }))
+        } else if flags.contains(HeaderFlags::COMPRESS_LZ4) {
+            Ok(LTXWriter::Lz4(FrameEncoder::with_frame_info(
+                FrameInfo::new()
+                    .block_size(opts.block_size.into())
+                    .block_checksums(opts.block_checksum)
+                    .content_checksum(opts.content_checksum),
+                w,
+            )))
+        } else if flags.contains(HeaderFlags::COMPRESS_ZSTD) {
+            #[cfg(feature = "zstd")]
+            return zstd::stream::write::Encoder::new(w, opts.compression_level)
+                .and_then(|mut enc| {
+                    enc.include_checksum(opts.content_checksum)?;
+                    Ok(LTXWriter::Zstd(enc))
+                })
+                .map_err(Error::from);
+            #[cfg(not(feature = "zstd"))]
+            return Err(Error::UnsupportedFlags(HeaderFlags::COMPRESS_ZSTD));
+        } else if let Some(codec) = compression::find(flags) {
+            Ok(LTXWriter::Custom(CompressWriter::new(w, codec.as_ref())))
+        } else {
+            Ok(LTXWriter::Uncompressed(w))
+        }
+    }
+
+    fn get_mut(&mut self) -> &mut W {
+        match self {
+            LTXWriter::Uncompressed(w) => w,
+            LTXWriter::Lz4(enc) => enc.get_mut(),
+            LTXWriter::Lz4Parallel(enc) => enc.get_mut(),
+            #[cfg(feature = "zstd")]
+            LTXWriter::Zstd(enc) => enc.get_mut(),
+            LTXWriter::Custom(enc) => enc.get_mut(),
+        }
+    }
+
+    /// End the current compressed frame. The next write starts a new one.
+    fn end_frame(&mut self) -> io::Result<()> {
+        match self {
+            LTXWriter::Uncompressed(_) => Ok(()),
+            LTXWriter::Lz4(enc) => enc.try_finish().map_err(io::Error::other),
+            LTXWriter::Lz4Parallel(enc) => enc.try_finish(),
+            #[cfg(feature = "zstd")]
+            LTXWriter::Zstd(enc) => enc.do_finish(),
+            LTXWriter::Custom(enc) => enc.end_frame(),
         }
     }
 
     fn finish(self) -> io::Result<W> {
-        if self.compressed {
-            self.enc
-                .finish()
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
-        } else {
-            Ok(self.enc.into_inner())
+        match self {
+            LTXWriter::Uncompressed(w) => Ok(w),
+            LTXWriter::Lz4(enc) => enc.finish().map_err(io::Error::other),
+            LTXWriter::Lz4Parallel(enc) => enc.finish(),
+            #[cfg(feature = "zstd")]
+            LTXWriter::Zstd(enc) => enc.finish(),
+            LTXWriter::Custom(enc) => enc.finish(),
         }
     }
 }
@@ -199,18 +1020,155 @@ where
     W: io::Write,
 {
     fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        if self.compressed {
-            self.enc.write(buf)
-        } else {
-            self.enc.get_mut().write(buf)
+        match self {
+            LTXWriter::Uncompressed(w) => w.write(buf),
+            LTXWriter::Lz4(enc) => enc.write(buf),
+            LTXWriter::Lz4Parallel(enc) => enc.write(buf),
+            #[cfg(feature = "zstd")]
+            LTXWriter::Zstd(enc) => enc.write(buf),
+            LTXWriter::Custom(enc) => enc.write(buf),
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        match self {
+            LTXWriter::Uncompressed(w) => w.write_vectored(bufs),
+            LTXWriter::Lz4(enc) => enc.write_vectored(bufs),
+            LTXWriter::Lz4Parallel(enc) => enc.write_vectored(bufs),
+            #[cfg(feature = "zstd")]
+            LTXWriter::Zstd(enc) => enc.write_vectored(bufs),
+            LTXWriter::Custom(enc) => enc.write_vectored(bufs),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            LTXWriter::Uncompressed(w) => w.flush(),
+            LTXWriter::Lz4(enc) => enc.flush(),
+            LTXWriter::Lz4Parallel(enc) => enc.flush(),
+            #[cfg(feature = "zstd")]
+            LTXWriter::Zstd(enc) => enc.flush(),
+            LTXWriter::Custom(enc) => enc.flush(),
+        }
+    }
+}
+
+/// The writer below the compression layer, optionally encrypting the page block.
+enum Output<W>
+where
+    W: io::Write,
+{
+    Plain(W),
+    #[cfg(feature = "encryption")]
+    Encrypted(EncryptWriter<W>),
+}
+
+impl<W> Output<W>
+where
+    W: io::Write,
+{
+    #[cfg_attr(not(feature = "encryption"), allow(unused_variables))]
+    fn new(
+        w: W,
+        flags: HeaderFlags,
+        key: Option<&[u8; 32]>,
+        header: &[u8],
+    ) -> Result<Output<W>, Error> {
+        match (flags.contains(HeaderFlags::ENCRYPTED), key) {
+            (false, None) => Ok(Output::Plain(w)),
+            #[cfg(feature = "encryption")]
+            (true, Some(key)) => Ok(Output::Encrypted(EncryptWriter::new(w, key, header))),
+            #[cfg(feature = "encryption")]
+            (true, None) => Err(Error::KeyRequired),
+            #[cfg(feature = "encryption")]
+            (false, Some(_)) => Err(Error::UnexpectedKey),
+            #[cfg(not(feature = "encryption"))]
+            _ => Err(Error::UnsupportedFlags(HeaderFlags::ENCRYPTED)),
+        }
+    }
+
+    fn get_mut(&mut self) -> &mut W {
+        match self {
+            Output::Plain(w) => w,
+            #[cfg(feature = "encryption")]
+            Output::Encrypted(enc) => enc.get_mut(),
+        }
+    }
+
+    fn finish(self) -> io::Result<W> {
+        match self {
+            Output::Plain(w) => Ok(w),
+            #[cfg(feature = "encryption")]
+            Output::Encrypted(enc) => enc.finish(),
+        }
+    }
+}
+
+impl<W> io::Write for Output<W>
+where
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(w) => w.write(buf),
+            #[cfg(feature = "encryption")]
+            Output::Encrypted(enc) => enc.write(buf),
+        }
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        match self {
+            Output::Plain(w) => w.write_vectored(bufs),
+            #[cfg(feature = "encryption")]
+            Output::Encrypted(enc) => enc.write_vectored(bufs),
         }
     }
 
     fn flush(&mut self) -> io::Result<()> {
-        if self.compressed {
-            self.enc.flush()?;
+        match self {
+            Output::Plain(w) => w.flush(),
+            #[cfg(feature = "encryption")]
+            Output::Encrypted(enc) => enc.flush(),
         }
-        self.enc.get_mut().flush()
+    }
+}
+
+/// An [`io::Write`] counting the bytes written.
+struct CountWrite<W>
+where
+    W: io::Write,
+{
+    inner: W,
+    count: u64,
+}
+
+impl<W> CountWrite<W>
+where
+    W: io::Write,
+{
+    fn new(inner: W) -> Self {
+        CountWrite { inner, count: 0 }
+    }
+}
+
+impl<W> io::Write for CountWrite<W>
+where
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        let written = self.inner.write_vectored(bufs)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
     }
 }
 
@@ -220,14 +1178,14 @@ where
     W: io::Write,
 {
     inner: W,
-    digest: &'a mut crc::Digest<'b, u64>,
+    digest: &'a mut Crc64Digest<'b>,
 }
 
 impl<'a, 'b, W> CrcDigestWrite<'a, 'b, W>
 where
     W: io::Write,
 {
-    fn new(inner: W, digest: &'a mut crc::Digest<'b, u64>) -> Self {
+    fn new(inner: W, digest: &'a mut Crc64Digest<'b>) -> Self {
         CrcDigestWrite { inner, digest }
     }
 }
@@ -242,19 +1200,58 @@ where
         Ok(written)
     }
 
+    fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        let written = self.inner.write_vectored(bufs)?;
+        let mut remaining = written;
+        for buf in bufs {
+            let n = remaining.min(buf.len());
+            self.digest.update(&buf[..n]);
+            remaining -= n;
+        }
+        Ok(written)
+    }
+
     fn flush(&mut self) -> io::Result<()> {
         self.inner.flush()
     }
 }
 
+/// Write all of `bufs` into `w`, with as few calls to [`io::Write::write_vectored`] as
+/// the writer allows.
+fn write_all_vectored<W>(mut w: W, mut bufs: &mut [io::IoSlice<'_>]) -> io::Result<()>
+where
+    W: io::Write,
+{
+    io::IoSlice::advance_slices(&mut bufs, 0);
+    while !bufs.is_empty() {
+        match w.write_vectored(bufs) {
+            Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+            Ok(n) => io::IoSlice::advance_slices(&mut bufs, n),
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{CrcDigestWrite, Encoder, Error};
+    use super::{CrcDigestWrite, Encoder, Error, Lz4BlockSize};
     use crate::{
+        dictionary::{self, Dictionary},
         ltx::{self, CRC64},
-        Checksum, Header, HeaderFlags, PageNum, PageSize, TXID,
+        Checksum, DatabaseChecksum, Decoder, Header, HeaderFlags, PageFilter, PageNum, PageSize,
+        Pos, TXID,
+    };
+    use std::{
+        io::{self, Write},
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        time,
     };
-    use std::{io::Write, time};
 
     #[test]
     fn crc_digest_write() {
@@ -270,20 +1267,93 @@ mod tests {
         assert_eq!(6672316476627126589, digest.finalize());
     }
 
-    #[test]
-    fn encoder() {
-        let mut buf = Vec::new();
+    /// A writer counting the calls writing to it.
+    #[derive(Default)]
+    struct CallCountWrite {
+        buf: Vec<u8>,
+        calls: usize,
+    }
 
-        let mut enc = Encoder::new(
-            &mut buf,
+    impl Write for CallCountWrite {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            self.buf.write(buf)
+        }
+
+        fn write_vectored(&mut self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+            self.calls += 1;
+            self.buf.write_vectored(bufs)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn encoder_vectored_writes() {
+        for flags in [HeaderFlags::empty(), HeaderFlags::PAGE_CHECKSUM] {
+            let header = Header {
+                flags,
+                page_size: PageSize::new(512).unwrap(),
+                commit: Some(PageNum::new(10).unwrap()),
+                min_txid: TXID::ONE,
+                max_txid: TXID::ONE,
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            };
+
+            let mut w = CallCountWrite::default();
+            let mut enc = Encoder::new(&mut w, &header).expect("failed to create encoder");
+            let calls = enc.w.get_mut().get_mut().inner.calls;
+            for n in 1..=10 {
+                enc.encode_page(PageNum::new(n).unwrap(), &[n as u8; 512])
+                    .expect("failed to encode page");
+            }
+            assert_eq!(calls + 10, enc.w.get_mut().get_mut().inner.calls);
+            enc.finish(Checksum::new(1))
+                .expect("failed to finish encoder");
+
+            let (mut dec, _) = Decoder::new(w.buf.as_slice()).expect("failed to create decoder");
+            for n in 1..=10 {
+                let (page_num, page) = dec
+                    .decode_page_ref()
+                    .expect("failed to decode page")
+                    .expect("missing page");
+                assert_eq!(PageNum::new(n).unwrap(), page_num);
+                assert_eq!(&[n as u8; 512], page);
+            }
+            assert!(matches!(dec.decode_page_ref(), Ok(None)));
+            dec.finish().expect("failed to finish decoder");
+        }
+    }
+
+    #[test]
+    fn encoder() {
+        let mut buf = Vec::new();
+
+        let mut enc = Encoder::new(
+            &mut buf,
             &Header {
                 flags: HeaderFlags::empty(),
                 page_size: PageSize::new(4096).unwrap(),
-                commit: PageNum::new(3).unwrap(),
+                commit: Some(PageNum::new(3).unwrap()),
                 min_txid: TXID::new(5).unwrap(),
                 max_txid: TXID::new(6).unwrap(),
                 timestamp: time::SystemTime::now(),
                 pre_apply_checksum: Some(Checksum::new(5)),
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
             },
         )
         .expect("failed to create encoder");
@@ -300,13 +1370,355 @@ mod tests {
             .finish(Checksum::new(6))
             .expect("failed to finish encoder");
 
-        assert_eq!(Checksum::new(6), trailer.post_apply_checksum);
+        assert_eq!(Some(Checksum::new(6)), trailer.post_apply_checksum);
         assert_eq!(
             ltx::HEADER_SIZE + (4096 + 4) * 2 + 4 + ltx::TRAILER_SIZE,
             buf.len()
         );
     }
 
+    #[test]
+    fn encoder_progress() {
+        let header = Header {
+            flags: HeaderFlags::empty(),
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(2).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let mut buf = Vec::new();
+        let mut events = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &header)
+            .expect("failed to create encoder")
+            .with_progress(|e| events.push(e));
+        for page_num in 1..=2 {
+            enc.encode_page(PageNum::new(page_num).unwrap(), &[1; 4096])
+                .expect("failed to encode page");
+        }
+        enc.finish(Checksum::new(1))
+            .expect("failed to finish encoder");
+
+        let size = |pages: usize| (ltx::HEADER_SIZE + (4096 + 4) * pages) as u64;
+        assert_eq!(
+            vec![
+                (1, size(1), size(1)),
+                (2, size(2), size(2)),
+                (2, buf.len() as u64, buf.len() as u64),
+            ],
+            events
+                .iter()
+                .map(|e| (e.pages, e.bytes_in, e.bytes_out))
+                .collect::<Vec<_>>()
+        );
+        assert!(events.windows(2).all(|w| w[0].elapsed <= w[1].elapsed));
+    }
+
+    #[test]
+    fn encoder_stats() {
+        let header = Header {
+            flags: HeaderFlags::COMPRESS_LZ4,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &header).expect("failed to create encoder");
+        for page_num in 1..=10 {
+            enc.encode_page(PageNum::new(page_num).unwrap(), &[page_num as u8; 4096])
+                .expect("failed to encode page");
+        }
+        let (trailer, stats) = enc
+            .finish_with_stats(Checksum::new(1))
+            .expect("failed to finish encoder");
+
+        assert_eq!(10, stats.pages);
+        assert_eq!(
+            (ltx::HEADER_SIZE + (4096 + 4) * 10 + 4 + ltx::TRAILER_SIZE) as u64,
+            stats.uncompressed_bytes
+        );
+        assert_eq!(buf.len() as u64, stats.compressed_bytes);
+        assert!(stats.compression_ratio() > 10.0);
+
+        let (mut dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        while dec.skip_page().expect("failed to skip page").is_some() {}
+        let (trailer_out, stats_out) = dec.finish_with_stats().expect("failed to finish decoder");
+        assert_eq!(trailer, trailer_out);
+        assert_eq!(
+            (
+                stats.pages,
+                stats.uncompressed_bytes,
+                stats.compressed_bytes
+            ),
+            (
+                stats_out.pages,
+                stats_out.uncompressed_bytes,
+                stats_out.compressed_bytes
+            )
+        );
+    }
+
+    #[test]
+    fn encoder_for_delta() {
+        let prev = Pos {
+            txid: TXID::new(5).unwrap(),
+            post_apply_checksum: Checksum::new(0x123),
+        };
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::for_delta(
+            &mut buf,
+            prev,
+            TXID::new(7).unwrap(),
+            Some(PageNum::new(3).unwrap()),
+            PageSize::new(4096).unwrap(),
+            HeaderFlags::COMPRESS_LZ4,
+        )
+        .expect("failed to create encoder");
+        enc.encode_page(PageNum::new(2).unwrap(), &[2; 4096])
+            .expect("failed to encode page");
+        enc.finish(Checksum::new(0x456))
+            .expect("failed to finish encoder");
+
+        let (_, header) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        assert_eq!(TXID::new(6).unwrap(), header.min_txid);
+        assert_eq!(TXID::new(7).unwrap(), header.max_txid);
+        assert_eq!(Some(prev.post_apply_checksum), header.pre_apply_checksum);
+        assert_eq!(Some(PageNum::new(3).unwrap()), header.commit);
+
+        let enc = Encoder::for_delta(
+            Vec::new(),
+            prev,
+            TXID::new(7).unwrap(),
+            Some(PageNum::new(3).unwrap()),
+            PageSize::new(4096).unwrap(),
+            HeaderFlags::NO_CHECKSUM,
+        );
+        assert!(enc.is_ok());
+
+        let enc = Encoder::for_delta(
+            Vec::new(),
+            prev,
+            prev.txid,
+            Some(PageNum::new(3).unwrap()),
+            PageSize::new(4096).unwrap(),
+            HeaderFlags::empty(),
+        );
+        assert!(matches!(enc, Err(Error::Header(_))));
+
+        let max = Pos {
+            txid: TXID::new(u64::MAX).unwrap(),
+            ..prev
+        };
+        let enc = Encoder::for_delta(
+            Vec::new(),
+            max,
+            max.txid,
+            Some(PageNum::new(3).unwrap()),
+            PageSize::new(4096).unwrap(),
+            HeaderFlags::empty(),
+        );
+        assert!(matches!(enc, Err(Error::TXIDOverflow(txid)) if txid == max.txid));
+    }
+
+    #[test]
+    fn encoder_finish_auto() {
+        let header = Header {
+            flags: HeaderFlags::empty(),
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(2).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::UNIX_EPOCH,
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let pages = [
+            (PageNum::ONE, [1; 4096]),
+            (PageNum::new(2).unwrap(), [2; 4096]),
+        ];
+        let mut enc = Encoder::new(Vec::new(), &header).expect("failed to create encoder");
+        for (pgno, page) in &pages {
+            enc.encode_page(*pgno, page).expect("failed to encode page");
+        }
+        let trailer = enc.finish_auto().expect("failed to finish encoder");
+
+        let mut checksum = DatabaseChecksum::new();
+        for (pgno, page) in &pages {
+            checksum.add_page(*pgno, page);
+        }
+        assert_eq!(Some(checksum.finish()), trailer.post_apply_checksum);
+
+        let enc = Encoder::new(
+            Vec::new(),
+            &Header {
+                flags: HeaderFlags::NO_CHECKSUM,
+                commit: None,
+                ..header.clone()
+            },
+        )
+        .expect("failed to create encoder");
+        let trailer = enc.finish_auto().expect("failed to finish encoder");
+        assert_eq!(None, trailer.post_apply_checksum);
+
+        let enc = Encoder::new(
+            Vec::new(),
+            &Header {
+                min_txid: TXID::new(2).unwrap(),
+                max_txid: TXID::new(2).unwrap(),
+                pre_apply_checksum: Some(Checksum::new(1)),
+                ..header
+            },
+        )
+        .expect("failed to create encoder");
+        assert!(matches!(enc.finish_auto(), Err(Error::NotSnapshot)));
+    }
+
+    #[test]
+    fn encoder_cancel() {
+        let header = Header {
+            flags: HeaderFlags::COMPRESS_LZ4,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(2).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let cancel = Arc::new(AtomicBool::new(false));
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &header)
+            .expect("failed to create encoder")
+            .with_cancel(cancel.clone());
+        enc.encode_page(PageNum::ONE, &[1; 4096])
+            .expect("failed to encode page");
+
+        cancel.store(true, Ordering::Relaxed);
+        assert!(matches!(
+            enc.encode_page(PageNum::new(2).unwrap(), &[2; 4096]),
+            Err(Error::Cancelled)
+        ));
+        assert!(matches!(
+            enc.finish(Checksum::new(1)),
+            Err(Error::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn encoder_deletion() {
+        let header = Header {
+            flags: HeaderFlags::COMPRESS_LZ4,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: None,
+            min_txid: TXID::new(5).unwrap(),
+            max_txid: TXID::new(5).unwrap(),
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: Some(Checksum::new(5)),
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &header).expect("failed to create encoder");
+        assert!(matches!(
+            enc.encode_page(PageNum::ONE, &[1; 4096]),
+            Err(Error::DeletedDatabase(page_num)) if page_num == PageNum::ONE
+        ));
+        let trailer = enc
+            .finish(Checksum::new(0))
+            .expect("failed to finish encoder");
+
+        let (mut dec, header_out) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        assert_eq!(None, header_out.commit);
+        assert_eq!(
+            None,
+            dec.decode_page(&mut [0; 4096])
+                .expect("failed to decode page")
+        );
+        assert_eq!(trailer, dec.finish().expect("failed to finish decoder"));
+    }
+
+    #[test]
+    fn encoder_no_checksum() {
+        let header = Header {
+            flags: HeaderFlags::NO_CHECKSUM,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(3).unwrap()),
+            min_txid: TXID::new(5).unwrap(),
+            max_txid: TXID::new(6).unwrap(),
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &header).expect("failed to create encoder");
+        enc.encode_page(PageNum::ONE, &[1; 4096])
+            .expect("failed to encode page");
+        let trailer = enc.finish(None).expect("failed to finish encoder");
+        assert_eq!(None, trailer.post_apply_checksum);
+
+        let (mut dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        let mut page = vec![0; 4096];
+        while dec
+            .decode_page(&mut page)
+            .expect("failed to decode page")
+            .is_some()
+        {}
+        assert_eq!(trailer, dec.finish().expect("failed to finish decoder"));
+
+        let enc = Encoder::new(
+            Vec::new(),
+            &Header {
+                flags: HeaderFlags::empty(),
+                pre_apply_checksum: Some(Checksum::new(5)),
+                ..header
+            },
+        )
+        .expect("failed to create encoder");
+        assert!(matches!(enc.finish(None), Err(Error::NoPostApplyChecksum)));
+    }
+
     #[test]
     fn encoder_compressed() {
         let mut buf = Vec::new();
@@ -316,11 +1728,17 @@ mod tests {
             &Header {
                 flags: HeaderFlags::COMPRESS_LZ4,
                 page_size: PageSize::new(4096).unwrap(),
-                commit: PageNum::new(3).unwrap(),
+                commit: Some(PageNum::new(3).unwrap()),
                 min_txid: TXID::new(5).unwrap(),
                 max_txid: TXID::new(6).unwrap(),
                 timestamp: time::SystemTime::now(),
                 pre_apply_checksum: Some(Checksum::new(5)),
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
             },
         )
         .expect("failed to create encoder");
@@ -336,10 +1754,557 @@ mod tests {
         let trailer = enc
             .finish(Checksum::new(6))
             .expect("failed to finish encoder");
-        assert_eq!(Checksum::new(6), trailer.post_apply_checksum);
+        assert_eq!(Some(Checksum::new(6)), trailer.post_apply_checksum);
         assert!(ltx::HEADER_SIZE + (4096 + 4) * 2 + 4 + ltx::TRAILER_SIZE > buf.len());
     }
 
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn encoder_zstd() {
+        let mut buf = Vec::new();
+
+        let mut enc = Encoder::new(
+            &mut buf,
+            &Header {
+                flags: HeaderFlags::COMPRESS_ZSTD,
+                page_size: PageSize::new(4096).unwrap(),
+                commit: Some(PageNum::new(3).unwrap()),
+                min_txid: TXID::new(5).unwrap(),
+                max_txid: TXID::new(6).unwrap(),
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum: Some(Checksum::new(5)),
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder");
+
+        let page1: Vec<u8> = (0..4096).map(|_| 1).collect();
+        let page2: Vec<u8> = (0..4096).map(|_| 2).collect();
+
+        enc.encode_page(PageNum::new(1).unwrap(), page1.as_slice())
+            .expect("failed to encode page1");
+        enc.encode_page(PageNum::new(2).unwrap(), page2.as_slice())
+            .expect("failed to encode page2");
+
+        let trailer = enc
+            .finish(Checksum::new(6))
+            .expect("failed to finish encoder");
+        assert_eq!(Some(Checksum::new(6)), trailer.post_apply_checksum);
+        assert!(ltx::HEADER_SIZE + (4096 + 4) * 2 + 4 + ltx::TRAILER_SIZE > buf.len());
+    }
+
+    #[test]
+    fn encoder_builder() {
+        let header = Header {
+            flags: HeaderFlags::COMPRESS_LZ4,
+            page_size: PageSize::new(65536).unwrap(),
+            commit: Some(PageNum::new(2).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+        let pages: Vec<Vec<u8>> = (0..2)
+            .map(|_| (0..65536).map(|_| rand::random::<u8>()).collect())
+            .collect();
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::builder()
+            .block_size(Lz4BlockSize::Max256KB)
+            .block_checksum(true)
+            .content_checksum(true)
+            .build(&mut buf, &header)
+            .expect("failed to create encoder");
+        for (page_num, page) in (1..).zip(&pages) {
+            enc.encode_page(PageNum::new(page_num).unwrap(), page)
+                .expect("failed to encode page");
+        }
+        let trailer = enc
+            .finish(Checksum::new(1))
+            .expect("failed to finish encoder");
+
+        // LZ4 frame descriptor: FLG with block and content checksums, BD with 256 KB blocks.
+        let flg = buf[ltx::HEADER_SIZE + 4];
+        assert_eq!(0x10 | 0x04, flg & (0x10 | 0x04));
+        assert_eq!(0x50, buf[ltx::HEADER_SIZE + 5]);
+
+        let (mut dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        let mut page = vec![0; 65536];
+        for expected in &pages {
+            dec.decode_page(&mut page)
+                .expect("failed to decode page")
+                .expect("missing page");
+            assert_eq!(expected, &page);
+        }
+        assert_eq!(
+            None,
+            dec.decode_page(&mut page).expect("failed to decode page")
+        );
+        assert_eq!(trailer, dec.finish().expect("failed to finish decoder"));
+    }
+
+    #[test]
+    fn encoder_abort() {
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(
+            &mut buf,
+            &Header {
+                flags: HeaderFlags::empty(),
+                page_size: PageSize::new(512).unwrap(),
+                commit: Some(PageNum::ONE),
+                min_txid: TXID::ONE,
+                max_txid: TXID::ONE,
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder");
+        enc.encode_page(PageNum::ONE, &[1; 512])
+            .expect("failed to encode page");
+        enc.abort();
+
+        let (mut dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        let mut page = vec![0; 512];
+        assert!(matches!(dec.decode_page(&mut page), Ok(Some(PageNum::ONE))));
+        assert!(dec.decode_page(&mut page).is_err());
+    }
+
+    #[test]
+    fn encoder_parallel() {
+        let header = Header {
+            flags: HeaderFlags::COMPRESS_LZ4,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(200).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+        let pages: Vec<Vec<u8>> = (0..200)
+            .map(|i| (0..4096).map(|j| ((i * j) % 7) as u8).collect())
+            .collect();
+
+        let encode = |threads| {
+            let mut buf = Vec::new();
+            let mut enc = Encoder::builder()
+                .threads(threads)
+                .build(&mut buf, &header)
+                .expect("failed to create encoder");
+            for (page_num, page) in (1..).zip(&pages) {
+                enc.encode_page(PageNum::new(page_num).unwrap(), page)
+                    .expect("failed to encode page");
+            }
+            let trailer = enc
+                .finish(Checksum::new(1))
+                .expect("failed to finish encoder");
+            (buf, trailer)
+        };
+
+        let (_, expected) = encode(1);
+        let (buf, trailer) = encode(4);
+        assert_eq!(expected, trailer);
+
+        let (mut dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        let mut page = vec![0; 4096];
+        for expected in &pages {
+            dec.decode_page(&mut page)
+                .expect("failed to decode page")
+                .expect("missing page");
+            assert_eq!(expected, &page);
+        }
+        assert_eq!(
+            None,
+            dec.decode_page(&mut page).expect("failed to decode page")
+        );
+        assert_eq!(trailer, dec.finish().expect("failed to finish decoder"));
+    }
+
+    #[test]
+    #[cfg(feature = "zstd")]
+    fn encoder_builder_zstd() {
+        let header = Header {
+            flags: HeaderFlags::COMPRESS_ZSTD,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::ONE),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::builder()
+            .compression_level(19)
+            .content_checksum(true)
+            .build(&mut buf, &header)
+            .expect("failed to create encoder");
+        enc.encode_page(PageNum::ONE, &[7; 4096])
+            .expect("failed to encode page");
+        enc.finish(Checksum::new(1))
+            .expect("failed to finish encoder");
+
+        let (mut dec, _) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        let mut page = vec![0; 4096];
+        while dec
+            .decode_page(&mut page)
+            .expect("failed to decode page")
+            .is_some()
+        {
+            assert_eq!(vec![7; 4096], page);
+        }
+        dec.finish().expect("failed to finish decoder");
+    }
+
+    #[test]
+    #[cfg(not(feature = "zstd"))]
+    fn encoder_zstd_unsupported() {
+        let mut buf = Vec::new();
+
+        assert!(matches!(
+            Encoder::new(
+                &mut buf,
+                &Header {
+                    flags: HeaderFlags::COMPRESS_ZSTD,
+                    page_size: PageSize::new(4096).unwrap(),
+                    commit: Some(PageNum::new(3).unwrap()),
+                    min_txid: TXID::new(5).unwrap(),
+                    max_txid: TXID::new(6).unwrap(),
+                    timestamp: time::SystemTime::now(),
+                    pre_apply_checksum: Some(Checksum::new(5)),
+                    node_id: 0,
+                    wal: None,
+                    app_data: None,
+                    page_filter: None,
+                    key_id: None,
+                    dict_id: None,
+                },
+            ),
+            Err(Error::UnsupportedFlags(HeaderFlags::COMPRESS_ZSTD))
+        ));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "encryption")]
+    fn encoder_encrypted() {
+        let header = Header {
+            flags: HeaderFlags::ENCRYPTED,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(3).unwrap()),
+            min_txid: TXID::new(5).unwrap(),
+            max_txid: TXID::new(6).unwrap(),
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: Some(Checksum::new(5)),
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        assert!(matches!(
+            Encoder::new(Vec::new(), &header),
+            Err(Error::KeyRequired)
+        ));
+
+        let mut buf = Vec::new();
+        let mut enc =
+            Encoder::new_encrypted(&mut buf, &header, &[1; 32]).expect("failed to create encoder");
+        let page: Vec<u8> = (0..4096).map(|_| 1).collect();
+        enc.encode_page(PageNum::new(1).unwrap(), page.as_slice())
+            .expect("failed to encode page");
+        enc.finish(Checksum::new(6))
+            .expect("failed to finish encoder");
+
+        // Nonce prefix, chunk length and tag are added to the page block.
+        assert_eq!(
+            ltx::HEADER_SIZE + 8 + 4 + (4096 + 4) + 4 + 16 + ltx::TRAILER_SIZE,
+            buf.len()
+        );
+        assert!(!buf.windows(4096).any(|w| w == page.as_slice()));
+    }
+
+    #[test]
+    #[cfg(not(feature = "encryption"))]
+    fn encoder_encrypted_unsupported() {
+        assert!(matches!(
+            Encoder::new(
+                Vec::new(),
+                &Header {
+                    flags: HeaderFlags::ENCRYPTED,
+                    page_size: PageSize::new(4096).unwrap(),
+                    commit: Some(PageNum::new(3).unwrap()),
+                    min_txid: TXID::new(5).unwrap(),
+                    max_txid: TXID::new(6).unwrap(),
+                    timestamp: time::SystemTime::now(),
+                    pre_apply_checksum: Some(Checksum::new(5)),
+                    node_id: 0,
+                    wal: None,
+                    app_data: None,
+                    page_filter: None,
+                    key_id: None,
+                    dict_id: None,
+                },
+            ),
+            Err(Error::UnsupportedFlags(HeaderFlags::ENCRYPTED))
+        ));
+    }
+
+    #[test]
+    fn encoder_page_index() {
+        let mut header = Header {
+            flags: HeaderFlags::PAGE_INDEX,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(3).unwrap()),
+            min_txid: TXID::new(5).unwrap(),
+            max_txid: TXID::new(6).unwrap(),
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: Some(Checksum::new(5)),
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &header).expect("failed to create encoder");
+        for page_num in 1..=2 {
+            enc.encode_page(PageNum::new(page_num).unwrap(), &[0; 4096])
+                .expect("failed to encode page");
+        }
+        enc.finish(Checksum::new(6))
+            .expect("failed to finish encoder");
+
+        assert_eq!(
+            ltx::HEADER_SIZE + (4096 + 4) * 2 + 4 + (12 * 2 + 4) + ltx::TRAILER_SIZE,
+            buf.len()
+        );
+        // The second entry points right past the first page.
+        let index = &buf[buf.len() - ltx::TRAILER_SIZE - 28..];
+        assert_eq!(
+            (ltx::HEADER_SIZE + 4096 + 4) as u64,
+            u64::from_be_bytes(index[16..24].try_into().unwrap())
+        );
+
+        header.flags |= HeaderFlags::COMPRESS_ZSTD;
+        assert!(matches!(
+            Encoder::new(Vec::new(), &header),
+            Err(Error::UnsupportedFlags(HeaderFlags::COMPRESS_ZSTD))
+        ));
+    }
+
+    #[test]
+    fn encoder_page_filter() {
+        let header = Header {
+            flags: HeaderFlags::COMPRESS_LZ4,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
+            min_txid: TXID::new(5).unwrap(),
+            max_txid: TXID::new(6).unwrap(),
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: Some(Checksum::new(5)),
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+        let pages = [2, 4].map(|n| PageNum::new(n).unwrap());
+        let filter = PageFilter::from_iter(pages);
+        let builder = Encoder::builder().page_filter(filter);
+
+        let mut buf = Vec::new();
+        let mut enc = builder
+            .build(&mut buf, &header)
+            .expect("failed to create encoder");
+        for page_num in pages {
+            enc.encode_page(page_num, &[0; 4096])
+                .expect("failed to encode page");
+        }
+        enc.finish(Checksum::new(6))
+            .expect("failed to finish encoder");
+
+        let (mut dec, header_out) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        assert!(header_out.flags.contains(HeaderFlags::PAGE_FILTER));
+        assert_eq!(Some(true), header_out.may_contain(pages[1]));
+        while dec.skip_page().expect("failed to skip page").is_some() {}
+        dec.finish().expect("failed to finish decoder");
+
+        let missing = (1..=10)
+            .map(|n| PageNum::new(n).unwrap())
+            .find(|pgno| !filter.may_contain(*pgno))
+            .unwrap();
+        let mut enc = builder
+            .build(Vec::new(), &header)
+            .expect("failed to create encoder");
+        assert!(matches!(
+            enc.encode_page(missing, &[0; 4096]),
+            Err(Error::PageNotInFilter(p)) if p == missing
+        ));
+    }
+
+    #[test]
+    fn encoder_dictionary() {
+        let dict: Vec<u8> = (0..4096).map(|i| (i * 31 % 253) as u8).collect();
+        dictionary::register(Dictionary::new(2578, dict.clone()).unwrap())
+            .expect("failed to register dictionary");
+
+        let encode = |dict_id: Option<u32>| {
+            let mut flags = HeaderFlags::COMPRESS_LZ4 | HeaderFlags::PAGE_INDEX;
+            if dict_id.is_some() {
+                flags |= HeaderFlags::LZ4_DICTIONARY;
+            }
+            let header = Header {
+                flags,
+                page_size: PageSize::new(512).unwrap(),
+                commit: Some(PageNum::new(8).unwrap()),
+                min_txid: TXID::ONE,
+                max_txid: TXID::ONE,
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id,
+            };
+            let mut buf = Vec::new();
+            let mut enc = Encoder::new(&mut buf, &header)?;
+            for (page_num, page) in (1..=8).zip(dict.chunks(512)) {
+                enc.encode_page(PageNum::new(page_num).unwrap(), page)?;
+            }
+            enc.finish_auto()?;
+            Ok::<_, Error>(buf)
+        };
+
+        let plain = encode(None).expect("failed to encode without dictionary");
+        let buf = encode(Some(2578)).expect("failed to encode with dictionary");
+        assert!(buf.len() < plain.len() / 2);
+
+        let (mut dec, header) =
+            Decoder::new(std::io::Cursor::new(&buf)).expect("failed to create decoder");
+        assert_eq!(Some(2578), header.dict_id);
+        let mut page = vec![0; 512];
+        assert!(dec
+            .seek_page(PageNum::new(5).unwrap(), &mut page)
+            .expect("failed to seek page"));
+        assert_eq!(&dict[2048..2560], page);
+        for chunk in dict.chunks(512) {
+            dec.decode_page(&mut page).expect("failed to decode page");
+            assert_eq!(chunk, page);
+        }
+        assert_eq!(None, dec.decode_page(&mut page).unwrap());
+        dec.finish().expect("failed to finish decoder");
+
+        assert!(matches!(
+            encode(Some(2579)),
+            Err(Error::UnknownDictionary(2579))
+        ));
+    }
+
+    #[test]
+    fn encoder_per_page_compression() {
+        let pages: Vec<Vec<u8>> = (0..8)
+            .map(|i| {
+                if i % 2 == 0 {
+                    (0..4096).map(|_| rand::random()).collect()
+                } else {
+                    vec![i as u8; 4096]
+                }
+            })
+            .collect();
+        let encode = |flags, pages: &[Vec<u8>]| {
+            let header = Header {
+                flags,
+                page_size: PageSize::new(4096).unwrap(),
+                commit: Some(PageNum::new(pages.len() as u32).unwrap()),
+                min_txid: TXID::ONE,
+                max_txid: TXID::ONE,
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            };
+            let mut buf = Vec::new();
+            let mut enc = Encoder::new(&mut buf, &header).expect("failed to create encoder");
+            for (page_num, page) in (1..).zip(pages) {
+                enc.encode_page(PageNum::new(page_num).unwrap(), page)
+                    .expect("failed to encode page");
+            }
+            enc.finish_auto().expect("failed to finish encoder");
+            buf
+        };
+        let per_page = HeaderFlags::COMPRESS_LZ4 | HeaderFlags::PER_PAGE_COMPRESSION;
+
+        // Incompressible pages take as much space as in an uncompressed file.
+        let random: Vec<_> = pages.iter().step_by(2).cloned().collect();
+        assert_eq!(
+            encode(HeaderFlags::empty(), &random).len(),
+            encode(per_page, &random).len()
+        );
+
+        let buf = encode(
+            per_page | HeaderFlags::PAGE_CHECKSUM | HeaderFlags::PAGE_INDEX,
+            &pages,
+        );
+        let offset = ltx::HEADER_SIZE + ltx::PAGE_HEADER_SIZE + 4096 + ltx::PAGE_CHECKSUM_SIZE;
+        assert_eq!(&2u32.to_be_bytes(), &buf[offset..offset + 4]);
+
+        let (mut dec, _) =
+            Decoder::new(std::io::Cursor::new(&buf)).expect("failed to create decoder");
+        let mut page = vec![0; 4096];
+        for n in [2, 5] {
+            assert!(dec
+                .seek_page(PageNum::new(n).unwrap(), &mut page)
+                .expect("failed to seek page"));
+            assert_eq!(pages[n as usize - 1], page);
+        }
+        for expected in &pages {
+            dec.decode_page(&mut page).expect("failed to decode page");
+            assert_eq!(expected, &page);
+        }
+        assert_eq!(None, dec.decode_page(&mut page).unwrap());
+        dec.finish().expect("failed to finish decoder");
+    }
+
     #[test]
     fn encoder_lock_page() {
         let mut buf = Vec::new();
@@ -349,11 +2314,17 @@ mod tests {
             &Header {
                 flags: HeaderFlags::empty(),
                 page_size: PageSize::new(4096).unwrap(),
-                commit: PageNum::new(3).unwrap(),
+                commit: Some(PageNum::new(3).unwrap()),
                 min_txid: TXID::new(1).unwrap(),
                 max_txid: TXID::new(1).unwrap(),
                 timestamp: time::SystemTime::now(),
                 pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
             },
         )
         .expect("failed to create encoder");
@@ -369,6 +2340,42 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn encoder_incomplete_snapshot() {
+        let header = Header {
+            flags: HeaderFlags::empty(),
+            page_size: PageSize::new(512).unwrap(),
+            commit: Some(PageNum::new(3).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let mut enc = Encoder::new(Vec::new(), &header).expect("failed to create encoder");
+        for page_num in 1..=2 {
+            enc.encode_page(PageNum::new(page_num).unwrap(), &[0; 512])
+                .expect("failed to encode page");
+        }
+        assert!(matches!(
+            enc.finish_auto(),
+            Err(Error::IncompleteSnapshot(Some(got), want))
+                if got == PageNum::new(2).unwrap() && want == PageNum::new(3).unwrap()
+        ));
+
+        let enc = Encoder::new(Vec::new(), &header).expect("failed to create encoder");
+        assert!(matches!(
+            enc.finish(Checksum::new(0)),
+            Err(Error::IncompleteSnapshot(None, _))
+        ));
+    }
+
     #[test]
     fn encoder_non_sequential() {
         let mut buf = Vec::new();
@@ -378,11 +2385,17 @@ mod tests {
             &Header {
                 flags: HeaderFlags::empty(),
                 page_size: PageSize::new(4096).unwrap(),
-                commit: PageNum::new(3).unwrap(),
+                commit: Some(PageNum::new(3).unwrap()),
                 min_txid: TXID::new(1).unwrap(),
                 max_txid: TXID::new(1).unwrap(),
                 timestamp: time::SystemTime::now(),
                 pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
             },
         )
         .expect("failed to create encoder");
@@ -407,11 +2420,17 @@ mod tests {
             &Header {
                 flags: HeaderFlags::empty(),
                 page_size: PageSize::new(4096).unwrap(),
-                commit: PageNum::new(3).unwrap(),
+                commit: Some(PageNum::new(3).unwrap()),
                 min_txid: TXID::new(2).unwrap(),
                 max_txid: TXID::new(5).unwrap(),
                 timestamp: time::SystemTime::now(),
                 pre_apply_checksum: Some(Checksum::new(1)),
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
             },
         )
         .expect("failed to create encoder");
@@ -436,11 +2455,17 @@ mod tests {
             &Header {
                 flags: HeaderFlags::empty(),
                 page_size: PageSize::new(4096).unwrap(),
-                commit: PageNum::new(3).unwrap(),
+                commit: Some(PageNum::new(3).unwrap()),
                 min_txid: TXID::new(1).unwrap(),
                 max_txid: TXID::new(1).unwrap(),
                 timestamp: time::SystemTime::now(),
                 pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
             },
         )
         .expect("failed to create encoder");
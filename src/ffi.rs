@@ -0,0 +1,497 @@
+//! A C interface to [`Encoder`] and [`Decoder`].
+//!
+//! Files are read and written through callbacks. Functions report failures through
+//! their return value, and [`ltx_last_error`] describes the last failure on the
+//! calling thread. The C declarations are in `include/litetx.h`, generated by cbindgen
+//! from this module. Build the library with e.g.
+//! `cargo rustc --release --features ffi --crate-type staticlib`.
+//!
+//! ```c
+//! LtxHeader header;
+//! LtxDecoder *dec = ltx_decoder_new(read_file, file, &header);
+//! if (dec == NULL) {
+//!     fprintf(stderr, "ltx: %s\n", ltx_last_error());
+//!     return -1;
+//! }
+//!
+//! uint8_t *page = malloc(header.page_size);
+//! int64_t page_num;
+//! while ((page_num = ltx_decode_page(dec, page, header.page_size)) > 0) {
+//!     // do something with the page
+//! }
+//! LtxTrailer trailer;
+//! if (page_num < 0 || ltx_decoder_finish(dec, &trailer) != 0) {
+//!     fprintf(stderr, "ltx: %s\n", ltx_last_error());
+//! }
+//! ```
+
+use crate::{Checksum, Decoder, Encoder, Header, HeaderFlags, PageNum, PageSize, WalFrames, TXID};
+use std::{
+    cell::RefCell,
+    error::Error,
+    ffi::{c_char, c_int, c_void, CString},
+    io, ptr, slice,
+};
+
+/// Reads up to `len` bytes into `buf`, returning the number of bytes read, zero at the
+/// end of the input, or a negative value on error.
+pub type LtxReadFn = unsafe extern "C" fn(ctx: *mut c_void, buf: *mut u8, len: usize) -> isize;
+
+/// Writes up to `len` bytes of `buf`, returning the number of bytes written or a
+/// negative value on error.
+pub type LtxWriteFn = unsafe extern "C" fn(ctx: *mut c_void, buf: *const u8, len: usize) -> isize;
+
+/// The header of an LTX file. Unset optional fields are zero.
+///
+/// Page filters aren't supported.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LtxHeader {
+    pub flags: u32,
+    pub page_size: u32,
+    pub commit: u32,
+    pub min_txid: u64,
+    pub max_txid: u64,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub pre_apply_checksum: u64,
+    pub wal_offset: u64,
+    pub wal_size: u64,
+    pub wal_salt1: u32,
+    pub wal_salt2: u32,
+    pub node_id: u64,
+    pub key_id: u32,
+    pub dict_id: u32,
+    pub app_data: [u8; 16],
+}
+
+/// The trailer of an LTX file. The post-apply checksum is zero if unset.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LtxTrailer {
+    pub post_apply_checksum: u64,
+    pub file_checksum: u64,
+}
+
+/// An LTX file decoder.
+pub struct LtxDecoder(Decoder<'static, CallbackReader>);
+
+/// An LTX file encoder.
+pub struct LtxEncoder(Encoder<'static, CallbackWriter>);
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Return the description of the last failure on the calling thread, or NULL if none.
+///
+/// The string is valid until the next failure on the thread.
+#[no_mangle]
+pub extern "C" fn ltx_last_error() -> *const c_char {
+    LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+/// Create a decoder reading from `read` called with `ctx`, and store the header of the
+/// file in `header`. Return NULL on failure.
+///
+/// # Safety
+/// `header` must be valid for writes, and `read` must follow [`LtxReadFn`] until the
+/// decoder is freed.
+#[no_mangle]
+pub unsafe extern "C" fn ltx_decoder_new(
+    read: LtxReadFn,
+    ctx: *mut c_void,
+    header: *mut LtxHeader,
+) -> *mut LtxDecoder {
+    if header.is_null() {
+        set_last_error("null header");
+        return ptr::null_mut();
+    }
+
+    match Decoder::new(CallbackReader { read, ctx }) {
+        Ok((dec, hdr)) => {
+            *header = LtxHeader::from(&hdr);
+            Box::into_raw(Box::new(LtxDecoder(dec)))
+        }
+        Err(e) => {
+            set_error(&e);
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Decode the next page into `buf` of `len` bytes, the page size of the file.
+///
+/// Return the page number, zero once all pages have been decoded, or -1 on failure.
+///
+/// # Safety
+/// `dec` must come from [`ltx_decoder_new`] and `buf` must be valid for writes of
+/// `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ltx_decode_page(dec: *mut LtxDecoder, buf: *mut u8, len: usize) -> i64 {
+    let (Some(dec), false) = (dec.as_mut(), buf.is_null()) else {
+        set_last_error("null decoder or buffer");
+        return -1;
+    };
+
+    match dec.0.decode_page(slice::from_raw_parts_mut(buf, len)) {
+        Ok(page_num) => page_num.map_or(0, |n| n.into_inner().into()),
+        Err(e) => {
+            set_error(&e);
+            -1
+        }
+    }
+}
+
+/// Verify the trailer and the file checksum once all pages have been decoded, store the
+/// trailer in `trailer` and free the decoder. Return 0 on success or -1 on failure.
+///
+/// # Safety
+/// `dec` must come from [`ltx_decoder_new`] and isn't valid afterwards. `trailer` must
+/// be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ltx_decoder_finish(
+    dec: *mut LtxDecoder,
+    trailer: *mut LtxTrailer,
+) -> c_int {
+    if dec.is_null() || trailer.is_null() {
+        set_last_error("null decoder or trailer");
+        return -1;
+    }
+
+    match Box::from_raw(dec).0.finish() {
+        Ok(t) => {
+            *trailer = LtxTrailer {
+                post_apply_checksum: t.post_apply_checksum.map_or(0, |c| c.into_inner()),
+                file_checksum: t.file_checksum.into_inner(),
+            };
+            0
+        }
+        Err(e) => {
+            set_error(&e);
+            -1
+        }
+    }
+}
+
+/// Free a decoder without finishing it.
+///
+/// # Safety
+/// `dec` must be NULL or come from [`ltx_decoder_new`], and isn't valid afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ltx_decoder_free(dec: *mut LtxDecoder) {
+    if !dec.is_null() {
+        drop(Box::from_raw(dec));
+    }
+}
+
+/// Create an encoder writing the file described by `header` to `write` called with
+/// `ctx`. Return NULL on failure.
+///
+/// # Safety
+/// `header` must be valid for reads, and `write` must follow [`LtxWriteFn`] until the
+/// encoder is freed.
+#[no_mangle]
+pub unsafe extern "C" fn ltx_encoder_new(
+    write: LtxWriteFn,
+    ctx: *mut c_void,
+    header: *const LtxHeader,
+) -> *mut LtxEncoder {
+    let Some(header) = header.as_ref() else {
+        set_last_error("null header");
+        return ptr::null_mut();
+    };
+
+    let enc = Header::try_from(header)
+        .and_then(|hdr| Ok(Encoder::new(CallbackWriter { write, ctx }, &hdr)?));
+    match enc {
+        Ok(enc) => Box::into_raw(Box::new(LtxEncoder(enc))),
+        Err(e) => {
+            set_error(e.as_ref());
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Encode page `page_num` from `buf` of `len` bytes, the page size of the file.
+/// Return 0 on success or -1 on failure.
+///
+/// # Safety
+/// `enc` must come from [`ltx_encoder_new`] and `buf` must be valid for reads of `len`
+/// bytes.
+#[no_mangle]
+pub unsafe extern "C" fn ltx_encoder_encode_page(
+    enc: *mut LtxEncoder,
+    page_num: u32,
+    buf: *const u8,
+    len: usize,
+) -> c_int {
+    let (Some(enc), false) = (enc.as_mut(), buf.is_null()) else {
+        set_last_error("null encoder or buffer");
+        return -1;
+    };
+
+    let result = PageNum::new(page_num)
+        .map_err(Box::<dyn Error>::from)
+        .and_then(|n| Ok(enc.0.encode_page(n, slice::from_raw_parts(buf, len))?));
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            set_error(e.as_ref());
+            -1
+        }
+    }
+}
+
+/// Write the trailer with `post_apply_checksum`, which is ignored for files without
+/// checksums, store it in `trailer` and free the encoder. Return 0 on success or -1 on
+/// failure.
+///
+/// # Safety
+/// `enc` must come from [`ltx_encoder_new`] and isn't valid afterwards. `trailer` must
+/// be NULL or valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn ltx_encoder_finish(
+    enc: *mut LtxEncoder,
+    post_apply_checksum: u64,
+    trailer: *mut LtxTrailer,
+) -> c_int {
+    if enc.is_null() {
+        set_last_error("null encoder");
+        return -1;
+    }
+
+    let checksum = (post_apply_checksum != 0).then(|| Checksum::new(post_apply_checksum));
+    match Box::from_raw(enc).0.finish(checksum) {
+        Ok(t) => {
+            if let Some(trailer) = trailer.as_mut() {
+                *trailer = LtxTrailer {
+                    post_apply_checksum: t.post_apply_checksum.map_or(0, |c| c.into_inner()),
+                    file_checksum: t.file_checksum.into_inner(),
+                };
+            }
+            0
+        }
+        Err(e) => {
+            set_error(&e);
+            -1
+        }
+    }
+}
+
+/// Free an encoder without finishing the file.
+///
+/// # Safety
+/// `enc` must be NULL or come from [`ltx_encoder_new`], and isn't valid afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn ltx_encoder_free(enc: *mut LtxEncoder) {
+    if !enc.is_null() {
+        drop(Box::from_raw(enc));
+    }
+}
+
+fn set_error<E>(e: &E)
+where
+    E: Error + ?Sized,
+{
+    let mut msg = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        msg.push_str(&format!(": {e}"));
+        source = e.source();
+    }
+    set_last_error(msg);
+}
+
+fn set_last_error<S>(msg: S)
+where
+    S: Into<String>,
+{
+    let msg = CString::new(msg.into().replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(msg));
+}
+
+impl From<&Header> for LtxHeader {
+    fn from(hdr: &Header) -> Self {
+        let wal = hdr.wal.unwrap_or(WalFrames {
+            offset: 0,
+            size: 0,
+            salt1: 0,
+            salt2: 0,
+        });
+        LtxHeader {
+            flags: hdr.flags.bits(),
+            page_size: hdr.page_size.into_inner(),
+            commit: hdr.commit.map_or(0, |c| c.into_inner()),
+            min_txid: hdr.min_txid.into_inner(),
+            max_txid: hdr.max_txid.into_inner(),
+            timestamp: hdr.timestamp_millis().unwrap_or(0),
+            pre_apply_checksum: hdr.pre_apply_checksum.map_or(0, |c| c.into_inner()),
+            wal_offset: wal.offset,
+            wal_size: wal.size,
+            wal_salt1: wal.salt1,
+            wal_salt2: wal.salt2,
+            node_id: hdr.node_id,
+            key_id: hdr.key_id.unwrap_or(0),
+            dict_id: hdr.dict_id.unwrap_or(0),
+            app_data: hdr.app_data.unwrap_or_default(),
+        }
+    }
+}
+
+impl TryFrom<&LtxHeader> for Header {
+    type Error = Box<dyn Error>;
+
+    fn try_from(h: &LtxHeader) -> Result<Self, Self::Error> {
+        let wal = WalFrames {
+            offset: h.wal_offset,
+            size: h.wal_size,
+            salt1: h.wal_salt1,
+            salt2: h.wal_salt2,
+        };
+        let mut hdr = Header {
+            flags: HeaderFlags::from_bits(h.flags).ok_or("unknown header flags")?,
+            page_size: PageSize::new(h.page_size)?,
+            commit: (h.commit != 0)
+                .then(|| PageNum::new(h.commit))
+                .transpose()?,
+            min_txid: TXID::new(h.min_txid)?,
+            max_txid: TXID::new(h.max_txid)?,
+            timestamp: std::time::UNIX_EPOCH,
+            pre_apply_checksum: (h.pre_apply_checksum != 0)
+                .then(|| Checksum::new(h.pre_apply_checksum)),
+            node_id: h.node_id,
+            wal: (wal.offset != 0 || wal.size != 0 || wal.salt1 != 0 || wal.salt2 != 0)
+                .then_some(wal),
+            app_data: h.app_data.iter().any(|&b| b != 0).then_some(h.app_data),
+            page_filter: None,
+            key_id: (h.key_id != 0).then_some(h.key_id),
+            dict_id: (h.dict_id != 0).then_some(h.dict_id),
+        };
+        hdr.set_timestamp_millis(h.timestamp);
+
+        Ok(hdr)
+    }
+}
+
+/// A reader calling a [`LtxReadFn`].
+struct CallbackReader {
+    read: LtxReadFn,
+    ctx: *mut c_void,
+}
+
+impl io::Read for CallbackReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = unsafe { (self.read)(self.ctx, buf.as_mut_ptr(), buf.len()) };
+        usize::try_from(n)
+            .ok()
+            .filter(|&n| n <= buf.len())
+            .ok_or_else(|| io::Error::other("read callback failed"))
+    }
+}
+
+/// A writer calling a [`LtxWriteFn`].
+struct CallbackWriter {
+    write: LtxWriteFn,
+    ctx: *mut c_void,
+}
+
+impl io::Write for CallbackWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = unsafe { (self.write)(self.ctx, buf.as_ptr(), buf.len()) };
+        usize::try_from(n)
+            .ok()
+            .filter(|&n| n <= buf.len())
+            .ok_or_else(|| io::Error::other("write callback failed"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        ltx_decode_page, ltx_decoder_finish, ltx_decoder_new, ltx_encoder_encode_page,
+        ltx_encoder_finish, ltx_encoder_new, ltx_last_error, LtxHeader, LtxTrailer,
+    };
+    use crate::{Checksum, HeaderFlags};
+    use std::ffi::{c_void, CStr};
+
+    unsafe extern "C" fn read_slice(ctx: *mut c_void, buf: *mut u8, len: usize) -> isize {
+        let r = &mut *(ctx as *mut &[u8]);
+        let n = len.min(r.len());
+        std::ptr::copy_nonoverlapping(r.as_ptr(), buf, n);
+        *r = &r[n..];
+        n as isize
+    }
+
+    unsafe extern "C" fn write_vec(ctx: *mut c_void, buf: *const u8, len: usize) -> isize {
+        let w = &mut *(ctx as *mut Vec<u8>);
+        w.extend_from_slice(std::slice::from_raw_parts(buf, len));
+        len as isize
+    }
+
+    #[test]
+    fn ffi_roundtrip() {
+        let header = LtxHeader {
+            flags: HeaderFlags::COMPRESS_LZ4.bits(),
+            page_size: 512,
+            commit: 2,
+            min_txid: 1,
+            max_txid: 1,
+            timestamp: 1234,
+            pre_apply_checksum: 0,
+            wal_offset: 0,
+            wal_size: 0,
+            wal_salt1: 0,
+            wal_salt2: 0,
+            node_id: 7,
+            key_id: 0,
+            dict_id: 0,
+            app_data: [0; 16],
+        };
+
+        let mut buf = Vec::new();
+        let mut trailer = LtxTrailer {
+            post_apply_checksum: 0,
+            file_checksum: 0,
+        };
+        unsafe {
+            let ctx = &mut buf as *mut Vec<u8> as *mut c_void;
+            let enc = ltx_encoder_new(write_vec, ctx, &header);
+            assert!(!enc.is_null());
+            assert_eq!(-1, ltx_encoder_encode_page(enc, 2, [1; 512].as_ptr(), 512));
+            let err = CStr::from_ptr(ltx_last_error()).to_str().unwrap();
+            assert!(err.contains("snapshot"), "{err}");
+            assert_eq!(0, ltx_encoder_encode_page(enc, 1, [1; 512].as_ptr(), 512));
+            assert_eq!(0, ltx_encoder_encode_page(enc, 2, [2; 512].as_ptr(), 512));
+            assert_eq!(0, ltx_encoder_finish(enc, 0x1234, &mut trailer));
+        }
+        assert_eq!(
+            Checksum::new(0x1234).into_inner(),
+            trailer.post_apply_checksum
+        );
+
+        let mut r = buf.as_slice();
+        let mut header_out = header;
+        header_out.flags = 0;
+        let mut trailer_out = trailer;
+        trailer_out.file_checksum = 0;
+        unsafe {
+            let ctx = &mut r as *mut &[u8] as *mut c_void;
+            let dec = ltx_decoder_new(read_slice, ctx, &mut header_out);
+            assert!(!dec.is_null());
+            let mut page = [0; 512];
+            assert_eq!(1, ltx_decode_page(dec, page.as_mut_ptr(), 512));
+            assert_eq!([1; 512], page);
+            assert_eq!(2, ltx_decode_page(dec, page.as_mut_ptr(), 512));
+            assert_eq!([2; 512], page);
+            assert_eq!(0, ltx_decode_page(dec, page.as_mut_ptr(), 512));
+            assert_eq!(0, ltx_decoder_finish(dec, &mut trailer_out));
+        }
+        assert_eq!(header, header_out);
+        assert_eq!(trailer, trailer_out);
+    }
+}
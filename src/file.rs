@@ -0,0 +1,307 @@
+use crate::{encoder::Error, Checksum, Encoder, Header, PageNum, Trailer};
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+};
+
+/// How a written file is made durable before the operation writing it returns.
+///
+/// Syncing trades throughput for crash safety: without it, a file that was written
+/// successfully may be lost or hold stale data after a power failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncMode {
+    /// Leave writing the file back to the operating system.
+    None,
+    /// Sync the contents of the file and the metadata needed to read them, e.g. its
+    /// size, as with `fdatasync`.
+    Data,
+    /// Sync the contents and all the metadata of the file, as with `fsync`.
+    #[default]
+    Full,
+}
+
+impl SyncMode {
+    /// Sync `file` according to the mode.
+    pub(crate) fn sync(self, file: &fs::File) -> io::Result<()> {
+        match self {
+            SyncMode::None => Ok(()),
+            SyncMode::Data => file.sync_data(),
+            SyncMode::Full => file.sync_all(),
+        }
+    }
+}
+
+/// An [`Encoder`] creating an LTX file atomically.
+///
+/// The file is written to `<path>.tmp`, which is synced to disk and renamed to `path`
+/// by [`FileEncoder::finish`]. If the encoder is dropped before it's finished, the
+/// temporary file is removed, so `path` never holds a partially written file. The sync
+/// of the file and of its directory, which makes the rename durable, can be relaxed
+/// with [`FileEncoder::sync`] and [`FileEncoder::sync_dir`].
+///
+/// # Example
+/// ```no_run
+/// # use std::time::SystemTime;
+/// # use litetx::PageChecksum;
+/// # let page = vec![0; 4096];
+/// let mut enc = litetx::FileEncoder::create(
+///     "0000000000000001-0000000000000001.ltx",
+///     &litetx::Header {
+///         flags: litetx::HeaderFlags::COMPRESS_LZ4,
+///         page_size: litetx::PageSize::new(4096).unwrap(),
+///         commit: Some(litetx::PageNum::ONE),
+///         min_txid: litetx::TXID::ONE,
+///         max_txid: litetx::TXID::ONE,
+///         timestamp: SystemTime::now(),
+///         pre_apply_checksum: None,
+///         node_id: 0,
+///         wal: None,
+///         app_data: None,
+///         page_filter: None,
+///         key_id: None,
+///         dict_id: None,
+///     },
+/// )
+/// .expect("file encoder");
+///
+/// enc.encode_page(litetx::PageNum::ONE, &page).expect("encode_page");
+/// enc.finish(page.page_checksum(litetx::PageNum::ONE)).expect("finish");
+/// ```
+pub struct FileEncoder<'a> {
+    enc: Encoder<'a, BufWriter<fs::File>>,
+    path: PathBuf,
+    tmp: TempPath,
+    sync: SyncMode,
+    sync_dir: bool,
+}
+
+impl<'a> FileEncoder<'a> {
+    /// Create a new [`FileEncoder`] that writes the LTX file to `path`.
+    ///
+    /// An existing temporary file is overwritten.
+    pub fn create<P>(path: P, hdr: &Header) -> Result<FileEncoder<'a>, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref().to_path_buf();
+        let mut tmp_path = OsString::from(&path);
+        tmp_path.push(".tmp");
+
+        let file = fs::File::create(&tmp_path)?;
+        let tmp = TempPath(Some(PathBuf::from(tmp_path)));
+        let enc = Encoder::new(BufWriter::new(file), hdr)?;
+
+        Ok(FileEncoder {
+            enc,
+            path,
+            tmp,
+            sync: SyncMode::Full,
+            sync_dir: true,
+        })
+    }
+
+    /// Set how the file is synced before it's renamed. Defaults to [`SyncMode::Full`].
+    pub fn sync(mut self, mode: SyncMode) -> Self {
+        self.sync = mode;
+        self
+    }
+
+    /// Set whether the directory of the file is synced after the rename. Defaults to
+    /// `true`.
+    pub fn sync_dir(mut self, sync: bool) -> Self {
+        self.sync_dir = sync;
+        self
+    }
+
+    /// Return the path of the resulting LTX file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Encode a page with the given `page_num` into the file.
+    ///
+    /// See [`Encoder::encode_page`] for details.
+    pub fn encode_page(&mut self, page_num: PageNum, data: &[u8]) -> Result<(), Error> {
+        self.enc.encode_page(page_num, data)
+    }
+
+    /// Write the LTX trailer, sync the file to disk and move it to its final path.
+    ///
+    /// See [`Encoder::finish`] for details.
+    pub fn finish<C>(self, post_apply_checksum: C) -> Result<Trailer, Error>
+    where
+        C: Into<Option<Checksum>>,
+    {
+        let FileEncoder {
+            enc,
+            path,
+            mut tmp,
+            sync,
+            sync_dir,
+        } = self;
+
+        let (trailer, w) = enc.finish_into_inner(post_apply_checksum.into())?;
+        let file = w.into_inner().map_err(io::IntoInnerError::into_error)?;
+        sync.sync(&file)?;
+        drop(file);
+
+        if let Some(tmp_path) = &tmp.0 {
+            fs::rename(tmp_path, &path)?;
+        }
+        tmp.0 = None;
+        if sync_dir {
+            sync_parent_dir(&path)?;
+        }
+
+        Ok(trailer)
+    }
+
+    /// Consume the encoder and remove the partially written temporary file.
+    ///
+    /// Unlike dropping the encoder, a failure to remove the file is reported.
+    pub fn abort(self) -> io::Result<()> {
+        let FileEncoder { enc, mut tmp, .. } = self;
+        enc.abort();
+
+        match tmp.0.take() {
+            Some(tmp_path) => fs::remove_file(tmp_path),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A temporary file path, removed on drop unless cleared.
+pub(crate) struct TempPath(pub(crate) Option<PathBuf>);
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if let Some(path) = self.0.take() {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Make the creation or rename of a file in its parent directory durable.
+#[cfg(unix)]
+pub(crate) fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    fs::File::open(dir)?.sync_all()
+}
+
+// Directories can't be opened, and so synced, on other platforms.
+#[cfg(not(unix))]
+pub(crate) fn sync_parent_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FileEncoder, SyncMode};
+    use crate::{utils::TempDir, Checksum, Decoder, Header, HeaderFlags, PageNum, PageSize, TXID};
+    use std::{fs, time};
+
+    fn header() -> Header {
+        Header {
+            flags: HeaderFlags::COMPRESS_LZ4,
+            page_size: PageSize::new(512).unwrap(),
+            commit: Some(PageNum::ONE),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        }
+    }
+
+    #[test]
+    fn file_encoder() {
+        let dir = TempDir::new();
+        let path = dir.path().join("0000000000000001-0000000000000001.ltx");
+        let tmp_path = dir.path().join("0000000000000001-0000000000000001.ltx.tmp");
+
+        let mut enc = FileEncoder::create(&path, &header()).expect("failed to create encoder");
+        enc.encode_page(PageNum::ONE, &[1; 512])
+            .expect("failed to encode page");
+        assert!(tmp_path.exists());
+        assert!(!path.exists());
+
+        let trailer = enc
+            .finish(Checksum::new(1))
+            .expect("failed to finish encoder");
+        assert!(!tmp_path.exists());
+
+        let (mut dec, _) = Decoder::new(fs::File::open(&path).expect("failed to open file"))
+            .expect("failed to create decoder");
+        let mut page = vec![0; 512];
+        while dec
+            .decode_page(&mut page)
+            .expect("failed to decode page")
+            .is_some()
+        {}
+        assert_eq!(trailer, dec.finish().expect("failed to finish decoder"));
+    }
+
+    #[test]
+    fn file_encoder_drop() {
+        let dir = TempDir::new();
+        let path = dir.path().join("0000000000000001-0000000000000001.ltx");
+
+        let mut enc = FileEncoder::create(&path, &header()).expect("failed to create encoder");
+        enc.encode_page(PageNum::ONE, &[1; 512])
+            .expect("failed to encode page");
+        drop(enc);
+
+        assert!(!path.exists());
+        assert_eq!(0, fs::read_dir(dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn file_encoder_abort() {
+        let dir = TempDir::new();
+        let path = dir.path().join("0000000000000001-0000000000000001.ltx");
+
+        let mut enc = FileEncoder::create(&path, &header()).expect("failed to create encoder");
+        enc.encode_page(PageNum::ONE, &[1; 512])
+            .expect("failed to encode page");
+        enc.abort().expect("failed to abort encoder");
+
+        assert!(!path.exists());
+        assert_eq!(0, fs::read_dir(dir.path()).unwrap().count());
+    }
+
+    #[test]
+    fn file_encoder_no_sync() {
+        let dir = TempDir::new();
+        let path = dir.path().join("0000000000000001-0000000000000001.ltx");
+
+        let mut enc = FileEncoder::create(&path, &header())
+            .expect("failed to create encoder")
+            .sync(SyncMode::None)
+            .sync_dir(false);
+        enc.encode_page(PageNum::ONE, &[1; 512])
+            .expect("failed to encode page");
+        let trailer = enc
+            .finish(Checksum::new(1))
+            .expect("failed to finish encoder");
+
+        let (mut dec, _) = Decoder::new(fs::File::open(&path).expect("failed to open file"))
+            .expect("failed to create decoder");
+        let mut page = vec![0; 512];
+        while dec
+            .decode_page(&mut page)
+            .expect("failed to decode page")
+            .is_some()
+        {}
+        assert_eq!(trailer, dec.finish().expect("failed to finish decoder"));
+    }
+}
@@ -0,0 +1,158 @@
+//! Planning of the removal of obsolete LTX files.
+
+use crate::{LtxFile, Pos, TXID};
+use std::path::PathBuf;
+
+/// An LTX file that is safe to delete, as reported by [`plan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Obsolete {
+    /// The path of the file.
+    pub path: PathBuf,
+    /// The path of the later snapshot covering all the transactions of the file.
+    pub covered_by: PathBuf,
+}
+
+/// The result of [`plan`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// The lowest referenced transaction, `None` if no position is referenced.
+    pub min_referenced: Option<TXID>,
+    /// The files that are safe to delete, ordered by transaction ID.
+    pub obsolete: Vec<Obsolete>,
+    /// The files that must be kept, ordered by transaction ID.
+    pub retained: Vec<PathBuf>,
+}
+
+/// Determine which of `files` are safe to delete, given the database positions still
+/// `referenced` by replicas or readers.
+///
+/// A file is obsolete if a later snapshot covers all of its transactions and no
+/// referenced position is lower than its last transaction, i.e. no reader needs it to
+/// catch up. The latest snapshot is always retained. Nothing is deleted, so the report
+/// can be audited before the files are removed.
+///
+/// # Example
+/// ```no_run
+/// let dir = litetx::LtxDirectory::open("/var/lib/ltx").expect("open");
+/// let replica: litetx::Pos = "0000000000000064/8a3b5f0e9c1d2e7a".parse().expect("pos");
+///
+/// let report = litetx::gc::plan(dir.files(), &[replica]);
+/// for file in &report.obsolete {
+///     println!("{} is covered by {}", file.path.display(), file.covered_by.display());
+/// }
+/// ```
+pub fn plan(files: &[LtxFile], referenced: &[Pos]) -> Report {
+    let min_referenced = referenced.iter().map(|pos| pos.txid).min();
+
+    let latest_snapshot = files
+        .iter()
+        .filter(|f| f.header.is_snapshot())
+        .max_by_key(|f| f.header.max_txid);
+
+    let mut obsolete = Vec::new();
+    let mut retained = Vec::new();
+    for file in files {
+        let covered_by = latest_snapshot.filter(|snapshot| {
+            snapshot.path != file.path && snapshot.header.max_txid >= file.header.max_txid
+        });
+        let needed = min_referenced.is_some_and(|txid| txid < file.header.max_txid);
+
+        match covered_by {
+            Some(snapshot) if !needed => obsolete.push(Obsolete {
+                path: file.path.clone(),
+                covered_by: snapshot.path.clone(),
+            }),
+            _ => retained.push(file.path.clone()),
+        }
+    }
+
+    Report {
+        min_referenced,
+        obsolete,
+        retained,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{plan, Obsolete};
+    use crate::{
+        name, Checksum, Header, HeaderFlags, LtxFile, PageNum, PageSize, Pos, Trailer, TXID,
+    };
+    use std::{path::PathBuf, time};
+
+    fn file(min_txid: u64, max_txid: u64) -> LtxFile {
+        let (min_txid, max_txid) = (TXID::new(min_txid).unwrap(), TXID::new(max_txid).unwrap());
+        LtxFile {
+            path: PathBuf::from(name::format_filename(min_txid, max_txid)),
+            header: Header {
+                flags: HeaderFlags::empty(),
+                page_size: PageSize::new(512).unwrap(),
+                commit: Some(PageNum::ONE),
+                min_txid,
+                max_txid,
+                timestamp: time::UNIX_EPOCH,
+                pre_apply_checksum: (min_txid > TXID::ONE).then_some(Checksum::new(1)),
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+            trailer: Trailer {
+                post_apply_checksum: Some(Checksum::new(1)),
+                file_checksum: Checksum::new(0),
+            },
+        }
+    }
+
+    fn pos(txid: u64) -> Pos {
+        Pos {
+            txid: TXID::new(txid).unwrap(),
+            post_apply_checksum: Checksum::new(1),
+        }
+    }
+
+    #[test]
+    fn plan_gc() {
+        let files = [
+            file(1, 2),
+            file(1, 4),
+            file(1, 6),
+            file(3, 3),
+            file(4, 4),
+            file(5, 6),
+            file(7, 7),
+        ];
+        let paths = |indices: &[usize]| -> Vec<PathBuf> {
+            indices.iter().map(|i| files[*i].path.clone()).collect()
+        };
+
+        let report = plan(&files, &[pos(6), pos(4)]);
+        assert_eq!(Some(TXID::new(4).unwrap()), report.min_referenced);
+        assert_eq!(
+            paths(&[0, 1, 3, 4]),
+            report
+                .obsolete
+                .iter()
+                .map(|o| o.path.clone())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            Obsolete {
+                path: files[0].path.clone(),
+                covered_by: files[2].path.clone(),
+            },
+            report.obsolete[0]
+        );
+        assert_eq!(paths(&[2, 5, 6]), report.retained);
+
+        let report = plan(&files, &[]);
+        assert_eq!(None, report.min_referenced);
+        assert_eq!(paths(&[2, 6]), report.retained);
+
+        let report = plan(&files, &[pos(1)]);
+        assert!(report.obsolete.is_empty());
+    }
+}
@@ -0,0 +1,199 @@
+//! Canonical LTX files with their expected contents, for asserting byte-level
+//! compatibility between implementations.
+//!
+//! The files live in `testdata/golden` so that other implementations can read them
+//! directly. They all use 512 byte pages, with page `n` holding `(i * n) as u8` at
+//! offset `i` (see [`page`]), and are timestamped [`TIMESTAMP_MS`]:
+//!
+//! | Name         | Flags          | TXIDs  | Commit | Pages   |
+//! |--------------|----------------|--------|--------|---------|
+//! | `snapshot`   |                | 1 – 1  | 2      | 1, 2    |
+//! | `delta`      |                | 2 – 3  | 3      | 3       |
+//! | `compressed` | `COMPRESS_LZ4` | 1 – 1  | 2      | 1, 2    |
+//! | `deletion`   |                | 4 – 4  | –      |         |
+//!
+//! The delta applies on top of the snapshot and the deletion on top of the delta, so
+//! their pre-apply checksums are the post-apply checksums of the previous file.
+
+use crate::{Checksum, Header, HeaderFlags, PageNum, PageSize, Trailer, TXID};
+use std::time;
+
+/// The page size of all the vectors.
+pub const PAGE_SIZE: u32 = 512;
+
+/// The timestamp of all the vectors, in milliseconds since the Unix epoch.
+pub const TIMESTAMP_MS: u64 = 1_700_000_000_000;
+
+/// A canonical LTX file.
+#[derive(Debug)]
+pub struct Vector {
+    /// The name of the file in `testdata/golden`, without the `.ltx` extension.
+    pub name: &'static str,
+    /// The encoded file.
+    pub data: &'static [u8],
+    /// The header of the file.
+    pub header: Header,
+    /// The pages of the file, in order.
+    pub pages: Vec<(PageNum, Vec<u8>)>,
+    /// The trailer of the file.
+    pub trailer: Trailer,
+}
+
+/// Return the contents of page `pgno` in the vectors.
+pub fn page(pgno: PageNum) -> Vec<u8> {
+    (0..PAGE_SIZE)
+        .map(|i| i.wrapping_mul(pgno.into_inner()) as u8)
+        .collect()
+}
+
+/// Return all the vectors.
+pub fn vectors() -> Vec<Vector> {
+    let header = Header {
+        flags: HeaderFlags::empty(),
+        page_size: PageSize::new(PAGE_SIZE).unwrap(),
+        commit: Some(PageNum::new(2).unwrap()),
+        min_txid: TXID::ONE,
+        max_txid: TXID::ONE,
+        timestamp: time::UNIX_EPOCH + time::Duration::from_millis(TIMESTAMP_MS),
+        pre_apply_checksum: None,
+        node_id: 0,
+        wal: None,
+        app_data: None,
+        page_filter: None,
+        key_id: None,
+        dict_id: None,
+    };
+    let pages = |pgnos: &[u32]| {
+        pgnos
+            .iter()
+            .map(|&n| PageNum::new(n).unwrap())
+            .map(|pgno| (pgno, page(pgno)))
+            .collect()
+    };
+
+    vec![
+        Vector {
+            name: "snapshot",
+            data: include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/testdata/golden/snapshot.ltx"
+            )),
+            header: header.clone(),
+            pages: pages(&[1, 2]),
+            trailer: Trailer {
+                post_apply_checksum: Some(Checksum::new(SNAPSHOT_CHECKSUM)),
+                file_checksum: Checksum::new(0x02606390de804b5f),
+            },
+        },
+        Vector {
+            name: "delta",
+            data: include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/testdata/golden/delta.ltx"
+            )),
+            header: Header {
+                commit: Some(PageNum::new(3).unwrap()),
+                min_txid: TXID::new(2).unwrap(),
+                max_txid: TXID::new(3).unwrap(),
+                pre_apply_checksum: Some(Checksum::new(SNAPSHOT_CHECKSUM)),
+                ..header.clone()
+            },
+            pages: pages(&[3]),
+            trailer: Trailer {
+                post_apply_checksum: Some(Checksum::new(DELTA_CHECKSUM)),
+                file_checksum: Checksum::new(0x234d88f94d43372b),
+            },
+        },
+        Vector {
+            name: "compressed",
+            data: include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/testdata/golden/compressed.ltx"
+            )),
+            header: Header {
+                flags: HeaderFlags::COMPRESS_LZ4,
+                ..header.clone()
+            },
+            pages: pages(&[1, 2]),
+            trailer: Trailer {
+                post_apply_checksum: Some(Checksum::new(SNAPSHOT_CHECKSUM)),
+                file_checksum: Checksum::new(0x777bfa33b5318d9e),
+            },
+        },
+        Vector {
+            name: "deletion",
+            data: include_bytes!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/testdata/golden/deletion.ltx"
+            )),
+            header: Header {
+                commit: None,
+                min_txid: TXID::new(4).unwrap(),
+                max_txid: TXID::new(4).unwrap(),
+                pre_apply_checksum: Some(Checksum::new(DELTA_CHECKSUM)),
+                ..header
+            },
+            pages: Vec::new(),
+            trailer: Trailer {
+                post_apply_checksum: Some(Checksum::new(0)),
+                file_checksum: Checksum::new(0x0b6c5fecb1d9aca6),
+            },
+        },
+    ]
+}
+
+const SNAPSHOT_CHECKSUM: u64 = 0x2e56578bf6222411;
+const DELTA_CHECKSUM: u64 = 0x44e39205f9244a37;
+
+#[cfg(test)]
+mod tests {
+    use super::{vectors, PAGE_SIZE};
+    use crate::{Decoder, Encoder};
+
+    #[test]
+    fn golden_encode() {
+        for v in vectors() {
+            let mut buf = Vec::new();
+            let mut enc = Encoder::new(&mut buf, &v.header).expect("failed to create encoder");
+            for (pgno, data) in &v.pages {
+                enc.encode_page(*pgno, data).expect("failed to encode page");
+            }
+            let trailer = if v.header.is_snapshot() {
+                enc.finish_auto()
+            } else {
+                enc.finish(v.trailer.post_apply_checksum)
+            }
+            .expect("failed to finish encoder");
+
+            assert_eq!(v.trailer, trailer, "{}", v.name);
+            assert!(v.data == buf.as_slice(), "{} differs", v.name);
+        }
+    }
+
+    #[test]
+    fn golden_decode() {
+        for v in vectors() {
+            let (mut dec, header) = Decoder::new(v.data).expect("failed to create decoder");
+            assert_eq!(v.header, header, "{}", v.name);
+
+            let mut page = vec![0; PAGE_SIZE as usize];
+            for (pgno, data) in &v.pages {
+                let page_num = dec.decode_page(&mut page).expect("failed to decode page");
+                assert_eq!(Some(*pgno), page_num, "{}", v.name);
+                assert_eq!(data, &page, "{}", v.name);
+            }
+            assert_eq!(
+                None,
+                dec.decode_page(&mut page).expect("failed to decode page"),
+                "{}",
+                v.name
+            );
+            assert_eq!(
+                v.trailer,
+                dec.finish().expect("failed to finish decoder"),
+                "{}",
+                v.name
+            );
+        }
+    }
+}
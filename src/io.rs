@@ -0,0 +1,168 @@
+//! The I/O traits used by the parts of the crate that build without `std`.
+//!
+//! With the `std` feature, these are the `std::io` items, so the API takes standard
+//! readers and writers. Without it, minimal `Read` and `Write` traits take their place,
+//! implemented for byte slices and vectors.
+
+#[cfg(feature = "std")]
+pub use std::io::{Error, ErrorKind, Read, Result, Write};
+
+#[cfg(not(feature = "std"))]
+pub use self::imp::*;
+
+#[cfg(not(feature = "std"))]
+mod imp {
+    use alloc::{boxed::Box, vec::Vec};
+    use core::{error, fmt};
+
+    /// A result of an I/O operation.
+    pub type Result<T> = core::result::Result<T, Error>;
+
+    /// A category of I/O errors.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[non_exhaustive]
+    pub enum ErrorKind {
+        InvalidInput,
+        InvalidData,
+        UnexpectedEof,
+        WriteZero,
+        Other,
+    }
+
+    /// An I/O error.
+    #[derive(Debug)]
+    pub struct Error {
+        kind: ErrorKind,
+        error: Option<Box<dyn error::Error + Send + Sync>>,
+    }
+
+    impl Error {
+        /// Create an error of the given kind from an arbitrary error payload.
+        pub fn new<E>(kind: ErrorKind, error: E) -> Error
+        where
+            E: Into<Box<dyn error::Error + Send + Sync>>,
+        {
+            Error {
+                kind,
+                error: Some(error.into()),
+            }
+        }
+
+        /// Create an error of kind [`ErrorKind::Other`].
+        pub fn other<E>(error: E) -> Error
+        where
+            E: Into<Box<dyn error::Error + Send + Sync>>,
+        {
+            Error::new(ErrorKind::Other, error)
+        }
+
+        /// Return the kind of the error.
+        pub fn kind(&self) -> ErrorKind {
+            self.kind
+        }
+    }
+
+    impl From<ErrorKind> for Error {
+        fn from(kind: ErrorKind) -> Self {
+            Error { kind, error: None }
+        }
+    }
+
+    impl fmt::Display for Error {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            match &self.error {
+                Some(error) => fmt::Display::fmt(error, f),
+                None => write!(f, "{:?}", self.kind),
+            }
+        }
+    }
+
+    impl error::Error for Error {
+        fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+            self.error.as_ref().and_then(|e| e.source())
+        }
+    }
+
+    /// A source of bytes.
+    pub trait Read {
+        /// Read some bytes into `buf`, returning how many were read. Zero means the end
+        /// of the input if `buf` isn't empty.
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize>;
+
+        /// Fill `buf` completely, failing with [`ErrorKind::UnexpectedEof`] if the input
+        /// ends first.
+        fn read_exact(&mut self, mut buf: &mut [u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.read(buf)? {
+                    0 => return Err(ErrorKind::UnexpectedEof.into()),
+                    n => buf = &mut buf[n..],
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// A sink of bytes.
+    pub trait Write {
+        /// Write some bytes of `buf`, returning how many were written.
+        fn write(&mut self, buf: &[u8]) -> Result<usize>;
+
+        /// Flush buffered data.
+        fn flush(&mut self) -> Result<()>;
+
+        /// Write all of `buf`, failing with [`ErrorKind::WriteZero`] if the sink stops
+        /// accepting data.
+        fn write_all(&mut self, mut buf: &[u8]) -> Result<()> {
+            while !buf.is_empty() {
+                match self.write(buf)? {
+                    0 => return Err(ErrorKind::WriteZero.into()),
+                    n => buf = &buf[n..],
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl<R> Read for &mut R
+    where
+        R: Read + ?Sized,
+    {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            (**self).read(buf)
+        }
+    }
+
+    impl Read for &[u8] {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+            let n = buf.len().min(self.len());
+            let (data, rest) = self.split_at(n);
+            buf[..n].copy_from_slice(data);
+            *self = rest;
+            Ok(n)
+        }
+    }
+
+    impl<W> Write for &mut W
+    where
+        W: Write + ?Sized,
+    {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            (**self).write(buf)
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            (**self).flush()
+        }
+    }
+
+    impl Write for Vec<u8> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize> {
+            self.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,163 @@
+//! The rollback journal protecting a database while [`chain`](crate::apply::chain)
+//! applies LTX files to it.
+//!
+//! The journal holds the original size of the database and the original contents of
+//! every page that may be written or truncated, followed by the CRC-ISO-64 of
+//! everything before it. It's synced to disk before the database is touched, so a
+//! journal with a mismatching checksum means the database is still intact.
+//!
+//! | Offset | Size | Description                              |
+//! | ------ | ---- | ---------------------------------------- |
+//! | 0      | 4    | Magic number. Always "LTXJ".             |
+//! | 4      | 4    | Page size, in bytes.                     |
+//! | 8      | 8    | Original size of the database, in bytes. |
+//! | 16     | ...  | Page number and contents of every page.  |
+//! | N      | 8    | Checksum (CRC-ISO-64).                   |
+
+use crate::{file, ltx::CRC64, PageSize};
+use std::{
+    ffi::OsString,
+    fs,
+    io::{self, Read, Seek, Write},
+    path::{Path, PathBuf},
+};
+
+const MAGIC: &[u8; 4] = b"LTXJ";
+const HEADER_SIZE: u64 = 16;
+const CHECKSUM_SIZE: u64 = 8;
+
+/// Return the path of the journal of the database at `db_path`.
+pub(crate) fn path(db_path: &Path) -> PathBuf {
+    let mut path = OsString::from(db_path);
+    path.push("-ltxjournal");
+    PathBuf::from(path)
+}
+
+/// Write the journal at `path` holding the pages of `db` in `pages`, and sync it along
+/// with its directory.
+///
+/// Pages past the end of the database are skipped.
+pub(crate) fn write<I>(
+    path: &Path,
+    db: &mut fs::File,
+    page_size: PageSize,
+    pages: I,
+) -> io::Result<()>
+where
+    I: IntoIterator<Item = u32>,
+{
+    let db_len = db.metadata()?.len();
+    let page_size = page_size.into_inner();
+    let db_pages = db_len / page_size as u64;
+
+    let mut w = io::BufWriter::new(fs::File::create(path)?);
+    let mut digest = CRC64.digest();
+    let mut write = |buf: &[u8]| {
+        digest.update(buf);
+        w.write_all(buf)
+    };
+
+    write(MAGIC)?;
+    write(&page_size.to_be_bytes())?;
+    write(&db_len.to_be_bytes())?;
+
+    let mut page = vec![0; page_size as usize];
+    for pgno in pages.into_iter().filter(|&n| n as u64 <= db_pages) {
+        db.seek(io::SeekFrom::Start((pgno as u64 - 1) * page_size as u64))?;
+        db.read_exact(&mut page)?;
+        write(&pgno.to_be_bytes())?;
+        write(&page)?;
+    }
+
+    w.write_all(&digest.finalize().to_be_bytes())?;
+    w.into_inner()
+        .map_err(io::IntoInnerError::into_error)?
+        .sync_all()?;
+    // The journal only protects the database once its directory entry is durable.
+    file::sync_parent_dir(path)
+}
+
+/// Restore `db` from the journal at `path` and remove the journal.
+///
+/// Returns `false` if there's no journal or it's incomplete, in which case the
+/// database is left as is.
+pub(crate) fn rollback(path: &Path, db: &mut fs::File) -> io::Result<bool> {
+    let mut r = match fs::File::open(path) {
+        Ok(f) => io::BufReader::new(f),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    let valid = match verify(&mut r)? {
+        Some((page_size, db_len)) => {
+            restore(&mut r, db, page_size, db_len)?;
+            true
+        }
+        None => false,
+    };
+
+    fs::remove_file(path)?;
+    Ok(valid)
+}
+
+/// Verify the checksum of the journal read from `r`, returning the page size and the
+/// original size of the database if it's intact.
+fn verify<R>(mut r: R) -> io::Result<Option<(u32, u64)>>
+where
+    R: Read + Seek,
+{
+    let len = r.seek(io::SeekFrom::End(0))?;
+    r.rewind()?;
+
+    let mut header = [0; HEADER_SIZE as usize];
+    match r.read_exact(&mut header) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        result => result?,
+    }
+    let page_size = u32::from_be_bytes(header[4..8].try_into().unwrap());
+    let db_len = u64::from_be_bytes(header[8..16].try_into().unwrap());
+    if &header[..4] != MAGIC
+        || PageSize::new(page_size).is_err()
+        || len < HEADER_SIZE + CHECKSUM_SIZE
+        || !(len - HEADER_SIZE - CHECKSUM_SIZE).is_multiple_of(4 + page_size as u64)
+    {
+        return Ok(None);
+    }
+
+    let mut digest = CRC64.digest();
+    digest.update(&header);
+    let mut buf = vec![0; page_size as usize];
+    let mut remaining = len - HEADER_SIZE - CHECKSUM_SIZE;
+    while remaining > 0 {
+        let n = remaining.min(buf.len() as u64) as usize;
+        r.read_exact(&mut buf[..n])?;
+        digest.update(&buf[..n]);
+        remaining -= n as u64;
+    }
+
+    let mut checksum = [0; CHECKSUM_SIZE as usize];
+    r.read_exact(&mut checksum)?;
+    Ok((u64::from_be_bytes(checksum) == digest.finalize()).then_some((page_size, db_len)))
+}
+
+/// Write the pages of a verified journal read from `r` back into `db`.
+fn restore<R>(mut r: R, db: &mut fs::File, page_size: u32, db_len: u64) -> io::Result<()>
+where
+    R: Read + Seek,
+{
+    let len = r.seek(io::SeekFrom::End(0))?;
+    r.seek(io::SeekFrom::Start(HEADER_SIZE))?;
+
+    let mut pgno = [0; 4];
+    let mut page = vec![0; page_size as usize];
+    for _ in 0..(len - HEADER_SIZE - CHECKSUM_SIZE) / (4 + page_size as u64) {
+        r.read_exact(&mut pgno)?;
+        r.read_exact(&mut page)?;
+        let pgno = u32::from_be_bytes(pgno) as u64;
+        db.seek(io::SeekFrom::Start((pgno - 1) * page_size as u64))?;
+        db.write_all(&page)?;
+    }
+
+    db.set_len(db_len)?;
+    db.sync_all()
+}
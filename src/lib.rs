@@ -1,12 +1,160 @@
 #![doc = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/README.md"))]
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod apply;
+#[cfg(feature = "async")]
+mod async_io;
+mod bare;
+#[cfg(feature = "std")]
+mod buffered;
+#[cfg(feature = "codec")]
+mod codec;
+#[cfg(feature = "std")]
+pub mod compaction;
+#[cfg(feature = "std")]
+mod compactor;
+#[cfg(feature = "std")]
+pub mod compression;
+#[cfg(feature = "encryption")]
+mod crypto;
+#[cfg(feature = "std")]
 mod decoder;
+#[cfg(feature = "dedup")]
+pub mod dedup;
+#[cfg(feature = "std")]
+pub mod dictionary;
+#[cfg(feature = "std")]
+mod diff;
+#[cfg(feature = "std")]
+mod direct;
+#[cfg(feature = "std")]
+mod directory;
+#[cfg(feature = "std")]
+mod dump;
+#[cfg(feature = "std")]
 mod encoder;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "std")]
+mod file;
+#[cfg(feature = "std")]
+pub mod gc;
+#[cfg(feature = "golden")]
+pub mod golden;
+pub mod io;
+#[cfg(feature = "std")]
+mod journal;
 mod ltx;
+mod lz4;
+#[cfg(feature = "std")]
+mod merkle;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "std")]
+pub mod name;
+#[cfg(feature = "rusqlite")]
+pub mod online;
+#[cfg(feature = "std")]
+pub mod overlay;
+#[cfg(feature = "std")]
+mod page_store;
+#[cfg(feature = "std")]
+mod pool;
+#[cfg(feature = "std")]
+mod progress;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "std")]
+pub mod repair;
+#[cfg(feature = "std")]
+pub mod restore;
+#[cfg(feature = "std")]
+pub mod salvage;
+#[cfg(feature = "signature")]
+pub mod signature;
+#[cfg(feature = "std")]
+mod snapshot;
+#[cfg(feature = "std")]
+mod split;
+#[cfg(feature = "std")]
+pub mod sqlite;
+#[cfg(feature = "store")]
+pub mod store;
+#[cfg(feature = "std")]
+mod stream;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+#[cfg(feature = "std")]
+mod throttle;
+pub mod time;
+#[cfg(feature = "std")]
+mod transcode;
 mod types;
+#[cfg(test)]
 mod utils;
+#[cfg(feature = "std")]
+mod verify;
+#[cfg(feature = "std")]
+pub mod wal;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
-pub use crate::ltx::{Header, HeaderFlags, PageChecksum, Trailer};
-pub use types::{Checksum, PageNum, PageSize, Pos, TXID};
+#[cfg(feature = "std")]
+pub use crate::ltx::read_header_from_path;
+pub use crate::ltx::{
+    page_checksum_from_reader, read_header, DatabaseChecksum, Header, HeaderDecodeError,
+    HeaderFlags, HeaderValidateError, PageChecksum, PageFilter, PageFilterError, PageHasher,
+    PosDecodeError, Trailer, TrailerDecodeError, WalFrames, APP_DATA_SIZE, PAGE_FILTER_SIZE,
+};
+pub use types::{Checksum, PageNum, PageSize, Pos, PosParseError, TXID};
 
-pub use decoder::{Decoder, Error as DecodeError};
-pub use encoder::{Encoder, Error as EncodeError};
+#[cfg(feature = "async")]
+pub use async_io::{AsyncDecoder, AsyncEncoder};
+pub use bare::{BareDecoder, BareEncoder, Error as BareError};
+#[cfg(feature = "std")]
+pub use buffered::{BufferedEncoder, Error as BufferedError};
+#[cfg(feature = "codec")]
+pub use codec::{Error as CodecError, LtxCodec};
+#[cfg(feature = "std")]
+pub use compactor::{Compactor, Error as CompactError};
+#[cfg(feature = "encryption")]
+pub use crypto::KeyProvider;
+#[cfg(feature = "std")]
+pub use decoder::{
+    DecodeLimit, DecodeOptions, Decoder, DecoderBuilder, Error as DecodeError, FileSection, Pages,
+};
+#[cfg(feature = "std")]
+pub use diff::{diff, DiffReport, Error as DiffError};
+#[cfg(feature = "std")]
+pub use direct::{AlignedBuf, DirectFile, DIRECT_ALIGN};
+#[cfg(feature = "std")]
+pub use directory::{Error as DirectoryError, LtxDirectory, LtxFile};
+#[cfg(feature = "std")]
+pub use dump::{dump, DumpFormat, Error as DumpError};
+#[cfg(feature = "std")]
+pub use encoder::{Encoder, EncoderBuilder, Error as EncodeError, Lz4BlockSize};
+#[cfg(feature = "std")]
+pub use file::{FileEncoder, SyncMode};
+#[cfg(feature = "mmap")]
+pub use mmap::{MmapDecoder, MmapPages};
+#[cfg(feature = "std")]
+pub use page_store::{Error as PageStoreError, PageStore};
+#[cfg(feature = "std")]
+pub use pool::{PagePool, PooledPage};
+#[cfg(feature = "std")]
+pub use progress::{ProgressEvent, Stats};
+#[cfg(feature = "std")]
+pub use snapshot::{encode_db_diff, encode_db_snapshot, Error as SnapshotError, SnapshotOptions};
+#[cfg(feature = "std")]
+pub use split::{Error as SplitError, SplitEncoder};
+#[cfg(feature = "std")]
+pub use stream::{Error as StreamError, StreamDecoder, StreamFile, StreamReader};
+#[cfg(feature = "std")]
+pub use throttle::Throttle;
+#[cfg(feature = "std")]
+pub use transcode::{transcode, Error as TranscodeError};
+#[cfg(feature = "std")]
+pub use verify::{verify, Error as VerifyError};
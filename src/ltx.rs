@@ -1,15 +1,63 @@
-use crate::types::{Checksum, PageNum, PageNumError, PageSize, PageSizeError, TXIDError, TXID};
-use std::{io, time};
-
-pub(crate) const CRC64: crc::Crc<u64> = crc::Crc::<u64>::new(&crc::CRC_64_GO_ISO);
+#[cfg(feature = "std")]
+use crate::compression;
+use crate::{
+    io, time,
+    types::{Checksum, PageNum, PageNumError, PageSize, PageSizeError, Pos, TXIDError, TXID},
+};
+#[cfg(feature = "std")]
+use alloc::vec::Vec;
+use alloc::{
+    string::{String, ToString},
+    vec,
+};
+use core::fmt;
+#[cfg(feature = "std")]
+use std::{fs, path::Path};
+
+// Slicing-by-16 processes 16 bytes per lookup step at the cost of a 32 KB table.
+#[cfg(feature = "fast-crc")]
+type Crc64Impl = crc::Table<16>;
+#[cfg(not(feature = "fast-crc"))]
+type Crc64Impl = crc::Table<1>;
+
+pub(crate) type Crc64Digest<'a> = crc::Digest<'a, u64, Crc64Impl>;
+
+pub(crate) const CRC64: crc::Crc<u64, Crc64Impl> =
+    crc::Crc::<u64, Crc64Impl>::new(&crc::CRC_64_GO_ISO);
 
 bitflags::bitflags! {
-    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    /// Serialized as the names of the set flags, e.g. `"COMPRESS_LZ4 | NO_CHECKSUM"`.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+    #[serde(transparent)]
     pub struct HeaderFlags: u32 {
-        const COMPRESS_LZ4 = 0b00000001;
+        const COMPRESS_LZ4 = 0x00000001;
+        const NO_CHECKSUM = 0x00000002;
+
+        // Flags in the upper half are extensions of this crate and are kept out of
+        // the range used by the Go implementation.
+        const COMPRESS_ZSTD = 0x00010000;
+        const ENCRYPTED = 0x00020000;
+        const PAGE_INDEX = 0x00040000;
+        const MERKLE_TREE = 0x00080000;
+        const PAGE_CHECKSUM = 0x00100000;
+        const PAGE_FILTER = 0x00200000;
+        const LZ4_DICTIONARY = 0x00400000;
+        const PER_PAGE_COMPRESSION = 0x00800000;
     }
 }
 
+impl HeaderFlags {
+    const COMPRESSION: HeaderFlags = HeaderFlags::COMPRESS_LZ4.union(HeaderFlags::COMPRESS_ZSTD);
+}
+
+/// Return the flags of the registered compression codecs, none without `std`.
+fn registered_compression_flags() -> HeaderFlags {
+    #[cfg(feature = "std")]
+    return compression::registered_flags();
+    #[cfg(not(feature = "std"))]
+    HeaderFlags::empty()
+}
+
 /// A header validation error.
 #[derive(thiserror::Error, Debug)]
 pub enum HeaderValidateError {
@@ -19,6 +67,22 @@ pub enum HeaderValidateError {
     PreApplyChecksumOnSnapshot,
     #[error("pre-apply checksum required on non-snapshot files")]
     NoPreApplyChecksum,
+    #[error("pre-apply checksum must be unset on files without checksums")]
+    PreApplyChecksumWithNoChecksum,
+    #[error("multiple compression flags set: {0:?}")]
+    CompressionFlags(HeaderFlags),
+    #[error("invalid WAL frames: {0:?}")]
+    WalFrames(WalFrames),
+    #[error("application data must not be all zeros")]
+    AppData,
+    #[error("page filter must be set if and only if the page filter flag is")]
+    PageFilter,
+    #[error("key id must be non-zero and only set on encrypted files")]
+    KeyId,
+    #[error("dictionary id must be set if and only if the dictionary flag is, with lz4")]
+    DictId,
+    #[error("per-page compression requires lz4 and a commit below 2^31")]
+    PerPageCompression,
 }
 
 /// A header encoding error.
@@ -43,8 +107,6 @@ pub enum HeaderDecodeError {
     Flags(u32),
     #[error("invalid page size record")]
     PageSize(#[from] PageSizeError),
-    #[error("invalid commit record: {0}")]
-    Commit(PageNumError),
     #[error("invalid min TX ID record: {0}")]
     MinTXID(TXIDError),
     #[error("invalid max TX ID record: {0}")]
@@ -56,28 +118,272 @@ pub enum HeaderDecodeError {
 }
 
 pub(crate) const HEADER_SIZE: usize = 100;
+/// The size of the application data stored in the reserved bytes at the end of the
+/// header.
+pub const APP_DATA_SIZE: usize = 16;
+const KEY_ID_OFFSET: usize = 80;
+const APP_DATA_OFFSET: usize = HEADER_SIZE - APP_DATA_SIZE;
 pub(crate) const TRAILER_SIZE: usize = 16;
 pub(crate) const PAGE_HEADER_SIZE: usize = 4;
+pub(crate) const PAGE_CHECKSUM_SIZE: usize = 8;
+/// The bit of the page header marking pages stored raw in files with the
+/// [`HeaderFlags::PER_PAGE_COMPRESSION`] flag.
+const RAW_PAGE_FLAG: u32 = 1 << 31;
+#[cfg(feature = "std")]
+pub(crate) const PAGE_INDEX_ENTRY_SIZE: usize = 12;
+#[cfg(feature = "std")]
+pub(crate) const PAGE_INDEX_COUNT_SIZE: usize = 4;
+/// The size of the [`PageFilter`] following the header of files with the
+/// [`HeaderFlags::PAGE_FILTER`] flag.
+pub const PAGE_FILTER_SIZE: usize = 256;
+const PAGE_FILTER_HASHES: u32 = 4;
+pub(crate) const DICT_ID_SIZE: usize = 4;
+/// The size of a header followed by a page filter and a dictionary ID.
+const MAX_HEADER_SIZE: usize = HEADER_SIZE + PAGE_FILTER_SIZE + DICT_ID_SIZE;
+
+/// A fixed-size buffer filled front to back, to encode without allocating.
+struct FixedBuf<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+}
+
+impl<const N: usize> FixedBuf<N> {
+    fn new() -> Self {
+        FixedBuf {
+            buf: [0; N],
+            len: 0,
+        }
+    }
+
+    fn put(&mut self, bytes: &[u8]) {
+        self.buf[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+    }
+
+    /// Fill the buffer with zeros up to `len` bytes.
+    fn pad(&mut self, len: usize) {
+        self.len = self.len.max(len);
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
 
 /// An LTX file header.
-#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// Serialized with camel case field names, hex transaction IDs and checksums, and
+/// the timestamp in milliseconds since the Unix epoch.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Header {
     /// Flags changing the behavior of LTX encoder/decoder.
     pub flags: HeaderFlags,
     /// The size of the database pages encoded in the file.
     pub page_size: PageSize,
-    /// The size of the database in pages.
-    pub commit: PageNum,
+    /// The size of the database in pages. `None` if the database has been deleted, in
+    /// which case the file contains no pages.
+    pub commit: Option<PageNum>,
     /// Minimum transaction ID in the file.
+    #[serde(rename = "minTXID")]
     pub min_txid: TXID,
     /// Maximum transaction ID in the file. May be equal to `min_txid` if the file
     /// contains only one transaction.
+    #[serde(rename = "maxTXID")]
     pub max_txid: TXID,
     /// The time when the LTX file was created.
+    #[serde(with = "timestamp_millis")]
     pub timestamp: time::SystemTime,
     /// Running database checksum before this LTX file is applied. `None` if the LTX
     /// file contains the full snapshot of a database.
     pub pre_apply_checksum: Option<Checksum>,
+    /// ID of the node that created the LTX file. Zero if unset.
+    #[serde(rename = "nodeID")]
+    pub node_id: u64,
+    /// The WAL frames the LTX file was created from. `None` if the file wasn't created
+    /// from a WAL.
+    pub wal: Option<WalFrames>,
+    /// Application defined data, e.g. an identifier of the site the file originates
+    /// from. `None` if unset, must not be all zeros otherwise.
+    ///
+    /// The data is stored in the reserved bytes at the end of the header, which the Go
+    /// implementation ignores when decoding and zeroes when encoding, so it's lost when
+    /// a file is rewritten by Go, e.g. by compaction.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub app_data: Option<[u8; APP_DATA_SIZE]>,
+    /// A filter of the page numbers in the file, stored right after the header. Must be
+    /// set if and only if the flags contain [`HeaderFlags::PAGE_FILTER`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_filter: Option<PageFilter>,
+    /// The ID of the key the page block is encrypted with, looked up in the key
+    /// provider of the encoder and decoder. `None` if unset, must not be zero and is
+    /// only valid with the [`HeaderFlags::ENCRYPTED`] flag.
+    ///
+    /// Stored in the reserved bytes of the header before the application data.
+    #[serde(default, rename = "keyID", skip_serializing_if = "Option::is_none")]
+    pub key_id: Option<u32>,
+    /// The ID of the [`Dictionary`](crate::dictionary::Dictionary) the page block is
+    /// compressed with, stored after the header and the page filter. Must be set if and
+    /// only if the flags contain [`HeaderFlags::LZ4_DICTIONARY`], which requires
+    /// [`HeaderFlags::COMPRESS_LZ4`].
+    #[serde(default, rename = "dictID", skip_serializing_if = "Option::is_none")]
+    pub dict_id: Option<u32>,
+}
+
+/// A Bloom filter of the page numbers in a file, answering whether a file may touch a
+/// page from its header alone.
+///
+/// The filter has no false negatives. False positives become frequent once the filter
+/// holds more than a few hundred pages, so it's meant for delta files.
+///
+/// # Example
+/// ```
+/// let pages = [2, 3, 5].map(|n| litetx::PageNum::new(n).unwrap());
+/// let filter = litetx::PageFilter::from_iter(pages);
+/// assert!(filter.may_contain(pages[1]));
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(into = "String", try_from = "String")]
+pub struct PageFilter([u8; PAGE_FILTER_SIZE]);
+
+impl PageFilter {
+    /// Construct an empty filter.
+    pub fn new() -> PageFilter {
+        PageFilter([0; PAGE_FILTER_SIZE])
+    }
+
+    /// Add a page to the filter.
+    pub fn insert(&mut self, pgno: PageNum) {
+        for bit in Self::bits(pgno) {
+            self.0[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+
+    /// Return `false` if the page is definitely not in the filter.
+    pub fn may_contain(&self, pgno: PageNum) -> bool {
+        Self::bits(pgno).all(|bit| self.0[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    /// Return the bytes of the filter, as stored in the file.
+    pub fn as_bytes(&self) -> &[u8; PAGE_FILTER_SIZE] {
+        &self.0
+    }
+
+    /// Return the bits set for `pgno`, derived by double hashing from the SplitMix64
+    /// finalizer of the page number.
+    fn bits(pgno: PageNum) -> impl Iterator<Item = usize> {
+        let mut hash = pgno.into_inner() as u64;
+        hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d049bb133111eb);
+        hash ^= hash >> 31;
+
+        let (h1, h2) = (hash as u32, (hash >> 32) as u32 | 1);
+        (0..PAGE_FILTER_HASHES)
+            .map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) as usize) % (PAGE_FILTER_SIZE * 8))
+    }
+}
+
+impl Default for PageFilter {
+    fn default() -> Self {
+        PageFilter::new()
+    }
+}
+
+impl FromIterator<PageNum> for PageFilter {
+    fn from_iter<I>(iter: I) -> Self
+    where
+        I: IntoIterator<Item = PageNum>,
+    {
+        let mut filter = PageFilter::new();
+        iter.into_iter().for_each(|pgno| filter.insert(pgno));
+        filter
+    }
+}
+
+impl fmt::Debug for PageFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PageFilter({self})")
+    }
+}
+
+impl fmt::Display for PageFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.iter().try_for_each(|b| write!(f, "{b:02x}"))
+    }
+}
+
+impl From<PageFilter> for String {
+    fn from(filter: PageFilter) -> Self {
+        filter.to_string()
+    }
+}
+
+impl TryFrom<String> for PageFilter {
+    type Error = PageFilterError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        if value.len() != PAGE_FILTER_SIZE * 2 || !value.is_ascii() {
+            return Err(PageFilterError);
+        }
+
+        let mut filter = PageFilter::new();
+        for (b, hex) in filter.0.iter_mut().zip(value.as_bytes().chunks_exact(2)) {
+            let hex = core::str::from_utf8(hex).map_err(|_| PageFilterError)?;
+            *b = u8::from_str_radix(hex, 16).map_err(|_| PageFilterError)?;
+        }
+
+        Ok(filter)
+    }
+}
+
+/// An error representing an invalid serialized [`PageFilter`].
+#[derive(thiserror::Error, Debug)]
+#[error("invalid page filter")]
+pub struct PageFilterError;
+
+/// The position of the SQLite WAL frames an LTX file was created from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct WalFrames {
+    /// Offset of the first frame in the WAL file. Must not be zero.
+    pub offset: u64,
+    /// Total size of the frames, in bytes. Must not be zero.
+    pub size: u64,
+    /// The first salt value of the WAL header.
+    pub salt1: u32,
+    /// The second salt value of the WAL header.
+    pub salt2: u32,
+}
+
+/// Serde of timestamps as milliseconds since the Unix epoch, as in the file header.
+mod timestamp_millis {
+    use crate::time;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub(super) fn serialize<S>(timestamp: &time::SystemTime, s: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let millis = timestamp
+            .duration_since(time::UNIX_EPOCH)
+            .map_err(serde::ser::Error::custom)?
+            .as_millis();
+        s.serialize_u64(millis as u64)
+    }
+
+    pub(super) fn deserialize<'de, D>(d: D) -> Result<time::SystemTime, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let millis = u64::deserialize(d)?;
+        Ok(time::UNIX_EPOCH + time::Duration::from_millis(millis))
+    }
+}
+
+impl WalFrames {
+    fn is_valid(&self) -> bool {
+        // Offset and size are signed in the Go implementation.
+        (1..=i64::MAX as u64).contains(&self.offset) && (1..=i64::MAX as u64).contains(&self.size)
+    }
 }
 
 impl Header {
@@ -87,6 +393,43 @@ impl Header {
         self.min_txid == TXID::ONE
     }
 
+    /// Return whether the file may contain the page `pgno` according to its page filter.
+    /// `None` if the file has no page filter.
+    pub fn may_contain(&self, pgno: PageNum) -> Option<bool> {
+        self.page_filter.map(|filter| filter.may_contain(pgno))
+    }
+
+    /// Return the timestamp in milliseconds since the Unix epoch, as encoded in the file.
+    ///
+    /// Fails if the timestamp is earlier than the epoch.
+    pub fn timestamp_millis(&self) -> Result<u64, time::SystemTimeError> {
+        let since_epoch = self.timestamp.duration_since(time::UNIX_EPOCH)?;
+        Ok(since_epoch.as_millis() as u64)
+    }
+
+    /// Set the timestamp to `millis` milliseconds since the Unix epoch.
+    ///
+    /// Unlike `SystemTime::now()`, this doesn't read the clock, so it works on targets
+    /// without one such as `wasm32-unknown-unknown`, given the time by the host.
+    pub fn set_timestamp_millis(&mut self, millis: u64) {
+        self.timestamp = time::UNIX_EPOCH + time::Duration::from_millis(millis);
+    }
+
+    /// Return the size of the encoded header and the blocks following it, given the
+    /// first [`HEADER_SIZE`] bytes of the file.
+    pub(crate) fn encoded_size(buf: &[u8]) -> usize {
+        let flags =
+            HeaderFlags::from_bits_retain(u32::from_be_bytes(buf[4..8].try_into().unwrap()));
+        let mut size = HEADER_SIZE;
+        if flags.contains(HeaderFlags::PAGE_FILTER) {
+            size += PAGE_FILTER_SIZE;
+        }
+        if flags.contains(HeaderFlags::LZ4_DICTIONARY) {
+            size += DICT_ID_SIZE;
+        }
+        size
+    }
+
     fn validate(&self) -> Result<(), HeaderValidateError> {
         if self.min_txid > self.max_txid {
             return Err(HeaderValidateError::TXIDOrder(self.min_txid, self.max_txid));
@@ -96,10 +439,58 @@ impl Header {
             return Err(HeaderValidateError::PreApplyChecksumOnSnapshot);
         }
 
-        if !self.is_snapshot() && self.pre_apply_checksum.is_none() {
+        if self.flags.contains(HeaderFlags::NO_CHECKSUM) {
+            if self.pre_apply_checksum.is_some() {
+                return Err(HeaderValidateError::PreApplyChecksumWithNoChecksum);
+            }
+        } else if !self.is_snapshot() && self.pre_apply_checksum.is_none() {
             return Err(HeaderValidateError::NoPreApplyChecksum);
         }
 
+        let compression = self
+            .flags
+            .intersection(HeaderFlags::COMPRESSION | registered_compression_flags());
+        if compression.bits().count_ones() > 1 {
+            return Err(HeaderValidateError::CompressionFlags(compression));
+        }
+
+        if let Some(wal) = self.wal.filter(|wal| !wal.is_valid()) {
+            return Err(HeaderValidateError::WalFrames(wal));
+        }
+
+        if self
+            .app_data
+            .is_some_and(|data| data.iter().all(|&b| b == 0))
+        {
+            return Err(HeaderValidateError::AppData);
+        }
+
+        if self.flags.contains(HeaderFlags::PAGE_FILTER) != self.page_filter.is_some() {
+            return Err(HeaderValidateError::PageFilter);
+        }
+
+        if self
+            .key_id
+            .is_some_and(|id| id == 0 || !self.flags.contains(HeaderFlags::ENCRYPTED))
+        {
+            return Err(HeaderValidateError::KeyId);
+        }
+
+        if self.flags.contains(HeaderFlags::LZ4_DICTIONARY) != self.dict_id.is_some()
+            || self.dict_id.is_some() && !self.flags.contains(HeaderFlags::COMPRESS_LZ4)
+        {
+            return Err(HeaderValidateError::DictId);
+        }
+
+        if self.flags.contains(HeaderFlags::PER_PAGE_COMPRESSION)
+            && (!self.flags.contains(HeaderFlags::COMPRESS_LZ4)
+                || self
+                    .commit
+                    .is_some_and(|c| c.into_inner() & RAW_PAGE_FLAG != 0))
+        {
+            return Err(HeaderValidateError::PerPageCompression);
+        }
+
         Ok(())
     }
 
@@ -107,12 +498,10 @@ impl Header {
     where
         W: io::Write,
     {
-        let mut buf = Vec::with_capacity(HEADER_SIZE);
+        let mut buf = FixedBuf::<MAX_HEADER_SIZE>::new();
         let timestamp = self
-            .timestamp
-            .duration_since(time::SystemTime::UNIX_EPOCH)
-            .map_err(HeaderEncodeError::Timestamp)?
-            .as_millis() as u64;
+            .timestamp_millis()
+            .map_err(HeaderEncodeError::Timestamp)?;
         let checksum = if let Some(c) = self.pre_apply_checksum {
             c.into_inner()
         } else {
@@ -121,22 +510,62 @@ impl Header {
 
         self.validate()?;
 
-        buf.extend_from_slice(Self::MAGIC.as_bytes());
-        buf.extend_from_slice(&self.flags.bits().to_be_bytes());
-        buf.extend_from_slice(&self.page_size.into_inner().to_be_bytes());
-        buf.extend_from_slice(&self.commit.into_inner().to_be_bytes());
-        buf.extend_from_slice(&self.min_txid.into_inner().to_be_bytes());
-        buf.extend_from_slice(&self.max_txid.into_inner().to_be_bytes());
-        buf.extend_from_slice(&timestamp.to_be_bytes());
-        buf.extend_from_slice(&checksum.to_be_bytes());
-        buf.resize(HEADER_SIZE, 0);
+        buf.put(Self::MAGIC.as_bytes());
+        buf.put(&self.flags.bits().to_be_bytes());
+        buf.put(&self.page_size.into_inner().to_be_bytes());
+        buf.put(&self.commit.map_or(0, |c| c.into_inner()).to_be_bytes());
+        buf.put(&self.min_txid.into_inner().to_be_bytes());
+        buf.put(&self.max_txid.into_inner().to_be_bytes());
+        buf.put(&timestamp.to_be_bytes());
+        buf.put(&checksum.to_be_bytes());
+        let wal = self.wal.unwrap_or(WalFrames {
+            offset: 0,
+            size: 0,
+            salt1: 0,
+            salt2: 0,
+        });
+        buf.put(&wal.offset.to_be_bytes());
+        buf.put(&wal.size.to_be_bytes());
+        buf.put(&wal.salt1.to_be_bytes());
+        buf.put(&wal.salt2.to_be_bytes());
+        buf.put(&self.node_id.to_be_bytes());
+        buf.put(&self.key_id.unwrap_or_default().to_be_bytes());
+        buf.pad(APP_DATA_OFFSET);
+        buf.put(&self.app_data.unwrap_or_default());
+        if let Some(filter) = &self.page_filter {
+            buf.put(filter.as_bytes());
+        }
+        if let Some(id) = self.dict_id {
+            buf.put(&id.to_be_bytes());
+        }
 
-        w.write_all(&buf)?;
+        w.write_all(buf.as_slice())?;
 
         Ok(())
     }
 
-    pub(crate) fn decode_from<R>(mut r: R) -> Result<Header, HeaderDecodeError>
+    /// Return the flags set in the header that are unknown to this crate.
+    ///
+    /// Headers with unknown flags are only decoded in lenient mode, see
+    /// [`DecodeOptions::lenient_flags`](crate::DecodeOptions::lenient_flags).
+    pub fn unknown_flags(&self) -> HeaderFlags {
+        self.flags
+            .difference(HeaderFlags::all() | registered_compression_flags())
+    }
+
+    pub(crate) fn decode_from<R>(r: R) -> Result<Header, HeaderDecodeError>
+    where
+        R: io::Read,
+    {
+        Header::decode_from_with(r, false)
+    }
+
+    /// Decode the header, keeping unknown flags instead of rejecting them if
+    /// `lenient_flags` is set.
+    pub(crate) fn decode_from_with<R>(
+        mut r: R,
+        lenient_flags: bool,
+    ) -> Result<Header, HeaderDecodeError>
     where
         R: io::Read,
     {
@@ -148,13 +577,17 @@ impl Header {
         }
 
         let flags = u32::from_be_bytes(buf[4..8].try_into().unwrap());
-        let flags = HeaderFlags::from_bits(flags).ok_or(HeaderDecodeError::Flags(flags))?;
+        let known = HeaderFlags::all() | registered_compression_flags();
+        if !lenient_flags && flags & !known.bits() != 0 {
+            return Err(HeaderDecodeError::Flags(flags));
+        }
+        let flags = HeaderFlags::from_bits_retain(flags);
 
         let page_size = u32::from_be_bytes(buf[8..12].try_into().unwrap());
         let page_size = PageSize::new(page_size)?;
 
         let commit = u32::from_be_bytes(buf[12..16].try_into().unwrap());
-        let commit = PageNum::new(commit).map_err(HeaderDecodeError::Commit)?;
+        let commit = PageNum::new(commit).ok();
 
         let min_txid = u64::from_be_bytes(buf[16..24].try_into().unwrap());
         let min_txid = TXID::new(min_txid).map_err(HeaderDecodeError::MinTXID)?;
@@ -174,6 +607,42 @@ impl Header {
             None
         };
 
+        let wal = WalFrames {
+            offset: u64::from_be_bytes(buf[48..56].try_into().unwrap()),
+            size: u64::from_be_bytes(buf[56..64].try_into().unwrap()),
+            salt1: u32::from_be_bytes(buf[64..68].try_into().unwrap()),
+            salt2: u32::from_be_bytes(buf[68..72].try_into().unwrap()),
+        };
+        let wal = if wal.offset != 0 || wal.size != 0 || wal.salt1 != 0 || wal.salt2 != 0 {
+            Some(wal)
+        } else {
+            None
+        };
+
+        let node_id = u64::from_be_bytes(buf[72..80].try_into().unwrap());
+
+        let key_id = u32::from_be_bytes(buf[KEY_ID_OFFSET..KEY_ID_OFFSET + 4].try_into().unwrap());
+        let key_id = Some(key_id).filter(|id| *id != 0);
+
+        let app_data: [u8; APP_DATA_SIZE] = buf[APP_DATA_OFFSET..].try_into().unwrap();
+        let app_data = Some(app_data).filter(|data| data.iter().any(|&b| b != 0));
+
+        let page_filter = if flags.contains(HeaderFlags::PAGE_FILTER) {
+            let mut filter = PageFilter::new();
+            r.read_exact(&mut filter.0)?;
+            Some(filter)
+        } else {
+            None
+        };
+
+        let dict_id = if flags.contains(HeaderFlags::LZ4_DICTIONARY) {
+            let mut id = [0; DICT_ID_SIZE];
+            r.read_exact(&mut id)?;
+            Some(u32::from_be_bytes(id))
+        } else {
+            None
+        };
+
         let hdr = Header {
             flags,
             page_size,
@@ -182,6 +651,12 @@ impl Header {
             max_txid,
             timestamp,
             pre_apply_checksum,
+            node_id,
+            wal,
+            app_data,
+            page_filter,
+            key_id,
+            dict_id,
         };
 
         hdr.validate()?;
@@ -190,6 +665,35 @@ impl Header {
     }
 }
 
+/// Read the header of an LTX file from `r`.
+///
+/// Only the header and the [`PageFilter`] and dictionary ID following it, if any, are
+/// read. Use
+/// [`Decoder`](crate::Decoder) to read the rest of the file.
+///
+/// # Example
+/// ```no_run
+/// # let v = Vec::new();
+/// # let r = &v[..];
+/// let header = litetx::read_header(r).expect("read_header");
+/// println!("{}-{}", header.min_txid, header.max_txid);
+/// ```
+pub fn read_header<R>(r: R) -> Result<Header, HeaderDecodeError>
+where
+    R: io::Read,
+{
+    Header::decode_from(r)
+}
+
+/// Read the header of the LTX file at `path`.
+#[cfg(feature = "std")]
+pub fn read_header_from_path<P>(path: P) -> Result<Header, HeaderDecodeError>
+where
+    P: AsRef<Path>,
+{
+    read_header(fs::File::open(path)?)
+}
+
 /// A trailer encoding error.
 #[derive(thiserror::Error, Debug)]
 pub enum TrailerEncodeError {
@@ -204,15 +708,21 @@ pub enum TrailerDecodeError {
     Read(#[from] io::Error),
     #[error("invalid post apply checksum: {0}")]
     PostApplyChecksum(u64),
+    #[error("post apply checksum required on files with checksums")]
+    NoPostApplyChecksum,
+    #[error("post apply checksum must be unset on files without checksums")]
+    PostApplyChecksumWithNoChecksum,
     #[error("invalid file checksum: {0}")]
     FileChecksum(u64),
 }
 
 /// An LTX file trailer.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Trailer {
-    /// Running database checksum after this LTX file has been applied.
-    pub post_apply_checksum: Checksum,
+    /// Running database checksum after this LTX file has been applied. `None` if the
+    /// file has the [`HeaderFlags::NO_CHECKSUM`] flag set.
+    pub post_apply_checksum: Option<Checksum>,
     /// LTX file checksum.
     pub file_checksum: Checksum,
 }
@@ -222,16 +732,46 @@ impl Trailer {
     where
         W: io::Write,
     {
-        let mut buf = Vec::with_capacity(TRAILER_SIZE);
-
-        buf.extend_from_slice(&self.post_apply_checksum.into_inner().to_be_bytes());
-        buf.extend_from_slice(&self.file_checksum.into_inner().to_be_bytes());
+        let mut buf = [0; TRAILER_SIZE];
+        buf[..8].copy_from_slice(&self.post_apply_checksum_bytes());
+        buf[8..].copy_from_slice(&self.file_checksum.into_inner().to_be_bytes());
 
         w.write_all(&buf)?;
 
         Ok(())
     }
 
+    /// Return the encoded post-apply checksum, which is zero if unset.
+    pub(crate) fn post_apply_checksum_bytes(&self) -> [u8; 8] {
+        self.post_apply_checksum
+            .map_or(0, |c| c.into_inner())
+            .to_be_bytes()
+    }
+
+    /// Check the trailer against the flags of the file header.
+    pub(crate) fn validate(&self, flags: HeaderFlags) -> Result<(), TrailerDecodeError> {
+        match (
+            flags.contains(HeaderFlags::NO_CHECKSUM),
+            self.post_apply_checksum,
+        ) {
+            (true, Some(_)) => Err(TrailerDecodeError::PostApplyChecksumWithNoChecksum),
+            (false, None) => Err(TrailerDecodeError::NoPostApplyChecksum),
+            _ => Ok(()),
+        }
+    }
+
+    /// Read the trailer from the end of a seekable LTX file.
+    ///
+    /// Only the last [`TRAILER_SIZE`] bytes are read, so the file checksum is not verified.
+    #[cfg(feature = "std")]
+    pub fn read_from_end<R>(mut r: R) -> Result<Trailer, TrailerDecodeError>
+    where
+        R: io::Read + std::io::Seek,
+    {
+        r.seek(std::io::SeekFrom::End(-(TRAILER_SIZE as i64)))?;
+        Trailer::decode_from(r)
+    }
+
     pub(crate) fn decode_from<R>(mut r: R) -> Result<Trailer, TrailerDecodeError>
     where
         R: io::Read,
@@ -243,10 +783,11 @@ impl Trailer {
         let file_checksum = u64::from_be_bytes(buf[8..16].try_into().unwrap());
 
         let trailer = Trailer {
-            post_apply_checksum: Checksum::new(post_apply_checksum),
+            post_apply_checksum: (post_apply_checksum != 0)
+                .then_some(Checksum::new(post_apply_checksum)),
             file_checksum: Checksum::new(file_checksum),
         };
-        if trailer.post_apply_checksum.into_inner() != post_apply_checksum {
+        if trailer.post_apply_checksum_bytes() != buf[0..8] {
             return Err(TrailerDecodeError::PostApplyChecksum(post_apply_checksum));
         }
         if trailer.file_checksum.into_inner() != file_checksum {
@@ -257,6 +798,42 @@ impl Trailer {
     }
 }
 
+/// An error reading the position of an LTX file.
+#[derive(thiserror::Error, Debug)]
+pub enum PosDecodeError {
+    #[error("header")]
+    Header(#[from] HeaderDecodeError),
+    #[error("trailer")]
+    Trailer(#[from] TrailerDecodeError),
+    #[error("file has no checksums")]
+    NoChecksum,
+}
+
+impl Pos {
+    /// Read the database position after applying a seekable LTX file.
+    ///
+    /// Only the header and the trailer are read, without decoding the pages or verifying
+    /// the file checksum.
+    #[cfg(feature = "std")]
+    pub fn read_from<R>(mut r: R) -> Result<Pos, PosDecodeError>
+    where
+        R: io::Read + std::io::Seek,
+    {
+        r.seek(std::io::SeekFrom::Start(0))
+            .map_err(HeaderDecodeError::from)?;
+        let header = Header::decode_from(&mut r)?;
+        let trailer = Trailer::read_from_end(&mut r)?;
+        trailer.validate(header.flags)?;
+
+        Ok(Pos {
+            txid: header.max_txid,
+            post_apply_checksum: trailer
+                .post_apply_checksum
+                .ok_or(PosDecodeError::NoChecksum)?,
+        })
+    }
+}
+
 /// A page header encoding error.
 #[derive(thiserror::Error, Debug)]
 pub enum PageHeaderEncodeError {
@@ -277,31 +854,168 @@ pub enum PageHeaderDecodeError {
 pub(crate) struct PageHeader(pub(crate) Option<PageNum>);
 
 impl PageHeader {
-    pub(crate) fn encode_into<W>(&self, mut w: W) -> Result<(), PageHeaderEncodeError>
+    pub(crate) fn encode_into<W>(&self, w: W) -> Result<(), PageHeaderEncodeError>
+    where
+        W: io::Write,
+    {
+        self.encode_raw_into(false, w)
+    }
+
+    /// Encode the header of a page of a file with the
+    /// [`HeaderFlags::PER_PAGE_COMPRESSION`] flag, marking the page as stored raw if `raw`.
+    pub(crate) fn encode_raw_into<W>(
+        &self,
+        raw: bool,
+        mut w: W,
+    ) -> Result<(), PageHeaderEncodeError>
     where
         W: io::Write,
     {
-        let page_num = self.0.map(|n| n.into_inner()).unwrap_or(0);
-        w.write_all(&page_num.to_be_bytes())?;
+        w.write_all(&self.encode_raw(raw))?;
 
         Ok(())
     }
 
-    pub(crate) fn decode_from<R>(mut r: R) -> Result<PageHeader, PageHeaderDecodeError>
+    /// Encode the header, marking the page as stored raw if `raw` as
+    /// [`PageHeader::encode_raw_into`] does.
+    pub(crate) fn encode_raw(&self, raw: bool) -> [u8; PAGE_HEADER_SIZE] {
+        let mut page_num = self.0.map(|n| n.into_inner()).unwrap_or(0);
+        if raw {
+            page_num |= RAW_PAGE_FLAG;
+        }
+        page_num.to_be_bytes()
+    }
+
+    pub(crate) fn decode_from<R>(r: R) -> Result<PageHeader, PageHeaderDecodeError>
+    where
+        R: io::Read,
+    {
+        Self::decode_with(r, false).map(|(header, _)| header)
+    }
+
+    /// Decode the header of a page of a file with the
+    /// [`HeaderFlags::PER_PAGE_COMPRESSION`] flag, along with whether the page is stored
+    /// raw.
+    #[cfg(feature = "std")]
+    pub(crate) fn decode_raw_from<R>(r: R) -> Result<(PageHeader, bool), PageHeaderDecodeError>
+    where
+        R: io::Read,
+    {
+        Self::decode_with(r, true)
+    }
+
+    fn decode_with<R>(mut r: R, raw_flag: bool) -> Result<(PageHeader, bool), PageHeaderDecodeError>
     where
         R: io::Read,
     {
         let mut buf = [0; PAGE_HEADER_SIZE];
         r.read_exact(&mut buf)?;
 
-        let page_num = u32::from_be_bytes(buf[0..4].try_into().unwrap());
-        let page_num = if page_num != 0 {
+        let mut page_num = u32::from_be_bytes(buf[0..4].try_into().unwrap());
+        let raw = raw_flag && page_num & RAW_PAGE_FLAG != 0;
+        if raw {
+            page_num &= !RAW_PAGE_FLAG;
+        }
+        let page_num = if page_num != 0 || raw {
             Some(PageNum::new(page_num).map_err(PageHeaderDecodeError::PageNum)?)
         } else {
             None
         };
 
-        Ok(PageHeader(page_num))
+        Ok((PageHeader(page_num), raw))
+    }
+}
+
+/// A page index encoding error.
+#[cfg(feature = "std")]
+#[derive(thiserror::Error, Debug)]
+pub enum PageIndexEncodeError {
+    #[error("write error")]
+    Write(#[from] io::Error),
+}
+
+/// A page index decoding error.
+#[cfg(feature = "std")]
+#[derive(thiserror::Error, Debug)]
+pub enum PageIndexDecodeError {
+    #[error("read error")]
+    Read(#[from] io::Error),
+    #[error("invalid page number record: {0}")]
+    PageNum(PageNumError),
+    #[error("invalid entry count: {0}, expected {1}")]
+    Count(u32, usize),
+    #[error("out-of-order page index entry: {0}")]
+    OutOfOrder(PageNum),
+    #[error("page index of {0} entries doesn't fit in the file")]
+    Size(u32),
+    #[error("page index entry doesn't point to page {0}")]
+    Offset(PageNum),
+}
+
+/// An index of page offsets in the file, written between the page block and the trailer.
+///
+/// The index consists of (page number, file offset) entries, each offset pointing to the
+/// page header, followed by the number of entries.
+#[cfg(feature = "std")]
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct PageIndex(pub(crate) Vec<(PageNum, u64)>);
+
+#[cfg(feature = "std")]
+impl PageIndex {
+    pub(crate) fn encoded_size(len: usize) -> usize {
+        len * PAGE_INDEX_ENTRY_SIZE + PAGE_INDEX_COUNT_SIZE
+    }
+
+    pub(crate) fn find(&self, page_num: PageNum) -> Option<u64> {
+        self.0
+            .binary_search_by_key(&page_num, |(n, _)| *n)
+            .ok()
+            .map(|i| self.0[i].1)
+    }
+
+    pub(crate) fn encode_into<W>(&self, mut w: W) -> Result<(), PageIndexEncodeError>
+    where
+        W: io::Write,
+    {
+        let mut buf = Vec::with_capacity(Self::encoded_size(self.0.len()));
+        for (page_num, offset) in &self.0 {
+            buf.extend_from_slice(&page_num.into_inner().to_be_bytes());
+            buf.extend_from_slice(&offset.to_be_bytes());
+        }
+        buf.extend_from_slice(&(self.0.len() as u32).to_be_bytes());
+
+        w.write_all(&buf)?;
+
+        Ok(())
+    }
+
+    /// Decode an index of `len` entries.
+    pub(crate) fn decode_from<R>(mut r: R, len: usize) -> Result<PageIndex, PageIndexDecodeError>
+    where
+        R: io::Read,
+    {
+        let mut buf = vec![0; Self::encoded_size(len)];
+        r.read_exact(&mut buf)?;
+
+        let (entries, count) = buf.split_at(len * PAGE_INDEX_ENTRY_SIZE);
+        let count = u32::from_be_bytes(count.try_into().unwrap());
+        if count as usize != len {
+            return Err(PageIndexDecodeError::Count(count, len));
+        }
+
+        let mut index = Vec::with_capacity(len);
+        for entry in entries.chunks_exact(PAGE_INDEX_ENTRY_SIZE) {
+            let page_num = u32::from_be_bytes(entry[0..4].try_into().unwrap());
+            let page_num = PageNum::new(page_num).map_err(PageIndexDecodeError::PageNum)?;
+            let offset = u64::from_be_bytes(entry[4..12].try_into().unwrap());
+
+            if matches!(index.last(), Some((last, _)) if *last >= page_num) {
+                return Err(PageIndexDecodeError::OutOfOrder(page_num));
+            }
+            index.push((page_num, offset));
+        }
+
+        Ok(PageIndex(index))
     }
 }
 
@@ -316,20 +1030,181 @@ where
     T: AsRef<[u8]>,
 {
     fn page_checksum(&self, pgno: PageNum) -> Checksum {
-        let mut digest = CRC64.digest();
+        let mut hasher = PageHasher::new(pgno);
+        hasher.update(self.as_ref());
+        hasher.finalize_checksum()
+    }
+}
 
+/// An incremental database page checksum calculation, for pages that aren't available
+/// as a contiguous slice, e.g. pages arriving in chunks.
+///
+/// # Example
+/// ```
+/// # use litetx::PageChecksum;
+/// # let page = vec![1; 4096];
+/// let mut hasher = litetx::PageHasher::new(litetx::PageNum::ONE);
+/// for chunk in page.chunks(1024) {
+///     hasher.update(chunk);
+/// }
+/// assert_eq!(page.page_checksum(litetx::PageNum::ONE), hasher.finalize_checksum());
+/// ```
+pub struct PageHasher(Crc64Digest<'static>);
+
+impl PageHasher {
+    /// Start the checksum calculation of page `pgno`.
+    pub fn new(pgno: PageNum) -> PageHasher {
+        let mut digest = CRC64.digest();
         digest.update(&pgno.into_inner().to_be_bytes());
-        digest.update(self.as_ref());
+        PageHasher(digest)
+    }
+
+    /// Add the next chunk of page data.
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.update(data);
+    }
 
-        Checksum::new(digest.finalize())
+    /// Return the checksum of the page data added so far.
+    pub fn finalize_checksum(self) -> Checksum {
+        Checksum::new(self.0.finalize())
+    }
+}
+
+impl io::Write for PageHasher {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Calculate the database page checksum of the `page_size` bytes of page `pgno` read
+/// from `r`, without holding the whole page in memory.
+///
+/// # Example
+/// ```
+/// # use litetx::PageChecksum;
+/// # let page = vec![1; 4096];
+/// let pgno = litetx::PageNum::ONE;
+/// let page_size = litetx::PageSize::new(4096).unwrap();
+/// let checksum = litetx::page_checksum_from_reader(pgno, page.as_slice(), page_size)
+///     .expect("page checksum");
+/// assert_eq!(page.page_checksum(pgno), checksum);
+/// ```
+pub fn page_checksum_from_reader<R>(
+    pgno: PageNum,
+    mut r: R,
+    page_size: PageSize,
+) -> io::Result<Checksum>
+where
+    R: io::Read,
+{
+    let mut hasher = PageHasher::new(pgno);
+
+    let mut buf = [0; 8192];
+    let mut remaining = page_size.into_inner() as usize;
+    while remaining > 0 {
+        let len = remaining.min(buf.len());
+        r.read_exact(&mut buf[..len])?;
+        hasher.update(&buf[..len]);
+        remaining -= len;
+    }
+
+    Ok(hasher.finalize_checksum())
+}
+
+/// A running database checksum.
+///
+/// The database checksum is the XOR of the checksums of all database pages, so pages can
+/// be added and removed in any order. The lock page is not part of the database checksum
+/// and must not be added.
+///
+/// # Example
+/// ```
+/// # let (page1, page2) = (vec![1; 4096], vec![2; 4096]);
+/// let mut checksum = litetx::DatabaseChecksum::new();
+/// checksum.add_page(litetx::PageNum::ONE, &page1);
+/// checksum.replace_page(litetx::PageNum::ONE, &page1, &page2);
+/// let checksum = checksum.finish();
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatabaseChecksum(Checksum);
+
+impl DatabaseChecksum {
+    /// Construct the checksum of an empty database.
+    pub const fn new() -> DatabaseChecksum {
+        DatabaseChecksum(Checksum::new(0))
+    }
+
+    /// Account for a page added to the database.
+    pub fn add_page(&mut self, pgno: PageNum, data: &[u8]) {
+        self.0 = self.0 ^ data.page_checksum(pgno);
+    }
+
+    /// Account for a page removed from the database, e.g. on truncation.
+    pub fn remove_page(&mut self, pgno: PageNum, old_data: &[u8]) {
+        // Removing a page cancels out its checksum.
+        self.add_page(pgno, old_data);
+    }
+
+    /// Account for a page overwritten with new contents.
+    pub fn replace_page(&mut self, pgno: PageNum, old_data: &[u8], new_data: &[u8]) {
+        self.0 = self.0.apply_page_change(pgno, old_data, new_data);
+    }
+
+    /// Return the resulting database checksum.
+    pub fn finish(self) -> Checksum {
+        self.0
+    }
+}
+
+impl Default for DatabaseChecksum {
+    fn default() -> Self {
+        DatabaseChecksum::new()
+    }
+}
+
+impl From<Checksum> for DatabaseChecksum {
+    /// Continue from an existing database checksum, e.g. the pre-apply checksum.
+    fn from(checksum: Checksum) -> Self {
+        DatabaseChecksum(checksum)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Header, HeaderFlags, HeaderValidateError, PageHeader, Trailer};
-    use crate::{utils::TimeRound, Checksum, PageNum, PageSize, TXID};
-    use std::time;
+    use super::{
+        read_header, DatabaseChecksum, Header, HeaderDecodeError, HeaderEncodeError, HeaderFlags,
+        HeaderValidateError, PageFilter, PageHasher, PageHeader, PageIndex, PageIndexDecodeError,
+        Trailer, TrailerDecodeError, WalFrames, APP_DATA_OFFSET, APP_DATA_SIZE, CRC64,
+        DICT_ID_SIZE, HEADER_SIZE, KEY_ID_OFFSET, PAGE_FILTER_SIZE, PAGE_INDEX_ENTRY_SIZE,
+    };
+    use crate::{utils::TimeRound, Checksum, PageChecksum, PageNum, PageSize, Pos, TXID};
+    use serde_test::{assert_tokens, Configure, Token};
+    use std::{io, time};
+
+    #[test]
+    fn crc64() {
+        let reference = crc::Crc::<u64, crc::NoTable>::new(&crc::CRC_64_GO_ISO);
+        let data: Vec<u8> = (0..10_000).map(|_| rand::random()).collect();
+
+        for len in [0, 1, 15, 16, 17, 4096, 10_000] {
+            assert_eq!(
+                reference.checksum(&data[..len]),
+                CRC64.checksum(&data[..len])
+            );
+        }
+
+        // Digests fed in unaligned chunks produce the same checksum.
+        let mut digest = CRC64.digest();
+        for chunk in data.chunks(13) {
+            digest.update(chunk);
+        }
+        assert_eq!(reference.checksum(&data), digest.finalize());
+    }
 
     fn encode_decode_header(mut hdr: Header) {
         let mut buf = Vec::new();
@@ -348,11 +1223,17 @@ mod tests {
         encode_decode_header(Header {
             flags: HeaderFlags::COMPRESS_LZ4,
             page_size: PageSize::new(4096).unwrap(),
-            commit: PageNum::new(10).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
             min_txid: TXID::new(1).unwrap(),
             max_txid: TXID::new(5).unwrap(),
             timestamp: time::SystemTime::now(),
             pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
         });
     }
 
@@ -361,11 +1242,17 @@ mod tests {
         encode_decode_header(Header {
             flags: HeaderFlags::COMPRESS_LZ4,
             page_size: PageSize::new(4096).unwrap(),
-            commit: PageNum::new(10).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
             min_txid: TXID::new(3).unwrap(),
             max_txid: TXID::new(5).unwrap(),
             timestamp: time::SystemTime::now(),
             pre_apply_checksum: Some(Checksum::new(123)),
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
         });
     }
 
@@ -374,11 +1261,17 @@ mod tests {
         let hdr = Header {
             flags: HeaderFlags::COMPRESS_LZ4,
             page_size: PageSize::new(4096).unwrap(),
-            commit: PageNum::new(10).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
             min_txid: TXID::new(5).unwrap(),
             max_txid: TXID::new(3).unwrap(),
             timestamp: time::SystemTime::now(),
             pre_apply_checksum: Some(Checksum::new(123)),
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
         };
         assert!(matches!(
             hdr.validate(),
@@ -387,11 +1280,17 @@ mod tests {
         let hdr = Header {
             flags: HeaderFlags::COMPRESS_LZ4,
             page_size: PageSize::new(4096).unwrap(),
-            commit: PageNum::new(10).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
             min_txid: TXID::new(1).unwrap(),
             max_txid: TXID::new(3).unwrap(),
             timestamp: time::SystemTime::now(),
             pre_apply_checksum: Some(Checksum::new(123)),
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
         };
         assert!(matches!(
             hdr.validate(),
@@ -401,16 +1300,323 @@ mod tests {
         let hdr = Header {
             flags: HeaderFlags::COMPRESS_LZ4,
             page_size: PageSize::new(4096).unwrap(),
-            commit: PageNum::new(10).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
             min_txid: TXID::new(3).unwrap(),
             max_txid: TXID::new(5).unwrap(),
             timestamp: time::SystemTime::now(),
             pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
         };
         assert!(matches!(
             hdr.validate(),
             Err(HeaderValidateError::NoPreApplyChecksum)
         ));
+
+        let hdr = Header {
+            flags: HeaderFlags::COMPRESS_LZ4 | HeaderFlags::COMPRESS_ZSTD,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
+            min_txid: TXID::new(1).unwrap(),
+            max_txid: TXID::new(1).unwrap(),
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+        assert!(matches!(
+            hdr.validate(),
+            Err(HeaderValidateError::CompressionFlags(f)) if f == hdr.flags
+        ));
+    }
+
+    #[test]
+    fn extended_header() {
+        let mut hdr = Header {
+            flags: HeaderFlags::empty(),
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
+            min_txid: TXID::new(3).unwrap(),
+            max_txid: TXID::new(5).unwrap(),
+            timestamp: time::SystemTime::UNIX_EPOCH,
+            pre_apply_checksum: Some(Checksum::new(123)),
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        // Unset extended fields are encoded as zeroes.
+        let mut buf = Vec::new();
+        hdr.encode_into(&mut buf).expect("failed to encode header");
+        assert_eq!(vec![0; HEADER_SIZE - 48], buf[48..]);
+
+        hdr.node_id = 0x0102030405060708;
+        hdr.wal = Some(WalFrames {
+            offset: 32,
+            size: 4120,
+            salt1: 0xaabbccdd,
+            salt2: 0x11223344,
+        });
+        encode_decode_header(hdr.clone());
+
+        let mut buf = Vec::new();
+        hdr.encode_into(&mut buf).expect("failed to encode header");
+        assert_eq!(&32u64.to_be_bytes(), &buf[48..56]);
+        assert_eq!(&4120u64.to_be_bytes(), &buf[56..64]);
+        assert_eq!(&0xaabbccddu32.to_be_bytes(), &buf[64..68]);
+        assert_eq!(&0x11223344u32.to_be_bytes(), &buf[68..72]);
+        assert_eq!(&0x0102030405060708u64.to_be_bytes(), &buf[72..80]);
+        assert_eq!(vec![0; HEADER_SIZE - 80], buf[80..]);
+
+        hdr.wal = Some(WalFrames {
+            offset: 0,
+            size: 4120,
+            salt1: 1,
+            salt2: 2,
+        });
+        assert!(matches!(
+            hdr.validate(),
+            Err(HeaderValidateError::WalFrames(wal)) if Some(wal) == hdr.wal
+        ));
+
+        // A decoded header must not contain salts without a WAL offset.
+        buf[48..56].copy_from_slice(&0u64.to_be_bytes());
+        assert!(matches!(
+            Header::decode_from(buf.as_slice()),
+            Err(HeaderDecodeError::Validation(
+                HeaderValidateError::WalFrames(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn app_data_header() {
+        let mut hdr = Header {
+            flags: HeaderFlags::empty(),
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
+            min_txid: TXID::new(1).unwrap(),
+            max_txid: TXID::new(1).unwrap(),
+            timestamp: time::SystemTime::UNIX_EPOCH,
+            pre_apply_checksum: None,
+            node_id: 0x0102030405060708,
+            wal: None,
+            app_data: Some(*b"site-ams-0000001"),
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+        encode_decode_header(hdr.clone());
+
+        let mut buf = Vec::new();
+        hdr.encode_into(&mut buf).expect("failed to encode header");
+        assert_eq!(&0x0102030405060708u64.to_be_bytes(), &buf[72..80]);
+        assert_eq!(vec![0; APP_DATA_OFFSET - 80], buf[80..APP_DATA_OFFSET]);
+        assert_eq!(b"site-ams-0000001", &buf[APP_DATA_OFFSET..]);
+
+        hdr.app_data = Some([0; APP_DATA_SIZE]);
+        assert!(matches!(hdr.validate(), Err(HeaderValidateError::AppData)));
+    }
+
+    #[test]
+    fn page_filter_header() {
+        let pages: Vec<_> = (1..=100).map(|n| PageNum::new(n * 3).unwrap()).collect();
+        let filter = PageFilter::from_iter(pages.iter().copied());
+        let mut hdr = Header {
+            flags: HeaderFlags::PAGE_FILTER,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(300).unwrap()),
+            min_txid: TXID::new(3).unwrap(),
+            max_txid: TXID::new(5).unwrap(),
+            timestamp: time::SystemTime::UNIX_EPOCH,
+            pre_apply_checksum: Some(Checksum::new(123)),
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: Some(filter),
+            key_id: None,
+            dict_id: None,
+        };
+        encode_decode_header(hdr.clone());
+
+        let mut buf = Vec::new();
+        hdr.encode_into(&mut buf).expect("failed to encode header");
+        assert_eq!(HEADER_SIZE + PAGE_FILTER_SIZE, buf.len());
+        assert_eq!(buf.len(), Header::encoded_size(&buf));
+
+        assert!(pages
+            .iter()
+            .all(|pgno| hdr.may_contain(*pgno) == Some(true)));
+        let false_positives = (1..=3000)
+            .filter(|n| n % 3 != 0 && filter.may_contain(PageNum::new(*n).unwrap()))
+            .count();
+        assert!(false_positives < 20, "{false_positives} false positives");
+
+        assert_eq!(filter, PageFilter::try_from(String::from(filter)).unwrap());
+        assert!(PageFilter::try_from("00".to_string()).is_err());
+
+        hdr.page_filter = None;
+        assert_eq!(None, hdr.may_contain(pages[0]));
+        assert!(matches!(
+            hdr.validate(),
+            Err(HeaderValidateError::PageFilter)
+        ));
+    }
+
+    #[test]
+    fn key_id_header() {
+        let mut hdr = Header {
+            flags: HeaderFlags::ENCRYPTED,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
+            min_txid: TXID::new(1).unwrap(),
+            max_txid: TXID::new(1).unwrap(),
+            timestamp: time::SystemTime::UNIX_EPOCH,
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: Some(0x01020304),
+            dict_id: None,
+        };
+        encode_decode_header(hdr.clone());
+
+        let mut buf = Vec::new();
+        hdr.encode_into(&mut buf).expect("failed to encode header");
+        assert_eq!(&[1, 2, 3, 4], &buf[KEY_ID_OFFSET..KEY_ID_OFFSET + 4]);
+
+        hdr.key_id = Some(0);
+        assert!(matches!(hdr.validate(), Err(HeaderValidateError::KeyId)));
+        hdr.key_id = Some(1);
+        hdr.flags = HeaderFlags::empty();
+        assert!(matches!(hdr.validate(), Err(HeaderValidateError::KeyId)));
+    }
+
+    #[test]
+    fn dict_id_header() {
+        let mut hdr = Header {
+            flags: HeaderFlags::COMPRESS_LZ4
+                | HeaderFlags::PAGE_FILTER
+                | HeaderFlags::LZ4_DICTIONARY,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
+            min_txid: TXID::new(1).unwrap(),
+            max_txid: TXID::new(1).unwrap(),
+            timestamp: time::SystemTime::UNIX_EPOCH,
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: Some(PageFilter::new()),
+            key_id: None,
+            dict_id: Some(0x01020304),
+        };
+        encode_decode_header(hdr.clone());
+
+        let mut buf = Vec::new();
+        hdr.encode_into(&mut buf).expect("failed to encode header");
+        assert_eq!(HEADER_SIZE + PAGE_FILTER_SIZE + DICT_ID_SIZE, buf.len());
+        assert_eq!(buf.len(), Header::encoded_size(&buf));
+        assert_eq!(&[1, 2, 3, 4], &buf[buf.len() - DICT_ID_SIZE..]);
+
+        hdr.flags.remove(HeaderFlags::COMPRESS_LZ4);
+        assert!(matches!(hdr.validate(), Err(HeaderValidateError::DictId)));
+        hdr.flags.insert(HeaderFlags::COMPRESS_LZ4);
+        hdr.dict_id = None;
+        assert!(matches!(hdr.validate(), Err(HeaderValidateError::DictId)));
+    }
+
+    #[test]
+    fn zstd_header() {
+        encode_decode_header(Header {
+            flags: HeaderFlags::COMPRESS_ZSTD,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
+            min_txid: TXID::new(1).unwrap(),
+            max_txid: TXID::new(5).unwrap(),
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        });
+    }
+
+    #[test]
+    fn read_header_prefix() {
+        let hdr = Header {
+            flags: HeaderFlags::COMPRESS_LZ4,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
+            min_txid: TXID::new(1).unwrap(),
+            max_txid: TXID::new(5).unwrap(),
+            timestamp: time::SystemTime::now()
+                .round(time::Duration::from_millis(1))
+                .unwrap(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let mut buf = Vec::new();
+        hdr.encode_into(&mut buf).expect("failed to encode header");
+        buf.extend_from_slice(&[1; 16]);
+
+        let mut r = buf.as_slice();
+        assert_eq!(hdr, read_header(&mut r).expect("failed to read header"));
+        assert_eq!(&[1; 16], r);
+
+        assert!(matches!(
+            read_header(&buf[..50]),
+            Err(HeaderDecodeError::Read(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+        ));
+    }
+
+    #[test]
+    fn unknown_header_flags() {
+        let mut buf = Vec::new();
+        Header {
+            flags: HeaderFlags::empty(),
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
+            min_txid: TXID::new(1).unwrap(),
+            max_txid: TXID::new(5).unwrap(),
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        }
+        .encode_into(&mut buf)
+        .expect("failed to encode header");
+        buf[7] = 0b10000000;
+
+        assert!(matches!(
+            Header::decode_from(buf.as_slice()),
+            Err(HeaderDecodeError::Flags(0b10000000))
+        ));
     }
 
     #[test]
@@ -418,7 +1624,7 @@ mod tests {
         let mut buf = Vec::new();
 
         let trailer = Trailer {
-            post_apply_checksum: Checksum::new(123),
+            post_apply_checksum: Some(Checksum::new(123)),
             file_checksum: Checksum::new(123),
         };
         trailer
@@ -429,6 +1635,244 @@ mod tests {
         assert_eq!(trailer_out, trailer);
     }
 
+    #[test]
+    fn header_timestamp_millis() {
+        let mut hdr = Header {
+            flags: HeaderFlags::empty(),
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(1).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::UNIX_EPOCH,
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+        hdr.set_timestamp_millis(1_700_000_000_123);
+        assert_eq!(1_700_000_000_123, hdr.timestamp_millis().unwrap());
+
+        let mut buf = Vec::new();
+        hdr.encode_into(&mut buf).expect("failed to encode header");
+        assert_eq!(1_700_000_000_123u64.to_be_bytes(), buf[32..40]);
+        assert_eq!(hdr, Header::decode_from(buf.as_slice()).unwrap());
+
+        hdr.timestamp = time::UNIX_EPOCH - time::Duration::from_millis(1);
+        assert!(hdr.timestamp_millis().is_err());
+        assert!(matches!(
+            hdr.encode_into(Vec::new()),
+            Err(HeaderEncodeError::Timestamp(_))
+        ));
+    }
+
+    #[test]
+    fn header_ser_de() {
+        let hdr = Header {
+            flags: HeaderFlags::COMPRESS_LZ4 | HeaderFlags::PAGE_INDEX,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
+            min_txid: TXID::new(3).unwrap(),
+            max_txid: TXID::new(5).unwrap(),
+            timestamp: time::UNIX_EPOCH + time::Duration::from_millis(1234),
+            pre_apply_checksum: Some(Checksum::new(0x456)),
+            node_id: 7,
+            wal: Some(WalFrames {
+                offset: 32,
+                size: 4120,
+                salt1: 1,
+                salt2: 2,
+            }),
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        assert_tokens(
+            &hdr.readable(),
+            &[
+                Token::Struct {
+                    name: "Header",
+                    len: 9,
+                },
+                Token::Str("flags"),
+                Token::Str("COMPRESS_LZ4 | PAGE_INDEX"),
+                Token::Str("pageSize"),
+                Token::U32(4096),
+                Token::Str("commit"),
+                Token::Some,
+                Token::U32(10),
+                Token::Str("minTXID"),
+                Token::String("0000000000000003"),
+                Token::Str("maxTXID"),
+                Token::String("0000000000000005"),
+                Token::Str("timestamp"),
+                Token::U64(1234),
+                Token::Str("preApplyChecksum"),
+                Token::Some,
+                Token::String("8000000000000456"),
+                Token::Str("nodeID"),
+                Token::U64(7),
+                Token::Str("wal"),
+                Token::Some,
+                Token::Struct {
+                    name: "WalFrames",
+                    len: 4,
+                },
+                Token::Str("offset"),
+                Token::U64(32),
+                Token::Str("size"),
+                Token::U64(4120),
+                Token::Str("salt1"),
+                Token::U32(1),
+                Token::Str("salt2"),
+                Token::U32(2),
+                Token::StructEnd,
+                Token::StructEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn trailer_ser_de() {
+        let trailer = Trailer {
+            post_apply_checksum: None,
+            file_checksum: Checksum::new(0x123),
+        };
+
+        assert_tokens(
+            &trailer,
+            &[
+                Token::Struct {
+                    name: "Trailer",
+                    len: 2,
+                },
+                Token::Str("postApplyChecksum"),
+                Token::None,
+                Token::Str("fileChecksum"),
+                Token::String("8000000000000123"),
+                Token::StructEnd,
+            ],
+        );
+    }
+
+    #[test]
+    fn no_checksum() {
+        let mut hdr = Header {
+            flags: HeaderFlags::NO_CHECKSUM,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
+            min_txid: TXID::new(3).unwrap(),
+            max_txid: TXID::new(5).unwrap(),
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+        encode_decode_header(hdr.clone());
+
+        hdr.pre_apply_checksum = Some(Checksum::new(1));
+        assert!(matches!(
+            hdr.validate(),
+            Err(HeaderValidateError::PreApplyChecksumWithNoChecksum)
+        ));
+
+        let trailer = Trailer {
+            post_apply_checksum: None,
+            file_checksum: Checksum::new(123),
+        };
+        let mut buf = Vec::new();
+        trailer
+            .encode_into(&mut buf)
+            .expect("failed to encode trailer");
+        assert_eq!([0; 8], buf[0..8]);
+        let trailer_out = Trailer::decode_from(buf.as_slice()).expect("failed to decode trailer");
+        assert_eq!(trailer, trailer_out);
+
+        trailer
+            .validate(HeaderFlags::NO_CHECKSUM)
+            .expect("failed to validate trailer");
+        assert!(matches!(
+            trailer.validate(HeaderFlags::empty()),
+            Err(TrailerDecodeError::NoPostApplyChecksum)
+        ));
+        assert!(matches!(
+            Trailer {
+                post_apply_checksum: Some(Checksum::new(1)),
+                file_checksum: Checksum::new(123),
+            }
+            .validate(HeaderFlags::NO_CHECKSUM),
+            Err(TrailerDecodeError::PostApplyChecksumWithNoChecksum)
+        ));
+    }
+
+    #[test]
+    fn trailer_read_from_end() {
+        let mut buf = vec![0; 40];
+        let trailer = Trailer {
+            post_apply_checksum: Some(Checksum::new(123)),
+            file_checksum: Checksum::new(456),
+        };
+        trailer
+            .encode_into(&mut buf)
+            .expect("failed to encode trailer");
+
+        let trailer_out =
+            Trailer::read_from_end(io::Cursor::new(&buf)).expect("failed to read trailer");
+        assert_eq!(trailer, trailer_out);
+
+        assert!(matches!(
+            Trailer::read_from_end(io::Cursor::new(&buf[..8])),
+            Err(TrailerDecodeError::Read(_))
+        ));
+    }
+
+    #[test]
+    fn pos_read_from() {
+        let mut buf = Vec::new();
+        Header {
+            flags: HeaderFlags::empty(),
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
+            min_txid: TXID::new(3).unwrap(),
+            max_txid: TXID::new(5).unwrap(),
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: Some(Checksum::new(1)),
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        }
+        .encode_into(&mut buf)
+        .expect("failed to encode header");
+        buf.resize(buf.len() + 4100, 0);
+        Trailer {
+            post_apply_checksum: Some(Checksum::new(123)),
+            file_checksum: Checksum::new(456),
+        }
+        .encode_into(&mut buf)
+        .expect("failed to encode trailer");
+
+        let mut r = io::Cursor::new(&buf);
+        r.set_position(42);
+        assert_eq!(
+            Pos {
+                txid: TXID::new(5).unwrap(),
+                post_apply_checksum: Checksum::new(123),
+            },
+            Pos::read_from(r).expect("failed to read position")
+        );
+    }
+
     #[test]
     fn page_header() {
         let mut buf = Vec::new();
@@ -456,4 +1900,108 @@ mod tests {
 
         assert_eq!(page_header_out, page_header);
     }
+
+    #[test]
+    fn raw_page_header() {
+        let mut buf = Vec::new();
+
+        let page_header = PageHeader(Some(PageNum::new(10).unwrap()));
+        page_header
+            .encode_raw_into(true, &mut buf)
+            .expect("failed to encode page header");
+        assert_eq!(&[0x80, 0, 0, 10], buf.as_slice());
+        let (page_header_out, raw) =
+            PageHeader::decode_raw_from(buf.as_slice()).expect("failed to decode page header");
+
+        assert_eq!(page_header_out, page_header);
+        assert!(raw);
+        assert!(PageHeader::decode_raw_from(&[0x80, 0, 0, 0][..]).is_err());
+    }
+
+    #[test]
+    fn page_index() {
+        let mut buf = Vec::new();
+
+        let index = PageIndex(vec![
+            (PageNum::new(1).unwrap(), 100),
+            (PageNum::new(7).unwrap(), 4200),
+        ]);
+        index
+            .encode_into(&mut buf)
+            .expect("failed to encode page index");
+        assert_eq!(PageIndex::encoded_size(2), buf.len());
+
+        let index_out =
+            PageIndex::decode_from(buf.as_slice(), 2).expect("failed to decode page index");
+        assert_eq!(index_out, index);
+        assert_eq!(Some(4200), index_out.find(PageNum::new(7).unwrap()));
+        assert_eq!(None, index_out.find(PageNum::new(2).unwrap()));
+
+        assert!(matches!(
+            PageIndex::decode_from(&buf[PAGE_INDEX_ENTRY_SIZE..], 1),
+            Err(PageIndexDecodeError::Count(2, 1))
+        ));
+    }
+
+    #[test]
+    fn page_hasher() {
+        let page = vec![5; 4096];
+        let pgno = PageNum::new(4).unwrap();
+
+        let mut hasher = PageHasher::new(pgno);
+        for chunk in page.chunks(1000) {
+            hasher.update(chunk);
+        }
+        assert_eq!(page.page_checksum(pgno), hasher.finalize_checksum());
+
+        let mut hasher = PageHasher::new(pgno);
+        io::copy(&mut page.as_slice(), &mut hasher).expect("failed to copy page");
+        assert_eq!(page.page_checksum(pgno), hasher.finalize_checksum());
+    }
+
+    #[test]
+    fn page_checksum_from_reader() {
+        let page = vec![7; 65536];
+        let pgno = PageNum::new(9).unwrap();
+        let page_size = PageSize::new(65536).unwrap();
+
+        assert_eq!(
+            page.page_checksum(pgno),
+            super::page_checksum_from_reader(pgno, page.as_slice(), page_size)
+                .expect("failed to calculate checksum")
+        );
+
+        let err = super::page_checksum_from_reader(pgno, &page[..1000], page_size)
+            .expect_err("short page");
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    #[test]
+    fn database_checksum() {
+        let (page1, page2, page3) = (vec![1; 512], vec![2; 512], vec![3; 512]);
+        let (one, two) = (PageNum::ONE, PageNum::new(2).unwrap());
+
+        let mut checksum = DatabaseChecksum::new();
+        checksum.add_page(one, &page1);
+        checksum.add_page(two, &page2);
+        assert_eq!(
+            page1.page_checksum(one) ^ page2.page_checksum(two),
+            checksum.finish()
+        );
+
+        checksum.replace_page(two, &page2, &page3);
+        assert_eq!(
+            page1.page_checksum(one) ^ page3.page_checksum(two),
+            checksum.finish()
+        );
+
+        let mut from = DatabaseChecksum::from(page1.page_checksum(one));
+        from.add_page(two, &page3);
+        assert_eq!(checksum, from);
+
+        checksum.remove_page(two, &page3);
+        checksum.remove_page(one, &page1);
+        assert_eq!(DatabaseChecksum::new(), checksum);
+        assert_eq!(Checksum::new(0), checksum.finish());
+    }
 }
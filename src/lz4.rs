@@ -0,0 +1,847 @@
+// Block-level access to the LZ4 frame format.
+//
+// `lz4_flex::frame` only provides blocking `io::Read`/`io::Write` adapters. The types
+// below split a frame into its descriptor and data blocks so that the frame can be
+// driven by other I/O models, or its blocks compressed in parallel.
+use crate::io;
+use alloc::{sync::Arc, vec, vec::Vec};
+use core::hash::Hasher;
+use twox_hash::XxHash32;
+
+const MAGIC: u32 = 0x184D2204;
+/// The maximum size of a dictionary, i.e. of the LZ4 window.
+pub(crate) const MAX_DICT_SIZE: usize = 64 * 1024;
+
+const FLG_VERSION_MASK: u8 = 0b11000000;
+const FLG_VERSION: u8 = 0b01000000;
+const FLG_INDEPENDENT_BLOCKS: u8 = 0b00100000;
+const FLG_BLOCK_CHECKSUMS: u8 = 0b00010000;
+const FLG_CONTENT_SIZE: u8 = 0b00001000;
+const FLG_CONTENT_CHECKSUM: u8 = 0b00000100;
+const FLG_DICT_ID: u8 = 0b00000001;
+
+const BLOCK_UNCOMPRESSED: u32 = 1 << 31;
+
+/// The number of blocks processed by each thread of [`ParallelFrameEncoder`] and
+/// [`ParallelFrameDecoder`] at once.
+const BLOCKS_PER_THREAD: usize = 4;
+
+/// The size of a block header and of the frame end mark.
+pub(crate) const BLOCK_HEADER_SIZE: usize = 4;
+/// The size of block and content checksums.
+pub(crate) const CHECKSUM_SIZE: usize = 4;
+
+/// A parsed LZ4 frame descriptor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FrameDescriptor {
+    pub(crate) independent_blocks: bool,
+    pub(crate) block_checksums: bool,
+    pub(crate) content_checksum: bool,
+    pub(crate) max_block_size: usize,
+    pub(crate) dict_id: Option<u32>,
+}
+
+impl FrameDescriptor {
+    /// The number of leading bytes required by [`FrameDescriptor::size`].
+    pub(crate) const PREFIX_SIZE: usize = 6;
+
+    /// Return the full size of the descriptor, including the magic number, from its first
+    /// [`FrameDescriptor::PREFIX_SIZE`] bytes.
+    pub(crate) fn size(prefix: &[u8]) -> io::Result<usize> {
+        if u32::from_le_bytes(prefix[0..4].try_into().unwrap()) != MAGIC {
+            return Err(invalid_data("invalid lz4 frame magic"));
+        }
+
+        let flg = prefix[4];
+        let mut size = Self::PREFIX_SIZE + 1;
+        if flg & FLG_CONTENT_SIZE != 0 {
+            size += 8;
+        }
+        if flg & FLG_DICT_ID != 0 {
+            size += 4;
+        }
+
+        Ok(size)
+    }
+
+    /// Parse a complete frame descriptor, including the magic number.
+    pub(crate) fn parse(buf: &[u8]) -> io::Result<FrameDescriptor> {
+        if buf.len() < Self::PREFIX_SIZE || Self::size(buf)? != buf.len() {
+            return Err(invalid_data("invalid lz4 frame descriptor size"));
+        }
+
+        let (desc, hc) = buf.split_at(buf.len() - 1);
+        if hc[0] != (XxHash32::oneshot(0, &desc[4..]) >> 8) as u8 {
+            return Err(invalid_data("lz4 frame header checksum mismatch"));
+        }
+
+        let (flg, bd) = (buf[4], buf[5]);
+        if flg & FLG_VERSION_MASK != FLG_VERSION {
+            return Err(invalid_data("unsupported lz4 frame version"));
+        }
+        let max_block_size = match (bd >> 4) & 0b111 {
+            4 => 64 * 1024,
+            5 => 256 * 1024,
+            6 => 1024 * 1024,
+            7 => 4 * 1024 * 1024,
+            _ => return Err(invalid_data("unsupported lz4 block size")),
+        };
+
+        Ok(FrameDescriptor {
+            independent_blocks: flg & FLG_INDEPENDENT_BLOCKS != 0,
+            block_checksums: flg & FLG_BLOCK_CHECKSUMS != 0,
+            content_checksum: flg & FLG_CONTENT_CHECKSUM != 0,
+            max_block_size,
+            dict_id: (flg & FLG_DICT_ID != 0).then(|| {
+                // The dictionary ID follows the optional content size.
+                let i = buf.len() - 5;
+                u32::from_le_bytes(buf[i..i + 4].try_into().unwrap())
+            }),
+        })
+    }
+
+    /// Encode the descriptor, including the magic number and the header checksum.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut flg = FLG_VERSION;
+        if self.independent_blocks {
+            flg |= FLG_INDEPENDENT_BLOCKS;
+        }
+        if self.block_checksums {
+            flg |= FLG_BLOCK_CHECKSUMS;
+        }
+        if self.content_checksum {
+            flg |= FLG_CONTENT_CHECKSUM;
+        }
+        if self.dict_id.is_some() {
+            flg |= FLG_DICT_ID;
+        }
+        let bd = match self.max_block_size {
+            size if size <= 64 * 1024 => 4,
+            size if size <= 256 * 1024 => 5,
+            size if size <= 1024 * 1024 => 6,
+            _ => 7,
+        } << 4;
+
+        let mut buf = Vec::with_capacity(Self::PREFIX_SIZE + 5);
+        buf.extend_from_slice(&MAGIC.to_le_bytes());
+        buf.extend_from_slice(&[flg, bd]);
+        if let Some(id) = self.dict_id {
+            buf.extend_from_slice(&id.to_le_bytes());
+        }
+        buf.push((XxHash32::oneshot(0, &buf[4..]) >> 8) as u8);
+
+        buf
+    }
+}
+
+/// A parsed LZ4 block header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BlockHeader {
+    /// The end of the frame.
+    EndMark,
+    /// A block of the given size.
+    Data { size: usize, compressed: bool },
+}
+
+impl BlockHeader {
+    pub(crate) fn parse(buf: [u8; BLOCK_HEADER_SIZE]) -> BlockHeader {
+        let v = u32::from_le_bytes(buf);
+        if v == 0 {
+            BlockHeader::EndMark
+        } else {
+            BlockHeader::Data {
+                size: (v & !BLOCK_UNCOMPRESSED) as usize,
+                compressed: v & BLOCK_UNCOMPRESSED == 0,
+            }
+        }
+    }
+}
+
+/// Decompresses the data blocks of a single LZ4 frame.
+pub(crate) struct BlockDecoder {
+    desc: FrameDescriptor,
+    // The frame dictionary, followed by the trailing output of the previous blocks for
+    // linked blocks.
+    dict: Vec<u8>,
+    scratch: Vec<u8>,
+}
+
+impl BlockDecoder {
+    #[cfg(any(test, feature = "async"))]
+    pub(crate) fn new(desc: FrameDescriptor) -> BlockDecoder {
+        BlockDecoder::with_dict(desc, &[])
+    }
+
+    /// Create a decoder for a frame compressed with the dictionary `dict`.
+    pub(crate) fn with_dict(desc: FrameDescriptor, dict: &[u8]) -> BlockDecoder {
+        BlockDecoder {
+            desc,
+            dict: dict.to_vec(),
+            scratch: vec![0; desc.max_block_size],
+        }
+    }
+
+    pub(crate) fn descriptor(&self) -> &FrameDescriptor {
+        &self.desc
+    }
+
+    /// Decode the block `data` described by `header` and append its contents to `out`.
+    ///
+    /// `data` must not include the block checksum.
+    pub(crate) fn decode(
+        &mut self,
+        header: BlockHeader,
+        data: &[u8],
+        out: &mut Vec<u8>,
+    ) -> io::Result<()> {
+        let (size, compressed) = match header {
+            BlockHeader::EndMark => return Err(invalid_data("unexpected lz4 end mark")),
+            BlockHeader::Data { size, compressed } => (size, compressed),
+        };
+        if size > self.desc.max_block_size || size != data.len() {
+            return Err(invalid_data("invalid lz4 block size"));
+        }
+
+        let block = if compressed {
+            let n = if self.dict.is_empty() {
+                lz4_flex::block::decompress_into(data, &mut self.scratch)
+            } else {
+                lz4_flex::block::decompress_into_with_dict(data, &mut self.scratch, &self.dict)
+            }
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            &self.scratch[..n]
+        } else {
+            data
+        };
+
+        if !self.desc.independent_blocks {
+            self.dict.extend_from_slice(block);
+            if self.dict.len() > MAX_DICT_SIZE {
+                self.dict.drain(..self.dict.len() - MAX_DICT_SIZE);
+            }
+        }
+        out.extend_from_slice(block);
+
+        Ok(())
+    }
+}
+
+/// An LZ4 frame encoder compressing independent blocks on several threads.
+///
+/// The input is buffered until every thread has a batch of full blocks to compress.
+/// The compressed blocks are written in order, so the output is a regular LZ4 frame.
+pub(crate) struct ParallelFrameEncoder<W>
+where
+    W: io::Write,
+{
+    w: W,
+    desc: FrameDescriptor,
+    dict: Arc<[u8]>,
+    threads: usize,
+    src: Vec<u8>,
+    content_hasher: XxHash32,
+    frame_open: bool,
+    frame_written: bool,
+}
+
+impl<W> ParallelFrameEncoder<W>
+where
+    W: io::Write,
+{
+    /// Create a new encoder using `threads` threads.
+    ///
+    /// The blocks of `desc` must be independent.
+    pub(crate) fn new(w: W, desc: FrameDescriptor, threads: usize) -> ParallelFrameEncoder<W> {
+        debug_assert!(desc.independent_blocks);
+
+        ParallelFrameEncoder {
+            w,
+            desc,
+            dict: Arc::from([]),
+            threads: threads.max(1),
+            src: Vec::new(),
+            content_hasher: XxHash32::with_seed(0),
+            frame_open: false,
+            frame_written: false,
+        }
+    }
+
+    /// Compress every block with the dictionary `dict`, which must be the one identified
+    /// by the dictionary ID of the descriptor.
+    #[cfg(feature = "std")]
+    pub(crate) fn with_dict(mut self, dict: Arc<[u8]>) -> ParallelFrameEncoder<W> {
+        self.dict = dict;
+        self
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn get_mut(&mut self) -> &mut W {
+        &mut self.w
+    }
+
+    /// End the current frame. The next write starts a new one.
+    pub(crate) fn try_finish(&mut self) -> io::Result<()> {
+        self.write_blocks(true)?;
+        if !self.frame_open && !self.frame_written {
+            self.begin_frame()?;
+        }
+        if self.frame_open {
+            self.w.write_all(&[0; BLOCK_HEADER_SIZE])?;
+            if self.desc.content_checksum {
+                self.w
+                    .write_all(&self.content_hasher.finish_32().to_le_bytes())?;
+            }
+            self.frame_open = false;
+            self.frame_written = true;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.w)
+    }
+
+    fn begin_frame(&mut self) -> io::Result<()> {
+        self.w.write_all(&self.desc.encode())?;
+        self.content_hasher = XxHash32::with_seed(0);
+        self.frame_open = true;
+        Ok(())
+    }
+
+    /// Compress and write the buffered full blocks, or all the buffered data if `all`.
+    fn write_blocks(&mut self, all: bool) -> io::Result<()> {
+        let block_size = self.desc.max_block_size;
+        let len = if all {
+            self.src.len()
+        } else {
+            self.src.len() / block_size * block_size
+        };
+        if len == 0 {
+            return Ok(());
+        }
+
+        let blocks: Vec<_> = self.src[..len].chunks(block_size).collect();
+        let desc = self.desc;
+        let dict = &self.dict[..];
+        let encode = move |blocks: &[&[u8]]| {
+            let mut out = Vec::new();
+            for block in blocks {
+                encode_block(&desc, dict, block, &mut out);
+            }
+            out
+        };
+
+        let per_thread = blocks.len().div_ceil(self.threads);
+        if per_thread == blocks.len() {
+            self.w.write_all(&encode(&blocks))?;
+        } else {
+            for out in map_chunks(&blocks, per_thread, encode) {
+                self.w.write_all(&out)?;
+            }
+        }
+
+        self.src.drain(..len);
+        Ok(())
+    }
+}
+
+impl<W> io::Write for ParallelFrameEncoder<W>
+where
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.frame_open {
+            self.begin_frame()?;
+        }
+        if self.desc.content_checksum {
+            self.content_hasher.write(buf);
+        }
+
+        self.src.extend_from_slice(buf);
+        if self.src.len() >= self.threads * BLOCKS_PER_THREAD * self.desc.max_block_size {
+            self.write_blocks(false)?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_blocks(true)?;
+        self.w.flush()
+    }
+}
+
+/// An LZ4 frame decoder decompressing independent blocks on several threads.
+///
+/// Batches of blocks are read from the underlying reader and decompressed in parallel,
+/// and their output is buffered until it has been read. Frames with linked blocks are
+/// decompressed on the calling thread. Like [`lz4_flex::frame::FrameDecoder`], the
+/// reader returns end of file once at the end of every frame and never reads past it.
+pub(crate) struct ParallelFrameDecoder<R>
+where
+    R: io::Read,
+{
+    r: R,
+    threads: usize,
+    dict_id: Option<u32>,
+    dict: Arc<[u8]>,
+    frame: Option<BlockDecoder>,
+    frame_ended: bool,
+    content_hasher: XxHash32,
+    out: Vec<u8>,
+    pos: usize,
+}
+
+/// A block read from the underlying reader.
+struct RawBlock {
+    header: BlockHeader,
+    data: Vec<u8>,
+    checksum: Option<u32>,
+}
+
+impl<R> ParallelFrameDecoder<R>
+where
+    R: io::Read,
+{
+    pub(crate) fn new(r: R, threads: usize) -> ParallelFrameDecoder<R> {
+        ParallelFrameDecoder {
+            r,
+            threads: threads.max(1),
+            dict_id: None,
+            dict: Arc::from([]),
+            frame: None,
+            frame_ended: false,
+            content_hasher: XxHash32::with_seed(0),
+            out: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Decompress frames with the dictionary `dict` identified by `dict_id`. Frames with
+    /// another dictionary ID, or none, are rejected.
+    #[cfg(feature = "std")]
+    pub(crate) fn with_dict(mut self, dict_id: u32, dict: Arc<[u8]>) -> ParallelFrameDecoder<R> {
+        self.dict_id = Some(dict_id);
+        self.dict = dict;
+        self
+    }
+
+    #[cfg(feature = "std")]
+    pub(crate) fn get_mut(&mut self) -> &mut R {
+        &mut self.r
+    }
+
+    pub(crate) fn into_inner(self) -> R {
+        self.r
+    }
+
+    fn begin_frame(&mut self) -> io::Result<()> {
+        let mut desc = vec![0; FrameDescriptor::PREFIX_SIZE];
+        self.r.read_exact(&mut desc)?;
+        desc.resize(FrameDescriptor::size(&desc)?, 0);
+        self.r
+            .read_exact(&mut desc[FrameDescriptor::PREFIX_SIZE..])?;
+
+        let desc = FrameDescriptor::parse(&desc)?;
+        if desc.dict_id != self.dict_id {
+            return Err(invalid_data("lz4 dictionary id mismatch"));
+        }
+        self.frame = Some(BlockDecoder::with_dict(desc, &self.dict));
+        self.content_hasher = XxHash32::with_seed(0);
+        Ok(())
+    }
+
+    /// Read and decompress the next batch of blocks of the current frame.
+    fn read_blocks(&mut self) -> io::Result<()> {
+        let Some(frame) = &mut self.frame else {
+            return Ok(());
+        };
+        let desc = *frame.descriptor();
+        let dict = &self.dict[..];
+
+        let mut blocks = Vec::new();
+        let mut end = false;
+        while blocks.len() < self.threads * BLOCKS_PER_THREAD {
+            let mut header = [0; BLOCK_HEADER_SIZE];
+            self.r.read_exact(&mut header)?;
+            let header = BlockHeader::parse(header);
+            let size = match header {
+                BlockHeader::EndMark => {
+                    end = true;
+                    break;
+                }
+                BlockHeader::Data { size, .. } if size <= desc.max_block_size => size,
+                BlockHeader::Data { .. } => return Err(invalid_data("invalid lz4 block size")),
+            };
+
+            let mut data = vec![0; size];
+            self.r.read_exact(&mut data)?;
+            let checksum = if desc.block_checksums {
+                let mut checksum = [0; CHECKSUM_SIZE];
+                self.r.read_exact(&mut checksum)?;
+                Some(u32::from_le_bytes(checksum))
+            } else {
+                None
+            };
+
+            blocks.push(RawBlock {
+                header,
+                data,
+                checksum,
+            });
+            // Linked blocks depend on each other, so there's nothing to parallelize.
+            if !desc.independent_blocks {
+                break;
+            }
+        }
+
+        self.out.clear();
+        self.pos = 0;
+
+        let per_thread = blocks.len().div_ceil(self.threads).max(1);
+        if per_thread >= blocks.len() || !desc.independent_blocks {
+            decode_blocks(frame, &blocks, &mut self.out)?;
+        } else {
+            let outputs = map_chunks(&blocks, per_thread, |group| {
+                let mut out = Vec::new();
+                let mut dec = BlockDecoder::with_dict(desc, dict);
+                decode_blocks(&mut dec, group, &mut out).map(|_| out)
+            });
+            for out in outputs {
+                self.out.extend_from_slice(&out?);
+            }
+        }
+
+        if desc.content_checksum {
+            self.content_hasher.write(&self.out);
+        }
+
+        if end {
+            if desc.content_checksum {
+                let mut checksum = [0; CHECKSUM_SIZE];
+                self.r.read_exact(&mut checksum)?;
+                if u32::from_le_bytes(checksum) != self.content_hasher.finish_32() {
+                    return Err(invalid_data("lz4 content checksum mismatch"));
+                }
+            }
+            self.frame = None;
+            self.frame_ended = true;
+        }
+
+        Ok(())
+    }
+}
+
+impl<R> io::Read for ParallelFrameDecoder<R>
+where
+    R: io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.pos == self.out.len() {
+            if self.frame_ended {
+                self.frame_ended = false;
+                return Ok(0);
+            }
+            if self.frame.is_none() {
+                self.begin_frame()?;
+            }
+            self.read_blocks()?;
+        }
+
+        let n = buf.len().min(self.out.len() - self.pos);
+        buf[..n].copy_from_slice(&self.out[self.pos..self.pos + n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+/// Apply `f` to the chunks of `per_thread` items of `items`, each on its own thread, or
+/// one after the other without `std`.
+fn map_chunks<T, U, F>(items: &[T], per_thread: usize, f: F) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&[T]) -> U + Sync,
+{
+    #[cfg(feature = "std")]
+    return std::thread::scope(|s| {
+        let f = &f;
+        items
+            .chunks(per_thread)
+            .map(|group| s.spawn(move || f(group)))
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|h| h.join().unwrap_or_else(|e| std::panic::resume_unwind(e)))
+            .collect()
+    });
+    #[cfg(not(feature = "std"))]
+    items.chunks(per_thread).map(f).collect()
+}
+
+/// Verify the checksums of `blocks` and append their contents to `out`.
+fn decode_blocks(dec: &mut BlockDecoder, blocks: &[RawBlock], out: &mut Vec<u8>) -> io::Result<()> {
+    for block in blocks {
+        if block
+            .checksum
+            .is_some_and(|checksum| checksum != XxHash32::oneshot(0, &block.data))
+        {
+            return Err(invalid_data("lz4 block checksum mismatch"));
+        }
+        dec.decode(block.header, &block.data, out)?;
+    }
+
+    Ok(())
+}
+
+/// Compress `src` into a single block, with the dictionary `dict` if it isn't empty,
+/// falling back to storing it uncompressed if it doesn't shrink, and append it to `out`.
+fn encode_block(desc: &FrameDescriptor, dict: &[u8], src: &[u8], out: &mut Vec<u8>) {
+    let mut compressed = vec![0; lz4_flex::block::get_maximum_output_size(src.len())];
+    let n = if dict.is_empty() {
+        lz4_flex::block::compress_into(src, &mut compressed)
+    } else {
+        lz4_flex::block::compress_into_with_dict(src, &mut compressed, dict)
+    };
+    let (header, data) = match n {
+        Ok(n) if n < src.len() => (n as u32, &compressed[..n]),
+        _ => (src.len() as u32 | BLOCK_UNCOMPRESSED, src),
+    };
+
+    out.extend_from_slice(&header.to_le_bytes());
+    out.extend_from_slice(data);
+    if desc.block_checksums {
+        out.extend_from_slice(&XxHash32::oneshot(0, data).to_le_bytes());
+    }
+}
+
+fn invalid_data(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        BlockDecoder, BlockHeader, FrameDescriptor, ParallelFrameDecoder, ParallelFrameEncoder,
+        BLOCK_HEADER_SIZE, CHECKSUM_SIZE,
+    };
+    use lz4_flex::frame::{BlockMode, BlockSize, FrameDecoder, FrameEncoder, FrameInfo};
+    use std::io::{Read, Write};
+
+    fn decode_frame(frame: &[u8]) -> Vec<u8> {
+        let desc_size = FrameDescriptor::size(&frame[..FrameDescriptor::PREFIX_SIZE])
+            .expect("failed to read descriptor size");
+        let desc = FrameDescriptor::parse(&frame[..desc_size]).expect("failed to parse descriptor");
+        let mut dec = BlockDecoder::new(desc);
+
+        let mut out = Vec::new();
+        let mut rest = &frame[desc_size..];
+        loop {
+            let header = BlockHeader::parse(rest[..BLOCK_HEADER_SIZE].try_into().unwrap());
+            rest = &rest[BLOCK_HEADER_SIZE..];
+            match header {
+                BlockHeader::EndMark => break,
+                BlockHeader::Data { size, .. } => {
+                    dec.decode(header, &rest[..size], &mut out)
+                        .expect("failed to decode block");
+                    rest = &rest[size..];
+                    if desc.block_checksums {
+                        rest = &rest[CHECKSUM_SIZE..];
+                    }
+                }
+            }
+        }
+        if desc.content_checksum {
+            rest = &rest[CHECKSUM_SIZE..];
+        }
+        assert!(rest.is_empty());
+
+        out
+    }
+
+    fn encode_frame(info: FrameInfo, data: &[u8]) -> Vec<u8> {
+        let mut enc = FrameEncoder::with_frame_info(info, Vec::new());
+        enc.write_all(data).expect("failed to write data");
+        enc.finish().expect("failed to finish frame")
+    }
+
+    #[test]
+    fn independent_blocks() {
+        let data: Vec<u8> = (0..300_000).map(|i| (i % 251) as u8).collect();
+        let frame = encode_frame(FrameInfo::new().block_size(BlockSize::Max64KB), &data);
+
+        assert_eq!(data, decode_frame(&frame));
+    }
+
+    #[test]
+    fn linked_blocks_with_checksums() {
+        let data: Vec<u8> = (0..300_000).map(|i| (i % 13) as u8).collect();
+        let frame = encode_frame(
+            FrameInfo::new()
+                .block_size(BlockSize::Max64KB)
+                .block_mode(BlockMode::Linked)
+                .block_checksums(true)
+                .content_checksum(true),
+            &data,
+        );
+
+        assert_eq!(data, decode_frame(&frame));
+    }
+
+    #[test]
+    fn uncompressed_blocks() {
+        let data: Vec<u8> = (0..100_000).map(|_| rand::random::<u8>()).collect();
+        let frame = encode_frame(FrameInfo::new().block_size(BlockSize::Max64KB), &data);
+
+        assert_eq!(data, decode_frame(&frame));
+    }
+
+    #[test]
+    fn parallel_encoder() {
+        let data: Vec<u8> = (0..3_000_000)
+            .map(|i| {
+                if i % 7 == 0 {
+                    rand::random()
+                } else {
+                    (i % 13) as u8
+                }
+            })
+            .collect();
+
+        for (block_checksums, content_checksum) in [(false, false), (true, true)] {
+            let desc = FrameDescriptor {
+                independent_blocks: true,
+                block_checksums,
+                content_checksum,
+                max_block_size: 64 * 1024,
+                dict_id: None,
+            };
+            let mut enc = ParallelFrameEncoder::new(Vec::new(), desc, 4);
+            for chunk in data.chunks(10_000) {
+                enc.write_all(chunk).expect("failed to write data");
+            }
+            let frame = enc.finish().expect("failed to finish frame");
+
+            let expected = encode_frame(
+                FrameInfo::new()
+                    .block_size(BlockSize::Max64KB)
+                    .block_checksums(block_checksums)
+                    .content_checksum(content_checksum),
+                &data,
+            );
+            assert_eq!(expected[..7], frame[..7]);
+
+            let mut out = Vec::new();
+            FrameDecoder::new(frame.as_slice())
+                .read_to_end(&mut out)
+                .expect("failed to decode frame");
+            assert_eq!(data, out);
+            assert_eq!(data, decode_frame(&frame));
+        }
+    }
+
+    #[test]
+    fn parallel_decoder() {
+        let data: Vec<u8> = (0..3_000_000)
+            .map(|i| {
+                if i % 7 == 0 {
+                    rand::random()
+                } else {
+                    (i % 13) as u8
+                }
+            })
+            .collect();
+
+        for info in [
+            FrameInfo::new().block_size(BlockSize::Max64KB),
+            FrameInfo::new()
+                .block_size(BlockSize::Max64KB)
+                .block_checksums(true)
+                .content_checksum(true),
+            FrameInfo::new()
+                .block_size(BlockSize::Max64KB)
+                .block_mode(BlockMode::Linked),
+        ] {
+            // Two frames followed by trailing data, which must not be consumed.
+            let mut input = encode_frame(info.clone(), &data);
+            input.extend(encode_frame(info, &data[..1000]));
+            input.extend_from_slice(b"tail");
+
+            let mut dec = ParallelFrameDecoder::new(input.as_slice(), 4);
+            for expected in [&data[..], &data[..1000]] {
+                let mut out = Vec::new();
+                dec.read_to_end(&mut out).expect("failed to decode frame");
+                assert_eq!(expected, out);
+            }
+            assert_eq!(b"tail", dec.into_inner());
+        }
+    }
+
+    #[test]
+    fn dictionary() {
+        let dict: std::sync::Arc<[u8]> = (0..4096).map(|i| (i * 7 % 256) as u8).collect();
+        let data: Vec<u8> = dict
+            .iter()
+            .cycle()
+            .skip(100)
+            .take(200_000)
+            .copied()
+            .collect();
+
+        let desc = FrameDescriptor {
+            independent_blocks: true,
+            block_checksums: false,
+            content_checksum: true,
+            max_block_size: 64 * 1024,
+            dict_id: Some(42),
+        };
+        let mut enc = ParallelFrameEncoder::new(Vec::new(), desc, 2).with_dict(dict.clone());
+        enc.write_all(&data).expect("failed to write data");
+        let frame = enc.finish().expect("failed to finish frame");
+        let desc_size = FrameDescriptor::size(&frame[..FrameDescriptor::PREFIX_SIZE]).unwrap();
+        assert_eq!(desc, FrameDescriptor::parse(&frame[..desc_size]).unwrap());
+
+        let mut out = Vec::new();
+        ParallelFrameDecoder::new(frame.as_slice(), 2)
+            .with_dict(42, dict.clone())
+            .read_to_end(&mut out)
+            .expect("failed to decode frame");
+        assert_eq!(data, out);
+
+        let err = ParallelFrameDecoder::new(frame.as_slice(), 2)
+            .with_dict(43, dict)
+            .read_to_end(&mut out)
+            .expect_err("expected dictionary id mismatch");
+        assert_eq!(std::io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn parallel_decoder_block_checksum_mismatch() {
+        let data: Vec<u8> = (0..300_000).map(|i| (i % 13) as u8).collect();
+        let mut frame = encode_frame(
+            FrameInfo::new()
+                .block_size(BlockSize::Max64KB)
+                .block_checksums(true),
+            &data,
+        );
+        // Corrupt the checksum of the first block.
+        let desc_size = FrameDescriptor::size(&frame[..FrameDescriptor::PREFIX_SIZE]).unwrap();
+        let header = BlockHeader::parse(
+            frame[desc_size..desc_size + BLOCK_HEADER_SIZE]
+                .try_into()
+                .unwrap(),
+        );
+        let BlockHeader::Data { size, .. } = header else {
+            panic!("expected data block");
+        };
+        frame[desc_size + BLOCK_HEADER_SIZE + size] ^= 0xff;
+
+        let mut out = Vec::new();
+        let err = ParallelFrameDecoder::new(frame.as_slice(), 4)
+            .read_to_end(&mut out)
+            .expect_err("expected checksum mismatch");
+        assert_eq!(std::io::ErrorKind::InvalidData, err.kind());
+    }
+}
@@ -0,0 +1,247 @@
+use crate::{ltx::CRC64, Checksum, PageNum};
+use std::io;
+
+pub(crate) const MERKLE_COUNT_SIZE: usize = 4;
+const PAGE_NUM_SIZE: usize = 4;
+const NODE_SIZE: usize = 8;
+
+/// A Merkle tree decoding error.
+#[derive(thiserror::Error, Debug)]
+pub enum MerkleDecodeError {
+    #[error("read error")]
+    Read(#[from] io::Error),
+    #[error("invalid page number record")]
+    PageNum,
+    #[error("tree of {0} pages doesn't fit in the file")]
+    Size(u32),
+    #[error("tree doesn't match the pages of the file")]
+    Mismatch,
+}
+
+/// A Merkle tree over the page checksums of a file, written after the page block.
+///
+/// The leaves are the page checksums in file order, and every other node is the CRC64
+/// of the concatenated big-endian children. The last node of an odd level is moved up
+/// to the next level as is. The tree is encoded as the page numbers of the leaves,
+/// followed by the nodes level by level from the leaves to the root, followed by the
+/// number of leaves.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct MerkleTree {
+    page_nums: Vec<PageNum>,
+    leaves: Vec<Checksum>,
+}
+
+impl MerkleTree {
+    pub(crate) fn push(&mut self, page_num: PageNum, checksum: Checksum) {
+        self.page_nums.push(page_num);
+        self.leaves.push(checksum);
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub(crate) fn encoded_size(len: usize) -> usize {
+        len * PAGE_NUM_SIZE + level_lens(len).iter().sum::<usize>() * NODE_SIZE + MERKLE_COUNT_SIZE
+    }
+
+    /// Return the root of the tree, zero for a tree without leaves.
+    #[cfg(test)]
+    pub(crate) fn root(&self) -> Checksum {
+        self.nodes().last().copied().unwrap_or(Checksum::new(0))
+    }
+
+    fn nodes(&self) -> Vec<Checksum> {
+        let mut nodes = self.leaves.clone();
+        let mut level = 0..nodes.len();
+        while level.len() > 1 {
+            let start = nodes.len();
+            for i in level.clone().step_by(2) {
+                let right = (i + 1 < level.end).then(|| nodes[i + 1]);
+                nodes.push(parent(nodes[i], right));
+            }
+            level = start..nodes.len();
+        }
+
+        nodes
+    }
+
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::encoded_size(self.len()));
+        for page_num in &self.page_nums {
+            buf.extend_from_slice(&page_num.into_inner().to_be_bytes());
+        }
+        for node in self.nodes() {
+            buf.extend_from_slice(&node.into_inner().to_be_bytes());
+        }
+        buf.extend_from_slice(&(self.len() as u32).to_be_bytes());
+
+        buf
+    }
+
+    /// Read the encoded tree from `r` and check that it's the tree of this one's pages.
+    pub(crate) fn verify_from<R>(&self, mut r: R) -> Result<(), MerkleDecodeError>
+    where
+        R: io::Read,
+    {
+        let mut buf = vec![0; Self::encoded_size(self.len())];
+        r.read_exact(&mut buf)?;
+        if buf != self.encode() {
+            return Err(MerkleDecodeError::Mismatch);
+        }
+
+        Ok(())
+    }
+}
+
+/// The location of an encoded [`MerkleTree`] in a seekable file, used to verify single
+/// pages without reading the rest of the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MerkleLayout {
+    offset: u64,
+    page_nums: Vec<PageNum>,
+}
+
+impl MerkleLayout {
+    /// Read the layout of the tree ending at the offset `end` of `r`.
+    pub(crate) fn read_from<R>(r: &mut R, end: u64) -> Result<MerkleLayout, MerkleDecodeError>
+    where
+        R: io::Read + io::Seek,
+    {
+        let mut count = [0; MERKLE_COUNT_SIZE];
+        let count_offset = end
+            .checked_sub(MERKLE_COUNT_SIZE as u64)
+            .ok_or(MerkleDecodeError::Size(0))?;
+        r.seek(io::SeekFrom::Start(count_offset))?;
+        r.read_exact(&mut count)?;
+        let count = u32::from_be_bytes(count);
+
+        let offset = end
+            .checked_sub(MerkleTree::encoded_size(count as usize) as u64)
+            .ok_or(MerkleDecodeError::Size(count))?;
+        r.seek(io::SeekFrom::Start(offset))?;
+        let mut buf = vec![0; count as usize * PAGE_NUM_SIZE];
+        r.read_exact(&mut buf)?;
+        let page_nums = buf
+            .chunks_exact(PAGE_NUM_SIZE)
+            .map(|b| PageNum::new(u32::from_be_bytes(b.try_into().unwrap())))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| MerkleDecodeError::PageNum)?;
+
+        Ok(MerkleLayout { offset, page_nums })
+    }
+
+    /// Read the root of the tree.
+    pub(crate) fn root<R>(&self, r: &mut R) -> Result<Checksum, MerkleDecodeError>
+    where
+        R: io::Read + io::Seek,
+    {
+        let lens = level_lens(self.page_nums.len());
+        match lens.iter().sum::<usize>() {
+            0 => Ok(Checksum::new(0)),
+            n => self.read_node(r, n - 1),
+        }
+    }
+
+    /// Check the page checksum `checksum` of the page `page_num` against the root by
+    /// reading the nodes on the path from its leaf. Returns `false` if the tree has no
+    /// such page.
+    pub(crate) fn verify<R>(
+        &self,
+        r: &mut R,
+        page_num: PageNum,
+        checksum: Checksum,
+    ) -> Result<bool, MerkleDecodeError>
+    where
+        R: io::Read + io::Seek,
+    {
+        let Ok(mut i) = self.page_nums.binary_search(&page_num) else {
+            return Ok(false);
+        };
+
+        let mut node = checksum;
+        let mut start = 0;
+        let lens = level_lens(self.page_nums.len());
+        for len in &lens[..lens.len() - 1] {
+            let sibling = i ^ 1;
+            node = match (sibling < *len, i % 2 == 0) {
+                (true, true) => parent(node, Some(self.read_node(r, start + sibling)?)),
+                (true, false) => parent(self.read_node(r, start + sibling)?, Some(node)),
+                (false, _) => node,
+            };
+            start += len;
+            i /= 2;
+        }
+
+        Ok(node == self.read_node(r, start)?)
+    }
+
+    fn read_node<R>(&self, r: &mut R, index: usize) -> Result<Checksum, MerkleDecodeError>
+    where
+        R: io::Read + io::Seek,
+    {
+        let offset = self.page_nums.len() * PAGE_NUM_SIZE + index * NODE_SIZE;
+        r.seek(io::SeekFrom::Start(self.offset + offset as u64))?;
+        let mut buf = [0; NODE_SIZE];
+        r.read_exact(&mut buf)?;
+
+        Ok(Checksum::new(u64::from_be_bytes(buf)))
+    }
+}
+
+/// Return the number of nodes of every level of a tree of `len` leaves.
+fn level_lens(len: usize) -> Vec<usize> {
+    let mut lens = vec![len];
+    while lens[lens.len() - 1] > 1 {
+        lens.push(lens[lens.len() - 1].div_ceil(2));
+    }
+
+    lens
+}
+
+fn parent(left: Checksum, right: Option<Checksum>) -> Checksum {
+    let Some(right) = right else {
+        return left;
+    };
+
+    let mut digest = CRC64.digest();
+    digest.update(&left.into_inner().to_be_bytes());
+    digest.update(&right.into_inner().to_be_bytes());
+    Checksum::new(digest.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MerkleLayout, MerkleTree};
+    use crate::{Checksum, PageNum};
+    use std::io;
+
+    #[test]
+    fn merkle_tree() {
+        for len in [0, 1, 2, 5, 8, 13] {
+            let mut tree = MerkleTree::default();
+            for n in 1..=len {
+                tree.push(PageNum::new(n * 2).unwrap(), Checksum::new(n as u64 * 7));
+            }
+
+            let mut buf = vec![0xff; 3];
+            buf.extend(tree.encode());
+            assert_eq!(MerkleTree::encoded_size(tree.len()), buf.len() - 3);
+            tree.verify_from(&buf[3..]).expect("failed to verify tree");
+
+            let mut r = io::Cursor::new(&buf);
+            let layout =
+                MerkleLayout::read_from(&mut r, buf.len() as u64).expect("failed to read layout");
+            assert_eq!(tree.root(), layout.root(&mut r).unwrap());
+            for n in 1..=len {
+                let page_num = PageNum::new(n * 2).unwrap();
+                let checksum = Checksum::new(n as u64 * 7);
+                assert!(layout.verify(&mut r, page_num, checksum).unwrap());
+                assert!(!layout.verify(&mut r, page_num, Checksum::new(1)).unwrap());
+            }
+            assert!(!layout
+                .verify(&mut r, PageNum::new(1).unwrap(), Checksum::new(7))
+                .unwrap());
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use crate::{
+    compression,
+    ltx::{PageHeader, HEADER_SIZE, PAGE_CHECKSUM_SIZE, PAGE_HEADER_SIZE},
+    DecodeError, Decoder, FileSection, Header, HeaderFlags, PageNum, Trailer,
+};
+use memmap2::Mmap;
+use std::{fs, path::Path, sync::Arc, thread};
+
+/// A decoder of uncompressed LTX files mapped into memory, yielding pages as slices of
+/// the mapping rather than copying them.
+///
+/// Pages are yielded without checking any checksum, which is what makes it fast. The
+/// file is only verified by [`MmapDecoder::verify`] or
+/// [`MmapDecoder::verify_in_background`], so pages must not be trusted, e.g. the
+/// database they're written to must not be used, until verification succeeds.
+///
+/// Compressed and encrypted files aren't supported.
+///
+/// # Example
+/// ```no_run
+/// let (dec, header) =
+///     litetx::MmapDecoder::open("0000000000000001-0000000000000064.ltx").expect("open");
+/// let verified = dec.verify_in_background();
+/// for page in dec.pages() {
+///     let (page_num, data) = page.expect("page");
+///     // write the page to the database
+/// }
+/// let trailer = verified.join().unwrap().expect("verify");
+/// ```
+#[derive(Debug)]
+pub struct MmapDecoder {
+    map: Arc<Mmap>,
+    page_size: usize,
+    page_checksums: bool,
+    page_block: usize,
+}
+
+impl MmapDecoder {
+    /// Map the LTX file at `path` and decode its header.
+    pub fn open<P>(path: P) -> Result<(MmapDecoder, Header), DecodeError>
+    where
+        P: AsRef<Path>,
+    {
+        MmapDecoder::new(&fs::File::open(path)?)
+    }
+
+    /// Map the LTX file `file` and decode its header.
+    ///
+    /// The file must not be modified while it's mapped, which is undefined behavior.
+    pub fn new(file: &fs::File) -> Result<(MmapDecoder, Header), DecodeError> {
+        // SAFETY: The mapping is read-only, and the file must not be modified while
+        // it's mapped, as documented.
+        let map = unsafe { Mmap::map(file)? };
+
+        let truncated = DecodeError::TruncatedFile {
+            expected: FileSection::Header,
+            offset: map.len() as u64,
+        };
+        if map.len() < HEADER_SIZE {
+            return Err(truncated);
+        }
+        let header_size = Header::encoded_size(&map[..HEADER_SIZE]);
+        let Some(header) = map.get(..header_size) else {
+            return Err(truncated);
+        };
+        let header = Header::decode_from(header)?;
+
+        let unsupported = header.flags.intersection(
+            HeaderFlags::COMPRESS_LZ4
+                | HeaderFlags::COMPRESS_ZSTD
+                | HeaderFlags::PER_PAGE_COMPRESSION
+                | HeaderFlags::ENCRYPTED
+                | compression::registered_flags(),
+        );
+        if !unsupported.is_empty() {
+            return Err(DecodeError::UnsupportedFlags(unsupported));
+        }
+
+        Ok((
+            MmapDecoder {
+                map: Arc::new(map),
+                page_size: header.page_size.into_inner() as usize,
+                page_checksums: header.flags.contains(HeaderFlags::PAGE_CHECKSUM),
+                page_block: header_size,
+            },
+            header,
+        ))
+    }
+
+    /// Return an iterator over the pages of the file, in file order.
+    ///
+    /// Only the page headers are checked: the iterator fails if the page block is
+    /// truncated or if page numbers aren't in increasing order, and ends after the
+    /// last page.
+    pub fn pages(&self) -> MmapPages<'_> {
+        MmapPages {
+            dec: self,
+            offset: self.page_block,
+            last_page_num: None,
+            done: false,
+        }
+    }
+
+    /// Decode and verify the whole file as [`Decoder`] does, returning its trailer.
+    pub fn verify(&self) -> Result<Trailer, DecodeError> {
+        verify(&self.map)
+    }
+
+    /// Run [`MmapDecoder::verify`] on a background thread, e.g. while the pages are
+    /// being applied.
+    pub fn verify_in_background(&self) -> thread::JoinHandle<Result<Trailer, DecodeError>> {
+        let map = self.map.clone();
+        thread::spawn(move || verify(&map))
+    }
+}
+
+fn verify(map: &Mmap) -> Result<Trailer, DecodeError> {
+    let (mut dec, _) = Decoder::new(&map[..])?;
+    while dec.decode_page_ref()?.is_some() {}
+    dec.finish()
+}
+
+/// An iterator over the pages of an [`MmapDecoder`], returned by
+/// [`MmapDecoder::pages`].
+#[derive(Debug)]
+pub struct MmapPages<'a> {
+    dec: &'a MmapDecoder,
+    offset: usize,
+    last_page_num: Option<PageNum>,
+    done: bool,
+}
+
+impl<'a> MmapPages<'a> {
+    fn next_page(&mut self) -> Result<Option<(PageNum, &'a [u8])>, DecodeError> {
+        let map: &'a [u8] = &self.dec.map;
+        let truncated = DecodeError::TruncatedFile {
+            expected: FileSection::PageBlock,
+            offset: map.len() as u64,
+        };
+
+        let Some(header) = map.get(self.offset..self.offset + PAGE_HEADER_SIZE) else {
+            return Err(truncated);
+        };
+        let Some(page_num) = PageHeader::decode_from(header)?.0 else {
+            return Ok(None);
+        };
+        if let Some(last) = self.last_page_num.filter(|&last| page_num <= last) {
+            return Err(DecodeError::OutOfOrderPage(last, page_num));
+        }
+
+        let start = self.offset + PAGE_HEADER_SIZE;
+        let mut end = start + self.dec.page_size;
+        let Some(page) = map.get(start..end) else {
+            return Err(truncated);
+        };
+        if self.dec.page_checksums {
+            end += PAGE_CHECKSUM_SIZE;
+        }
+
+        self.offset = end;
+        self.last_page_num = Some(page_num);
+        Ok(Some((page_num, page)))
+    }
+}
+
+impl<'a> Iterator for MmapPages<'a> {
+    type Item = Result<(PageNum, &'a [u8]), DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.next_page().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+impl std::iter::FusedIterator for MmapPages<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::MmapDecoder;
+    use crate::{
+        test_util::{encode_ltx, test_header},
+        utils::TempDir,
+        Checksum, DecodeError, Decoder, FileSection, HeaderFlags, PageNum,
+    };
+    use std::{fs, path::PathBuf};
+
+    fn encode(dir: &TempDir, flags: HeaderFlags) -> (PathBuf, Vec<u8>) {
+        let header = test_header(flags, 2..=2, 8, Some(Checksum::new(1)));
+        let pages = [(1, 1), (3, 3), (4, 4), (8, 8)];
+        let buf = encode_ltx(&header, &pages, Some(Checksum::new(2)));
+
+        let path = dir.path().join("file.ltx");
+        fs::write(&path, &buf).expect("failed to write file");
+        (path, buf)
+    }
+
+    fn decode(buf: &[u8]) -> Vec<(PageNum, Vec<u8>)> {
+        let (mut dec, _) = Decoder::new(buf).expect("failed to create decoder");
+        let mut pages = Vec::new();
+        while let Some((page_num, page)) = dec.decode_page_ref().expect("failed to decode page") {
+            pages.push((page_num, page.to_vec()));
+        }
+        pages
+    }
+
+    #[test]
+    fn mmap_decoder() {
+        let dir = TempDir::new();
+        for flags in [
+            HeaderFlags::empty(),
+            HeaderFlags::PAGE_CHECKSUM | HeaderFlags::PAGE_INDEX,
+        ] {
+            let (path, buf) = encode(&dir, flags);
+            let (dec, header) = MmapDecoder::open(&path).expect("failed to open file");
+            assert_eq!(flags, header.flags);
+
+            let pages: Vec<_> = dec
+                .pages()
+                .map(|page| {
+                    let (page_num, page) = page.expect("failed to decode page");
+                    (page_num, page.to_vec())
+                })
+                .collect();
+            assert_eq!(decode(&buf), pages);
+
+            let trailer = dec
+                .verify_in_background()
+                .join()
+                .unwrap()
+                .expect("failed to verify");
+            assert_eq!(Some(Checksum::new(2)), trailer.post_apply_checksum);
+        }
+    }
+
+    #[test]
+    fn mmap_decoder_corrupt() {
+        let dir = TempDir::new();
+        let (path, mut buf) = encode(&dir, HeaderFlags::empty());
+        let len = buf.len();
+        buf[len - 100] ^= 1;
+        fs::write(&path, &buf).unwrap();
+
+        let (dec, _) = MmapDecoder::open(&path).expect("failed to open file");
+        assert_eq!(4, dec.pages().filter(Result::is_ok).count());
+        assert!(matches!(
+            dec.verify().as_ref().map_err(DecodeError::inner),
+            Err(DecodeError::FileChecksumMismatch)
+        ));
+    }
+
+    #[test]
+    fn mmap_decoder_truncated() {
+        let dir = TempDir::new();
+        let (path, buf) = encode(&dir, HeaderFlags::empty());
+        fs::write(&path, &buf[..buf.len() / 2]).unwrap();
+
+        let (dec, _) = MmapDecoder::open(&path).expect("failed to open file");
+        let pages: Vec<_> = dec.pages().collect();
+        assert!(pages[..pages.len() - 1].iter().all(Result::is_ok));
+        assert!(matches!(
+            pages.last(),
+            Some(Err(DecodeError::TruncatedFile {
+                expected: FileSection::PageBlock,
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn mmap_decoder_compressed() {
+        let dir = TempDir::new();
+        let (path, _) = encode(&dir, HeaderFlags::COMPRESS_LZ4);
+        assert!(matches!(
+            MmapDecoder::open(&path),
+            Err(DecodeError::UnsupportedFlags(HeaderFlags::COMPRESS_LZ4))
+        ));
+    }
+}
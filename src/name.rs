@@ -0,0 +1,139 @@
+//! LTX file naming convention.
+//!
+//! LTX files are named after the range of transactions they contain, formatted as
+//! `<min_txid>-<max_txid>.ltx` with both IDs written as 16 lowercase hex digits.
+
+use crate::{types::TXIDError, Header, TXID};
+
+const EXTENSION: &str = ".ltx";
+const TXID_LEN: usize = 16;
+
+/// An error that can be returned while parsing or validating LTX file names.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("invalid LTX file name: {0}")]
+    Format(String),
+    #[error("invalid transaction ID")]
+    TXID(#[from] TXIDError),
+    #[error("transaction ids out of order: ({0}, {1})")]
+    TXIDOrder(TXID, TXID),
+    #[error("file name doesn't match header transaction ids: ({0}, {1})")]
+    HeaderMismatch(TXID, TXID),
+}
+
+/// Format the name of an LTX file containing transactions from `min_txid` to `max_txid`.
+///
+/// # Example
+/// ```
+/// # use litetx::{name, TXID};
+/// let name = name::format_filename(TXID::ONE, TXID::new(2).unwrap());
+/// assert_eq!("0000000000000001-0000000000000002.ltx", name);
+/// ```
+pub fn format_filename(min_txid: TXID, max_txid: TXID) -> String {
+    format!("{min_txid}-{max_txid}{EXTENSION}")
+}
+
+/// Parse the transaction ID range from the name of an LTX file.
+pub fn parse_filename(name: &str) -> Result<(TXID, TXID), Error> {
+    let format_err = || Error::Format(name.to_owned());
+
+    let range = name.strip_suffix(EXTENSION).ok_or_else(format_err)?;
+    let (min_txid, max_txid) = range.split_once('-').ok_or_else(format_err)?;
+    let min_txid = parse_txid(min_txid).ok_or_else(format_err)?;
+    let max_txid = parse_txid(max_txid).ok_or_else(format_err)?;
+
+    let (min_txid, max_txid) = (TXID::new(min_txid)?, TXID::new(max_txid)?);
+    if min_txid > max_txid {
+        return Err(Error::TXIDOrder(min_txid, max_txid));
+    }
+
+    Ok((min_txid, max_txid))
+}
+
+/// Check that the file `name` matches the transaction ID range of its `header`.
+pub fn validate_filename(name: &str, header: &Header) -> Result<(), Error> {
+    let (min_txid, max_txid) = parse_filename(name)?;
+    if (min_txid, max_txid) != (header.min_txid, header.max_txid) {
+        return Err(Error::HeaderMismatch(min_txid, max_txid));
+    }
+
+    Ok(())
+}
+
+fn parse_txid(s: &str) -> Option<u64> {
+    if s.len() != TXID_LEN || !s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) {
+        return None;
+    }
+
+    u64::from_str_radix(s, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_filename, parse_filename, validate_filename, Error};
+    use crate::{Checksum, Header, HeaderFlags, PageNum, PageSize, TXID};
+    use std::time;
+
+    #[test]
+    fn format_parse() {
+        let (min_txid, max_txid) = (TXID::new(0xab).unwrap(), TXID::new(0x1000).unwrap());
+        let name = format_filename(min_txid, max_txid);
+        assert_eq!("00000000000000ab-0000000000001000.ltx", name);
+        assert_eq!(
+            (min_txid, max_txid),
+            parse_filename(&name).expect("failed to parse file name")
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        for name in [
+            "0000000000000001-0000000000000002",
+            "0000000000000001-0000000000000002.ltx.tmp",
+            "0000000000000001.ltx",
+            "1-2.ltx",
+            "00000000000000AB-0000000000000002.ltx",
+            "+000000000000001-0000000000000002.ltx",
+        ] {
+            assert!(
+                matches!(parse_filename(name), Err(Error::Format(_))),
+                "{name}"
+            );
+        }
+
+        assert!(matches!(
+            parse_filename("0000000000000000-0000000000000002.ltx"),
+            Err(Error::TXID(_))
+        ));
+        assert!(matches!(
+            parse_filename("0000000000000003-0000000000000002.ltx"),
+            Err(Error::TXIDOrder(_, _))
+        ));
+    }
+
+    #[test]
+    fn validate() {
+        let header = Header {
+            flags: HeaderFlags::empty(),
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(1).unwrap()),
+            min_txid: TXID::new(2).unwrap(),
+            max_txid: TXID::new(3).unwrap(),
+            timestamp: time::SystemTime::now(),
+            pre_apply_checksum: Some(Checksum::new(1)),
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        validate_filename("0000000000000002-0000000000000003.ltx", &header)
+            .expect("failed to validate file name");
+        assert!(matches!(
+            validate_filename("0000000000000002-0000000000000004.ltx", &header),
+            Err(Error::HeaderMismatch(_, _))
+        ));
+    }
+}
@@ -0,0 +1,484 @@
+//! Application of LTX files to databases held open through `rusqlite`.
+
+use crate::{
+    apply::{self, Error as ApplyError},
+    sqlite, Checksum, DatabaseChecksum, Pos,
+};
+use rusqlite::{
+    backup::{Backup, StepResult},
+    ffi, Connection, OpenFlags, TransactionBehavior,
+};
+use std::{
+    ffi::{c_char, c_int, c_uint},
+    io, thread, time,
+};
+
+const MAIN: &[u8] = b"main\0";
+const SQLITE_DESERIALIZE_READONLY: c_uint = 4;
+
+// Not all the bindings of `libsqlite3-sys` declare these, since they were added in
+// SQLite 3.36.
+extern "C" {
+    fn sqlite3_serialize(
+        db: *mut ffi::sqlite3,
+        schema: *const c_char,
+        size: *mut i64,
+        flags: c_uint,
+    ) -> *mut u8;
+    fn sqlite3_deserialize(
+        db: *mut ffi::sqlite3,
+        schema: *const c_char,
+        data: *mut u8,
+        size: i64,
+        buf_size: i64,
+        flags: c_uint,
+    ) -> c_int;
+}
+
+/// An error that can be returned by [`apply_online`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("apply")]
+    Apply(#[from] ApplyError),
+    #[error("sqlite")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("failed to serialize database")]
+    Serialize,
+    #[error("database must be in WAL mode, not {0}")]
+    JournalMode(String),
+    #[error("LTX file leaves the database out of WAL mode")]
+    NotWal,
+    #[error("databases can't be deleted online")]
+    Deletion,
+    #[error("database modified while the LTX file was applied")]
+    ConcurrentWrite,
+}
+
+/// Apply the LTX file read from `ltx` to the main database of `conn`, which stays open
+/// and usable throughout.
+///
+/// The database is copied into memory and the LTX file applied to the copy as
+/// [`apply_ltx`](crate::apply::apply_ltx) does, verifying the pre- and post-apply
+/// checksums. The copy then replaces the database in a single transaction through the
+/// SQLite backup API, so other connections see either the old or the new state, and
+/// the checksum of the replaced database is verified again.
+///
+/// Requires SQLite 3.36 or later. The database must be in WAL mode, since the backup
+/// API bumps the change counter of databases in rollback journal mode, and must stay in
+/// WAL mode and not be deleted after the LTX file is applied. Nothing is written if the
+/// LTX file doesn't apply, but the database is left modified if the second verification
+/// fails. The schema version of the database is set just before the backup, so that the
+/// backup bumps it to the value in the LTX file. It's set in a transaction of its own,
+/// after checking the database is still the copy the LTX file was applied to, and
+/// restored if the backup fails, unless another writer changed it in the meantime.
+///
+/// The backup waits for other writers and then holds the write lock of the database
+/// until it's done. Before any page is copied, the database is checked against the copy
+/// the LTX file was applied to, failing with [`Error::ConcurrentWrite`] if another
+/// writer committed in the meantime, whose changes would otherwise be overwritten.
+///
+/// Returns the position of the database after the LTX file has been applied.
+///
+/// # Example
+/// ```no_run
+/// let mut conn = rusqlite::Connection::open("db.sqlite").expect("open");
+/// let f = std::fs::File::open("0000000000000002-0000000000000002.ltx").expect("open");
+/// let pos = litetx::online::apply_online(&mut conn, f).expect("apply_online");
+/// ```
+pub fn apply_online<R>(conn: &mut Connection, ltx: R) -> Result<Pos, Error>
+where
+    R: io::Read,
+{
+    let mode: String = conn.pragma_query_value(None, "journal_mode", |row| row.get(0))?;
+    if !mode.eq_ignore_ascii_case("wal") {
+        return Err(Error::JournalMode(mode));
+    }
+
+    let db = serialize(conn)?;
+    let checksum = db_checksum(&db)?;
+    let header = db.get(..sqlite::HEADER_SIZE).unwrap_or_default().to_vec();
+    let mut db = io::Cursor::new(db);
+    let pos = apply::apply(&mut db, ltx)?;
+    let mut image = db.into_inner();
+    if image.is_empty() {
+        return Err(Error::Deletion);
+    }
+    // The file format version numbers are 2 in WAL mode. In-memory databases can't be
+    // opened in WAL mode, and the backup sets them back to 2 in the destination.
+    if image[18..20] != [2, 2] {
+        return Err(Error::NotWal);
+    }
+    image[18..20].copy_from_slice(&[1, 1]);
+
+    // The backup sets the schema version to the one the destination had plus one.
+    let new_version = u32::from_be_bytes(image[40..44].try_into().unwrap());
+    let backup_version = new_version.wrapping_sub(1) as i32;
+    let schema_version = {
+        // The schema version is only set while no other writer can commit, and if the
+        // database is still the one the LTX file was applied to.
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        if db_checksum(&serialize(&tx)?)? != checksum {
+            return Err(Error::ConcurrentWrite);
+        }
+        let schema_version: i32 =
+            tx.pragma_query_value(None, "schema_version", |row| row.get(0))?;
+        tx.pragma_update(None, "schema_version", backup_version)?;
+        tx.commit()?;
+        schema_version
+    };
+    if let Err(e) = replace(conn, &image, checksum, &header) {
+        // The backup is rolled back, but the schema version was committed on its own.
+        reset_schema_version(conn, backup_version, schema_version)?;
+        return Err(e);
+    }
+
+    let checksum = db_checksum(&serialize(conn)?)?;
+    if checksum != pos.post_apply_checksum {
+        return Err(
+            ApplyError::PostApplyChecksumMismatch(checksum, pos.post_apply_checksum).into(),
+        );
+    }
+
+    Ok(pos)
+}
+
+/// Replace the main database of `conn` with `image` through the backup API, unless the
+/// database changed since its checksum was `checksum`, with `header` as database header.
+fn replace(
+    conn: &mut Connection,
+    image: &[u8],
+    checksum: Checksum,
+    header: &[u8],
+) -> Result<(), Error> {
+    let path = conn.path().map(str::to_owned);
+
+    let src = Connection::open_in_memory()?;
+    // The image must outlive `src`, which reads it in place.
+    // SAFETY: The database handle is valid and the image isn't modified while `src`
+    // is open, since it's deserialized read-only.
+    let rc = unsafe {
+        sqlite3_deserialize(
+            src.handle(),
+            MAIN.as_ptr().cast(),
+            image.as_ptr().cast_mut(),
+            image.len() as i64,
+            image.len() as i64,
+            SQLITE_DESERIALIZE_READONLY,
+        )
+    };
+    if rc != ffi::SQLITE_OK {
+        return Err(Error::Serialize);
+    }
+
+    let backup = Backup::new(&src, conn)?;
+    // A step without pages only takes the write lock of the destination, held until
+    // the backup is done or dropped, which rolls it back.
+    step(&backup, 0)?;
+
+    // Changes committed since the database was copied would be overwritten. They're
+    // read through another connection, the lock being held by the backup.
+    if let Some(path) = path.filter(|path| !path.is_empty()) {
+        let current = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let mut current = serialize(&current)?;
+        // Setting the schema version for the backup also bumped the change counter
+        // and its version-valid-for number.
+        if current.len() >= sqlite::HEADER_SIZE && header.len() == sqlite::HEADER_SIZE {
+            for range in [24..28, 40..44, 92..96] {
+                current[range.clone()].copy_from_slice(&header[range]);
+            }
+        }
+        if db_checksum(&current)? != checksum {
+            return Err(Error::ConcurrentWrite);
+        }
+    }
+
+    step(&backup, -1)
+}
+
+/// Set the schema version of the main database of `conn` back to `version` if it's
+/// still `backup_version`. Another writer may have committed since it was set, and if
+/// it changed the schema, its schema version must be kept for other connections to
+/// reload their cached schema.
+fn reset_schema_version(
+    conn: &mut Connection,
+    backup_version: i32,
+    version: i32,
+) -> Result<(), Error> {
+    let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let current: i32 = tx.pragma_query_value(None, "schema_version", |row| row.get(0))?;
+    if current == backup_version {
+        tx.pragma_update(None, "schema_version", version)?;
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Run a step of `backup` copying `pages` pages, or all of them if negative, waiting
+/// for other connections to release their locks.
+fn step(backup: &Backup, pages: c_int) -> Result<(), Error> {
+    loop {
+        match backup.step(pages)? {
+            StepResult::Busy | StepResult::Locked => thread::sleep(time::Duration::from_millis(10)),
+            _ => return Ok(()),
+        }
+    }
+}
+
+/// Calculate the checksum of the database image `db`.
+fn db_checksum(db: &[u8]) -> Result<Checksum, Error> {
+    if db.is_empty() {
+        return Ok(DatabaseChecksum::new().finish());
+    }
+
+    let page_size = sqlite::DatabaseHeader::decode(db[..sqlite::HEADER_SIZE].try_into().unwrap())
+        .map_err(|_| Error::Serialize)?
+        .page_size;
+    let pages = db.len() as u64 / page_size.into_inner() as u64;
+    let checksum = apply::pages_checksum(db, page_size, 1..=pages).map_err(ApplyError::from)?;
+
+    Ok(checksum)
+}
+
+/// Copy the main database of `conn` into memory.
+fn serialize(conn: &Connection) -> Result<Vec<u8>, Error> {
+    let mut size = 0;
+    // SAFETY: The database handle is valid and the returned buffer, if any, holds
+    // `size` bytes until freed.
+    unsafe {
+        let ptr = sqlite3_serialize(conn.handle(), MAIN.as_ptr().cast(), &mut size, 0);
+        if ptr.is_null() {
+            return if size == 0 {
+                Ok(Vec::new())
+            } else {
+                Err(Error::Serialize)
+            };
+        }
+        let image = std::slice::from_raw_parts(ptr, size as usize).to_vec();
+        ffi::sqlite3_free(ptr.cast());
+        Ok(image)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_online, db_checksum, replace, reset_schema_version, serialize, Error};
+    use crate::{
+        apply::Error as ApplyError, encode_db_diff, encode_db_snapshot, utils::TempDir,
+        HeaderFlags, Pos, SnapshotOptions, TXID,
+    };
+    use rusqlite::Connection;
+
+    fn open_wal(dir: &TempDir, name: &str) -> Connection {
+        let conn = Connection::open(dir.path().join(name)).expect("failed to open database");
+        conn.pragma_update(None, "journal_mode", "wal")
+            .expect("failed to enable WAL");
+        conn
+    }
+
+    fn insert(conn: &Connection, rows: usize) {
+        for _ in 0..rows {
+            let data: Vec<u8> = (0..300).map(|_| rand::random::<u8>()).collect();
+            conn.execute("INSERT INTO t (data) VALUES (?)", [data])
+                .expect("failed to insert");
+        }
+    }
+
+    fn count(conn: &Connection) -> u32 {
+        conn.query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+            .expect("failed to count rows")
+    }
+
+    fn schema_version(conn: &Connection) -> i32 {
+        conn.pragma_query_value(None, "schema_version", |row| row.get(0))
+            .expect("failed to query schema version")
+    }
+
+    #[test]
+    fn apply_online_snapshot_and_delta() {
+        let dir = TempDir::new();
+        let primary = open_wal(&dir, "primary");
+        primary
+            .execute("CREATE TABLE t (data BLOB)", ())
+            .expect("failed to create table");
+        insert(&primary, 20);
+
+        let old = serialize(&primary).expect("failed to serialize primary");
+        let mut snapshot = Vec::new();
+        let trailer = encode_db_snapshot(
+            old.as_slice(),
+            &mut snapshot,
+            SnapshotOptions {
+                flags: HeaderFlags::COMPRESS_LZ4,
+                ..Default::default()
+            },
+        )
+        .expect("failed to encode snapshot");
+
+        let mut replica = open_wal(&dir, "replica");
+        let reader = Connection::open(dir.path().join("replica")).expect("failed to open reader");
+        let pos =
+            apply_online(&mut replica, snapshot.as_slice()).expect("failed to apply snapshot");
+        assert_eq!(trailer.post_apply_checksum, Some(pos.post_apply_checksum));
+        assert_eq!(20, count(&reader));
+        assert_eq!(
+            old,
+            serialize(&replica).expect("failed to serialize replica")
+        );
+
+        insert(&primary, 50);
+        primary
+            .execute("DELETE FROM t WHERE rowid % 3 = 0", ())
+            .expect("failed to delete");
+        let new = serialize(&primary).expect("failed to serialize primary");
+        let mut delta = Vec::new();
+        let txid = TXID::new(2).unwrap();
+        encode_db_diff(
+            old.as_slice(),
+            new.as_slice(),
+            pos,
+            txid..=txid,
+            &mut delta,
+            HeaderFlags::empty(),
+        )
+        .expect("failed to encode delta");
+
+        let pos = apply_online(&mut replica, delta.as_slice()).expect("failed to apply delta");
+        assert_eq!(txid, pos.txid);
+        assert_eq!(count(&primary), count(&reader));
+        assert_eq!(
+            new,
+            serialize(&replica).expect("failed to serialize replica")
+        );
+    }
+
+    #[test]
+    fn apply_online_pre_apply_checksum_mismatch() {
+        let dir = TempDir::new();
+        let mut conn = open_wal(&dir, "db");
+        conn.execute("CREATE TABLE t (data BLOB)", ())
+            .expect("failed to create table");
+        let old = serialize(&conn).expect("failed to serialize database");
+        let trailer = encode_db_snapshot(old.as_slice(), Vec::new(), SnapshotOptions::default())
+            .expect("failed to encode snapshot");
+        insert(&conn, 5);
+        let new = serialize(&conn).expect("failed to serialize database");
+
+        // The delta applies to the old database, not to the current one.
+        let mut delta = Vec::new();
+        let txid = TXID::new(2).unwrap();
+        let prev = Pos {
+            txid: TXID::ONE,
+            post_apply_checksum: trailer.post_apply_checksum.unwrap(),
+        };
+        encode_db_diff(
+            old.as_slice(),
+            new.as_slice(),
+            prev,
+            txid..=txid,
+            &mut delta,
+            HeaderFlags::empty(),
+        )
+        .expect("failed to encode delta");
+
+        assert!(matches!(
+            apply_online(&mut conn, delta.as_slice()),
+            Err(Error::Apply(ApplyError::PreApplyChecksumMismatch(_, expected)))
+                if expected == prev.post_apply_checksum
+        ));
+        assert_eq!(5, count(&conn));
+    }
+
+    #[test]
+    fn apply_online_rollback_journal() {
+        let dir = TempDir::new();
+        let mut conn = Connection::open(dir.path().join("db")).expect("failed to open database");
+        assert!(matches!(
+            apply_online(&mut conn, &[][..]),
+            Err(Error::JournalMode(mode)) if mode == "delete"
+        ));
+    }
+
+    #[test]
+    fn apply_online_backup_failure() {
+        let dir = TempDir::new();
+        let primary =
+            Connection::open(dir.path().join("primary")).expect("failed to open database");
+        primary
+            .execute_batch("PRAGMA page_size = 1024; PRAGMA journal_mode = wal")
+            .expect("failed to set page size");
+        primary
+            .execute("CREATE TABLE t (data BLOB)", ())
+            .expect("failed to create table");
+        insert(&primary, 5);
+        let mut snapshot = Vec::new();
+        encode_db_snapshot(
+            serialize(&primary)
+                .expect("failed to serialize primary")
+                .as_slice(),
+            &mut snapshot,
+            SnapshotOptions::default(),
+        )
+        .expect("failed to encode snapshot");
+
+        // Backups to WAL databases can't change the page size.
+        let mut replica = open_wal(&dir, "replica");
+        replica
+            .execute_batch("CREATE TABLE t (data BLOB); CREATE TABLE u (id INTEGER)")
+            .expect("failed to create tables");
+        let version = schema_version(&replica);
+        assert!(matches!(
+            apply_online(&mut replica, snapshot.as_slice()),
+            Err(Error::Sqlite(_))
+        ));
+        assert_eq!(version, schema_version(&replica));
+        assert_eq!(0, count(&replica));
+    }
+
+    #[test]
+    fn apply_online_concurrent_write() {
+        let dir = TempDir::new();
+        let mut conn = open_wal(&dir, "db");
+        conn.execute("CREATE TABLE t (data BLOB)", ())
+            .expect("failed to create table");
+        let mut image = serialize(&conn).expect("failed to serialize database");
+        let checksum = db_checksum(&image).expect("failed to compute checksum");
+        image[18..20].copy_from_slice(&[1, 1]);
+
+        // Another connection commits after the database was copied.
+        let writer = Connection::open(dir.path().join("db")).expect("failed to open writer");
+        insert(&writer, 3);
+
+        assert!(matches!(
+            replace(&mut conn, &image, checksum, &image[..100]),
+            Err(Error::ConcurrentWrite)
+        ));
+        assert_eq!(3, count(&conn));
+    }
+
+    #[test]
+    fn reset_schema_version_after_schema_change() {
+        let dir = TempDir::new();
+        let mut conn = open_wal(&dir, "db");
+        conn.execute("CREATE TABLE t (data BLOB)", ())
+            .expect("failed to create table");
+        let version = schema_version(&conn);
+
+        conn.pragma_update(None, "schema_version", version + 10)
+            .expect("failed to set schema version");
+        reset_schema_version(&mut conn, version + 10, version)
+            .expect("failed to reset schema version");
+        assert_eq!(version, schema_version(&conn));
+
+        // A schema change by another writer is kept.
+        conn.pragma_update(None, "schema_version", version + 10)
+            .expect("failed to set schema version");
+        let writer = Connection::open(dir.path().join("db")).expect("failed to open writer");
+        writer
+            .execute("CREATE TABLE u (id INTEGER)", ())
+            .expect("failed to create table");
+        reset_schema_version(&mut conn, version + 10, version)
+            .expect("failed to reset schema version");
+        assert_eq!(version + 11, schema_version(&conn));
+    }
+}
@@ -0,0 +1,269 @@
+//! Random access to the pages of a database stored as a snapshot and a chain of deltas.
+
+use crate::{
+    decoder::Error as DecodeError, ltx::TrailerDecodeError, Decoder, Header, HeaderFlags, PageNum,
+    PageSize, Pos, Trailer, TXID,
+};
+use std::{collections::BTreeSet, io};
+
+/// An error that can be returned by [`PageReader`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("the base file isn't a snapshot")]
+    NotSnapshot,
+    #[error("input page size mismatch: {0}, expected {1}")]
+    PageSizeMismatch(PageSize, PageSize),
+    #[error("non-contiguous transaction ids: ({0}, {1})")]
+    NonContiguousTXID(TXID, TXID),
+    #[error(
+        "post-apply checksum of transaction {0} doesn't match pre-apply checksum of the next input"
+    )]
+    ChecksumMismatch(TXID),
+    #[error("page {0} not found")]
+    PageNotFound(PageNum),
+    #[error("decode")]
+    Decode(#[from] DecodeError),
+    #[error("trailer")]
+    Trailer(#[from] TrailerDecodeError),
+    #[error("io")]
+    Io(#[from] io::Error),
+}
+
+enum Layer<R>
+where
+    R: io::Read + io::Seek,
+{
+    /// A file with a page index, read with [`Decoder::seek_page`].
+    Indexed(Box<Decoder<'static, R>>),
+    /// A file without a page index, decoded from the start up to the requested page.
+    Scanned { r: R, pages: BTreeSet<PageNum> },
+}
+
+/// Reads the latest version of database pages from a snapshot and a chain of deltas
+/// applied on top of it, without materializing the database.
+///
+/// Every page is looked up in the deltas from the latest to the earliest, falling back to
+/// the snapshot. Files with the [`HeaderFlags::PAGE_INDEX`] flag are read directly at the
+/// page offset. Files without it are scanned once when the reader is created and
+/// decoded from the start for every page read from them, so indexed files are much
+/// faster to read from.
+///
+/// The file checksums of files without an index are verified by the scan.
+///
+/// # Example
+/// ```no_run
+/// let base = std::fs::File::open("0000000000000001-0000000000000064.ltx").expect("open");
+/// let delta = std::fs::File::open("0000000000000065-0000000000000065.ltx").expect("open");
+///
+/// let mut reader = litetx::overlay::PageReader::new(base, [delta]).expect("reader");
+/// let page = reader.read_page(litetx::PageNum::ONE).expect("read_page");
+/// ```
+pub struct PageReader<R>
+where
+    R: io::Read + io::Seek,
+{
+    layers: Vec<Layer<R>>,
+    page_size: PageSize,
+    commit: Option<PageNum>,
+    pos: Option<Pos>,
+}
+
+impl<R> PageReader<R>
+where
+    R: io::Read + io::Seek,
+{
+    /// Create a new [`PageReader`] serving the pages of the snapshot `base` with the
+    /// `deltas` applied in order.
+    ///
+    /// The files must form a contiguous sequence of transactions with matching
+    /// checksums.
+    pub fn new<I>(base: R, deltas: I) -> Result<PageReader<R>, Error>
+    where
+        I: IntoIterator<Item = R>,
+    {
+        let (layer, header, trailer) = open_layer(base)?;
+        if !header.is_snapshot() {
+            return Err(Error::NotSnapshot);
+        }
+
+        let mut layers = vec![layer];
+        let (mut prev, mut prev_trailer) = (header, trailer);
+        for r in deltas {
+            let (layer, header, trailer) = open_layer(r)?;
+            if header.page_size != prev.page_size {
+                return Err(Error::PageSizeMismatch(header.page_size, prev.page_size));
+            }
+            if prev.max_txid.checked_add(1) != Some(header.min_txid) {
+                return Err(Error::NonContiguousTXID(prev.max_txid, header.min_txid));
+            }
+            if !header.flags.contains(HeaderFlags::NO_CHECKSUM)
+                && header.pre_apply_checksum != prev_trailer.post_apply_checksum
+            {
+                return Err(Error::ChecksumMismatch(prev.max_txid));
+            }
+
+            layers.push(layer);
+            (prev, prev_trailer) = (header, trailer);
+        }
+
+        Ok(PageReader {
+            layers,
+            page_size: prev.page_size,
+            commit: prev.commit,
+            pos: prev_trailer
+                .post_apply_checksum
+                .map(|post_apply_checksum| Pos {
+                    txid: prev.max_txid,
+                    post_apply_checksum,
+                }),
+        })
+    }
+
+    /// Return the page size of the database.
+    pub fn page_size(&self) -> PageSize {
+        self.page_size
+    }
+
+    /// Return the size of the database in pages after the last delta, `None` if the
+    /// database was deleted.
+    pub fn commit(&self) -> Option<PageNum> {
+        self.commit
+    }
+
+    /// Return the position of the database after the last delta. `None` if the last file
+    /// has no checksums.
+    pub fn pos(&self) -> Option<Pos> {
+        self.pos
+    }
+
+    /// Read the latest version of the page with the given `page_num`.
+    ///
+    /// Fails with [`Error::PageNotFound`] if the page is past the end of the database or
+    /// none of the files contains it, e.g. because it's the lock page.
+    pub fn read_page(&mut self, page_num: PageNum) -> Result<Vec<u8>, Error> {
+        if self.commit.is_none_or(|commit| page_num > commit) {
+            return Err(Error::PageNotFound(page_num));
+        }
+
+        let mut data = vec![0; self.page_size.into_inner() as usize];
+        for layer in self.layers.iter_mut().rev() {
+            if layer.read_page(page_num, &mut data)? {
+                return Ok(data);
+            }
+        }
+
+        Err(Error::PageNotFound(page_num))
+    }
+}
+
+impl<R> Layer<R>
+where
+    R: io::Read + io::Seek,
+{
+    fn read_page(&mut self, page_num: PageNum, data: &mut [u8]) -> Result<bool, Error> {
+        match self {
+            Layer::Indexed(dec) => Ok(dec.seek_page(page_num, data)?),
+            Layer::Scanned { r, pages } => {
+                if !pages.contains(&page_num) {
+                    return Ok(false);
+                }
+
+                r.rewind()?;
+                let (mut dec, _) = Decoder::new(&mut *r)?;
+                while let Some(num) = dec.decode_page(data)? {
+                    if num == page_num {
+                        return Ok(true);
+                    }
+                }
+                Err(Error::PageNotFound(page_num))
+            }
+        }
+    }
+}
+
+fn open_layer<R>(mut r: R) -> Result<(Layer<R>, Header, Trailer), Error>
+where
+    R: io::Read + io::Seek,
+{
+    let trailer = Trailer::read_from_end(&mut r)?;
+    r.rewind()?;
+
+    let (mut dec, header) = Decoder::new(r)?;
+    if header.flags.contains(HeaderFlags::PAGE_INDEX) {
+        return Ok((Layer::Indexed(Box::new(dec)), header, trailer));
+    }
+
+    let mut pages = BTreeSet::new();
+    while let Some(page_num) = dec.skip_page()? {
+        pages.insert(page_num);
+    }
+    let (trailer, mut r, _) = dec.finish_into_inner()?;
+    r.rewind()?;
+
+    Ok((Layer::Scanned { r, pages }, header, trailer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, PageReader};
+    use crate::{
+        test_util::{encode_ltx, test_header},
+        Checksum, HeaderFlags, PageNum, TXID,
+    };
+    use std::io;
+
+    fn encode(flags: HeaderFlags, txid: u64, commit: u32, pages: &[u32]) -> io::Cursor<Vec<u8>> {
+        let is_snapshot = pages.len() == commit as usize;
+        let min_txid = if is_snapshot { 1 } else { txid };
+        let header = test_header(
+            flags,
+            min_txid..=txid,
+            commit,
+            (!is_snapshot).then(|| Checksum::new(txid - 1)),
+        );
+        let pages: Vec<_> = pages.iter().map(|n| (*n, txid as u8)).collect();
+
+        io::Cursor::new(encode_ltx(&header, &pages, Some(Checksum::new(txid))))
+    }
+
+    #[test]
+    fn page_reader() {
+        for flags in [
+            HeaderFlags::empty(),
+            HeaderFlags::COMPRESS_LZ4,
+            HeaderFlags::COMPRESS_LZ4 | HeaderFlags::PAGE_INDEX,
+        ] {
+            let base = encode(flags, 1, 4, &[1, 2, 3, 4]);
+            let deltas = [encode(flags, 2, 5, &[2, 5]), encode(flags, 3, 3, &[3])];
+
+            let mut reader = PageReader::new(base, deltas).expect("failed to create reader");
+            assert_eq!(PageNum::new(3).ok(), reader.commit());
+            assert_eq!(TXID::new(3).unwrap(), reader.pos().unwrap().txid);
+            for (page_num, txid) in [(3, 3), (1, 1), (2, 2)] {
+                assert_eq!(
+                    vec![txid; 512],
+                    reader
+                        .read_page(PageNum::new(page_num).unwrap())
+                        .expect("failed to read page")
+                );
+            }
+            assert!(matches!(
+                reader.read_page(PageNum::new(4).unwrap()),
+                Err(Error::PageNotFound(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn page_reader_invalid_chain() {
+        let flags = HeaderFlags::empty();
+        assert!(matches!(
+            PageReader::new(encode(flags, 2, 2, &[2]), []),
+            Err(Error::NotSnapshot)
+        ));
+        assert!(matches!(
+            PageReader::new(encode(flags, 1, 1, &[1]), [encode(flags, 3, 2, &[2])]),
+            Err(Error::NonContiguousTXID(_, _))
+        ));
+    }
+}
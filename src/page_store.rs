@@ -0,0 +1,505 @@
+use crate::{
+    decoder::Error as DecodeError, encoder::Error as EncodeError, Checksum, DatabaseChecksum,
+    Decoder, Encoder, Header, HeaderFlags, PageNum, PageSize, Pos, Trailer, TXID,
+};
+use std::{
+    collections::BTreeSet,
+    fs, io,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+    time,
+};
+
+/// An error that can be returned by [`PageStore`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("decode")]
+    Decode(#[from] DecodeError),
+    #[error("encode")]
+    Encode(#[from] EncodeError),
+    #[error("pre-apply checksum mismatch: {0}, expected {1}")]
+    PreApplyChecksumMismatch(Checksum, Checksum),
+    #[error("post-apply checksum mismatch: {0}, expected {1}")]
+    PostApplyChecksumMismatch(Checksum, Checksum),
+    #[error("page file {0}: invalid size")]
+    PageSize(PathBuf),
+    #[error("page {0} is missing")]
+    MissingPage(PageNum),
+    #[error("the store has no pages")]
+    Empty,
+    #[error("delta must start at transaction {1}, got {0}")]
+    MinTXID(TXID, TXID),
+    #[error("io")]
+    Io(#[from] io::Error),
+}
+
+/// A database stored as one file per page in a directory tree.
+///
+/// Page `n` is stored in `pages/<n>` below the root directory, where `<n>` is the page
+/// number in hexadecimal as formatted by `PathBuf::from(PageNum)`. A sharded store groups
+/// every 256 consecutive pages into a subdirectory, i.e. `pages/000001/000001ab`, to
+/// keep directories of large databases small.
+///
+/// # Example
+/// ```no_run
+/// let store = litetx::PageStore::new("/var/lib/pages").sharded(true);
+///
+/// let r = std::fs::File::open("0000000000000001-0000000000000064.ltx").expect("open");
+/// let pos = store.write_ltx(r).expect("write_ltx");
+///
+/// let mut w = std::fs::File::create("snapshot.ltx").expect("create");
+/// store
+///     .encode_snapshot(&mut w, pos.txid, litetx::HeaderFlags::COMPRESS_LZ4)
+///     .expect("encode_snapshot");
+/// ```
+#[derive(Debug, Clone)]
+pub struct PageStore {
+    dir: PathBuf,
+    sharded: bool,
+}
+
+impl PageStore {
+    /// Create a new [`PageStore`] rooted at `root`.
+    ///
+    /// The directories are created when the first page is written.
+    pub fn new<P>(root: P) -> PageStore
+    where
+        P: AsRef<Path>,
+    {
+        PageStore {
+            dir: root.as_ref().join("pages"),
+            sharded: false,
+        }
+    }
+
+    /// Store pages in subdirectories of 256 pages each.
+    pub fn sharded(mut self, enabled: bool) -> Self {
+        self.sharded = enabled;
+        self
+    }
+
+    /// Return the path of the file storing the page with the given `page_num`.
+    pub fn page_path(&self, page_num: PageNum) -> PathBuf {
+        let name = PathBuf::from(page_num);
+        if self.sharded {
+            // The shard is the page number without its lowest byte.
+            let shard = &name.to_str().unwrap()[..6];
+            self.dir.join(shard).join(name)
+        } else {
+            self.dir.join(name)
+        }
+    }
+
+    /// Read the page with the given `page_num`, `None` if it isn't stored.
+    pub fn read_page(&self, page_num: PageNum) -> Result<Option<Vec<u8>>, Error> {
+        match fs::read(self.page_path(page_num)) {
+            Ok(data) => Ok(Some(data)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Return the numbers of the stored pages.
+    pub fn page_nums(&self) -> Result<BTreeSet<PageNum>, Error> {
+        let mut page_nums = BTreeSet::new();
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(page_nums),
+            Err(e) => return Err(e.into()),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            if self.sharded && entry.file_type()?.is_dir() {
+                for entry in fs::read_dir(entry.path())? {
+                    page_nums.extend(parse_page_name(&entry?.file_name()));
+                }
+            } else {
+                page_nums.extend(parse_page_name(&entry.file_name()));
+            }
+        }
+
+        Ok(page_nums)
+    }
+
+    /// Apply the LTX file read from `r` to the stored pages.
+    ///
+    /// Snapshots replace all the stored pages. For other files the checksum of the
+    /// stored pages is verified against the pre-apply checksum before any page is
+    /// written. Pages past the `commit` size of the file are removed, and the resulting
+    /// checksum is verified against the post-apply checksum from the LTX trailer.
+    ///
+    /// Note that the pages are left modified if the post-apply verification fails.
+    ///
+    /// Returns the position of the stored database after the file has been applied.
+    pub fn write_ltx<R>(&self, r: R) -> Result<Pos, Error>
+    where
+        R: io::Read,
+    {
+        let (mut dec, header) = Decoder::new(r)?;
+        let mut page_nums = self.page_nums()?;
+
+        let mut checksum = if header.is_snapshot() {
+            for page_num in std::mem::take(&mut page_nums) {
+                fs::remove_file(self.page_path(page_num))?;
+            }
+            Some(DatabaseChecksum::new())
+        } else if let Some(expected) = header.pre_apply_checksum {
+            let checksum = self.checksum(&page_nums)?;
+            if checksum != expected {
+                return Err(Error::PreApplyChecksumMismatch(checksum, expected));
+            }
+            Some(DatabaseChecksum::from(checksum))
+        } else {
+            None
+        };
+
+        let mut page = vec![0; header.page_size.into_inner() as usize];
+        while let Some(page_num) = dec.decode_page(&mut page)? {
+            if let Some(checksum) = &mut checksum {
+                if page_nums.contains(&page_num) {
+                    checksum.replace_page(page_num, &self.read(page_num)?, &page);
+                } else {
+                    checksum.add_page(page_num, &page);
+                }
+            }
+            self.write_page(page_num, &page)?;
+            page_nums.insert(page_num);
+        }
+
+        // Pages past the end of the database are removed.
+        let truncated = match header.commit {
+            Some(commit) => commit
+                .checked_add(1)
+                .map_or_else(BTreeSet::new, |next| page_nums.split_off(&next)),
+            None => std::mem::take(&mut page_nums),
+        };
+        for page_num in truncated {
+            if let Some(checksum) = &mut checksum {
+                checksum.remove_page(page_num, &self.read(page_num)?);
+            }
+            fs::remove_file(self.page_path(page_num))?;
+        }
+
+        let trailer = dec.finish()?;
+        let checksum = match checksum {
+            Some(checksum) => checksum.finish(),
+            None => self.checksum(&page_nums)?,
+        };
+        if let Some(expected) = trailer.post_apply_checksum {
+            if checksum != expected {
+                return Err(Error::PostApplyChecksumMismatch(checksum, expected));
+            }
+        }
+
+        Ok(Pos {
+            txid: header.max_txid,
+            post_apply_checksum: checksum,
+        })
+    }
+
+    /// Encode the stored pages as a snapshot at transaction `txid` into `w`.
+    ///
+    /// All pages up to the highest stored page must be present, except for the lock
+    /// page. The post-apply checksum is computed from the stored pages.
+    pub fn encode_snapshot<W>(&self, w: W, txid: TXID, flags: HeaderFlags) -> Result<Trailer, Error>
+    where
+        W: io::Write,
+    {
+        let page_nums = self.page_nums()?;
+        let (Some(first), Some(commit)) = (page_nums.first(), page_nums.last()) else {
+            return Err(Error::Empty);
+        };
+        let page_size = self.page_size(*first)?;
+
+        let mut enc = Encoder::new(
+            w,
+            &Header {
+                flags,
+                page_size,
+                commit: Some(*commit),
+                min_txid: TXID::ONE,
+                max_txid: txid,
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )?;
+        for page_num in PageNum::snapshot_range(page_size, *commit) {
+            if !page_nums.contains(&page_num) {
+                return Err(Error::MissingPage(page_num));
+            }
+            let page = self.read(page_num)?;
+            if page.len() != page_size.into_inner() as usize {
+                return Err(Error::PageSize(self.page_path(page_num)));
+            }
+            enc.encode_page(page_num, &page)?;
+        }
+
+        Ok(enc.finish_auto()?)
+    }
+
+    /// Encode the stored pages with the given `page_nums` as a delta covering `txids`
+    /// into `w`.
+    ///
+    /// The store must hold the whole database after the delta is applied on top of
+    /// `prev`, the position of the database before the staged pages were written, so
+    /// `txids` must start right after it. The page numbers are sorted and deduplicated,
+    /// and all of them must be stored. The `commit` of the delta is the highest stored
+    /// page, and the post-apply checksum is computed from all the stored pages.
+    ///
+    /// # Example
+    /// ```no_run
+    /// # let mut w = Vec::new();
+    /// # let prev: litetx::Pos = "0000000000000005/8000000000000123".parse().unwrap();
+    /// let store = litetx::PageStore::new("/var/lib/pages");
+    /// let page_num = litetx::PageNum::new(7).unwrap();
+    /// store.write_page(page_num, &[0; 4096]).expect("write_page");
+    ///
+    /// let txid = litetx::TXID::new(6).unwrap();
+    /// store
+    ///     .export_delta([page_num], txid..=txid, prev, &mut w, litetx::HeaderFlags::empty())
+    ///     .expect("export_delta");
+    /// ```
+    pub fn export_delta<I, W>(
+        &self,
+        page_nums: I,
+        txids: RangeInclusive<TXID>,
+        prev: Pos,
+        w: W,
+        flags: HeaderFlags,
+    ) -> Result<Trailer, Error>
+    where
+        I: IntoIterator<Item = PageNum>,
+        W: io::Write,
+    {
+        let min_txid = prev
+            .txid
+            .checked_add(1)
+            .ok_or(EncodeError::TXIDOverflow(prev.txid))?;
+        if *txids.start() != min_txid {
+            return Err(Error::MinTXID(*txids.start(), min_txid));
+        }
+
+        let stored = self.page_nums()?;
+        let Some(commit) = stored.last() else {
+            return Err(Error::Empty);
+        };
+        let page_size = self.page_size(*commit)?;
+
+        let page_nums: BTreeSet<_> = page_nums.into_iter().collect();
+        if let Some(page_num) = page_nums.difference(&stored).next() {
+            return Err(Error::MissingPage(*page_num));
+        }
+
+        let mut enc = Encoder::for_delta(w, prev, *txids.end(), Some(*commit), page_size, flags)?;
+        for page_num in page_nums {
+            let page = self.read(page_num)?;
+            if page.len() != page_size.into_inner() as usize {
+                return Err(Error::PageSize(self.page_path(page_num)));
+            }
+            enc.encode_page(page_num, &page)?;
+        }
+
+        let checksum = self.checksum(&stored)?;
+        Ok(enc.finish(checksum)?)
+    }
+
+    /// Store `data` as the page with the given `page_num`, e.g. to stage a write to be
+    /// exported with [`PageStore::export_delta`].
+    pub fn write_page(&self, page_num: PageNum, data: &[u8]) -> Result<(), Error> {
+        let path = self.page_path(page_num);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, data)?;
+
+        Ok(())
+    }
+
+    fn read(&self, page_num: PageNum) -> Result<Vec<u8>, Error> {
+        Ok(fs::read(self.page_path(page_num))?)
+    }
+
+    fn page_size(&self, page_num: PageNum) -> Result<PageSize, Error> {
+        let path = self.page_path(page_num);
+        let size = fs::metadata(&path)?.len();
+        u32::try_from(size)
+            .ok()
+            .and_then(|size| PageSize::new(size).ok())
+            .ok_or(Error::PageSize(path))
+    }
+
+    fn checksum(&self, page_nums: &BTreeSet<PageNum>) -> Result<Checksum, Error> {
+        let mut checksum = DatabaseChecksum::new();
+        for page_num in page_nums {
+            checksum.add_page(*page_num, &self.read(*page_num)?);
+        }
+
+        Ok(checksum.finish())
+    }
+}
+
+fn parse_page_name(name: &std::ffi::OsStr) -> Option<PageNum> {
+    PageNum::try_from(Path::new(name)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, PageStore};
+    use crate::{
+        test_util::{encode_ltx, test_header},
+        utils::TempDir,
+        Checksum, DatabaseChecksum, Decoder, HeaderFlags, PageNum, TXID,
+    };
+    use std::fs;
+
+    fn encode(txid: u64, commit: u32, pages: &[(u32, u8)], pre: Option<Checksum>) -> Vec<u8> {
+        let min_txid = if pre.is_some() { txid } else { 1 };
+        let header = test_header(HeaderFlags::COMPRESS_LZ4, min_txid..=txid, commit, pre);
+        // Deltas only have to apply to the checksum they're given.
+        encode_ltx(&header, pages, pre.map(|_| Checksum::new(txid)))
+    }
+
+    #[test]
+    fn page_store() {
+        for sharded in [false, true] {
+            let dir = TempDir::new();
+            let store = PageStore::new(dir.path()).sharded(sharded);
+
+            let snapshot = encode(1, 3, &[(1, 1), (2, 2), (3, 3)], None);
+            let pos = store
+                .write_ltx(snapshot.as_slice())
+                .expect("failed to write snapshot");
+            assert_eq!(TXID::ONE, pos.txid);
+            assert!(store.page_path(PageNum::ONE).exists());
+
+            // Page 2 changes to 4, page 3 is truncated.
+            let expected = {
+                let mut checksum = DatabaseChecksum::new();
+                checksum.add_page(PageNum::new(1).unwrap(), &[1; 512]);
+                checksum.add_page(PageNum::new(2).unwrap(), &[4; 512]);
+                checksum.finish()
+            };
+            let header = test_header(
+                HeaderFlags::empty(),
+                2..=2,
+                2,
+                Some(pos.post_apply_checksum),
+            );
+            let delta = encode_ltx(&header, &[(2, 4)], Some(expected));
+
+            let pos = store
+                .write_ltx(delta.as_slice())
+                .expect("failed to write delta");
+            assert_eq!(expected, pos.post_apply_checksum);
+            assert_eq!(None, store.read_page(PageNum::new(3).unwrap()).unwrap());
+
+            let mut buf = Vec::new();
+            let trailer = store
+                .encode_snapshot(&mut buf, pos.txid, HeaderFlags::COMPRESS_LZ4)
+                .expect("failed to encode snapshot");
+            assert_eq!(Some(expected), trailer.post_apply_checksum);
+
+            let (mut dec, header) = Decoder::new(buf.as_slice()).expect("failed to decode");
+            assert_eq!(PageNum::new(2).ok(), header.commit);
+            let mut page = vec![0; 512];
+            assert_eq!(Some(PageNum::ONE), dec.decode_page(&mut page).unwrap());
+            assert_eq!(
+                Some(PageNum::new(2).unwrap()),
+                dec.decode_page(&mut page).unwrap()
+            );
+            assert_eq!(vec![4; 512], page);
+            assert_eq!(None, dec.decode_page(&mut page).unwrap());
+            dec.finish().expect("failed to finish decoder");
+        }
+    }
+
+    #[test]
+    fn page_store_export_delta() {
+        let (dir, replica_dir) = (TempDir::new(), TempDir::new());
+        let (store, replica) = (
+            PageStore::new(dir.path()),
+            PageStore::new(replica_dir.path()),
+        );
+        let snapshot = encode(1, 2, &[(1, 1), (2, 2)], None);
+        let prev = store
+            .write_ltx(snapshot.as_slice())
+            .expect("failed to write snapshot");
+        replica
+            .write_ltx(snapshot.as_slice())
+            .expect("failed to write snapshot");
+
+        let page = |n| PageNum::new(n).unwrap();
+        store.write_page(page(3), &[3; 512]).unwrap();
+        store.write_page(page(1), &[4; 512]).unwrap();
+
+        let mut delta = Vec::new();
+        let txid = TXID::new(2).unwrap();
+        let trailer = store
+            .export_delta(
+                [page(3), page(1), page(3)],
+                txid..=txid,
+                prev,
+                &mut delta,
+                HeaderFlags::COMPRESS_LZ4,
+            )
+            .expect("failed to export delta");
+
+        let pos = replica
+            .write_ltx(delta.as_slice())
+            .expect("failed to apply delta");
+        assert_eq!(trailer.post_apply_checksum, Some(pos.post_apply_checksum));
+        assert_eq!(Some(vec![4; 512]), replica.read_page(page(1)).unwrap());
+        assert_eq!(Some(vec![3; 512]), replica.read_page(page(3)).unwrap());
+
+        assert!(matches!(
+            store.export_delta([page(5)], txid..=txid, prev, Vec::new(), HeaderFlags::empty()),
+            Err(Error::MissingPage(page_num)) if page_num == page(5)
+        ));
+        assert!(matches!(
+            store.export_delta(
+                [page(1)],
+                prev.txid..=txid,
+                prev,
+                Vec::new(),
+                HeaderFlags::empty()
+            ),
+            Err(Error::MinTXID(_, _))
+        ));
+    }
+
+    #[test]
+    fn page_store_checksum_mismatch() {
+        let dir = TempDir::new();
+        let store = PageStore::new(dir.path());
+        store
+            .write_ltx(encode(1, 1, &[(1, 1)], None).as_slice())
+            .expect("failed to write snapshot");
+
+        assert!(matches!(
+            store.write_ltx(encode(2, 1, &[(1, 2)], Some(Checksum::new(7))).as_slice()),
+            Err(Error::PreApplyChecksumMismatch(_, expected)) if expected == Checksum::new(7)
+        ));
+
+        store
+            .write_ltx(encode(2, 2, &[(1, 1), (2, 2)], None).as_slice())
+            .expect("failed to write snapshot");
+        fs::remove_file(store.page_path(PageNum::ONE)).unwrap();
+        assert!(matches!(
+            store.encode_snapshot(Vec::new(), TXID::ONE, HeaderFlags::empty()),
+            Err(Error::MissingPage(page_num)) if page_num == PageNum::ONE
+        ));
+
+        fs::remove_file(store.page_path(PageNum::new(2).unwrap())).unwrap();
+        assert!(matches!(
+            store.encode_snapshot(Vec::new(), TXID::ONE, HeaderFlags::empty()),
+            Err(Error::Empty)
+        ));
+    }
+}
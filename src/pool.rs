@@ -0,0 +1,129 @@
+use std::{
+    fmt, ops,
+    sync::{Arc, Mutex},
+};
+
+/// A pool of reusable page buffers.
+///
+/// Buffers are returned to the pool when the [`PooledPage`] holding them is dropped,
+/// which can happen on any thread. Cloning the pool returns a handle to the same pool.
+///
+/// # Example
+/// ```no_run
+/// # let v = Vec::new();
+/// # let r = &v[..];
+/// let pool = litetx::PagePool::new();
+/// let (mut dec, _) = litetx::Decoder::new(r).expect("decoder");
+/// for page in dec.pages(&pool) {
+///     let (page_num, page) = page.expect("page");
+///     // send the page to a worker thread, the buffer is reused once it's dropped
+/// }
+/// ```
+#[derive(Clone, Default)]
+pub struct PagePool {
+    buffers: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl PagePool {
+    /// Create a new empty pool.
+    pub fn new() -> PagePool {
+        PagePool::default()
+    }
+
+    /// Take a buffer of `size` bytes from the pool, allocating a new one if the pool
+    /// is empty.
+    ///
+    /// The contents of a reused buffer are unspecified.
+    pub fn get(&self, size: usize) -> PooledPage {
+        let mut data = self.lock().pop().unwrap_or_default();
+        data.resize(size, 0);
+
+        PooledPage {
+            data,
+            pool: self.clone(),
+        }
+    }
+
+    /// Return the number of idle buffers in the pool.
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    /// Return `true` if the pool has no idle buffers.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Vec<u8>>> {
+        self.buffers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl fmt::Debug for PagePool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PagePool")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// A page buffer taken from a [`PagePool`], returned to the pool on drop.
+pub struct PooledPage {
+    data: Vec<u8>,
+    pool: PagePool,
+}
+
+impl ops::Deref for PooledPage {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+impl ops::DerefMut for PooledPage {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+impl fmt::Debug for PooledPage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PooledPage")
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
+
+impl Drop for PooledPage {
+    fn drop(&mut self) {
+        let data = std::mem::take(&mut self.data);
+        self.pool.lock().push(data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PagePool;
+    use std::thread;
+
+    #[test]
+    fn page_pool() {
+        let pool = PagePool::new();
+        assert!(pool.is_empty());
+
+        let mut page = pool.get(512);
+        assert_eq!(512, page.len());
+        page.fill(1);
+
+        thread::spawn(move || drop(page)).join().unwrap();
+        assert_eq!(1, pool.len());
+
+        // The buffer is reused and resized.
+        let page = pool.get(1024);
+        assert_eq!(1024, page.len());
+        assert!(pool.is_empty());
+        drop(page);
+        assert_eq!(1, pool.len());
+    }
+}
@@ -0,0 +1,108 @@
+use crate::ltx::{PageIndex, HEADER_SIZE, PAGE_HEADER_SIZE, TRAILER_SIZE};
+use std::time;
+
+/// Progress of an [`Encoder`](crate::Encoder) or a [`Decoder`](crate::Decoder),
+/// reported after every page and once more when the file is finished.
+///
+/// For an encoder, `bytes_in` counts the uncompressed header and page frames and
+/// `bytes_out` the bytes written to the underlying writer, which lag behind while the
+/// compressor buffers data. For a decoder, `bytes_in` counts the bytes read from the
+/// underlying reader, including data read ahead by decompressors, and `bytes_out` the
+/// uncompressed header and page frames.
+///
+/// The byte rates measure the observed throughput, e.g. of a [`Throttle`](crate::Throttle)
+/// wrapped writer or reader.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProgressEvent {
+    /// The number of pages encoded or decoded so far.
+    pub pages: u64,
+    /// The number of bytes consumed so far.
+    pub bytes_in: u64,
+    /// The number of bytes produced so far.
+    pub bytes_out: u64,
+    /// The time since the encoder or the decoder was created.
+    pub elapsed: time::Duration,
+}
+
+impl ProgressEvent {
+    /// Return the average number of bytes consumed per second.
+    pub fn bytes_in_per_sec(&self) -> f64 {
+        self.bytes_in as f64 / self.elapsed.as_secs_f64()
+    }
+
+    /// Return the average number of bytes produced per second.
+    pub fn bytes_out_per_sec(&self) -> f64 {
+        self.bytes_out as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Statistics of a finished LTX file, returned by
+/// [`Encoder::finish_with_stats`](crate::Encoder::finish_with_stats) and
+/// [`Decoder::finish_with_stats`](crate::Decoder::finish_with_stats).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of pages in the file.
+    pub pages: u64,
+    /// The size of the file without compression.
+    pub uncompressed_bytes: u64,
+    /// The number of bytes written to the underlying writer or read from the underlying
+    /// reader, i.e. the size of the file as stored.
+    pub compressed_bytes: u64,
+    /// The time since the encoder or the decoder was created.
+    pub elapsed: time::Duration,
+}
+
+impl Stats {
+    /// Return the ratio of the uncompressed size to the stored size of the file.
+    pub fn compression_ratio(&self) -> f64 {
+        self.uncompressed_bytes as f64 / self.compressed_bytes as f64
+    }
+}
+
+/// Measures the time since an encoder or a decoder was created.
+///
+/// `wasm32-unknown-unknown` has no clock, and reading it panics, so the elapsed time
+/// is always zero there.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Stopwatch {
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    started: time::Instant,
+}
+
+impl Stopwatch {
+    pub(crate) fn start() -> Stopwatch {
+        Stopwatch {
+            #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+            started: time::Instant::now(),
+        }
+    }
+
+    pub(crate) fn elapsed(&self) -> time::Duration {
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        return self.started.elapsed();
+        #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+        time::Duration::ZERO
+    }
+}
+
+/// The number of pages between tracing events of encoders and decoders.
+#[cfg(feature = "tracing")]
+pub(crate) const TRACE_PAGES: u64 = 1000;
+
+/// A callback receiving [`ProgressEvent`]s.
+pub(crate) type ProgressFn<'a> = Box<dyn FnMut(ProgressEvent) + Send + 'a>;
+
+/// Return the uncompressed size of the header and `pages` page frames.
+pub(crate) fn uncompressed_size(pages: u64, page_size: u32) -> u64 {
+    HEADER_SIZE as u64 + pages * (PAGE_HEADER_SIZE as u64 + page_size as u64)
+}
+
+/// Return the size of a whole file of `pages` pages without compression.
+pub(crate) fn uncompressed_file_size(pages: u64, page_size: u32, indexed: bool) -> u64 {
+    let index = if indexed {
+        PageIndex::encoded_size(pages as usize) as u64
+    } else {
+        0
+    };
+    uncompressed_size(pages, page_size) + PAGE_HEADER_SIZE as u64 + index + TRAILER_SIZE as u64
+}
@@ -0,0 +1,323 @@
+//! Reading remote LTX files over HTTP.
+//!
+//! [`RangeReader`] fetches a file in chunks with HTTP range requests, so the
+//! [`Decoder`](crate::Decoder) and the page index can read files from object storage
+//! without downloading them in full.
+
+use std::{
+    io::{self, Read},
+    thread,
+    time::Duration,
+};
+
+const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+const DEFAULT_RETRIES: usize = 3;
+const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// A builder of [`RangeReader`].
+///
+/// # Example
+/// ```no_run
+/// let r = litetx::remote::RangeReader::builder()
+///     .chunk_size(4 * 1024 * 1024)
+///     .retries(5)
+///     .open("https://example.com/0000000000000001-0000000000000001.ltx")
+///     .expect("range reader");
+/// let (dec, header) = litetx::Decoder::new(r).expect("decoder");
+/// ```
+#[derive(Debug, Clone)]
+pub struct RangeReaderBuilder {
+    chunk_size: usize,
+    retries: usize,
+    timeout: Option<Duration>,
+}
+
+impl Default for RangeReaderBuilder {
+    fn default() -> Self {
+        RangeReaderBuilder {
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            retries: DEFAULT_RETRIES,
+            timeout: None,
+        }
+    }
+}
+
+impl RangeReaderBuilder {
+    /// Set the number of bytes requested at once, 1 MiB by default.
+    pub fn chunk_size(mut self, size: usize) -> Self {
+        self.chunk_size = size.max(1);
+        self
+    }
+
+    /// Set the number of times a failed request is retried, 3 by default.
+    ///
+    /// Connection errors and server errors are retried, client errors are not.
+    pub fn retries(mut self, retries: usize) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Set the timeout of every request, none by default.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Create a new [`RangeReader`] reading the file at `url`.
+    ///
+    /// The first chunk of the file is requested right away to find out its size.
+    pub fn open(&self, url: &str) -> io::Result<RangeReader> {
+        let mut agent = ureq::AgentBuilder::new();
+        if let Some(timeout) = self.timeout {
+            agent = agent.timeout(timeout);
+        }
+
+        let mut r = RangeReader {
+            agent: agent.build(),
+            url: url.to_string(),
+            chunk_size: self.chunk_size,
+            retries: self.retries,
+            len: 0,
+            pos: 0,
+            buf: Vec::new(),
+            buf_start: 0,
+        };
+        r.len = r.fetch(0)?;
+
+        Ok(r)
+    }
+}
+
+/// An [`io::Read`] and [`io::Seek`] over a file served with HTTP range requests.
+///
+/// Data is requested in chunks of a configurable size and only the last chunk is
+/// kept, so seeking back before it requests the data again.
+pub struct RangeReader {
+    agent: ureq::Agent,
+    url: String,
+    chunk_size: usize,
+    retries: usize,
+    len: u64,
+    pos: u64,
+    buf: Vec<u8>,
+    buf_start: u64,
+}
+
+impl RangeReader {
+    /// Return a builder of [`RangeReader`].
+    pub fn builder() -> RangeReaderBuilder {
+        RangeReaderBuilder::default()
+    }
+
+    /// Create a new [`RangeReader`] with the default options.
+    pub fn open(url: &str) -> io::Result<RangeReader> {
+        RangeReaderBuilder::default().open(url)
+    }
+
+    /// Return the size of the remote file.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Return `true` if the remote file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Fetch the chunk starting at `start` into the buffer and return the size of the
+    /// file reported by the server.
+    fn fetch(&mut self, start: u64) -> io::Result<u64> {
+        let end = start + self.chunk_size as u64 - 1;
+        let range = format!("bytes={start}-{end}");
+
+        let mut attempt = 0;
+        let resp = loop {
+            match self.agent.get(&self.url).set("Range", &range).call() {
+                Ok(resp) => break resp,
+                Err(ureq::Error::Status(416, _)) if start == 0 => {
+                    // Empty files have no satisfiable ranges.
+                    self.buf.clear();
+                    self.buf_start = 0;
+                    return Ok(0);
+                }
+                Err(ureq::Error::Status(code, _)) if code < 500 || attempt >= self.retries => {
+                    return Err(io::Error::other(format!(
+                        "{}: HTTP status {code}",
+                        self.url
+                    )));
+                }
+                Err(ureq::Error::Transport(e)) if attempt >= self.retries => {
+                    return Err(io::Error::other(e));
+                }
+                Err(_) => {
+                    attempt += 1;
+                    thread::sleep(RETRY_DELAY * attempt as u32);
+                }
+            }
+        };
+
+        if resp.status() != 206 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{}: server doesn't support range requests", self.url),
+            ));
+        }
+        let len = resp
+            .header("Content-Range")
+            .and_then(|range| range.rsplit_once('/'))
+            .and_then(|(_, len)| len.parse().ok())
+            .ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "invalid Content-Range header")
+            })?;
+
+        self.buf.clear();
+        resp.into_reader()
+            .take(self.chunk_size as u64)
+            .read_to_end(&mut self.buf)?;
+        self.buf_start = start;
+
+        Ok(len)
+    }
+}
+
+impl io::Read for RangeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len {
+            return Ok(0);
+        }
+
+        let buf_end = self.buf_start + self.buf.len() as u64;
+        if self.pos < self.buf_start || self.pos >= buf_end {
+            self.fetch(self.pos)?;
+            if self.buf.is_empty() {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+
+        let offset = (self.pos - self.buf_start) as usize;
+        let n = buf.len().min(self.buf.len() - offset);
+        buf[..n].copy_from_slice(&self.buf[offset..offset + n]);
+        self.pos += n as u64;
+
+        Ok(n)
+    }
+}
+
+impl io::Seek for RangeReader {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            io::SeekFrom::Start(pos) => Some(pos),
+            io::SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            io::SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+
+        self.pos = pos.ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative position",
+            )
+        })?;
+
+        Ok(self.pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RangeReader;
+    use std::{
+        io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+        net::TcpListener,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        thread,
+    };
+
+    /// Serve `data` with range requests, failing the first `failures` requests.
+    fn serve(data: Vec<u8>, failures: usize) -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/file.ltx", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        let counter = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut range = None;
+                for line in BufReader::new(&stream).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.strip_prefix("Range: bytes=") {
+                        let (start, end) = value.split_once('-').unwrap();
+                        range = Some((
+                            start.parse::<usize>().unwrap(),
+                            end.parse::<usize>().unwrap(),
+                        ));
+                    }
+                }
+
+                if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                    write!(
+                        stream,
+                        "HTTP/1.1 503 Unavailable\r\nContent-Length: 0\r\n\r\n"
+                    )
+                    .unwrap();
+                    continue;
+                }
+
+                let (start, end) = range.unwrap();
+                let end = end.min(data.len() - 1);
+                let body = &data[start..=end];
+                write!(
+                    stream,
+                    "HTTP/1.1 206 Partial Content\r\nContent-Range: bytes {start}-{end}/{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    data.len(),
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(body).unwrap();
+            }
+        });
+
+        (url, requests)
+    }
+
+    #[test]
+    fn range_reader() {
+        let data: Vec<u8> = (0..1000).map(|n| n as u8).collect();
+        let (url, requests) = serve(data.clone(), 1);
+
+        let mut r = RangeReader::builder()
+            .chunk_size(300)
+            .retries(1)
+            .open(&url)
+            .expect("failed to open reader");
+        assert_eq!(1000, r.len());
+
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf).expect("failed to read");
+        assert_eq!(data, buf);
+
+        let mut buf = [0; 10];
+        r.seek(SeekFrom::End(-10)).unwrap();
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(data[990..], buf);
+
+        r.seek(SeekFrom::Start(100)).unwrap();
+        r.read_exact(&mut buf).unwrap();
+        assert_eq!(data[100..110], buf);
+
+        // A failed request, four chunks and a refetch of the first one.
+        assert_eq!(6, requests.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn range_reader_retries_exhausted() {
+        let (url, _) = serve(vec![0; 10], 2);
+        assert!(RangeReader::builder().retries(1).open(&url).is_err());
+    }
+}
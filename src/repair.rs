@@ -0,0 +1,166 @@
+//! Repair of LTX files whose page block is intact but whose trailer is missing or
+//! damaged, e.g. after a crash while the file was written.
+
+use crate::{
+    Checksum, DatabaseChecksum, DecodeError, Decoder, EncodeError, Encoder, HeaderFlags, Trailer,
+};
+use std::io;
+
+/// An error that can be returned by [`rewrite_trailer`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("decode")]
+    Decode(#[from] DecodeError),
+    #[error("encode")]
+    Encode(#[from] EncodeError),
+    #[error("post-apply checksum of delta files must be supplied")]
+    PostApplyChecksumRequired,
+    #[error("post-apply checksum mismatch: {0}, expected {1}")]
+    PostApplyChecksumMismatch(Checksum, Checksum),
+}
+
+/// Re-encode the LTX file read from `r` into `w` with a rebuilt trailer.
+///
+/// The header and the pages are decoded from `r` up to the end of the page block, and
+/// everything after it is ignored. The pages are encoded again with the same header, so
+/// the file checksum, along with the Merkle tree and page index, if any, is recomputed.
+///
+/// **The post-apply checksum can't be recovered from the file.** It's recomputed from
+/// the pages of snapshots, in which case `known_post_apply` is checked against it if
+/// given. For deltas, it must be supplied as `known_post_apply`, e.g. from the
+/// pre-apply checksum of the next file, otherwise [`Error::PostApplyChecksumRequired`]
+/// is returned before anything is written. A wrong checksum makes a file that decodes
+/// fine but corrupts the running checksum of the database it's applied to. Files with
+/// the [`HeaderFlags::NO_CHECKSUM`] flag have no post-apply checksum.
+///
+/// The page block isn't covered by any checksum until the trailer, so only files whose
+/// page block is known to be intact should be repaired. Returns the new trailer.
+///
+/// # Example
+/// ```no_run
+/// let r = std::fs::File::open("0000000000000001-0000000000000064.ltx").expect("open");
+/// let w = std::fs::File::create("0000000000000001-0000000000000064.ltx.repaired")
+///     .expect("create");
+/// let trailer = litetx::repair::rewrite_trailer(r, w, None).expect("rewrite_trailer");
+/// ```
+pub fn rewrite_trailer<R, W>(
+    r: R,
+    w: W,
+    known_post_apply: Option<Checksum>,
+) -> Result<Trailer, Error>
+where
+    R: io::Read,
+    W: io::Write,
+{
+    let (mut dec, header) = Decoder::new(r)?;
+    if !header.is_snapshot()
+        && !header.flags.contains(HeaderFlags::NO_CHECKSUM)
+        && known_post_apply.is_none()
+    {
+        return Err(Error::PostApplyChecksumRequired);
+    }
+
+    let mut enc = Encoder::new(w, &header)?;
+    let mut checksum = DatabaseChecksum::new();
+    while let Some((page_num, page)) = dec.decode_page_ref()? {
+        enc.encode_page(page_num, page)?;
+        checksum.add_page(page_num, page);
+    }
+
+    let post_apply = if header.flags.contains(HeaderFlags::NO_CHECKSUM) {
+        None
+    } else if header.is_snapshot() {
+        let checksum = checksum.finish();
+        match known_post_apply {
+            Some(known) if known != checksum => {
+                return Err(Error::PostApplyChecksumMismatch(checksum, known));
+            }
+            _ => Some(checksum),
+        }
+    } else {
+        known_post_apply
+    };
+
+    #[cfg(feature = "tracing")]
+    tracing::warn!(
+        min_txid = %header.min_txid,
+        max_txid = %header.max_txid,
+        post_apply_checksum = ?post_apply,
+        "rewriting LTX trailer"
+    );
+
+    Ok(enc.finish(post_apply)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{rewrite_trailer, Error};
+    use crate::{
+        test_util::{encode_ltx, test_header},
+        verify, Checksum, Header, HeaderFlags,
+    };
+
+    fn encode(flags: HeaderFlags, pre_apply_checksum: Option<Checksum>) -> (Vec<u8>, Header) {
+        let (txid, pages) = if pre_apply_checksum.is_some() {
+            (2, &[(2, 2), (3, 3)][..])
+        } else {
+            (1, &[(1, 1), (2, 2), (3, 3)][..])
+        };
+        let header = test_header(flags, txid..=txid, 3, pre_apply_checksum);
+        let buf = encode_ltx(&header, pages, Some(Checksum::new(7)));
+
+        (buf, header)
+    }
+
+    #[test]
+    fn rewrite_trailer_snapshot() {
+        for flags in [
+            HeaderFlags::empty(),
+            HeaderFlags::COMPRESS_LZ4 | HeaderFlags::PAGE_INDEX,
+        ] {
+            // A snapshot with a wrong post-apply checksum and a garbage file checksum.
+            let (mut buf, header) = encode(flags, None);
+            let len = buf.len();
+            buf[len - 1] ^= 0xff;
+
+            let mut out = Vec::new();
+            let trailer =
+                rewrite_trailer(buf.as_slice(), &mut out, None).expect("failed to rewrite trailer");
+            assert_eq!(
+                (header, trailer),
+                verify(out.as_slice()).expect("failed to verify")
+            );
+        }
+    }
+
+    #[test]
+    fn rewrite_trailer_delta() {
+        // A delta whose trailer is missing.
+        let (mut buf, header) = encode(HeaderFlags::empty(), Some(Checksum::new(1)));
+        buf.truncate(buf.len() - 16);
+
+        assert!(matches!(
+            rewrite_trailer(buf.as_slice(), Vec::new(), None),
+            Err(Error::PostApplyChecksumRequired)
+        ));
+
+        let mut out = Vec::new();
+        let trailer = rewrite_trailer(buf.as_slice(), &mut out, Some(Checksum::new(7)))
+            .expect("failed to rewrite trailer");
+        assert_eq!(Some(Checksum::new(7)), trailer.post_apply_checksum);
+        assert_eq!(
+            (header, trailer),
+            verify(out.as_slice()).expect("failed to verify")
+        );
+    }
+
+    #[test]
+    fn rewrite_trailer_checksum_mismatch() {
+        let (buf, _) = encode(HeaderFlags::empty(), None);
+
+        assert!(matches!(
+            rewrite_trailer(buf.as_slice(), Vec::new(), Some(Checksum::new(7))),
+            Err(Error::PostApplyChecksumMismatch(_, known)) if known == Checksum::new(7)
+        ));
+    }
+}
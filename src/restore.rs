@@ -0,0 +1,371 @@
+//! Point-in-time restore of databases from directories of LTX files.
+
+use crate::{
+    apply::{self, Error as ApplyError},
+    directory::Error as DirectoryError,
+    file::{self, TempPath},
+    LtxDirectory, Pos, TXID,
+};
+use std::{
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    time,
+};
+
+/// An error that can be returned by [`to_txid`] and [`to_timestamp`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("directory")]
+    Directory(#[from] DirectoryError),
+    #[error("no snapshot at or before transaction {0}")]
+    NoSnapshot(TXID),
+    #[error("no transaction at or before {0:?}")]
+    NoTransaction(time::SystemTime),
+    #[error("transaction {0} can't be reached, files end at transaction {1}")]
+    Unreachable(TXID, TXID),
+    #[error(
+        "post-apply checksum of transaction {0} doesn't match pre-apply checksum of the next file"
+    )]
+    ChecksumMismatch(TXID),
+    #[error("file {0}: apply")]
+    Apply(PathBuf, #[source] ApplyError),
+    #[error("database io")]
+    Io(#[from] io::Error),
+}
+
+/// Restore the database at transaction `target` from the LTX files in `dir` into the
+/// database file at `db_out`.
+///
+/// The newest snapshot at or before `target` is applied first, followed by the deltas up
+/// to `target`. The pre-apply checksum of every delta must match the position reached
+/// so far, and the checksums are verified as the files are applied. Where files overlap,
+/// e.g. deltas and a compacted file covering them, the file reaching furthest towards
+/// `target` is applied. The database is restored into `<db_out>.tmp`, which replaces
+/// an existing database at `db_out` only once the restore succeeded.
+///
+/// Returns the position of the restored database.
+///
+/// # Example
+/// ```no_run
+/// let target = litetx::TXID::new(100).unwrap();
+/// let pos = litetx::restore::to_txid("/var/lib/ltx".as_ref(), target, "db.sqlite".as_ref())
+///     .expect("restore");
+/// ```
+pub fn to_txid(dir: &Path, target: TXID, db_out: &Path) -> Result<Pos, Error> {
+    restore(&LtxDirectory::open(dir)?, target, db_out)
+}
+
+/// The result of [`to_timestamp`].
+#[derive(Debug)]
+pub struct TimestampRestore {
+    /// The position of the restored database.
+    pub pos: Pos,
+    /// The files whose timestamp is earlier than the timestamp of files with lower
+    /// transaction IDs, in transaction order.
+    pub anomalies: Vec<PathBuf>,
+}
+
+/// Restore the latest database state at or before `timestamp` from the LTX files in
+/// `dir` into the database file at `db_out`.
+///
+/// Files are ordered by transaction ID and the last transaction before the first file
+/// with a later timestamp is restored with [`to_txid`], even if files with higher
+/// transaction IDs have earlier timestamps. Such files are reported as anomalies. Of the
+/// files ending at the same transaction, e.g. a delta and a compacted snapshot, the
+/// latest timestamp is used.
+///
+/// # Example
+/// ```no_run
+/// let at = std::time::SystemTime::now() - std::time::Duration::from_secs(3600);
+/// let restored = litetx::restore::to_timestamp("/var/lib/ltx".as_ref(), at, "db.sqlite".as_ref())
+///     .expect("restore");
+/// for path in &restored.anomalies {
+///     eprintln!("{} is out of order", path.display());
+/// }
+/// ```
+pub fn to_timestamp(
+    dir: &Path,
+    timestamp: time::SystemTime,
+    db_out: &Path,
+) -> Result<TimestampRestore, Error> {
+    let dir = LtxDirectory::open(dir)?;
+
+    let mut files: Vec<_> = dir.files().iter().collect();
+    files.sort_by_key(|f| (f.header.max_txid, f.header.timestamp));
+
+    let mut target = None;
+    let mut latest = time::SystemTime::UNIX_EPOCH;
+    let mut past = false;
+    let mut anomalies = Vec::new();
+    for (i, file) in files.iter().enumerate() {
+        // Only the last of the files ending at the same transaction is considered.
+        if files
+            .get(i + 1)
+            .is_some_and(|next| next.header.max_txid == file.header.max_txid)
+        {
+            continue;
+        }
+
+        if file.header.timestamp < latest {
+            anomalies.push(file.path.clone());
+        } else {
+            latest = file.header.timestamp;
+        }
+        // Transactions are restored in order, so the first file past the timestamp ends
+        // the restored range.
+        past |= latest > timestamp;
+        if !past {
+            target = Some(file.header.max_txid);
+        }
+    }
+    let target = target.ok_or(Error::NoTransaction(timestamp))?;
+
+    Ok(TimestampRestore {
+        pos: restore(&dir, target, db_out)?,
+        anomalies,
+    })
+}
+
+fn restore(dir: &LtxDirectory, target: TXID, db_out: &Path) -> Result<Pos, Error> {
+    let files = dir.files();
+
+    let start = files
+        .iter()
+        .rposition(|f| f.header.is_snapshot() && f.header.max_txid <= target)
+        .ok_or(Error::NoSnapshot(target))?;
+
+    // The database is restored into a temporary file, so a failed restore leaves an
+    // existing database at `db_out` untouched.
+    let mut tmp_path = OsString::from(db_out);
+    tmp_path.push(".tmp");
+    let mut tmp = TempPath(Some(PathBuf::from(&tmp_path)));
+    let mut db = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&tmp_path)?;
+
+    let snapshot = &files[start];
+    let mut pos = apply_file(&mut db, &snapshot.path)?;
+    while pos.txid < target {
+        // Of the files continuing from the position reached so far, the one reaching
+        // furthest without passing the target is applied, e.g. a compacted delta
+        // rather than the first of the deltas it covers.
+        let Some(file) = files[start + 1..]
+            .iter()
+            .filter(|f| {
+                pos.txid.checked_add(1) == Some(f.header.min_txid) && f.header.max_txid <= target
+            })
+            .max_by_key(|f| f.header.max_txid)
+        else {
+            break;
+        };
+        if file
+            .header
+            .pre_apply_checksum
+            .is_some_and(|checksum| checksum != pos.post_apply_checksum)
+        {
+            return Err(Error::ChecksumMismatch(pos.txid));
+        }
+
+        pos = apply_file(&mut db, &file.path)?;
+    }
+
+    if pos.txid != target {
+        return Err(Error::Unreachable(target, pos.txid));
+    }
+    db.sync_all()?;
+    drop(db);
+
+    fs::rename(&tmp_path, db_out)?;
+    tmp.0 = None;
+    file::sync_parent_dir(db_out)?;
+
+    Ok(pos)
+}
+
+fn apply_file(db: &mut fs::File, path: &Path) -> Result<Pos, Error> {
+    let f = io::BufReader::new(fs::File::open(path)?);
+    apply::apply(db, f).map_err(|e| Error::Apply(path.to_path_buf(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_timestamp, to_txid, Error};
+    use crate::{
+        name, utils::TempDir, DatabaseChecksum, Encoder, Header, HeaderFlags, PageNum, PageSize,
+        TXID,
+    };
+    use std::{fs, time};
+
+    /// Write an LTX file turning the database `prev` into `db`, one byte per page.
+    fn write_file(dir: &TempDir, min_txid: u64, max_txid: u64, prev: &[u8], db: &[u8]) {
+        let timestamp = time::SystemTime::UNIX_EPOCH + time::Duration::from_secs(max_txid);
+        write_file_at(dir, min_txid, max_txid, prev, db, timestamp);
+    }
+
+    fn write_file_at(
+        dir: &TempDir,
+        min_txid: u64,
+        max_txid: u64,
+        prev: &[u8],
+        db: &[u8],
+        timestamp: time::SystemTime,
+    ) {
+        let checksum = |db: &[u8]| {
+            let mut checksum = DatabaseChecksum::new();
+            for (i, fill) in db.iter().enumerate() {
+                checksum.add_page(PageNum::new(i as u32 + 1).unwrap(), &[*fill; 512]);
+            }
+            checksum.finish()
+        };
+
+        let (min_txid, max_txid) = (TXID::new(min_txid).unwrap(), TXID::new(max_txid).unwrap());
+        let path = dir.path().join(name::format_filename(min_txid, max_txid));
+        let mut enc = Encoder::new(
+            fs::File::create(path).expect("failed to create file"),
+            &Header {
+                flags: HeaderFlags::COMPRESS_LZ4,
+                page_size: PageSize::new(512).unwrap(),
+                commit: PageNum::new(db.len() as u32).ok(),
+                min_txid,
+                max_txid,
+                timestamp,
+                pre_apply_checksum: (min_txid > TXID::ONE).then(|| checksum(prev)),
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder");
+        for (i, fill) in db.iter().enumerate() {
+            if prev.get(i) != Some(fill) || min_txid == TXID::ONE {
+                enc.encode_page(PageNum::new(i as u32 + 1).unwrap(), &[*fill; 512])
+                    .expect("failed to encode page");
+            }
+        }
+        enc.finish(checksum(db)).expect("failed to finish encoder");
+    }
+
+    fn contents(db: &[u8]) -> Vec<u8> {
+        db.iter().flat_map(|fill| [*fill; 512]).collect()
+    }
+
+    #[test]
+    fn restore() {
+        let dir = TempDir::new();
+        write_file(&dir, 1, 1, &[], &[1, 1]);
+        write_file(&dir, 2, 2, &[1, 1], &[1, 2]);
+        write_file(&dir, 3, 4, &[1, 2], &[3, 2, 3]);
+        write_file(&dir, 1, 4, &[], &[3, 2, 3]);
+        write_file(&dir, 5, 5, &[3, 2, 3], &[5]);
+        let db_out = dir.path().join("db");
+
+        for (target, db) in [(1, &[1, 1][..]), (2, &[1, 2]), (4, &[3, 2, 3]), (5, &[5])] {
+            let pos = to_txid(dir.path(), TXID::new(target).unwrap(), &db_out)
+                .expect("failed to restore");
+            assert_eq!(TXID::new(target).unwrap(), pos.txid);
+            assert_eq!(contents(db), fs::read(&db_out).unwrap());
+        }
+
+        assert!(matches!(
+            to_txid(dir.path(), TXID::new(3).unwrap(), &db_out),
+            Err(Error::Unreachable(target, reached))
+                if target == TXID::new(3).unwrap() && reached == TXID::new(2).unwrap()
+        ));
+    }
+
+    #[test]
+    fn restore_compacted() {
+        let dir = TempDir::new();
+        write_file(&dir, 1, 1, &[], &[1]);
+        write_file(&dir, 2, 5, &[1], &[5]);
+        write_file(&dir, 2, 10, &[1], &[10]);
+        write_file(&dir, 6, 10, &[5], &[10]);
+        write_file(&dir, 11, 11, &[10], &[11]);
+        let db_out = dir.path().join("db");
+
+        for (target, db) in [(5, [5]), (10, [10]), (11, [11])] {
+            let pos = to_txid(dir.path(), TXID::new(target).unwrap(), &db_out)
+                .expect("failed to restore");
+            assert_eq!(TXID::new(target).unwrap(), pos.txid);
+            assert_eq!(contents(&db), fs::read(&db_out).unwrap());
+        }
+    }
+
+    #[test]
+    fn restore_failure_keeps_database() {
+        let dir = TempDir::new();
+        write_file(&dir, 1, 1, &[], &[1]);
+        write_file(&dir, 2, 2, &[1], &[2]);
+        let db_out = dir.path().join("db");
+        fs::write(&db_out, b"existing").unwrap();
+
+        assert!(matches!(
+            to_txid(dir.path(), TXID::new(3).unwrap(), &db_out),
+            Err(Error::Unreachable(..))
+        ));
+        assert_eq!(b"existing", &fs::read(&db_out).unwrap()[..]);
+        assert!(!dir.path().join("db.tmp").exists());
+    }
+
+    #[test]
+    fn restore_no_snapshot() {
+        let dir = TempDir::new();
+        write_file(&dir, 2, 2, &[1], &[2]);
+
+        assert!(matches!(
+            to_txid(dir.path(), TXID::new(2).unwrap(), &dir.path().join("db")),
+            Err(Error::NoSnapshot(_))
+        ));
+    }
+
+    #[test]
+    fn restore_checksum_mismatch() {
+        let dir = TempDir::new();
+        write_file(&dir, 1, 1, &[], &[1]);
+        write_file(&dir, 2, 2, &[7], &[2]);
+
+        assert!(matches!(
+            to_txid(dir.path(), TXID::new(2).unwrap(), &dir.path().join("db")),
+            Err(Error::ChecksumMismatch(txid)) if txid == TXID::ONE
+        ));
+    }
+
+    #[test]
+    fn restore_timestamp() {
+        let at = |secs| time::SystemTime::UNIX_EPOCH + time::Duration::from_secs(secs);
+        let dir = TempDir::new();
+        write_file_at(&dir, 1, 1, &[], &[1], at(10));
+        write_file_at(&dir, 2, 2, &[1], &[2], at(20));
+        write_file_at(&dir, 3, 3, &[2], &[3], at(40));
+        write_file_at(&dir, 4, 4, &[3], &[4], at(30));
+        // Compacted snapshots keep the timestamp of their first transaction.
+        write_file_at(&dir, 1, 2, &[], &[2], at(10));
+        let db_out = dir.path().join("db");
+
+        let restored = to_timestamp(dir.path(), at(25), &db_out).expect("failed to restore");
+        assert_eq!(TXID::new(2).unwrap(), restored.pos.txid);
+        assert_eq!(contents(&[2]), fs::read(&db_out).unwrap());
+        assert_eq!(
+            vec![dir.path().join(name::format_filename(
+                TXID::new(4).unwrap(),
+                TXID::new(4).unwrap()
+            ))],
+            restored.anomalies
+        );
+
+        let restored = to_timestamp(dir.path(), at(45), &db_out).expect("failed to restore");
+        assert_eq!(TXID::new(4).unwrap(), restored.pos.txid);
+
+        assert!(matches!(
+            to_timestamp(dir.path(), at(5), &db_out),
+            Err(Error::NoTransaction(_))
+        ));
+    }
+}
@@ -0,0 +1,168 @@
+//! Recovery of the readable pages of damaged LTX files.
+
+use crate::{DecodeError, Decoder, Header, PageNum, Trailer};
+use std::io;
+
+/// The result of [`recover`].
+#[derive(Debug)]
+pub struct SalvageReport {
+    /// The header of the file, `None` if it couldn't be decoded, in which case no pages
+    /// are recovered.
+    pub header: Option<Header>,
+    /// The pages decoded before decoding failed, in file order.
+    pub pages: Vec<(PageNum, Vec<u8>)>,
+    /// The trailer of the file, `None` unless the whole file was decoded and its
+    /// checksum verified.
+    pub trailer: Option<Trailer>,
+    /// The error decoding stopped at, `None` if the file is intact.
+    pub error: Option<DecodeError>,
+}
+
+impl SalvageReport {
+    /// Return whether the whole file was decoded and verified.
+    pub fn is_complete(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Return the offset in the file at which decoding failed, if known.
+    pub fn failed_at(&self) -> Option<u64> {
+        match self.error.as_ref()? {
+            DecodeError::TruncatedFile { offset, .. }
+            | DecodeError::Corrupt { offset, .. }
+            | DecodeError::Position { offset, .. }
+            | DecodeError::TrailingData(offset) => Some(*offset),
+            _ => None,
+        }
+    }
+}
+
+/// Decode as many leading pages as possible from the truncated or corrupt LTX file read
+/// from `r`.
+///
+/// Decoding stops at the first error, which is returned in the report along with the
+/// pages decoded so far. The file checksum only covers the whole file, so unless the
+/// file has the [`HeaderFlags::PAGE_CHECKSUM`](crate::HeaderFlags::PAGE_CHECKSUM) flag,
+/// the last pages recovered from a corrupt file may hold corrupt data.
+///
+/// # Example
+/// ```no_run
+/// let f = std::fs::File::open("0000000000000005-0000000000000005.ltx").expect("open");
+/// let report = litetx::salvage::recover(f);
+/// if !report.is_complete() {
+///     eprintln!("recovered {} pages", report.pages.len());
+/// }
+/// ```
+pub fn recover<R>(r: R) -> SalvageReport
+where
+    R: io::Read,
+{
+    let mut report = SalvageReport {
+        header: None,
+        pages: Vec::new(),
+        trailer: None,
+        error: None,
+    };
+
+    let (mut dec, header) = match Decoder::new(r) {
+        Ok(dec) => dec,
+        Err(e) => {
+            report.error = Some(e);
+            return report;
+        }
+    };
+    let page_size = header.page_size.into_inner() as usize;
+    report.header = Some(header);
+
+    loop {
+        let mut page = vec![0; page_size];
+        match dec.decode_page(&mut page) {
+            Ok(Some(page_num)) => report.pages.push((page_num, page)),
+            Ok(None) => break,
+            Err(e) => {
+                report.error = Some(e);
+                return report;
+            }
+        }
+    }
+
+    match dec.finish() {
+        Ok(trailer) => report.trailer = Some(trailer),
+        Err(e) => report.error = Some(e),
+    }
+
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::recover;
+    use crate::{
+        ltx::{HEADER_SIZE, PAGE_HEADER_SIZE},
+        test_util::{encode_ltx, test_header},
+        DecodeError, FileSection, HeaderFlags, PageNum,
+    };
+
+    fn encode(flags: HeaderFlags) -> Vec<u8> {
+        let pages = [(1, 1), (2, 2), (3, 3), (4, 4)];
+        encode_ltx(&test_header(flags, 1..=1, 4, None), &pages, None)
+    }
+
+    #[test]
+    fn recover_intact() {
+        let report = recover(encode(HeaderFlags::COMPRESS_LZ4).as_slice());
+        assert!(report.is_complete());
+        assert!(report.header.is_some() && report.trailer.is_some());
+        assert_eq!(4, report.pages.len());
+    }
+
+    #[test]
+    fn recover_truncated() {
+        let buf = encode(HeaderFlags::empty());
+        let len = HEADER_SIZE + 2 * (PAGE_HEADER_SIZE + 512) + 100;
+
+        let report = recover(&buf[..len]);
+        assert!(!report.is_complete());
+        assert!(report.header.is_some() && report.trailer.is_none());
+        assert_eq!(
+            vec![
+                (PageNum::new(1).unwrap(), vec![1; 512]),
+                (PageNum::new(2).unwrap(), vec![2; 512])
+            ],
+            report.pages
+        );
+        assert!(matches!(
+            report.error,
+            Some(DecodeError::TruncatedFile {
+                expected: FileSection::PageBlock,
+                ..
+            })
+        ));
+        assert_eq!(Some(len as u64), report.failed_at());
+    }
+
+    #[test]
+    fn recover_corrupt() {
+        let mut buf = encode(HeaderFlags::PAGE_CHECKSUM);
+        buf[HEADER_SIZE + 2 * (PAGE_HEADER_SIZE + 512 + 8) + PAGE_HEADER_SIZE] ^= 1;
+
+        let report = recover(buf.as_slice());
+        assert_eq!(2, report.pages.len());
+        assert!(matches!(
+            report.error.as_ref().map(DecodeError::inner),
+            Some(DecodeError::PageChecksumMismatch(n)) if *n == PageNum::new(3).unwrap()
+        ));
+    }
+
+    #[test]
+    fn recover_bad_header() {
+        let report = recover(&encode(HeaderFlags::empty())[..10]);
+        assert!(report.header.is_none() && report.pages.is_empty());
+        assert!(matches!(
+            report.error,
+            Some(DecodeError::TruncatedFile {
+                expected: FileSection::Header,
+                offset: 10
+            })
+        ));
+    }
+}
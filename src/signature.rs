@@ -0,0 +1,156 @@
+//! Detached Ed25519 signatures of LTX files.
+//!
+//! A signature covers the SHA-512 hash of every byte of the file, so a replica fetching
+//! files from shared object storage can check that they were written by a trusted
+//! node. The file checksum isn't signed on its own since CRC64 is trivial to forge.
+//!
+//! Files are decoded and their file checksum verified before being signed or checked
+//! against a signature, so encrypted files aren't supported.
+//!
+//! # Example
+//! ```no_run
+//! use litetx::signature::{sign, verify_signature, SigningKey};
+//! use std::fs::File;
+//!
+//! let path = "0000000000000001-0000000000000064.ltx";
+//! let key = SigningKey::from_bytes(&[7; 32]);
+//! let sig = sign(File::open(path).expect("open"), &key).expect("sign");
+//!
+//! verify_signature(File::open(path).expect("open"), &sig, &key.verifying_key())
+//!     .expect("verify_signature");
+//! ```
+
+use crate::{decoder::Error as DecodeError, Decoder, Header};
+use ed25519_dalek::Signer;
+use sha2::{Digest, Sha512};
+use std::io;
+
+pub use ed25519_dalek::{Signature, SigningKey, VerifyingKey};
+
+/// Prefix of the signed message, keeping LTX signatures from being valid for anything
+/// else signed with the same key.
+const CONTEXT: &[u8] = b"litetx signature v1\0";
+
+/// An error that can be returned by [`sign`] and [`verify_signature`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("decode")]
+    Decode(#[from] DecodeError),
+    #[error("invalid signature")]
+    InvalidSignature,
+    #[error("read")]
+    Io(#[from] io::Error),
+}
+
+/// Sign the LTX file read from `r` with `key`.
+pub fn sign<R>(r: R, key: &SigningKey) -> Result<Signature, Error>
+where
+    R: io::Read,
+{
+    let (_, message) = signed_message(r)?;
+    Ok(key.sign(&message))
+}
+
+/// Check the signature `sig` of the LTX file read from `r` against the public key
+/// `key`, returning the header of the file.
+///
+/// Fails with [`Error::InvalidSignature`] if any byte of the file differs from the
+/// signed one, including bytes after the trailer.
+pub fn verify_signature<R>(r: R, sig: &Signature, key: &VerifyingKey) -> Result<Header, Error>
+where
+    R: io::Read,
+{
+    let (header, message) = signed_message(r)?;
+    key.verify_strict(&message, sig)
+        .map_err(|_| Error::InvalidSignature)?;
+
+    Ok(header)
+}
+
+fn signed_message<R>(r: R) -> Result<(Header, Vec<u8>), Error>
+where
+    R: io::Read,
+{
+    let mut r = HashRead {
+        inner: r,
+        hasher: Sha512::new(),
+    };
+    let (mut dec, header) = Decoder::new(&mut r)?;
+    while dec.skip_page()?.is_some() {}
+    dec.finish()?;
+    io::copy(&mut r, &mut io::sink())?;
+
+    let mut message = CONTEXT.to_vec();
+    message.extend_from_slice(&r.hasher.finalize());
+
+    Ok((header, message))
+}
+
+/// A reader hashing all the bytes read through it.
+struct HashRead<R> {
+    inner: R,
+    hasher: Sha512,
+}
+
+impl<R> io::Read for HashRead<R>
+where
+    R: io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sign, verify_signature, Error, SigningKey};
+    use crate::{
+        test_util::{encode_ltx, test_header},
+        HeaderFlags, TXID,
+    };
+
+    fn encode(fill: u8) -> Vec<u8> {
+        let header = test_header(HeaderFlags::COMPRESS_LZ4, 1..=1, 2, None);
+        encode_ltx(&header, &[(1, fill), (2, fill)], None)
+    }
+
+    #[test]
+    fn sign_verify() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let file = encode(1);
+        let sig = sign(file.as_slice(), &key).expect("failed to sign");
+
+        let header = verify_signature(file.as_slice(), &sig, &key.verifying_key())
+            .expect("failed to verify signature");
+        assert_eq!(TXID::ONE, header.max_txid);
+
+        let other = encode(2);
+        assert!(matches!(
+            verify_signature(other.as_slice(), &sig, &key.verifying_key()),
+            Err(Error::InvalidSignature)
+        ));
+
+        let other_key = SigningKey::from_bytes(&[8; 32]);
+        assert!(matches!(
+            verify_signature(file.as_slice(), &sig, &other_key.verifying_key()),
+            Err(Error::InvalidSignature)
+        ));
+
+        let mut trailing = file.clone();
+        trailing.push(0);
+        assert!(matches!(
+            verify_signature(trailing.as_slice(), &sig, &key.verifying_key()),
+            Err(Error::InvalidSignature)
+        ));
+
+        let mut corrupt = file;
+        let len = corrupt.len();
+        corrupt[len - 1] ^= 1;
+        assert!(matches!(
+            verify_signature(corrupt.as_slice(), &sig, &key.verifying_key()),
+            Err(Error::Decode(_))
+        ));
+    }
+}
@@ -0,0 +1,420 @@
+use crate::{
+    encoder::Error as EncodeError,
+    sqlite::{self, DatabaseHeader},
+    Checksum, DatabaseChecksum, Encoder, Header, HeaderFlags, PageNum, PageSize, Pos, Trailer,
+    TXID,
+};
+use std::{io, ops::RangeInclusive, time};
+
+/// An error that can be returned by [`encode_db_snapshot`] and [`encode_db_diff`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("database header")]
+    Header(#[from] sqlite::Error),
+    #[error("database size in header is not valid")]
+    DatabaseSize,
+    #[error("database page size {0} doesn't match the old database page size {1}")]
+    PageSizeMismatch(PageSize, PageSize),
+    #[error("delta must start at transaction {1}, got {0}")]
+    MinTXID(TXID, TXID),
+    #[error("old database checksum mismatch: {0}, expected {1}")]
+    PreApplyChecksumMismatch(Checksum, Checksum),
+    #[error("encode")]
+    Encode(#[from] EncodeError),
+    #[error("read")]
+    Read(#[from] io::Error),
+}
+
+/// Options of the snapshot produced by [`encode_db_snapshot`].
+#[derive(Debug, Clone)]
+pub struct SnapshotOptions {
+    /// Flags of the resulting LTX file.
+    pub flags: HeaderFlags,
+    /// Transaction ID of the database state. Used as the `max_txid` of the snapshot.
+    pub txid: TXID,
+    /// The time when the snapshot was created.
+    pub timestamp: time::SystemTime,
+}
+
+impl Default for SnapshotOptions {
+    fn default() -> Self {
+        SnapshotOptions {
+            flags: HeaderFlags::empty(),
+            txid: TXID::ONE,
+            timestamp: time::SystemTime::now(),
+        }
+    }
+}
+
+/// Encode the SQLite database read from `db` as a snapshot LTX file written into `w`.
+///
+/// The page size and the number of pages are taken from the database header, so the
+/// database must not be modified while it is being read. The lock page is skipped.
+///
+/// # Example
+/// ```no_run
+/// # let mut w = Vec::new();
+/// let db = std::fs::File::open("db.sqlite").expect("open");
+/// let trailer = litetx::encode_db_snapshot(db, &mut w, litetx::SnapshotOptions {
+///     flags: litetx::HeaderFlags::COMPRESS_LZ4,
+///     ..Default::default()
+/// })
+/// .expect("encode_db_snapshot");
+/// ```
+pub fn encode_db_snapshot<R, W>(mut db: R, w: W, opts: SnapshotOptions) -> Result<Trailer, Error>
+where
+    R: io::Read,
+    W: io::Write,
+{
+    let (page_size, commit, mut page) = read_first_page(&mut db)?;
+
+    let mut enc = Encoder::new(
+        w,
+        &Header {
+            flags: opts.flags,
+            page_size,
+            commit: Some(commit),
+            min_txid: TXID::ONE,
+            max_txid: opts.txid,
+            timestamp: opts.timestamp,
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        },
+    )?;
+
+    let lock_page = PageNum::lock_page(page_size);
+    for page_num in 1..=commit.into_inner() {
+        let page_num = PageNum::new(page_num).unwrap();
+        if page_num > PageNum::ONE {
+            db.read_exact(&mut page)?;
+        }
+        if page_num == lock_page {
+            continue;
+        }
+
+        enc.encode_page(page_num, &page)?;
+    }
+
+    Ok(enc.finish_auto()?)
+}
+
+/// Encode the changes from the SQLite database read from `old_db` to the one read from
+/// `new_db` as a delta LTX file written into `w`.
+///
+/// The databases are compared page by page and only the changed pages are encoded. The
+/// delta is applied on top of `prev`, the position of the old database, so `txids` must
+/// start right after it. The checksum of the old database is verified against `prev`
+/// once it has been read in full, which fails with
+/// [`Error::PreApplyChecksumMismatch`] before the trailer is written. Pages past the end
+/// of a shrunk database are truncated by the `commit` of the delta.
+///
+/// # Example
+/// ```no_run
+/// # let mut w = Vec::new();
+/// # let prev: litetx::Pos = "0000000000000005/8000000000000123".parse().unwrap();
+/// let old_db = std::fs::File::open("old.sqlite").expect("open");
+/// let new_db = std::fs::File::open("new.sqlite").expect("open");
+/// let txid = litetx::TXID::new(6).unwrap();
+/// let trailer = litetx::encode_db_diff(
+///     old_db,
+///     new_db,
+///     prev,
+///     txid..=txid,
+///     &mut w,
+///     litetx::HeaderFlags::COMPRESS_LZ4,
+/// )
+/// .expect("encode_db_diff");
+/// ```
+pub fn encode_db_diff<O, N, W>(
+    mut old_db: O,
+    mut new_db: N,
+    prev: Pos,
+    txids: RangeInclusive<TXID>,
+    w: W,
+    flags: HeaderFlags,
+) -> Result<Trailer, Error>
+where
+    O: io::Read,
+    N: io::Read,
+    W: io::Write,
+{
+    let min_txid = prev
+        .txid
+        .checked_add(1)
+        .ok_or(EncodeError::TXIDOverflow(prev.txid))?;
+    if *txids.start() != min_txid {
+        return Err(Error::MinTXID(*txids.start(), min_txid));
+    }
+
+    let (page_size, old_commit, mut old_page) = read_first_page(&mut old_db)?;
+    let (new_page_size, commit, mut new_page) = read_first_page(&mut new_db)?;
+    if new_page_size != page_size {
+        return Err(Error::PageSizeMismatch(new_page_size, page_size));
+    }
+
+    let mut enc = Encoder::for_delta(w, prev, *txids.end(), Some(commit), page_size, flags)?;
+
+    let lock_page = PageNum::lock_page(page_size);
+    let mut old_checksum = DatabaseChecksum::new();
+    let mut checksum = DatabaseChecksum::new();
+    for page_num in 1..=old_commit.max(commit).into_inner() {
+        let page_num = PageNum::new(page_num).unwrap();
+        let in_old = page_num <= old_commit;
+        let in_new = page_num <= commit;
+        if page_num > PageNum::ONE {
+            if in_old {
+                old_db.read_exact(&mut old_page)?;
+            }
+            if in_new {
+                new_db.read_exact(&mut new_page)?;
+            }
+        }
+        if page_num == lock_page {
+            continue;
+        }
+
+        if in_old {
+            old_checksum.add_page(page_num, &old_page);
+        }
+        if in_new {
+            checksum.add_page(page_num, &new_page);
+            if !in_old || old_page != new_page {
+                enc.encode_page(page_num, &new_page)?;
+            }
+        }
+    }
+
+    let old_checksum = old_checksum.finish();
+    if old_checksum != prev.post_apply_checksum {
+        return Err(Error::PreApplyChecksumMismatch(
+            old_checksum,
+            prev.post_apply_checksum,
+        ));
+    }
+
+    Ok(enc.finish(checksum.finish())?)
+}
+
+/// Read page 1 of the SQLite database `db` and parse the page size and the number of
+/// pages from its header.
+fn read_first_page<R>(mut db: R) -> Result<(PageSize, PageNum, Vec<u8>), Error>
+where
+    R: io::Read,
+{
+    let mut db_header = [0; sqlite::HEADER_SIZE];
+    db.read_exact(&mut db_header)?;
+    let DatabaseHeader {
+        page_size,
+        page_count,
+        ..
+    } = DatabaseHeader::decode(&db_header)?;
+    let commit = page_count.ok_or(Error::DatabaseSize)?;
+
+    let mut page = vec![0; page_size.into_inner() as usize];
+    page[..sqlite::HEADER_SIZE].copy_from_slice(&db_header);
+    db.read_exact(&mut page[sqlite::HEADER_SIZE..])?;
+
+    Ok((page_size, commit, page))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_db_diff, encode_db_snapshot, Error, SnapshotOptions};
+    use crate::{
+        apply::pages_checksum, sqlite, utils::TempDir, Checksum, Decoder, HeaderFlags, PageNum,
+        PageSize, Pos, TXID,
+    };
+    use std::fs;
+
+    fn test_db(page_size: u32, page_count: u32) -> Vec<u8> {
+        let mut db: Vec<u8> = (0..page_size * page_count)
+            .map(|_| rand::random::<u8>())
+            .collect();
+        sqlite::test_header(&mut db, page_size, page_count);
+        db
+    }
+
+    #[test]
+    fn snapshot() {
+        let db = test_db(512, 5);
+
+        let mut buf = Vec::new();
+        let trailer = encode_db_snapshot(
+            db.as_slice(),
+            &mut buf,
+            SnapshotOptions {
+                flags: HeaderFlags::COMPRESS_LZ4,
+                txid: TXID::new(3).unwrap(),
+                ..Default::default()
+            },
+        )
+        .expect("failed to encode snapshot");
+
+        let (mut dec, header) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        assert_eq!(PageSize::new(512).unwrap(), header.page_size);
+        assert_eq!(Some(PageNum::new(5).unwrap()), header.commit);
+        assert_eq!(TXID::ONE, header.min_txid);
+        assert_eq!(TXID::new(3).unwrap(), header.max_txid);
+
+        let mut out = Vec::new();
+        let mut page = vec![0; 512];
+        while dec
+            .decode_page(&mut page)
+            .expect("failed to decode page")
+            .is_some()
+        {
+            out.extend_from_slice(&page);
+        }
+        assert_eq!(trailer, dec.finish().expect("failed to finish decoder"));
+        assert_eq!(db, out);
+    }
+
+    #[test]
+    fn snapshot_without_schema() {
+        let dir = TempDir::new();
+        let path = dir.path().join("db");
+        let conn = rusqlite::Connection::open(&path).expect("failed to open database");
+        conn.execute_batch("PRAGMA user_version = 5")
+            .expect("failed to set user version");
+        drop(conn);
+
+        let mut buf = Vec::new();
+        encode_db_snapshot(
+            fs::File::open(&path).unwrap(),
+            &mut buf,
+            SnapshotOptions::default(),
+        )
+        .expect("failed to encode snapshot");
+        assert_eq!((Some(PageNum::ONE), vec![1]), decode_pages(&buf));
+    }
+
+    #[test]
+    fn snapshot_invalid_header() {
+        let mut db = test_db(512, 1);
+        db[0] = b'X';
+        assert!(matches!(
+            encode_db_snapshot(db.as_slice(), Vec::new(), SnapshotOptions::default()),
+            Err(Error::Header(sqlite::Error::Magic))
+        ));
+
+        let mut db = test_db(512, 1);
+        db[95] = 8;
+        assert!(matches!(
+            encode_db_snapshot(db.as_slice(), Vec::new(), SnapshotOptions::default()),
+            Err(Error::DatabaseSize)
+        ));
+    }
+
+    fn db_pos(db: &[u8], txid: u64) -> Pos {
+        let pages = (db.len() / 512) as u64;
+        Pos {
+            txid: TXID::new(txid).unwrap(),
+            post_apply_checksum: pages_checksum(db, PageSize::new(512).unwrap(), 1..=pages)
+                .unwrap(),
+        }
+    }
+
+    fn decode_pages(buf: &[u8]) -> (Option<PageNum>, Vec<u32>) {
+        let (mut dec, header) = Decoder::new(buf).expect("failed to create decoder");
+        let mut pages = Vec::new();
+        while let Some(page_num) = dec.skip_page().expect("failed to skip page") {
+            pages.push(page_num.into_inner());
+        }
+        dec.finish().expect("failed to finish decoder");
+        (header.commit, pages)
+    }
+
+    #[test]
+    fn db_diff() {
+        let old = test_db(512, 5);
+        let prev = db_pos(&old, 5);
+        let txid = TXID::new(6).unwrap();
+
+        // Grown database with a changed page.
+        let mut new = old.clone();
+        new.extend_from_slice(&[6; 512]);
+        sqlite::test_header(&mut new, 512, 6);
+        new[2 * 512] ^= 1;
+
+        let mut buf = Vec::new();
+        let trailer = encode_db_diff(
+            old.as_slice(),
+            new.as_slice(),
+            prev,
+            txid..=txid,
+            &mut buf,
+            HeaderFlags::COMPRESS_LZ4,
+        )
+        .expect("failed to encode diff");
+        assert_eq!(
+            Some(db_pos(&new, 6).post_apply_checksum),
+            trailer.post_apply_checksum
+        );
+        assert_eq!(
+            (Some(PageNum::new(6).unwrap()), vec![1, 3, 6]),
+            decode_pages(&buf)
+        );
+
+        // Shrunk database.
+        let mut new = old[..4 * 512].to_vec();
+        sqlite::test_header(&mut new, 512, 4);
+
+        let mut buf = Vec::new();
+        let trailer = encode_db_diff(
+            old.as_slice(),
+            new.as_slice(),
+            prev,
+            txid..=txid,
+            &mut buf,
+            HeaderFlags::empty(),
+        )
+        .expect("failed to encode diff");
+        assert_eq!(
+            Some(db_pos(&new, 6).post_apply_checksum),
+            trailer.post_apply_checksum
+        );
+        assert_eq!(
+            (Some(PageNum::new(4).unwrap()), vec![1]),
+            decode_pages(&buf)
+        );
+    }
+
+    #[test]
+    fn db_diff_invalid_prev() {
+        let old = test_db(512, 2);
+        let txid = TXID::new(6).unwrap();
+
+        let prev = Pos {
+            post_apply_checksum: Checksum::new(1),
+            ..db_pos(&old, 5)
+        };
+        assert!(matches!(
+            encode_db_diff(
+                old.as_slice(),
+                old.as_slice(),
+                prev,
+                txid..=txid,
+                Vec::new(),
+                HeaderFlags::empty(),
+            ),
+            Err(Error::PreApplyChecksumMismatch(_, expected)) if expected == Checksum::new(1)
+        ));
+
+        assert!(matches!(
+            encode_db_diff(
+                old.as_slice(),
+                old.as_slice(),
+                db_pos(&old, 4),
+                txid..=txid,
+                Vec::new(),
+                HeaderFlags::empty(),
+            ),
+            Err(Error::MinTXID(got, want)) if got == txid && want == TXID::new(5).unwrap()
+        ));
+    }
+}
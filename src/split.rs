@@ -0,0 +1,280 @@
+use crate::{
+    encoder::Error as EncodeError, DatabaseChecksum, Encoder, Header, HeaderFlags, PageNum,
+    Trailer, TXID,
+};
+use std::io;
+
+/// An error that can be returned by [`SplitEncoder`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("split encoder requires a snapshot header")]
+    NotSnapshot,
+    #[error("no transaction ids left for the next file after {0}")]
+    TXIDExhausted(TXID),
+    #[error("encode")]
+    Encode(#[from] EncodeError),
+}
+
+/// Encodes a database snapshot into a chain of LTX files of limited size.
+///
+/// Pages go to the current file until its output grows past `max_size` bytes, at which
+/// point the file is finished and the next one is created with the writer returned by
+/// the factory for its header. The limit is checked before every page and compressors
+/// buffer some data, so the files may exceed it by about a page plus a compressed block.
+///
+/// The first file is a snapshot with transaction ID `min_txid`, and every next file is
+/// a delta with the following transaction ID whose pre-apply checksum is the post-apply
+/// checksum of the previous one. If the chain ends before `max_txid`, a final file
+/// without pages covers the remaining transactions. All the files have the `commit` of
+/// the snapshot, so only the whole chain describes a complete database. It can be merged
+/// back into a single snapshot with [`Compactor`](crate::Compactor), decoding the first
+/// file with [`DecoderBuilder::incomplete_snapshots`](crate::DecoderBuilder::incomplete_snapshots).
+///
+/// # Example
+/// ```no_run
+/// # use std::time::SystemTime;
+/// # let pages: Vec<(litetx::PageNum, Vec<u8>)> = Vec::new();
+/// let mut files = Vec::new();
+/// let mut enc = litetx::SplitEncoder::new(
+///     &litetx::Header {
+///         flags: litetx::HeaderFlags::COMPRESS_LZ4,
+///         page_size: litetx::PageSize::new(4096).unwrap(),
+///         commit: Some(litetx::PageNum::new(1000).unwrap()),
+///         min_txid: litetx::TXID::ONE,
+///         max_txid: litetx::TXID::new(100).unwrap(),
+///         timestamp: SystemTime::now(),
+///         pre_apply_checksum: None,
+///         node_id: 0,
+///         wal: None,
+///         app_data: None,
+///         page_filter: None,
+///         key_id: None,
+///         dict_id: None,
+///     },
+///     1 << 20,
+///     |hdr| {
+///         files.push(hdr);
+///         Vec::new()
+///     },
+/// )
+/// .expect("split encoder");
+///
+/// for (page_num, page) in &pages {
+///     enc.encode_page(*page_num, page).expect("encode_page");
+/// }
+/// let outputs = enc.finish().expect("finish");
+/// ```
+pub struct SplitEncoder<'a, W, F>
+where
+    W: io::Write,
+    F: FnMut(Header) -> W,
+{
+    enc: Encoder<'a, W>,
+    header: Header,
+    part: Header,
+    max_size: u64,
+    factory: F,
+    checksum: DatabaseChecksum,
+    outputs: Vec<(W, Trailer)>,
+}
+
+impl<'a, W, F> SplitEncoder<'a, W, F>
+where
+    W: io::Write,
+    F: FnMut(Header) -> W,
+{
+    /// Create a new [`SplitEncoder`] for the snapshot described by `hdr`.
+    ///
+    /// The writer of the first file is requested from `factory` right away.
+    pub fn new(hdr: &Header, max_size: u64, mut factory: F) -> Result<Self, Error> {
+        if !hdr.is_snapshot() || hdr.commit.is_none() {
+            return Err(Error::NotSnapshot);
+        }
+
+        let part = Header {
+            max_txid: hdr.min_txid,
+            wal: None,
+            ..hdr.clone()
+        };
+        // The first file is only completed by the following ones.
+        let enc = Encoder::new(factory(part.clone()), &part)?.allow_incomplete_snapshot();
+
+        Ok(SplitEncoder {
+            enc,
+            header: hdr.clone(),
+            part,
+            max_size,
+            factory,
+            checksum: DatabaseChecksum::new(),
+            outputs: Vec::new(),
+        })
+    }
+
+    /// Encode a page with the given `page_num` and `data`, starting a new file first
+    /// if the current one is over the size limit.
+    ///
+    /// Pages must be passed in the same order as to [`Encoder::encode_page`] for a
+    /// snapshot.
+    pub fn encode_page(&mut self, page_num: PageNum, data: &[u8]) -> Result<(), Error> {
+        if self.enc.bytes_written() >= self.max_size {
+            let txid = self.part.max_txid;
+            let next = txid
+                .checked_add(1)
+                .filter(|next| *next <= self.header.max_txid)
+                .ok_or(Error::TXIDExhausted(txid))?;
+            self.next_part(next, next)?;
+        }
+
+        self.enc.encode_page(page_num, data)?;
+        self.checksum.add_page(page_num, data);
+
+        Ok(())
+    }
+
+    /// Finish the last file and return the writers of all the files along with their
+    /// trailers, in order.
+    pub fn finish(mut self) -> Result<Vec<(W, Trailer)>, Error> {
+        if self.part.max_txid < self.header.max_txid {
+            self.next_part(self.part.max_txid + 1, self.header.max_txid)?;
+        }
+
+        let post_apply_checksum = self.checksum.finish();
+        let (trailer, w) = self.enc.finish_into_inner(Some(post_apply_checksum))?;
+        self.outputs.push((w, trailer));
+
+        Ok(self.outputs)
+    }
+
+    /// Finish the current file and start a delta covering `min_txid..=max_txid`.
+    fn next_part(&mut self, min_txid: TXID, max_txid: TXID) -> Result<(), Error> {
+        let no_checksum = self.header.flags.contains(HeaderFlags::NO_CHECKSUM);
+        let checksum = self.checksum.finish();
+
+        self.part = Header {
+            min_txid,
+            max_txid,
+            pre_apply_checksum: (!no_checksum).then_some(checksum),
+            ..self.part.clone()
+        };
+        let enc = Encoder::new((self.factory)(self.part.clone()), &self.part)?;
+
+        let prev = std::mem::replace(&mut self.enc, enc);
+        let (trailer, w) = prev.finish_into_inner(Some(checksum))?;
+        self.outputs.push((w, trailer));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, SplitEncoder};
+    use crate::{
+        apply::pages_checksum, Compactor, Decoder, Header, HeaderFlags, PageNum, PageSize, TXID,
+    };
+    use std::time;
+
+    fn header(max_txid: u64) -> Header {
+        Header {
+            flags: HeaderFlags::empty(),
+            page_size: PageSize::new(512).unwrap(),
+            commit: Some(PageNum::new(10).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::new(max_txid).unwrap(),
+            timestamp: time::SystemTime::UNIX_EPOCH,
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        }
+    }
+
+    fn pages() -> Vec<Vec<u8>> {
+        (1..=10).map(|n| vec![n as u8; 512]).collect()
+    }
+
+    #[test]
+    fn split_encoder() {
+        let mut headers = Vec::new();
+        let mut enc = SplitEncoder::new(&header(20), 1500, |hdr| {
+            headers.push(hdr);
+            Vec::new()
+        })
+        .expect("failed to create encoder");
+        for (i, page) in pages().iter().enumerate() {
+            enc.encode_page(PageNum::new(i as u32 + 1).unwrap(), page)
+                .expect("failed to encode page");
+        }
+        let outputs = enc.finish().expect("failed to finish encoder");
+
+        // Three pages fit in a file, followed by a file without pages.
+        assert_eq!(5, outputs.len());
+        assert_eq!(
+            vec![(1, 1), (2, 2), (3, 3), (4, 4), (5, 20)],
+            headers
+                .iter()
+                .map(|h| (h.min_txid.into_inner(), h.max_txid.into_inner()))
+                .collect::<Vec<_>>()
+        );
+        for (w, _) in &outputs[..4] {
+            assert!(w.len() < 1500 + 512 + 100);
+        }
+
+        let inputs = outputs
+            .iter()
+            .map(|(w, _)| Decoder::builder().incomplete_snapshots(true).build(&w[..]))
+            .collect::<Result<Vec<_>, _>>()
+            .expect("failed to create decoders");
+        let mut w = Vec::new();
+        let trailer = Compactor::new(inputs, HeaderFlags::empty())
+            .expect("failed to create compactor")
+            .compact(&mut w)
+            .expect("failed to compact");
+
+        let db = pages().concat();
+        let checksum = pages_checksum(&db[..], PageSize::new(512).unwrap(), 1..=10).unwrap();
+        assert_eq!(Some(checksum), trailer.post_apply_checksum);
+
+        let (mut dec, hdr) = Decoder::new(&w[..]).expect("failed to create decoder");
+        assert_eq!(
+            (TXID::ONE, TXID::new(20).unwrap()),
+            (hdr.min_txid, hdr.max_txid)
+        );
+        let mut page = vec![0; 512];
+        for expected in pages() {
+            assert!(dec.decode_page(&mut page).unwrap().is_some());
+            assert_eq!(expected, page);
+        }
+        assert!(dec.decode_page(&mut page).unwrap().is_none());
+    }
+
+    #[test]
+    fn split_encoder_txid_exhausted() {
+        let mut enc =
+            SplitEncoder::new(&header(2), 1000, |_| Vec::new()).expect("failed to create encoder");
+        let mut result = Ok(());
+        for (i, page) in pages().iter().enumerate() {
+            result = enc.encode_page(PageNum::new(i as u32 + 1).unwrap(), page);
+            if result.is_err() {
+                break;
+            }
+        }
+        assert!(matches!(result, Err(Error::TXIDExhausted(txid)) if txid == TXID::new(2).unwrap()));
+    }
+
+    #[test]
+    fn split_encoder_not_snapshot() {
+        let hdr = Header {
+            min_txid: TXID::new(2).unwrap(),
+            pre_apply_checksum: Some(crate::Checksum::new(1)),
+            ..header(2)
+        };
+        assert!(matches!(
+            SplitEncoder::new(&hdr, 1000, |_| Vec::new()),
+            Err(Error::NotSnapshot)
+        ));
+    }
+}
@@ -0,0 +1,182 @@
+//! Parsing of SQLite database file headers.
+
+use crate::{types::PageSizeError, PageNum, PageSize};
+use std::io;
+
+/// The size of the SQLite database header at the start of page 1.
+pub const HEADER_SIZE: usize = 100;
+
+const MAGIC: &[u8; 16] = b"SQLite format 3\0";
+
+/// An error that can be returned while parsing a database header.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("invalid database header magic")]
+    Magic,
+    #[error("invalid database page size")]
+    PageSize(#[from] PageSizeError),
+    #[error("invalid database text encoding: {0}")]
+    TextEncoding(u32),
+    #[error("read")]
+    Read(#[from] io::Error),
+}
+
+/// The encoding of text values in a database.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    /// No encoding is set yet, which is the case of databases without a schema. SQLite
+    /// sets the encoding once the first table is created.
+    Unset,
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+/// A parsed SQLite database header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DatabaseHeader {
+    pub page_size: PageSize,
+    /// The size of the database in pages, `None` if the in-header size isn't valid,
+    /// e.g. because the database was last written by an old version of SQLite.
+    pub page_count: Option<PageNum>,
+    /// The file change counter, incremented by every committed transaction in rollback
+    /// journal mode.
+    pub change_counter: u32,
+    pub text_encoding: TextEncoding,
+}
+
+impl DatabaseHeader {
+    /// Decode the database header from the first [`HEADER_SIZE`] bytes of a database file.
+    pub fn decode(buf: &[u8; HEADER_SIZE]) -> Result<DatabaseHeader, Error> {
+        if &buf[0..16] != MAGIC {
+            return Err(Error::Magic);
+        }
+        let word = |i: usize| u32::from_be_bytes(buf[i..i + 4].try_into().unwrap());
+
+        let page_size =
+            PageSize::from_sqlite_encoding(u16::from_be_bytes(buf[16..18].try_into().unwrap()))?;
+
+        // The in-header database size is only valid if the change counter matches the
+        // version-valid-for number.
+        let change_counter = word(24);
+        let page_count = if change_counter == word(92) {
+            PageNum::new(word(28)).ok()
+        } else {
+            None
+        };
+
+        let text_encoding = match word(56) {
+            0 => TextEncoding::Unset,
+            1 => TextEncoding::Utf8,
+            2 => TextEncoding::Utf16Le,
+            3 => TextEncoding::Utf16Be,
+            n => return Err(Error::TextEncoding(n)),
+        };
+
+        Ok(DatabaseHeader {
+            page_size,
+            page_count,
+            change_counter,
+            text_encoding,
+        })
+    }
+}
+
+/// Read and decode the database header from the start of the database file `r`.
+///
+/// # Example
+/// ```no_run
+/// let db = std::fs::File::open("db.sqlite").expect("open");
+/// let header = litetx::sqlite::read_header(db).expect("read_header");
+/// println!("{} pages of {} bytes", header.page_count.unwrap(), header.page_size);
+/// ```
+pub fn read_header<R>(mut r: R) -> Result<DatabaseHeader, Error>
+where
+    R: io::Read,
+{
+    let mut buf = [0; HEADER_SIZE];
+    r.read_exact(&mut buf)?;
+
+    DatabaseHeader::decode(&buf)
+}
+
+/// Write a minimal database header for testing into the start of `db`.
+#[cfg(test)]
+pub(crate) fn test_header(db: &mut [u8], page_size: u32, page_count: u32) {
+    db[0..16].copy_from_slice(MAGIC);
+    db[16..18].copy_from_slice(&(page_size as u16).to_be_bytes());
+    db[24..28].copy_from_slice(&7u32.to_be_bytes());
+    db[28..32].copy_from_slice(&page_count.to_be_bytes());
+    db[56..60].copy_from_slice(&1u32.to_be_bytes());
+    db[92..96].copy_from_slice(&7u32.to_be_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_header, test_header, DatabaseHeader, Error, TextEncoding, HEADER_SIZE};
+    use crate::{utils::TempDir, PageNum, PageSize};
+    use std::fs;
+
+    #[test]
+    fn decode_header() {
+        let mut buf = [0; HEADER_SIZE];
+        test_header(&mut buf, 4096, 12);
+
+        assert_eq!(
+            DatabaseHeader {
+                page_size: PageSize::new(4096).unwrap(),
+                page_count: Some(PageNum::new(12).unwrap()),
+                change_counter: 7,
+                text_encoding: TextEncoding::Utf8,
+            },
+            read_header(buf.as_slice()).expect("failed to read header")
+        );
+
+        buf[16..18].copy_from_slice(&1u16.to_be_bytes());
+        buf[56..60].copy_from_slice(&3u32.to_be_bytes());
+        let header = DatabaseHeader::decode(&buf).expect("failed to decode header");
+        assert_eq!(PageSize::new(65536).unwrap(), header.page_size);
+        assert_eq!(TextEncoding::Utf16Be, header.text_encoding);
+
+        buf[95] = 8;
+        let header = DatabaseHeader::decode(&buf).expect("failed to decode header");
+        assert_eq!(None, header.page_count);
+    }
+
+    #[test]
+    fn decode_header_without_schema() {
+        let dir = TempDir::new();
+        let path = dir.path().join("db");
+        let conn = rusqlite::Connection::open(&path).expect("failed to open database");
+        conn.execute_batch("PRAGMA user_version = 5")
+            .expect("failed to set user version");
+        drop(conn);
+
+        let header = read_header(fs::File::open(&path).unwrap()).expect("failed to read header");
+        assert_eq!(TextEncoding::Unset, header.text_encoding);
+        assert_eq!(Some(PageNum::ONE), header.page_count);
+    }
+
+    #[test]
+    fn decode_invalid_header() {
+        let mut buf = [0; HEADER_SIZE];
+        test_header(&mut buf, 4096, 12);
+        buf[0] = b'X';
+        assert!(matches!(DatabaseHeader::decode(&buf), Err(Error::Magic)));
+
+        test_header(&mut buf, 1000, 12);
+        assert!(matches!(
+            DatabaseHeader::decode(&buf),
+            Err(Error::PageSize(_))
+        ));
+
+        test_header(&mut buf, 4096, 12);
+        buf[59] = 4;
+        assert!(matches!(
+            DatabaseHeader::decode(&buf),
+            Err(Error::TextEncoding(4))
+        ));
+
+        assert!(matches!(read_header(&buf[..50]), Err(Error::Read(_))));
+    }
+}
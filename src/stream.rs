@@ -0,0 +1,269 @@
+use crate::{
+    decoder::Error as DecodeError, Checksum, Decoder, DecoderBuilder, Header, Trailer, TXID,
+};
+use std::io::{self, Read};
+
+/// An error that can be returned by [`StreamDecoder`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("previous file of the stream hasn't been finished")]
+    UnfinishedFile,
+    #[error("non-contiguous transaction ids: ({0}, {1})")]
+    NonContiguousTXID(TXID, TXID),
+    #[error("pre-apply checksum of transaction {0} doesn't match post-apply checksum of the previous file")]
+    ChecksumMismatch(TXID),
+    #[error("decode")]
+    Decode(#[from] DecodeError),
+}
+
+/// The [`Decoder`] of a single file of a [`StreamDecoder`].
+pub type StreamFile<R> = Decoder<'static, StreamReader<R>>;
+
+/// Decodes a sequence of LTX files concatenated back-to-back in a single stream.
+///
+/// Every file is read with a regular [`Decoder`] returned by
+/// [`StreamDecoder::next_file`], which must be passed back to
+/// [`StreamDecoder::finish_file`] before the next file can be read. Consecutive files
+/// must have contiguous transaction IDs, and the pre-apply checksum of a file must match
+/// the post-apply checksum of the previous one unless either of them has no checksums.
+///
+/// # Example
+/// ```no_run
+/// # let v = Vec::new();
+/// # let r = &v[..];
+/// let mut stream = litetx::StreamDecoder::new(r);
+/// while let Some((mut dec, header)) = stream.next_file().expect("next_file") {
+///     let mut buf = vec![0; header.page_size.into_inner() as usize];
+///     while let Some(page_num) = dec.decode_page(&mut buf).expect("decode_page") {
+///         // do something with the page
+///     }
+///     let trailer = stream.finish_file(dec).expect("finish_file");
+/// }
+/// ```
+pub struct StreamDecoder<R>
+where
+    R: io::Read,
+{
+    r: Option<StreamReader<R>>,
+    opts: DecoderBuilder,
+    last: Option<(TXID, Option<Checksum>)>,
+}
+
+impl<R> StreamDecoder<R>
+where
+    R: io::Read,
+{
+    /// Create a new [`StreamDecoder`] that reads from `r`.
+    pub fn new(r: R) -> StreamDecoder<R> {
+        DecoderBuilder::default().build_stream(r)
+    }
+
+    pub(crate) fn with_options(r: R, opts: DecoderBuilder) -> StreamDecoder<R> {
+        StreamDecoder {
+            r: Some(StreamReader {
+                inner: r,
+                buf: Vec::new(),
+                pos: 0,
+            }),
+            opts,
+            last: None,
+        }
+    }
+
+    /// Start decoding the next file of the stream.
+    ///
+    /// Returns `Ok(None)` if the stream ends after the previous file.
+    pub fn next_file(&mut self) -> Result<Option<(StreamFile<R>, Header)>, Error> {
+        let mut r = self.r.take().ok_or(Error::UnfinishedFile)?;
+
+        let mut buf = [0; 1];
+        let n = loop {
+            match r.read(&mut buf) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result.map_err(DecodeError::from)?,
+            }
+        };
+        if n == 0 {
+            self.r = Some(r);
+            return Ok(None);
+        }
+        r.unread(&buf);
+
+        let (dec, header) = self.opts.build(r)?;
+        if let Some((max_txid, post_apply_checksum)) = self.last {
+            if max_txid.checked_add(1) != Some(header.min_txid) {
+                return Err(Error::NonContiguousTXID(max_txid, header.min_txid));
+            }
+            if let (Some(pre), Some(post)) = (header.pre_apply_checksum, post_apply_checksum) {
+                if pre != post {
+                    return Err(Error::ChecksumMismatch(header.min_txid));
+                }
+            }
+        }
+        self.last = Some((header.max_txid, None));
+
+        Ok(Some((dec, header)))
+    }
+
+    /// Verify the trailer of the file returned by [`StreamDecoder::next_file`] and
+    /// position the stream at the start of the next file.
+    ///
+    /// The remaining pages of the file are not decoded, so all of them must be
+    /// consumed beforehand.
+    pub fn finish_file(&mut self, dec: StreamFile<R>) -> Result<Trailer, Error> {
+        let (trailer, mut r, buf) = dec.finish_into_inner()?;
+        r.unread(&buf);
+
+        self.r = Some(r);
+        if let Some((_, checksum)) = &mut self.last {
+            *checksum = trailer.post_apply_checksum;
+        }
+
+        Ok(trailer)
+    }
+}
+
+/// The reader of a [`StreamDecoder`] given to the [`Decoder`] of every file.
+///
+/// Data read ahead past the end of a file is put back in front of the underlying
+/// reader.
+pub struct StreamReader<R>
+where
+    R: io::Read,
+{
+    inner: R,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<R> StreamReader<R>
+where
+    R: io::Read,
+{
+    fn unread(&mut self, data: &[u8]) {
+        self.buf.drain(..self.pos);
+        self.buf.splice(..0, data.iter().copied());
+        self.pos = 0;
+    }
+}
+
+impl<R> io::Read for StreamReader<R>
+where
+    R: io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.buf.len() {
+            return self.inner.read(buf);
+        }
+
+        let n = buf.len().min(self.buf.len() - self.pos);
+        buf[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, StreamDecoder};
+    use crate::{
+        test_util::{encode_ltx, test_header},
+        Checksum, HeaderFlags, TXID,
+    };
+
+    fn encode_file(flags: HeaderFlags, txid: u64, pre: Option<u64>, post: u64) -> Vec<u8> {
+        let header = test_header(flags, txid..=txid, 2, pre.map(Checksum::new));
+        let fill = txid as u8;
+        encode_ltx(&header, &[(1, fill), (2, fill)], Some(Checksum::new(post)))
+    }
+
+    fn stream_decoder_test(flags: HeaderFlags) {
+        let stream = [
+            encode_file(flags, 1, None, 1),
+            encode_file(flags, 2, Some(1), 2),
+            encode_file(flags, 3, Some(2), 3),
+        ]
+        .concat();
+
+        let mut dec = StreamDecoder::new(&stream[..]);
+        let mut page = vec![0; 512];
+        for txid in 1..=3 {
+            let (mut file, header) = dec
+                .next_file()
+                .expect("failed to start file")
+                .expect("missing file");
+            assert_eq!(TXID::new(txid).unwrap(), header.min_txid);
+            while file
+                .decode_page(&mut page)
+                .expect("failed to decode page")
+                .is_some()
+            {
+                assert_eq!(vec![txid as u8; 512], page);
+            }
+
+            let trailer = dec.finish_file(file).expect("failed to finish file");
+            assert_eq!(Some(Checksum::new(txid)), trailer.post_apply_checksum);
+        }
+        assert!(dec.next_file().expect("failed to read stream").is_none());
+    }
+
+    #[test]
+    fn stream_decoder() {
+        stream_decoder_test(HeaderFlags::empty());
+        stream_decoder_test(HeaderFlags::COMPRESS_LZ4);
+        #[cfg(feature = "zstd")]
+        stream_decoder_test(HeaderFlags::COMPRESS_ZSTD);
+    }
+
+    #[test]
+    fn stream_decoder_custom_compression() {
+        crate::compression::tests::register_xor();
+        stream_decoder_test(crate::compression::tests::XOR_FLAG);
+    }
+
+    #[test]
+    fn stream_decoder_continuity() {
+        let flags = HeaderFlags::empty();
+        for (stream, expected) in [
+            (
+                [
+                    encode_file(flags, 1, None, 1),
+                    encode_file(flags, 3, Some(1), 3),
+                ]
+                .concat(),
+                "non-contiguous",
+            ),
+            (
+                [
+                    encode_file(flags, 1, None, 1),
+                    encode_file(flags, 2, Some(5), 2),
+                ]
+                .concat(),
+                "checksum",
+            ),
+        ] {
+            let mut dec = StreamDecoder::new(&stream[..]);
+            let (mut file, _) = dec.next_file().unwrap().unwrap();
+            while file.skip_page().unwrap().is_some() {}
+            dec.finish_file(file).unwrap();
+
+            match dec.next_file() {
+                Err(Error::NonContiguousTXID(..)) => assert_eq!("non-contiguous", expected),
+                Err(Error::ChecksumMismatch(txid)) => {
+                    assert_eq!("checksum", expected);
+                    assert_eq!(TXID::new(2).unwrap(), txid);
+                }
+                _ => panic!("expected continuity error"),
+            }
+        }
+    }
+
+    #[test]
+    fn stream_decoder_unfinished_file() {
+        let stream = encode_file(HeaderFlags::empty(), 1, None, 1);
+        let mut dec = StreamDecoder::new(&stream[..]);
+        let (_file, _) = dec.next_file().unwrap().unwrap();
+        assert!(matches!(dec.next_file(), Err(Error::UnfinishedFile)));
+    }
+}
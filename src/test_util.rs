@@ -0,0 +1,215 @@
+//! Generation of valid LTX files, random or from given pages, for use as fixtures in
+//! tests.
+//!
+//! ```
+//! use litetx::test_util::{generate_ltx, GenOptions};
+//!
+//! let mut rng = rand::thread_rng();
+//! let (data, header, trailer) = generate_ltx(&mut rng, GenOptions::default());
+//!
+//! assert_eq!((header, trailer), litetx::verify(data.as_slice()).unwrap());
+//! ```
+
+use crate::{Checksum, Encoder, Header, HeaderFlags, PageNum, PageSize, Trailer, TXID};
+use rand::{seq::index, Rng};
+use std::{ops::RangeInclusive, time};
+
+/// Options of the files produced by [`generate_ltx`].
+#[derive(Debug, Clone)]
+pub struct GenOptions {
+    /// The number of pages in the file. Must not be zero.
+    pub pages: u32,
+    /// The page size of the file.
+    pub page_size: PageSize,
+    /// Whether the page block is compressed with LZ4.
+    pub compressed: bool,
+    /// Whether the file is a snapshot of pages `1..=pages`, skipping the lock page.
+    /// Otherwise the file is a delta with pages picked at random up to a random commit
+    /// and random pre- and post-apply checksums.
+    pub snapshot: bool,
+}
+
+impl Default for GenOptions {
+    fn default() -> Self {
+        GenOptions {
+            pages: 16,
+            page_size: PageSize::new(4096).unwrap(),
+            compressed: true,
+            snapshot: true,
+        }
+    }
+}
+
+/// Generate a random valid LTX file, returning its contents along with the header and
+/// the trailer a decoder is expected to return for it.
+///
+/// Pages are half random bytes and half zeros, so that compression has an effect.
+///
+/// # Panics
+/// Panics if `opts.pages` is zero or doesn't fit in the database.
+pub fn generate_ltx<R: Rng + ?Sized>(rng: &mut R, opts: GenOptions) -> (Vec<u8>, Header, Trailer) {
+    assert!(opts.pages > 0, "generated files must contain pages");
+
+    let (pages, commit) = if opts.snapshot {
+        let pages: Vec<_> =
+            PageNum::snapshot_range(opts.page_size, PageNum::new(u32::MAX).unwrap())
+                .take(opts.pages as usize)
+                .collect();
+        let commit = *pages.last().unwrap();
+        (pages, commit)
+    } else {
+        let lock_page = PageNum::lock_page(opts.page_size);
+        let commit = opts.pages + rng.gen_range(0..=opts.pages);
+        let mut pages: Vec<_> = index::sample(rng, commit as usize, opts.pages as usize)
+            .into_iter()
+            .map(|i| PageNum::new(i as u32 + 1).unwrap())
+            .map(|pgno| {
+                if pgno == lock_page {
+                    PageNum::new(commit + 1).unwrap()
+                } else {
+                    pgno
+                }
+            })
+            .collect();
+        pages.sort();
+        let commit = pages[pages.len() - 1].max(PageNum::new(commit).unwrap());
+        (pages, commit)
+    };
+
+    let min_txid = if opts.snapshot {
+        TXID::ONE
+    } else {
+        TXID::new(rng.gen_range(2..1 << 32)).unwrap()
+    };
+    let header = Header {
+        flags: if opts.compressed {
+            HeaderFlags::COMPRESS_LZ4
+        } else {
+            HeaderFlags::empty()
+        },
+        page_size: opts.page_size,
+        commit: Some(commit),
+        min_txid,
+        max_txid: TXID::new(min_txid.into_inner() + rng.gen_range(0..16)).unwrap(),
+        timestamp: time::UNIX_EPOCH + time::Duration::from_millis(rng.gen_range(0..1 << 42)),
+        pre_apply_checksum: (!opts.snapshot).then(|| Checksum::new(rng.gen())),
+        node_id: 0,
+        wal: None,
+        app_data: None,
+        page_filter: None,
+        key_id: None,
+        dict_id: None,
+    };
+
+    let mut data = Vec::new();
+    let mut enc = Encoder::new(&mut data, &header).expect("failed to create encoder");
+    let mut page = vec![0; opts.page_size.into_inner() as usize];
+    let random = page.len() / 2;
+    for pgno in pages {
+        rng.fill(&mut page[..random]);
+        enc.encode_page(pgno, &page).expect("failed to encode page");
+    }
+    let trailer = if opts.snapshot {
+        enc.finish_auto()
+    } else {
+        enc.finish(Checksum::new(rng.gen()))
+    }
+    .expect("failed to finish encoder");
+
+    (data, header, trailer)
+}
+
+/// Return the header of a file with 512-byte pages covering the transactions in
+/// `txids`, for use with [`encode_ltx`].
+///
+/// The timestamp is the Unix epoch and the optional fields are unset. Other values can
+/// be set with struct update syntax.
+///
+/// # Panics
+/// Panics if a transaction ID or `commit` is zero.
+pub fn test_header(
+    flags: HeaderFlags,
+    txids: RangeInclusive<u64>,
+    commit: u32,
+    pre_apply_checksum: Option<Checksum>,
+) -> Header {
+    Header {
+        flags,
+        page_size: PageSize::new(512).unwrap(),
+        commit: Some(PageNum::new(commit).unwrap()),
+        min_txid: TXID::new(*txids.start()).unwrap(),
+        max_txid: TXID::new(*txids.end()).unwrap(),
+        timestamp: time::UNIX_EPOCH,
+        pre_apply_checksum,
+        node_id: 0,
+        wal: None,
+        app_data: None,
+        page_filter: None,
+        key_id: None,
+        dict_id: None,
+    }
+}
+
+/// Encode a file with the given `header` and `pages`, each given by its page number
+/// and the byte it's filled with, returning its contents.
+///
+/// The post-apply checksum is computed from the pages if `post_apply_checksum` is
+/// `None`, which only works for snapshots.
+///
+/// # Panics
+/// Panics if the file can't be encoded, e.g. if the pages are out of order.
+pub fn encode_ltx(
+    header: &Header,
+    pages: &[(u32, u8)],
+    post_apply_checksum: Option<Checksum>,
+) -> Vec<u8> {
+    let mut data = Vec::new();
+    let mut enc = Encoder::new(&mut data, header).expect("failed to create encoder");
+    let mut page = vec![0; header.page_size.into_inner() as usize];
+    for (pgno, fill) in pages {
+        page.fill(*fill);
+        enc.encode_page(PageNum::new(*pgno).unwrap(), &page)
+            .expect("failed to encode page");
+    }
+    match post_apply_checksum {
+        Some(checksum) => enc.finish(checksum),
+        None => enc.finish_auto(),
+    }
+    .expect("failed to finish encoder");
+
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{generate_ltx, GenOptions};
+    use crate::{verify, Decoder, PageNum, PageSize};
+
+    #[test]
+    fn generate_ltx_valid() {
+        let mut rng = rand::thread_rng();
+        for (compressed, snapshot) in [(false, false), (false, true), (true, false), (true, true)] {
+            let opts = GenOptions {
+                pages: 40,
+                page_size: PageSize::new(512).unwrap(),
+                compressed,
+                snapshot,
+            };
+            let (data, header, trailer) = generate_ltx(&mut rng, opts);
+            assert_eq!((header.clone(), trailer), verify(data.as_slice()).unwrap());
+            assert_eq!(!snapshot, header.pre_apply_checksum.is_some());
+
+            let mut pages = Vec::new();
+            let (mut dec, _) = Decoder::new(data.as_slice()).unwrap();
+            let mut page = vec![0; 512];
+            while let Some(pgno) = dec.decode_page(&mut page).unwrap() {
+                pages.push(pgno);
+            }
+            assert_eq!(40, pages.len());
+            assert!(header.commit >= pages.last().copied());
+            assert!(
+                !snapshot || pages[0] == PageNum::ONE && header.commit == pages.last().copied()
+            );
+        }
+    }
+}
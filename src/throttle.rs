@@ -0,0 +1,157 @@
+use std::{
+    io, thread,
+    time::{Duration, Instant},
+};
+
+/// The number of chunks a second of budget is split into, so transfers are spread
+/// evenly instead of arriving in bursts once a second.
+const CHUNKS_PER_SEC: u64 = 10;
+
+/// An [`io::Write`] or [`io::Read`] limiting the throughput of the wrapped writer or
+/// reader to a number of bytes per second.
+///
+/// Wrapping the writer of an [`Encoder`](crate::Encoder) or the reader of a
+/// [`Decoder`](crate::Decoder) keeps large snapshots from saturating the disk or the
+/// network, while [`ProgressEvent`](crate::ProgressEvent)s report the resulting
+/// throughput. Calls block until the transfer fits into the budget, and large buffers
+/// are transferred in chunks of a tenth of the per-second budget.
+///
+/// # Example
+/// ```no_run
+/// # let v = Vec::new();
+/// # let r = &v[..];
+/// let r = litetx::Throttle::new(r, 10 * 1024 * 1024);
+/// let (dec, _) = litetx::Decoder::new(r).expect("decoder");
+/// let dec = dec.with_progress(|e| eprintln!("{:.0} B/s", e.bytes_in_per_sec()));
+/// ```
+pub struct Throttle<T> {
+    inner: T,
+    bytes_per_sec: u64,
+    started: Instant,
+    transferred: u64,
+}
+
+impl<T> Throttle<T> {
+    /// Create a new [`Throttle`] transferring at most `bytes_per_sec` bytes per second.
+    pub fn new(inner: T, bytes_per_sec: u64) -> Throttle<T> {
+        Throttle {
+            inner,
+            bytes_per_sec: bytes_per_sec.max(1),
+            started: Instant::now(),
+            transferred: 0,
+        }
+    }
+
+    /// Change the limit to `bytes_per_sec` bytes per second, starting from now.
+    pub fn set_rate(&mut self, bytes_per_sec: u64) {
+        self.bytes_per_sec = bytes_per_sec.max(1);
+        self.started = Instant::now();
+        self.transferred = 0;
+    }
+
+    /// Return the limit in bytes per second.
+    pub fn rate(&self) -> u64 {
+        self.bytes_per_sec
+    }
+
+    /// Return a reference to the underlying writer or reader.
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    /// Return a mutable reference to the underlying writer or reader.
+    ///
+    /// Data transferred directly isn't accounted for.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Consume the [`Throttle`] and return the underlying writer or reader.
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Return the size of the next chunk to transfer out of `len` bytes.
+    fn chunk(&self, len: usize) -> usize {
+        let chunk = (self.bytes_per_sec / CHUNKS_PER_SEC).max(1);
+        len.min(usize::try_from(chunk).unwrap_or(usize::MAX))
+    }
+
+    /// Account for `n` transferred bytes and wait until they fit into the budget.
+    fn consume(&mut self, n: usize) {
+        self.transferred += n as u64;
+        let due = Duration::from_secs_f64(self.transferred as f64 / self.bytes_per_sec as f64);
+        if let Some(wait) = due.checked_sub(self.started.elapsed()) {
+            thread::sleep(wait);
+        }
+    }
+}
+
+impl<W> io::Write for Throttle<W>
+where
+    W: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(&buf[..self.chunk(buf.len())])?;
+        self.consume(n);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R> io::Read for Throttle<R>
+where
+    R: io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.chunk(buf.len());
+        let n = self.inner.read(&mut buf[..len])?;
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Throttle;
+    use std::{
+        io::{self, Read, Write},
+        time::{Duration, Instant},
+    };
+
+    #[test]
+    fn throttle_write() {
+        let mut w = Throttle::new(Vec::new(), 100_000);
+        let started = Instant::now();
+        w.write_all(&[1; 25_000]).expect("failed to write");
+
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert_eq!(vec![1; 25_000], w.into_inner());
+    }
+
+    #[test]
+    fn throttle_read() {
+        let data = vec![2; 25_000];
+        let mut r = Throttle::new(data.as_slice(), 100_000);
+        let started = Instant::now();
+        let mut buf = Vec::new();
+        r.read_to_end(&mut buf).expect("failed to read");
+
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert_eq!(data, buf);
+    }
+
+    #[test]
+    fn throttle_set_rate() {
+        let mut w = Throttle::new(io::sink(), 1);
+        w.set_rate(100_000_000);
+        assert_eq!(100_000_000, w.rate());
+
+        let started = Instant::now();
+        w.write_all(&[0; 1_000_000]).expect("failed to write");
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,72 @@
+//! The time types used by [`Header`](crate::Header).
+//!
+//! With the `std` feature, these are the `std::time` types. Without it, [`SystemTime`]
+//! is a point in time counted from the Unix epoch, which has to be built from a
+//! timestamp provided by the host since there is no clock to read.
+
+#[cfg(feature = "std")]
+pub use std::time::{Duration, SystemTime, SystemTimeError, UNIX_EPOCH};
+
+#[cfg(not(feature = "std"))]
+pub use self::imp::*;
+
+#[cfg(not(feature = "std"))]
+mod imp {
+    use core::{fmt, ops};
+
+    pub use core::time::Duration;
+
+    /// The Unix epoch.
+    pub const UNIX_EPOCH: SystemTime = SystemTime::UNIX_EPOCH;
+
+    /// A point in time, counted from the Unix epoch.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct SystemTime(Duration);
+
+    impl SystemTime {
+        /// The Unix epoch.
+        pub const UNIX_EPOCH: SystemTime = SystemTime(Duration::ZERO);
+
+        /// Return the time elapsed from `earlier` to this time, failing if `earlier` is
+        /// later.
+        pub fn duration_since(&self, earlier: SystemTime) -> Result<Duration, SystemTimeError> {
+            self.0
+                .checked_sub(earlier.0)
+                .ok_or(SystemTimeError(earlier.0 - self.0))
+        }
+
+        /// Return the time `duration` after this time, or `None` on overflow.
+        pub fn checked_add(&self, duration: Duration) -> Option<SystemTime> {
+            self.0.checked_add(duration).map(SystemTime)
+        }
+    }
+
+    impl ops::Add<Duration> for SystemTime {
+        type Output = SystemTime;
+
+        fn add(self, rhs: Duration) -> SystemTime {
+            self.checked_add(rhs)
+                .expect("overflow adding duration to time")
+        }
+    }
+
+    /// An error returned by [`SystemTime::duration_since`] if the time is earlier than
+    /// the other one.
+    #[derive(Debug, Clone)]
+    pub struct SystemTimeError(Duration);
+
+    impl SystemTimeError {
+        /// Return how much earlier the time is.
+        pub fn duration(&self) -> Duration {
+            self.0
+        }
+    }
+
+    impl fmt::Display for SystemTimeError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "second time provided was later than self")
+        }
+    }
+
+    impl core::error::Error for SystemTimeError {}
+}
@@ -0,0 +1,156 @@
+use crate::{
+    compression, decoder::Error as DecodeError, encoder::Error as EncodeError, Decoder, Encoder,
+    Header, HeaderFlags, Trailer,
+};
+use std::io;
+
+/// An error that can be returned by [`transcode`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("decode")]
+    Decode(#[from] DecodeError),
+    #[error("encode")]
+    Encode(#[from] EncodeError),
+    #[error("not compression flags: {0:?}")]
+    Flags(HeaderFlags),
+}
+
+/// Re-encode the LTX file read from `r` into `w` with the compression given by
+/// `new_flags`, e.g. [`HeaderFlags::COMPRESS_LZ4`] or an empty set for no compression.
+///
+/// `new_flags` replaces the compression flags of the file, i.e. the built-in and the
+/// [registered](compression::register) codecs and [`HeaderFlags::PER_PAGE_COMPRESSION`],
+/// and must not contain any other flag. All other header fields, the pages and their
+/// order, and the post-apply checksum are kept, so the result can take the place of the
+/// original in a chain of files. Only the file checksum, along with the Merkle tree and
+/// page index, if any, is recomputed. An LZ4 dictionary, if any, is dropped.
+///
+/// The original file is fully decoded and its file checksum verified before the new
+/// trailer is written. Returns the new trailer.
+///
+/// # Example
+/// ```no_run
+/// use litetx::HeaderFlags;
+///
+/// let r = std::fs::File::open("0000000000000001-0000000000000064.ltx").expect("open");
+/// let w = std::fs::File::create("0000000000000001-0000000000000064.ltx.lz4").expect("create");
+/// let trailer = litetx::transcode(r, w, HeaderFlags::COMPRESS_LZ4).expect("transcode");
+/// ```
+pub fn transcode<R, W>(r: R, w: W, new_flags: HeaderFlags) -> Result<Trailer, Error>
+where
+    R: io::Read,
+    W: io::Write,
+{
+    let compression = HeaderFlags::COMPRESS_LZ4
+        | HeaderFlags::COMPRESS_ZSTD
+        | HeaderFlags::PER_PAGE_COMPRESSION
+        | compression::registered_flags();
+    if !compression.contains(new_flags) {
+        return Err(Error::Flags(new_flags.difference(compression)));
+    }
+
+    let (mut dec, header) = Decoder::new(r)?;
+    let header = Header {
+        flags: header
+            .flags
+            .difference(compression | HeaderFlags::LZ4_DICTIONARY)
+            .union(new_flags),
+        dict_id: None,
+        ..header
+    };
+
+    let mut enc = Encoder::new(w, &header)?;
+    while let Some((page_num, page)) = dec.decode_page_ref()? {
+        enc.encode_page(page_num, page)?;
+    }
+    let trailer = dec.finish()?;
+
+    Ok(enc.finish(trailer.post_apply_checksum)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{transcode, Error};
+    use crate::{
+        test_util::{encode_ltx, test_header},
+        verify, Checksum, Header, HeaderFlags, PageNum,
+    };
+
+    fn encode(flags: HeaderFlags) -> (Vec<u8>, Header) {
+        let header = Header {
+            node_id: 7,
+            ..test_header(flags, 3..=4, 5, Some(Checksum::new(1)))
+        };
+        let buf = encode_ltx(&header, &[(2, 2), (3, 3), (5, 5)], Some(Checksum::new(9)));
+
+        (buf, header)
+    }
+
+    fn pages(data: &[u8]) -> Vec<(PageNum, Vec<u8>)> {
+        let (mut dec, _) = crate::Decoder::new(data).expect("failed to create decoder");
+        let mut pages = Vec::new();
+        while let Some((page_num, page)) = dec.decode_page_ref().expect("failed to decode page") {
+            pages.push((page_num, page.to_vec()));
+        }
+        pages
+    }
+
+    #[test]
+    fn transcode_compression() {
+        let mut all = vec![
+            HeaderFlags::empty(),
+            HeaderFlags::COMPRESS_LZ4,
+            HeaderFlags::COMPRESS_LZ4 | HeaderFlags::PER_PAGE_COMPRESSION,
+        ];
+        if cfg!(feature = "zstd") {
+            all.push(HeaderFlags::COMPRESS_ZSTD);
+        }
+
+        for &from in &all {
+            let (buf, header) = encode(from | HeaderFlags::PAGE_CHECKSUM);
+            for &to in &all {
+                let mut out = Vec::new();
+                let trailer = transcode(buf.as_slice(), &mut out, to).expect("failed to transcode");
+
+                let (new_header, new_trailer) = verify(out.as_slice()).expect("failed to verify");
+                assert_eq!(
+                    Header {
+                        flags: to | HeaderFlags::PAGE_CHECKSUM,
+                        ..header.clone()
+                    },
+                    new_header
+                );
+                assert_eq!(trailer, new_trailer);
+                assert_eq!(Some(Checksum::new(9)), trailer.post_apply_checksum);
+                assert_eq!(pages(&buf), pages(&out));
+                if from == to {
+                    assert_eq!(buf, out);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn transcode_invalid_flags() {
+        let (buf, _) = encode(HeaderFlags::empty());
+        assert!(matches!(
+            transcode(
+                buf.as_slice(),
+                Vec::new(),
+                HeaderFlags::COMPRESS_LZ4 | HeaderFlags::PAGE_INDEX
+            ),
+            Err(Error::Flags(flags)) if flags == HeaderFlags::PAGE_INDEX
+        ));
+    }
+
+    #[test]
+    fn transcode_corrupt() {
+        let (mut buf, _) = encode(HeaderFlags::COMPRESS_LZ4);
+        let len = buf.len();
+        buf[len - 1] ^= 1;
+        assert!(matches!(
+            transcode(buf.as_slice(), Vec::new(), HeaderFlags::empty()),
+            Err(Error::Decode(_))
+        ));
+    }
+}
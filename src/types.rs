@@ -1,7 +1,11 @@
-use std::{
-    fmt, io, num, ops,
-    path::{Path, PathBuf},
+use crate::{io, PageChecksum};
+use alloc::{
+    borrow::ToOwned,
+    string::{String, ToString},
 };
+use core::{fmt, num, ops, str};
+#[cfg(feature = "std")]
+use std::path::{Path, PathBuf};
 
 /// An ID of a database transaction.
 #[derive(
@@ -26,6 +30,19 @@ impl TXID {
     pub const fn into_inner(&self) -> u64 {
         self.0.get()
     }
+
+    /// Add `rhs` to the transaction ID, returning `None` on overflow.
+    pub const fn checked_add(self, rhs: u64) -> Option<TXID> {
+        match self.0.checked_add(rhs) {
+            Some(id) => Some(TXID(id)),
+            None => None,
+        }
+    }
+
+    /// Add `rhs` to the transaction ID, saturating at the maximum value.
+    pub const fn saturating_add(self, rhs: u64) -> TXID {
+        TXID(self.0.saturating_add(rhs))
+    }
 }
 
 impl fmt::Display for TXID {
@@ -49,14 +66,30 @@ impl TryFrom<String> for TXID {
     }
 }
 
+impl TryFrom<u64> for TXID {
+    type Error = TXIDError;
+
+    fn try_from(v: u64) -> Result<Self, Self::Error> {
+        TXID::new(v)
+    }
+}
+
 impl ops::Add<u64> for TXID {
     type Output = TXID;
 
     fn add(self, rhs: u64) -> Self::Output {
-        let sum = self.into_inner() + rhs;
-        assert!(sum != 0, "TX ID overflow");
+        self.checked_add(rhs).expect("TX ID overflow")
+    }
+}
+
+impl ops::Sub<TXID> for TXID {
+    type Output = u64;
 
-        TXID(unsafe { num::NonZeroU64::new_unchecked(sum) })
+    /// Return the number of transactions between `rhs` and `self`.
+    fn sub(self, rhs: TXID) -> Self::Output {
+        self.into_inner()
+            .checked_sub(rhs.into_inner())
+            .expect("TX ID underflow")
     }
 }
 
@@ -87,6 +120,12 @@ impl Checksum {
     pub const fn into_inner(&self) -> u64 {
         self.0
     }
+
+    /// Return the database checksum after page `pgno` changed from `old_page` to
+    /// `new_page`.
+    pub fn apply_page_change(self, pgno: PageNum, old_page: &[u8], new_page: &[u8]) -> Checksum {
+        self ^ old_page.page_checksum(pgno) ^ new_page.page_checksum(pgno)
+    }
 }
 
 impl fmt::Display for Checksum {
@@ -124,7 +163,8 @@ impl ops::BitXor<Checksum> for Checksum {
 pub struct ChecksumError;
 
 /// A database page size in bytes.
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(into = "u32", try_from = "u32")]
 pub struct PageSize(u32);
 
 impl PageSize {
@@ -144,6 +184,38 @@ impl PageSize {
     pub const fn into_inner(&self) -> u32 {
         self.0
     }
+
+    /// Construct a database page size from its encoding in the SQLite database header,
+    /// where 65536 is encoded as 1.
+    pub const fn from_sqlite_encoding(s: u16) -> Result<PageSize, PageSizeError> {
+        match s {
+            1 => PageSize::new(Self::MAX_PAGE_SIZE),
+            s => PageSize::new(s as u32),
+        }
+    }
+
+    /// Return the encoding of the database page size in the SQLite database header.
+    pub const fn to_sqlite_encoding(&self) -> u16 {
+        if self.0 == Self::MAX_PAGE_SIZE {
+            1
+        } else {
+            self.0 as u16
+        }
+    }
+}
+
+impl TryFrom<u32> for PageSize {
+    type Error = PageSizeError;
+
+    fn try_from(v: u32) -> Result<Self, Self::Error> {
+        PageSize::new(v)
+    }
+}
+
+impl From<PageSize> for u32 {
+    fn from(page_size: PageSize) -> Self {
+        page_size.into_inner()
+    }
 }
 
 impl fmt::Display for PageSize {
@@ -158,8 +230,10 @@ impl fmt::Display for PageSize {
 pub struct PageSizeError(u32);
 
 /// A database page number.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Deserialize)]
-#[serde(try_from = "u32")]
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(into = "u32", try_from = "u32")]
 pub struct PageNum(num::NonZeroU32);
 
 impl PageNum {
@@ -179,11 +253,50 @@ impl PageNum {
         self.0.get()
     }
 
+    /// Add `rhs` to the page number, returning `None` on overflow.
+    pub const fn checked_add(self, rhs: u32) -> Option<PageNum> {
+        match self.0.checked_add(rhs) {
+            Some(n) => Some(PageNum(n)),
+            None => None,
+        }
+    }
+
+    /// Add `rhs` to the page number, saturating at the maximum value.
+    pub const fn saturating_add(self, rhs: u32) -> PageNum {
+        PageNum(self.0.saturating_add(rhs))
+    }
+
     /// Return the [lock page](https://www.sqlite.org/fileformat.html#the_lock_byte_page) number for the
     /// given page size.
     pub const fn lock_page(page_size: PageSize) -> PageNum {
         PageNum(unsafe { num::NonZeroU32::new_unchecked(0x40000000 / page_size.into_inner() + 1) })
     }
+
+    /// Return the page following `self` in a snapshot, skipping the lock page.
+    pub(crate) fn next_snapshot_page(self, page_size: PageSize) -> Option<PageNum> {
+        match self.checked_add(1) {
+            Some(next) if next == PageNum::lock_page(page_size) => next.checked_add(1),
+            next => next,
+        }
+    }
+
+    /// Return the last page of a snapshot of a database of `commit` pages.
+    pub(crate) fn last_snapshot_page(page_size: PageSize, commit: PageNum) -> PageNum {
+        // The lock page is never encoded, so it can't be the last page of a snapshot.
+        match PageNum::new(commit.into_inner() - 1) {
+            Ok(prev) if commit == PageNum::lock_page(page_size) => prev,
+            _ => commit,
+        }
+    }
+
+    /// Return the page numbers of a snapshot of a database of `commit` pages, i.e.
+    /// `1..=commit` without the lock page, in the order expected by the encoder.
+    pub fn snapshot_range(page_size: PageSize, commit: PageNum) -> impl Iterator<Item = PageNum> {
+        let lock_page = PageNum::lock_page(page_size);
+        (1..=commit.into_inner())
+            .filter_map(|n| PageNum::new(n).ok())
+            .filter(move |&pgno| pgno != lock_page)
+    }
 }
 
 impl TryFrom<u32> for PageNum {
@@ -194,12 +307,20 @@ impl TryFrom<u32> for PageNum {
     }
 }
 
+impl From<PageNum> for u32 {
+    fn from(pgno: PageNum) -> Self {
+        pgno.into_inner()
+    }
+}
+
+#[cfg(feature = "std")]
 impl From<PageNum> for PathBuf {
     fn from(pgno: PageNum) -> Self {
         format!("{:08x}", pgno.0.get()).into()
     }
 }
 
+#[cfg(feature = "std")]
 impl TryFrom<&Path> for PageNum {
     type Error = PageNumError;
 
@@ -220,10 +341,7 @@ impl ops::Add<u32> for PageNum {
     type Output = PageNum;
 
     fn add(self, rhs: u32) -> Self::Output {
-        let sum = self.into_inner() + rhs;
-        assert!(sum != 0, "page number overflow");
-
-        PageNum(unsafe { num::NonZeroU32::new_unchecked(sum) })
+        self.checked_add(rhs).expect("page number overflow")
     }
 }
 
@@ -255,9 +373,45 @@ impl fmt::Display for Pos {
     }
 }
 
+impl str::FromStr for Pos {
+    type Err = PosParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (txid, checksum) = s.split_once('/').ok_or(PosParseError::Separator)?;
+
+        Ok(Pos {
+            txid: TXID::try_from(txid.to_owned())?,
+            post_apply_checksum: Checksum::try_from(checksum.to_owned())?,
+        })
+    }
+}
+
+impl TryFrom<&str> for Pos {
+    type Error = PosParseError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+/// An error representing an invalid database position string.
+#[derive(thiserror::Error, Debug)]
+pub enum PosParseError {
+    #[error("missing '/' separator")]
+    Separator,
+    #[error("invalid transaction ID")]
+    TXID(#[from] TXIDError),
+    #[error("invalid checksum")]
+    Checksum(#[from] ChecksumError),
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Checksum, PageNum, PageNumError, PageSize, PageSizeError, Pos, TXIDError, TXID};
+    use super::{
+        Checksum, PageNum, PageNumError, PageSize, PageSizeError, Pos, PosParseError, TXIDError,
+        TXID,
+    };
+    use crate::PageChecksum;
     use serde_test::{assert_de_tokens, assert_tokens, Token};
     use std::path::{Path, PathBuf};
 
@@ -268,12 +422,50 @@ mod tests {
         assert_eq!("000000000000000a", format!("{}", TXID::new(10).unwrap()))
     }
 
+    #[test]
+    fn txid_arithmetic() {
+        let txid = TXID::new(10).unwrap();
+        assert_eq!(TXID::new(15).unwrap(), txid + 5);
+        assert_eq!(Some(TXID::new(15).unwrap()), txid.checked_add(5));
+        assert_eq!(None, txid.checked_add(u64::MAX));
+        assert_eq!(TXID::new(u64::MAX).unwrap(), txid.saturating_add(u64::MAX));
+        assert_eq!(7, txid - TXID::new(3).unwrap());
+        assert_eq!(0, txid - txid);
+        assert!(matches!(TXID::try_from(0u64), Err(TXIDError::Zero)));
+        assert_eq!(txid, TXID::try_from(10u64).unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "TX ID overflow")]
+    fn txid_overflow() {
+        let _ = TXID::new(u64::MAX).unwrap() + 1;
+    }
+
+    #[test]
+    #[should_panic(expected = "TX ID underflow")]
+    fn txid_underflow() {
+        let _ = TXID::ONE - TXID::new(2).unwrap();
+    }
+
     #[test]
     fn checksum() {
         assert_eq!(1 | Checksum::NON_ZERO_FLAG, Checksum::new(1).into_inner());
         assert_eq!(Checksum::NON_ZERO_FLAG, Checksum::new(0).into_inner());
     }
 
+    #[test]
+    fn checksum_apply_page_change() {
+        let (page1, page2) = (vec![1; 512], vec![2; 512]);
+        let pgno = PageNum::new(3).unwrap();
+
+        let checksum = Checksum::new(0x123) ^ page1.page_checksum(pgno);
+        assert_eq!(
+            Checksum::new(0x123) ^ page2.page_checksum(pgno),
+            checksum.apply_page_change(pgno, &page1, &page2)
+        );
+        assert_eq!(checksum, checksum.apply_page_change(pgno, &page1, &page1));
+    }
+
     #[test]
     fn page_size() {
         assert_eq!(512, PageSize::new(512).unwrap().into_inner());
@@ -283,10 +475,32 @@ mod tests {
         assert!(matches!(PageSize::new(131072), Err(PageSizeError(131072))));
     }
 
+    #[test]
+    fn page_size_sqlite_encoding() {
+        let max = PageSize::new(65536).unwrap();
+        assert_eq!(max, PageSize::from_sqlite_encoding(1).unwrap());
+        assert_eq!(1, max.to_sqlite_encoding());
+
+        let page_size = PageSize::new(4096).unwrap();
+        assert_eq!(page_size, PageSize::from_sqlite_encoding(4096).unwrap());
+        assert_eq!(4096, page_size.to_sqlite_encoding());
+
+        assert!(matches!(
+            PageSize::from_sqlite_encoding(0),
+            Err(PageSizeError(0))
+        ));
+    }
+
     #[test]
     fn page_num() {
         assert_eq!(10, PageNum::new(10).unwrap().into_inner());
         assert!(matches!(PageNum::new(0), Err(PageNumError)));
+        assert_eq!(PageNum::new(12).unwrap(), PageNum::new(10).unwrap() + 2);
+        assert_eq!(None, PageNum::new(10).unwrap().checked_add(u32::MAX));
+        assert_eq!(
+            PageNum::new(u32::MAX).unwrap(),
+            PageNum::new(10).unwrap().saturating_add(u32::MAX)
+        );
 
         assert_eq!(
             Path::new("000000ff"),
@@ -299,6 +513,28 @@ mod tests {
         );
     }
 
+    #[test]
+    fn page_num_snapshot_range() {
+        let page_size = PageSize::new(65536).unwrap();
+        let lock_page = PageNum::lock_page(page_size);
+
+        let pages: Vec<_> = PageNum::snapshot_range(page_size, PageNum::new(3).unwrap()).collect();
+        assert_eq!(
+            vec![
+                PageNum::ONE,
+                PageNum::new(2).unwrap(),
+                PageNum::new(3).unwrap()
+            ],
+            pages
+        );
+
+        let commit = lock_page + 2;
+        let pages: Vec<_> = PageNum::snapshot_range(page_size, commit).collect();
+        assert_eq!(commit.into_inner() as usize - 1, pages.len());
+        assert!(!pages.contains(&lock_page));
+        assert_eq!(Some(&commit), pages.last());
+    }
+
     #[test]
     fn pos_ser_de() {
         let pos = Pos {
@@ -322,6 +558,32 @@ mod tests {
         );
     }
 
+    #[test]
+    fn pos_parse() {
+        let pos = Pos {
+            txid: TXID::new(0x123).unwrap(),
+            post_apply_checksum: Checksum::new(0x456),
+        };
+
+        assert_eq!(pos, pos.to_string().parse().unwrap());
+        assert_eq!(
+            pos,
+            Pos::try_from("0000000000000123/8000000000000456").unwrap()
+        );
+        assert!(matches!(
+            "0000000000000123".parse::<Pos>(),
+            Err(PosParseError::Separator)
+        ));
+        assert!(matches!(
+            "0000000000000000/8000000000000456".parse::<Pos>(),
+            Err(PosParseError::TXID(TXIDError::Zero))
+        ));
+        assert!(matches!(
+            "0000000000000123/xyz".parse::<Pos>(),
+            Err(PosParseError::Checksum(_))
+        ));
+    }
+
     #[test]
     fn page_num_de() {
         let pgnum = PageNum::new(123).unwrap();
@@ -1,4 +1,8 @@
-use std::time;
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    time,
+};
 
 // Provides a convenience method to round time to specific resolution.
 pub(crate) trait TimeRound {
@@ -22,6 +26,27 @@ impl TimeRound for time::SystemTime {
     }
 }
 
+/// A temporary directory removed on drop.
+pub(crate) struct TempDir(PathBuf);
+
+impl TempDir {
+    pub(crate) fn new() -> TempDir {
+        let dir = env::temp_dir().join(format!("litetx-{:016x}", rand::random::<u64>()));
+        fs::create_dir(&dir).expect("failed to create temp dir");
+        TempDir(dir)
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::TimeRound;
@@ -0,0 +1,135 @@
+use crate::{decoder::Error as DecodeError, Checksum, DatabaseChecksum, Decoder, Header, Trailer};
+use std::io;
+
+/// An error that can be returned by [`verify`].
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("decode")]
+    Decode(#[from] DecodeError),
+    #[error("post-apply checksum mismatch: {0}, expected {1}")]
+    PostApplyChecksumMismatch(Checksum, Checksum),
+}
+
+/// Decode the whole LTX file read from `r` and verify its integrity.
+///
+/// The file is decoded in [strict mode](crate::DecoderBuilder::strict), so data after
+/// the trailer is an error. The file checksum is always verified. For snapshots, the
+/// post-apply checksum is additionally recomputed from the page contents and compared
+/// with the trailer, unless the file has no checksums.
+///
+/// # Example
+/// ```no_run
+/// # let v = Vec::new();
+/// # let r = &v[..];
+/// let (header, trailer) = litetx::verify(r).expect("verify");
+/// ```
+pub fn verify<R>(r: R) -> Result<(Header, Trailer), Error>
+where
+    R: io::Read,
+{
+    let (mut dec, header) = Decoder::builder().strict().build(r)?;
+
+    let mut checksum = DatabaseChecksum::new();
+    while let Some((page_num, page)) = dec.decode_page_ref()? {
+        checksum.add_page(page_num, page);
+    }
+    let checksum = checksum.finish();
+
+    let trailer = dec.finish()?;
+
+    match trailer.post_apply_checksum {
+        Some(expected) if header.is_snapshot() && checksum != expected => {
+            return Err(Error::PostApplyChecksumMismatch(checksum, expected));
+        }
+        _ => (),
+    }
+
+    Ok((header, trailer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify, Error};
+    use crate::{
+        decoder::Error as DecodeError, utils::TimeRound, Checksum, DatabaseChecksum, Encoder,
+        Header, HeaderFlags, PageNum, PageSize, TXID,
+    };
+    use std::time;
+
+    fn encode_snapshot(post_apply_checksum: Option<Checksum>) -> (Header, Vec<u8>) {
+        let header = Header {
+            flags: HeaderFlags::COMPRESS_LZ4,
+            page_size: PageSize::new(4096).unwrap(),
+            commit: Some(PageNum::new(2).unwrap()),
+            min_txid: TXID::ONE,
+            max_txid: TXID::ONE,
+            timestamp: time::SystemTime::now()
+                .round(time::Duration::from_millis(1))
+                .unwrap(),
+            pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        };
+
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(&mut buf, &header).expect("failed to create encoder");
+        let mut checksum = DatabaseChecksum::new();
+        for page_num in 1..=2 {
+            let page_num = PageNum::new(page_num).unwrap();
+            let page: Vec<u8> = (0..4096).map(|_| rand::random::<u8>()).collect();
+            enc.encode_page(page_num, &page)
+                .expect("failed to encode page");
+            checksum.add_page(page_num, &page);
+        }
+        enc.finish(post_apply_checksum.unwrap_or(checksum.finish()))
+            .expect("failed to finish encoder");
+
+        (header, buf)
+    }
+
+    #[test]
+    fn verify_snapshot() {
+        let (header, buf) = encode_snapshot(None);
+
+        let (header_out, _) = verify(buf.as_slice()).expect("failed to verify");
+        assert_eq!(header, header_out);
+    }
+
+    #[test]
+    fn verify_post_apply_checksum_mismatch() {
+        let (_, buf) = encode_snapshot(Some(Checksum::new(1)));
+
+        assert!(matches!(
+            verify(buf.as_slice()),
+            Err(Error::PostApplyChecksumMismatch(_, e)) if e == Checksum::new(1)
+        ));
+    }
+
+    #[test]
+    fn verify_file_checksum_mismatch() {
+        let (_, mut buf) = encode_snapshot(None);
+        let len = buf.len();
+        buf[len - 1] ^= 1;
+
+        assert!(matches!(
+            verify(buf.as_slice()),
+            Err(Error::Decode(e)) if matches!(e.inner(), DecodeError::FileChecksumMismatch)
+        ));
+    }
+
+    #[test]
+    fn verify_trailing_data() {
+        let (_, mut buf) = encode_snapshot(None);
+        let len = buf.len();
+        buf.push(0);
+
+        assert!(matches!(
+            verify(buf.as_slice()),
+            Err(Error::Decode(DecodeError::TrailingData(offset))) if offset == len as u64
+        ));
+    }
+}
@@ -0,0 +1,768 @@
+//! Conversion between SQLite WAL files and LTX files.
+
+use crate::{
+    apply::read_pages, decoder::Error as DecodeError, encoder::Error as EncodeError,
+    types::PageSizeError, DatabaseChecksum, Decoder, Encoder, Header, HeaderFlags, PageNum,
+    PageSize, Pos, Trailer, WalFrames,
+};
+use std::{collections::BTreeMap, io, time};
+
+const WAL_MAGIC_LE: u32 = 0x377f0682;
+const WAL_MAGIC_BE: u32 = 0x377f0683;
+const WAL_VERSION: u32 = 3007000;
+const WAL_HEADER_SIZE: usize = 32;
+const WAL_FRAME_HEADER_SIZE: usize = 24;
+
+/// An error that can be returned while converting a WAL file.
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("invalid WAL header magic: {0:x}")]
+    Magic(u32),
+    #[error("unsupported WAL format version: {0}")]
+    Version(u32),
+    #[error("invalid WAL page size")]
+    PageSize(#[from] PageSizeError),
+    #[error("WAL header checksum mismatch")]
+    HeaderChecksum,
+    #[error("WAL page size {0} doesn't match the database page size")]
+    PageSizeMismatch(PageSize),
+    #[error("WAL contains no committed transactions")]
+    NoCommit,
+    #[error("LTX file contains no pages")]
+    NoPages,
+    #[error("decode")]
+    Decode(#[from] DecodeError),
+    #[error("encode")]
+    Encode(#[from] EncodeError),
+    #[error("io")]
+    Io(#[from] io::Error),
+}
+
+/// Options of the LTX file produced by [`encode_delta`].
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// Flags of the resulting LTX file.
+    pub flags: HeaderFlags,
+    /// The time when the LTX file was created.
+    pub timestamp: time::SystemTime,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            flags: HeaderFlags::empty(),
+            timestamp: time::SystemTime::now(),
+        }
+    }
+}
+
+/// A parsed SQLite WAL header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct WalHeader {
+    big_endian: bool,
+    page_size: PageSize,
+    salt: (u32, u32),
+    checksum: (u32, u32),
+}
+
+impl WalHeader {
+    fn decode(buf: &[u8; WAL_HEADER_SIZE]) -> Result<WalHeader, Error> {
+        let word = |i: usize| u32::from_be_bytes(buf[i..i + 4].try_into().unwrap());
+
+        let big_endian = match word(0) {
+            WAL_MAGIC_LE => false,
+            WAL_MAGIC_BE => true,
+            magic => return Err(Error::Magic(magic)),
+        };
+        if word(4) != WAL_VERSION {
+            return Err(Error::Version(word(4)));
+        }
+
+        let checksum = (word(24), word(28));
+        if wal_checksum(big_endian, &buf[..24], (0, 0)) != checksum {
+            return Err(Error::HeaderChecksum);
+        }
+
+        Ok(WalHeader {
+            big_endian,
+            page_size: PageSize::new(word(8))?,
+            salt: (word(16), word(20)),
+            checksum,
+        })
+    }
+
+    /// Create a big-endian header of a new WAL file.
+    fn new(page_size: PageSize, salt: (u32, u32)) -> WalHeader {
+        let mut hdr = WalHeader {
+            big_endian: true,
+            page_size,
+            salt,
+            checksum: (0, 0),
+        };
+        hdr.checksum = wal_checksum(true, &hdr.encode()[..24], (0, 0));
+        hdr
+    }
+
+    fn encode(&self) -> [u8; WAL_HEADER_SIZE] {
+        let magic = if self.big_endian {
+            WAL_MAGIC_BE
+        } else {
+            WAL_MAGIC_LE
+        };
+        let words = [
+            magic,
+            WAL_VERSION,
+            self.page_size.into_inner(),
+            0,
+            self.salt.0,
+            self.salt.1,
+            self.checksum.0,
+            self.checksum.1,
+        ];
+
+        let mut buf = [0; WAL_HEADER_SIZE];
+        for (chunk, word) in buf.chunks_exact_mut(4).zip(words) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        buf
+    }
+}
+
+/// Calculate the cumulative WAL checksum of `data` starting from `s`.
+fn wal_checksum(big_endian: bool, data: &[u8], s: (u32, u32)) -> (u32, u32) {
+    let word = |b: &[u8]| {
+        let b = b.try_into().unwrap();
+        if big_endian {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        }
+    };
+
+    let (mut s0, mut s1) = s;
+    for chunk in data.chunks_exact(8) {
+        s0 = s0.wrapping_add(word(&chunk[0..4])).wrapping_add(s1);
+        s1 = s1.wrapping_add(word(&chunk[4..8])).wrapping_add(s0);
+    }
+
+    (s0, s1)
+}
+
+/// The last committed version of every page along with the resulting database size
+/// and the number of committed frames.
+type CommittedPages = (BTreeMap<u32, Vec<u8>>, PageNum, u64);
+
+/// Read the last committed version of every page from the WAL.
+///
+/// Reading stops at the first frame with mismatching salt or checksum, as SQLite does
+/// during recovery. Returns `None` if the WAL has no committed transactions.
+fn read_committed_pages<R>(mut r: R, hdr: &WalHeader) -> io::Result<Option<CommittedPages>>
+where
+    R: io::Read,
+{
+    let page_size = hdr.page_size.into_inner() as usize;
+    let mut committed = BTreeMap::new();
+    let mut pending = BTreeMap::new();
+    let mut commit = None;
+    let (mut frames, mut committed_frames) = (0, 0);
+    let mut checksum = hdr.checksum;
+
+    let mut frame_header = [0; WAL_FRAME_HEADER_SIZE];
+    loop {
+        let mut page = vec![0; page_size];
+        match r
+            .read_exact(&mut frame_header)
+            .and_then(|_| r.read_exact(&mut page))
+        {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+            Ok(()) => (),
+        }
+
+        let word = |i: usize| u32::from_be_bytes(frame_header[i..i + 4].try_into().unwrap());
+        if (word(8), word(12)) != hdr.salt {
+            break;
+        }
+        checksum = wal_checksum(hdr.big_endian, &frame_header[..8], checksum);
+        checksum = wal_checksum(hdr.big_endian, &page, checksum);
+        if checksum != (word(16), word(20)) {
+            break;
+        }
+
+        let Ok(page_num) = PageNum::new(word(0)) else {
+            break;
+        };
+        pending.insert(page_num.into_inner(), page);
+        frames += 1;
+
+        if let Ok(db_size) = PageNum::new(word(4)) {
+            committed.append(&mut pending);
+            commit = Some(db_size);
+            committed_frames = frames;
+        }
+    }
+
+    Ok(commit.map(|commit| (committed, commit, committed_frames)))
+}
+
+/// Convert the committed transactions of a SQLite WAL file into a delta LTX file.
+///
+/// The LTX file written into `w` contains the last committed version of every page
+/// in the WAL read from `wal`, and is meant to be applied on top of the database
+/// state at `prev`. The database file `db` must be in that state and is used to
+/// calculate the post-apply checksum, unless the [`HeaderFlags::NO_CHECKSUM`] flag is
+/// set.
+///
+/// Returns the trailer of the resulting LTX file, whose transaction ID is the one
+/// following `prev.txid`. The position of the committed WAL frames is recorded in the
+/// header of the file.
+pub fn encode_delta<L, D, W>(
+    mut wal: L,
+    mut db: D,
+    w: W,
+    prev: Pos,
+    opts: Options,
+) -> Result<Trailer, Error>
+where
+    L: io::Read,
+    D: io::Read + io::Seek,
+    W: io::Write,
+{
+    let mut buf = [0; WAL_HEADER_SIZE];
+    wal.read_exact(&mut buf)?;
+    let hdr = WalHeader::decode(&buf)?;
+
+    let (pages, commit, frames) = read_committed_pages(wal, &hdr)?.ok_or(Error::NoCommit)?;
+
+    let page_size = hdr.page_size.into_inner() as u64;
+    let db_len = db.seek(io::SeekFrom::End(0))?;
+    if db_len % page_size != 0 {
+        return Err(Error::PageSizeMismatch(hdr.page_size));
+    }
+    let db_pages = db_len / page_size;
+
+    let no_checksum = opts.flags.contains(HeaderFlags::NO_CHECKSUM);
+    let txid = prev
+        .txid
+        .checked_add(1)
+        .ok_or(EncodeError::TXIDOverflow(prev.txid))?;
+    let mut enc = Encoder::new(
+        w,
+        &Header {
+            flags: opts.flags,
+            page_size: hdr.page_size,
+            commit: Some(commit),
+            min_txid: txid,
+            max_txid: txid,
+            timestamp: opts.timestamp,
+            pre_apply_checksum: (!no_checksum).then_some(prev.post_apply_checksum),
+            node_id: 0,
+            wal: Some(WalFrames {
+                offset: WAL_HEADER_SIZE as u64,
+                size: frames * (WAL_FRAME_HEADER_SIZE as u64 + page_size),
+                salt1: hdr.salt.0,
+                salt2: hdr.salt.1,
+            }),
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
+        },
+    )?;
+
+    let mut checksum = DatabaseChecksum::from(prev.post_apply_checksum);
+    let mut old_page = vec![0; page_size as usize];
+    for (page_num, page) in pages.range(..=commit.into_inner()) {
+        let page_num = PageNum::new(*page_num).unwrap();
+
+        if !no_checksum && (page_num.into_inner() as u64) <= db_pages {
+            db.seek(io::SeekFrom::Start(
+                (page_num.into_inner() as u64 - 1) * page_size,
+            ))?;
+            db.read_exact(&mut old_page)?;
+            checksum.replace_page(page_num, &old_page, page);
+        } else {
+            checksum.add_page(page_num, page);
+        }
+
+        enc.encode_page(page_num, page)?;
+    }
+
+    // Pages beyond the new database size are dropped.
+    let commit = commit.into_inner() as u64;
+    if !no_checksum && commit < db_pages {
+        db.seek(io::SeekFrom::Start(commit * page_size))?;
+        read_pages(
+            &mut db,
+            hdr.page_size,
+            commit + 1..=db_pages,
+            |pgno, page| checksum.remove_page(pgno, page),
+        )?;
+    }
+
+    Ok(enc.finish((!no_checksum).then_some(checksum.finish()))?)
+}
+
+/// Write the pages of the LTX file read from `ltx` into `w` as a new SQLite WAL file
+/// holding a single transaction.
+///
+/// Once the WAL file is placed next to a database in the state preceding the LTX file,
+/// SQLite applies the pages on its own, first to readers and then to the database file
+/// when it checkpoints the WAL. The pre-apply checksum isn't verified against the
+/// database. The commit frame truncates the database to the commit of the LTX file,
+/// which therefore must contain at least one page.
+///
+/// `salt` is written into the WAL header and every frame, and should be random.
+/// SQLite only reads WAL files written by other processes when it recovers the WAL,
+/// i.e. when the database is opened with no shared memory index (`-shm` file) in
+/// place, so the WAL file must not be swapped under open connections.
+///
+/// The file checksum of the LTX file is verified after all frames are written, so the
+/// output must be discarded on error. Returns the position of the frames in the WAL.
+pub fn to_wal<R, W>(ltx: R, salt: (u32, u32), mut w: W) -> Result<WalFrames, Error>
+where
+    R: io::Read,
+    W: io::Write,
+{
+    let (mut dec, header) = Decoder::new(ltx)?;
+    let commit = header.commit.ok_or(Error::NoPages)?;
+
+    let hdr = WalHeader::new(header.page_size, salt);
+    w.write_all(&hdr.encode())?;
+
+    let mut checksum = hdr.checksum;
+    let mut write_frame = |page_num: PageNum, db_size: u32, page: &[u8]| {
+        let mut frame_header = [0; WAL_FRAME_HEADER_SIZE];
+        for (chunk, word) in
+            frame_header
+                .chunks_exact_mut(4)
+                .zip([page_num.into_inner(), db_size, salt.0, salt.1])
+        {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        checksum = wal_checksum(true, &frame_header[..8], checksum);
+        checksum = wal_checksum(true, page, checksum);
+        frame_header[16..20].copy_from_slice(&checksum.0.to_be_bytes());
+        frame_header[20..24].copy_from_slice(&checksum.1.to_be_bytes());
+
+        w.write_all(&frame_header)?;
+        w.write_all(page)
+    };
+
+    // The last frame carries the database size, so every frame is written once the
+    // next one is known.
+    let mut frames = 0;
+    let mut last: Option<(PageNum, Vec<u8>)> = None;
+    while let Some((page_num, page)) = dec.decode_page_ref()? {
+        match &mut last {
+            Some((last_num, last_page)) => {
+                write_frame(*last_num, 0, last_page)?;
+                *last_num = page_num;
+                last_page.copy_from_slice(page);
+            }
+            None => last = Some((page_num, page.to_vec())),
+        }
+        frames += 1;
+    }
+    let (page_num, page) = last.ok_or(Error::NoPages)?;
+    write_frame(page_num, commit.into_inner(), &page)?;
+    dec.finish()?;
+
+    Ok(WalFrames {
+        offset: WAL_HEADER_SIZE as u64,
+        size: frames * (WAL_FRAME_HEADER_SIZE as u64 + header.page_size.into_inner() as u64),
+        salt1: salt.0,
+        salt2: salt.1,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        encode_delta, read_committed_pages, to_wal, wal_checksum, Error, Options, WalHeader,
+        WAL_HEADER_SIZE, WAL_MAGIC_BE, WAL_VERSION,
+    };
+    use crate::{
+        apply::apply_ltx, encoder::Error as EncodeError, Checksum, DatabaseChecksum, Decoder,
+        Encoder, Header, HeaderFlags, PageNum, PageSize, Pos, WalFrames, TXID,
+    };
+    use std::{env, fs, io, path::PathBuf, time};
+
+    struct TestWal {
+        buf: Vec<u8>,
+        salt: (u32, u32),
+        checksum: (u32, u32),
+    }
+
+    impl TestWal {
+        fn new() -> TestWal {
+            let mut buf = Vec::new();
+            for word in [WAL_MAGIC_BE, WAL_VERSION, 512, 0, 11, 22] {
+                buf.extend_from_slice(&word.to_be_bytes());
+            }
+            let checksum = wal_checksum(true, &buf, (0, 0));
+            buf.extend_from_slice(&checksum.0.to_be_bytes());
+            buf.extend_from_slice(&checksum.1.to_be_bytes());
+
+            TestWal {
+                buf,
+                salt: (11, 22),
+                checksum,
+            }
+        }
+
+        fn frame(&mut self, page_num: u32, db_size: u32, fill: u8) {
+            let mut header = Vec::new();
+            for word in [page_num, db_size, self.salt.0, self.salt.1] {
+                header.extend_from_slice(&word.to_be_bytes());
+            }
+            let page = [fill; 512];
+            self.checksum = wal_checksum(true, &header[..8], self.checksum);
+            self.checksum = wal_checksum(true, &page, self.checksum);
+            header.extend_from_slice(&self.checksum.0.to_be_bytes());
+            header.extend_from_slice(&self.checksum.1.to_be_bytes());
+
+            self.buf.extend_from_slice(&header);
+            self.buf.extend_from_slice(&page);
+        }
+    }
+
+    fn db_checksum(db: &[u8]) -> Checksum {
+        db_checksum_with_page_size(db, 512)
+    }
+
+    fn db_checksum_with_page_size(db: &[u8], page_size: usize) -> Checksum {
+        let mut checksum = DatabaseChecksum::new();
+        for (page, page_num) in db.chunks(page_size).zip(1..) {
+            checksum.add_page(PageNum::new(page_num).unwrap(), page);
+        }
+        checksum.finish()
+    }
+
+    #[test]
+    fn wal_to_ltx() {
+        let db: Vec<u8> = (0..3).flat_map(|n| [n as u8; 512]).collect();
+        let prev = Pos {
+            txid: TXID::new(4).unwrap(),
+            post_apply_checksum: db_checksum(&db),
+        };
+
+        let mut wal = TestWal::new();
+        wal.frame(2, 0, 0xa);
+        wal.frame(4, 4, 0xb);
+        wal.frame(2, 0, 0xc);
+        wal.frame(1, 4, 0xd);
+        // Uncommitted frame.
+        wal.frame(3, 0, 0xe);
+
+        let mut buf = Vec::new();
+        let trailer = encode_delta(
+            wal.buf.as_slice(),
+            io::Cursor::new(&db),
+            &mut buf,
+            prev,
+            Options::default(),
+        )
+        .expect("failed to encode delta");
+
+        let expected: Vec<u8> = [0xd, 0xc, 2, 0xb]
+            .into_iter()
+            .flat_map(|n| [n as u8; 512])
+            .collect();
+        assert_eq!(Some(db_checksum(&expected)), trailer.post_apply_checksum);
+
+        let (mut dec, header) = Decoder::new(buf.as_slice()).expect("failed to create decoder");
+        assert_eq!(TXID::new(5).unwrap(), header.min_txid);
+        assert_eq!(TXID::new(5).unwrap(), header.max_txid);
+        assert_eq!(Some(PageNum::new(4).unwrap()), header.commit);
+        assert_eq!(Some(db_checksum(&db)), header.pre_apply_checksum);
+        assert_eq!(
+            Some(WalFrames {
+                offset: 32,
+                size: 4 * (24 + 512),
+                salt1: 11,
+                salt2: 22,
+            }),
+            header.wal
+        );
+
+        let mut page = vec![0; 512];
+        let mut pages = Vec::new();
+        while let Some(page_num) = dec.decode_page(&mut page).expect("failed to decode page") {
+            pages.push((page_num.into_inner(), page[0]));
+        }
+        assert_eq!(vec![(1, 0xd), (2, 0xc), (4, 0xb)], pages);
+        dec.finish().expect("failed to finish decoder");
+    }
+
+    #[test]
+    fn wal_shrink_and_invalid_frames() {
+        let db: Vec<u8> = (0..3).flat_map(|n| [n as u8; 512]).collect();
+        let prev = Pos {
+            txid: TXID::new(4).unwrap(),
+            post_apply_checksum: db_checksum(&db),
+        };
+
+        let mut wal = TestWal::new();
+        wal.frame(1, 2, 0xa);
+        // A frame from a previous WAL generation ends the log.
+        wal.salt.0 += 1;
+        wal.frame(2, 3, 0xb);
+
+        let mut buf = Vec::new();
+        let trailer = encode_delta(
+            wal.buf.as_slice(),
+            io::Cursor::new(&db),
+            &mut buf,
+            prev,
+            Options::default(),
+        )
+        .expect("failed to encode delta");
+
+        let mut expected = vec![0xa; 512];
+        expected.extend_from_slice(&[1; 512]);
+        assert_eq!(Some(db_checksum(&expected)), trailer.post_apply_checksum);
+    }
+
+    #[test]
+    fn wal_no_commit() {
+        let mut wal = TestWal::new();
+        wal.frame(1, 0, 0xa);
+
+        assert!(matches!(
+            encode_delta(
+                wal.buf.as_slice(),
+                io::Cursor::new(Vec::new()),
+                Vec::new(),
+                Pos {
+                    txid: TXID::ONE,
+                    post_apply_checksum: Checksum::new(0),
+                },
+                Options::default(),
+            ),
+            Err(Error::NoCommit)
+        ));
+    }
+
+    #[test]
+    fn wal_txid_overflow() {
+        let mut wal = TestWal::new();
+        wal.frame(1, 1, 0xa);
+        let max = TXID::new(u64::MAX).unwrap();
+
+        assert!(matches!(
+            encode_delta(
+                wal.buf.as_slice(),
+                io::Cursor::new(Vec::new()),
+                Vec::new(),
+                Pos {
+                    txid: max,
+                    post_apply_checksum: Checksum::new(0),
+                },
+                Options::default(),
+            ),
+            Err(Error::Encode(EncodeError::TXIDOverflow(txid))) if txid == max
+        ));
+    }
+
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> TempDir {
+            let dir = env::temp_dir().join(format!("litetx-{:016x}", rand::random::<u64>()));
+            fs::create_dir(&dir).expect("failed to create temp dir");
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn sqlite_wal() {
+        let dir = TempDir::new();
+        let db_path = dir.0.join("db");
+        let conn = rusqlite::Connection::open(&db_path).expect("failed to open database");
+        conn.pragma_update(None, "page_size", 4096)
+            .expect("failed to set page size");
+        conn.pragma_update(None, "journal_mode", "wal")
+            .expect("failed to enable WAL");
+        conn.pragma_update(None, "wal_autocheckpoint", 0)
+            .expect("failed to disable checkpoints");
+        conn.execute("CREATE TABLE t (data BLOB)", ())
+            .expect("failed to create table");
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+            .expect("failed to checkpoint");
+
+        let db = fs::read(&db_path).expect("failed to read database");
+        for _ in 0..50 {
+            let data: Vec<u8> = (0..300).map(|_| rand::random::<u8>()).collect();
+            conn.execute("INSERT INTO t (data) VALUES (?)", [data])
+                .expect("failed to insert");
+        }
+        let wal = fs::read(dir.0.join("db-wal")).expect("failed to read WAL");
+
+        let prev = Pos {
+            txid: TXID::ONE,
+            post_apply_checksum: db_checksum_with_page_size(&db, 4096),
+        };
+        let mut ltx = Vec::new();
+        encode_delta(
+            wal.as_slice(),
+            io::Cursor::new(&db),
+            &mut ltx,
+            prev,
+            Options::default(),
+        )
+        .expect("failed to encode delta");
+
+        let replica_path = dir.0.join("replica");
+        fs::write(&replica_path, &db).expect("failed to write replica");
+        apply_ltx(&replica_path, ltx.as_slice()).expect("failed to apply delta");
+
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+            .expect("failed to checkpoint");
+        assert_eq!(
+            fs::read(&db_path).expect("failed to read database"),
+            fs::read(&replica_path).expect("failed to read replica")
+        );
+    }
+
+    fn encode_ltx(commit: Option<u32>, pages: &[(u32, u8)]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(
+            &mut buf,
+            &Header {
+                flags: HeaderFlags::COMPRESS_LZ4,
+                page_size: PageSize::new(512).unwrap(),
+                commit: commit.map(|c| PageNum::new(c).unwrap()),
+                min_txid: TXID::new(2).unwrap(),
+                max_txid: TXID::new(2).unwrap(),
+                timestamp: time::SystemTime::now(),
+                pre_apply_checksum: Some(Checksum::new(1)),
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder");
+        for &(page_num, fill) in pages {
+            enc.encode_page(PageNum::new(page_num).unwrap(), &[fill; 512])
+                .expect("failed to encode page");
+        }
+        enc.finish(Checksum::new(2))
+            .expect("failed to finish encoder");
+        buf
+    }
+
+    #[test]
+    fn ltx_to_wal() {
+        let ltx = encode_ltx(Some(5), &[(1, 0xa), (3, 0xb), (5, 0xc)]);
+
+        let mut wal = Vec::new();
+        let frames = to_wal(ltx.as_slice(), (11, 22), &mut wal).expect("failed to write WAL");
+        assert_eq!(
+            WalFrames {
+                offset: 32,
+                size: 3 * (24 + 512),
+                salt1: 11,
+                salt2: 22,
+            },
+            frames
+        );
+        assert_eq!(frames.offset + frames.size, wal.len() as u64);
+
+        let hdr = WalHeader::decode(wal[..WAL_HEADER_SIZE].try_into().unwrap())
+            .expect("failed to decode WAL header");
+        assert_eq!((11, 22), hdr.salt);
+        let (pages, commit, committed_frames) = read_committed_pages(&wal[WAL_HEADER_SIZE..], &hdr)
+            .expect("failed to read WAL")
+            .expect("no committed transaction");
+        assert_eq!(PageNum::new(5).unwrap(), commit);
+        assert_eq!(3, committed_frames);
+        assert_eq!(
+            vec![(1, 0xa), (3, 0xb), (5, 0xc)],
+            pages.iter().map(|(n, p)| (*n, p[0])).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn ltx_to_wal_no_pages() {
+        for ltx in [encode_ltx(Some(2), &[]), encode_ltx(None, &[])] {
+            assert!(matches!(
+                to_wal(ltx.as_slice(), (1, 2), Vec::new()),
+                Err(Error::NoPages)
+            ));
+        }
+    }
+
+    #[test]
+    fn sqlite_ltx_to_wal() {
+        let dir = TempDir::new();
+        let db_path = dir.0.join("db");
+        let conn = rusqlite::Connection::open(&db_path).expect("failed to open database");
+        conn.pragma_update(None, "journal_mode", "wal")
+            .expect("failed to enable WAL");
+        conn.pragma_update(None, "wal_autocheckpoint", 0)
+            .expect("failed to disable checkpoints");
+        conn.execute("CREATE TABLE t (data BLOB)", ())
+            .expect("failed to create table");
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+            .expect("failed to checkpoint");
+
+        let db = fs::read(&db_path).expect("failed to read database");
+        for _ in 0..50 {
+            let data: Vec<u8> = (0..300).map(|_| rand::random::<u8>()).collect();
+            conn.execute("INSERT INTO t (data) VALUES (?)", [data])
+                .expect("failed to insert");
+        }
+        let wal = fs::read(dir.0.join("db-wal")).expect("failed to read WAL");
+
+        let mut ltx = Vec::new();
+        encode_delta(
+            wal.as_slice(),
+            io::Cursor::new(&db),
+            &mut ltx,
+            Pos {
+                txid: TXID::ONE,
+                post_apply_checksum: db_checksum_with_page_size(&db, 4096),
+            },
+            Options::default(),
+        )
+        .expect("failed to encode delta");
+
+        let replica_path = dir.0.join("replica");
+        fs::write(&replica_path, &db).expect("failed to write replica");
+        let replica_wal =
+            fs::File::create(dir.0.join("replica-wal")).expect("failed to create replica WAL");
+        to_wal(
+            ltx.as_slice(),
+            (rand::random(), rand::random()),
+            replica_wal,
+        )
+        .expect("failed to write WAL");
+
+        let replica = rusqlite::Connection::open(&replica_path).expect("failed to open replica");
+        let count: u32 = replica
+            .query_row("SELECT COUNT(*) FROM t", [], |row| row.get(0))
+            .expect("failed to query replica");
+        assert_eq!(50, count);
+        replica
+            .query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+            .expect("failed to checkpoint replica");
+        drop(replica);
+
+        conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))
+            .expect("failed to checkpoint");
+        assert_eq!(
+            fs::read(&db_path).expect("failed to read database"),
+            fs::read(&replica_path).expect("failed to read replica")
+        );
+    }
+}
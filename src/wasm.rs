@@ -0,0 +1,167 @@
+//! JavaScript bindings for browsers and Node.js, built with `wasm-bindgen`.
+//!
+//! Files are passed as `Uint8Array`s. Headers and trailers are converted to plain
+//! objects with the same fields as their JSON form, and errors are thrown as `Error`s
+//! describing the whole chain of causes.
+//!
+//! ```js
+//! import { parseHeader, verify, Pages } from "litetx";
+//!
+//! const { header, trailer } = verify(bytes);
+//!
+//! const pages = new Pages(bytes);
+//! for (let page = pages.next(); page !== undefined; page = pages.next()) {
+//!     console.log(page.pageNum, page.data.length);
+//! }
+//! pages.finish();
+//! ```
+
+use crate::{read_header, verify as verify_file, Decoder, Header, Trailer};
+use std::{error::Error, io};
+use wasm_bindgen::prelude::{wasm_bindgen, JsError, JsValue};
+
+/// Parse the header of the LTX file `bytes`, which may be truncated after it.
+#[wasm_bindgen(js_name = parseHeader)]
+pub fn parse_header(bytes: &[u8]) -> Result<JsValue, JsError> {
+    let header = read_header(bytes).map_err(|e| js_error(&e))?;
+    Ok(serde_wasm_bindgen::to_value(&header)?)
+}
+
+/// Decode the whole LTX file `bytes` and verify its checksums, returning its header and
+/// trailer as `{ header, trailer }`.
+///
+/// See [`verify`](crate::verify) for details.
+#[wasm_bindgen]
+pub fn verify(bytes: &[u8]) -> Result<JsValue, JsError> {
+    #[derive(serde::Serialize)]
+    struct Verified {
+        header: Header,
+        trailer: Trailer,
+    }
+
+    let (header, trailer) = verify_file(bytes).map_err(|e| js_error(&e))?;
+    Ok(serde_wasm_bindgen::to_value(&Verified { header, trailer })?)
+}
+
+/// An iterator over the pages of an LTX file, decoding one page per call to
+/// [`Pages::next`] so that large files are never expanded at once.
+#[wasm_bindgen]
+pub struct Pages {
+    dec: Decoder<'static, io::Cursor<Vec<u8>>>,
+    header: Header,
+}
+
+#[wasm_bindgen]
+impl Pages {
+    /// Start decoding the LTX file `bytes`.
+    #[wasm_bindgen(constructor)]
+    pub fn new(bytes: Vec<u8>) -> Result<Pages, JsError> {
+        let (dec, header) = Decoder::new(io::Cursor::new(bytes)).map_err(|e| js_error(&e))?;
+        Ok(Pages { dec, header })
+    }
+
+    /// The header of the file.
+    #[wasm_bindgen(getter)]
+    pub fn header(&self) -> Result<JsValue, JsError> {
+        Ok(serde_wasm_bindgen::to_value(&self.header)?)
+    }
+
+    /// Decode the next page, or return `undefined` once all pages have been decoded.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Result<Option<Page>, JsError> {
+        let mut data = vec![0; self.header.page_size.into_inner() as usize];
+        let page_num = self.dec.decode_page(&mut data).map_err(|e| js_error(&e))?;
+
+        Ok(page_num.map(|page_num| Page {
+            page_num: page_num.into_inner(),
+            data,
+        }))
+    }
+
+    /// Verify the trailer and the file checksum once all pages have been decoded,
+    /// returning the trailer.
+    pub fn finish(self) -> Result<JsValue, JsError> {
+        let trailer = self.dec.finish().map_err(|e| js_error(&e))?;
+        Ok(serde_wasm_bindgen::to_value(&trailer)?)
+    }
+}
+
+/// A page decoded by [`Pages`].
+#[wasm_bindgen]
+pub struct Page {
+    page_num: u32,
+    data: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl Page {
+    /// The page number.
+    #[wasm_bindgen(getter, js_name = pageNum)]
+    pub fn page_num(&self) -> u32 {
+        self.page_num
+    }
+
+    /// The page contents, copied into a new `Uint8Array`.
+    #[wasm_bindgen(getter)]
+    pub fn data(&self) -> Vec<u8> {
+        self.data.clone()
+    }
+}
+
+fn js_error(e: &dyn Error) -> JsError {
+    let mut msg = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        msg.push_str(&format!(": {e}"));
+        source = e.source();
+    }
+
+    JsError::new(&msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Pages;
+    use crate::{Encoder, Header, HeaderFlags, PageNum, PageSize, TXID};
+    use std::time;
+
+    #[test]
+    fn wasm_pages() {
+        let mut buf = Vec::new();
+        let mut enc = Encoder::new(
+            &mut buf,
+            &Header {
+                flags: HeaderFlags::COMPRESS_LZ4,
+                page_size: PageSize::new(512).unwrap(),
+                commit: Some(PageNum::new(2).unwrap()),
+                min_txid: TXID::ONE,
+                max_txid: TXID::ONE,
+                timestamp: time::SystemTime::UNIX_EPOCH,
+                pre_apply_checksum: None,
+                node_id: 0,
+                wal: None,
+                app_data: None,
+                page_filter: None,
+                key_id: None,
+                dict_id: None,
+            },
+        )
+        .expect("failed to create encoder");
+        for n in 1..=2 {
+            enc.encode_page(PageNum::new(n).unwrap(), &[n as u8; 512])
+                .expect("failed to encode page");
+        }
+        enc.finish_auto().expect("failed to finish encoder");
+
+        let mut pages = Pages::new(buf).unwrap_or_else(|_| panic!("failed to decode header"));
+        for n in 1..=2 {
+            let page = pages
+                .next()
+                .unwrap_or_else(|_| panic!("failed to decode page"))
+                .expect("missing page");
+            assert_eq!(n, page.page_num());
+            assert_eq!(vec![n as u8; 512], page.data());
+        }
+        assert!(pages.next().is_ok_and(|page| page.is_none()));
+    }
+}
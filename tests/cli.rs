@@ -0,0 +1,79 @@
+#![cfg(feature = "cli")]
+
+use std::{ffi::OsStr, process};
+
+mod common;
+
+fn run_ltx<T: AsRef<OsStr>>(args: &[T]) -> String {
+    let output = process::Command::new(env!("CARGO_BIN_EXE_ltx"))
+        .args(args)
+        .output()
+        .expect("execute ltx binary");
+    assert!(
+        output.status.success(),
+        "ltx failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    String::from_utf8(output.stdout).expect("utf-8 output")
+}
+
+#[test]
+fn encode_verify_apply() {
+    let test_db = common::setup_test_db();
+    let ltx_out = common::temp_file();
+    let compacted = common::temp_file();
+    let db_out = common::temp_file();
+
+    run_ltx(&[
+        OsStr::new("encode-db"),
+        OsStr::new("-o"),
+        ltx_out.as_os_str(),
+        OsStr::new("-c"),
+        test_db.path.as_os_str(),
+    ]);
+    assert!(run_ltx(&[OsStr::new("verify"), ltx_out.as_os_str()]).ends_with(": ok\n"));
+
+    let info = run_ltx(&[OsStr::new("info"), ltx_out.as_os_str()]);
+    assert!(info.contains(&format!("page size:  {}\n", test_db.page_size)));
+    assert!(info.contains(&format!("commit:     {}\n", test_db.page_count)));
+
+    let dump = run_ltx(&[OsStr::new("dump"), ltx_out.as_os_str()]);
+    assert_eq!(
+        test_db.page_count.into_inner() as usize,
+        dump.lines()
+            .filter_map(|l| l.split_whitespace().next()?.parse::<u32>().ok())
+            .count()
+    );
+
+    let dump = run_ltx(&[OsStr::new("dump"), OsStr::new("-json"), ltx_out.as_os_str()]);
+    assert_eq!(
+        test_db.page_count.into_inner() as usize,
+        dump.matches("\"pgno\"").count()
+    );
+
+    run_ltx(&[
+        OsStr::new("compact"),
+        OsStr::new("-o"),
+        compacted.as_os_str(),
+        ltx_out.as_os_str(),
+    ]);
+    run_ltx(&[
+        OsStr::new("apply"),
+        OsStr::new("-db"),
+        db_out.as_os_str(),
+        compacted.as_os_str(),
+    ]);
+
+    common::compare_files(&test_db.path, &db_out);
+}
+
+#[test]
+fn invalid_arguments() {
+    let status = process::Command::new(env!("CARGO_BIN_EXE_ltx"))
+        .args(["encode-db", "-x"])
+        .output()
+        .expect("execute ltx binary")
+        .status;
+    assert_eq!(Some(1), status.code());
+}
@@ -3,7 +3,7 @@ use rand::prelude::*;
 use rusqlite::{Connection, OpenFlags};
 use std::{
     env, ffi, fs,
-    io::{self, Read},
+    io::{self, BufReader, Read},
     ops, path, process,
 };
 use uuid::Uuid;
@@ -109,8 +109,8 @@ where
     P1: AsRef<path::Path>,
     P2: AsRef<path::Path>,
 {
-    let f1 = fs::File::open(f1).expect("open first file");
-    let f2 = fs::File::open(f2).expect("open second file");
+    let f1 = BufReader::new(fs::File::open(f1).expect("open first file"));
+    let f2 = BufReader::new(fs::File::open(f2).expect("open second file"));
 
     assert!(
         f1.bytes()
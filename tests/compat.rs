@@ -34,11 +34,17 @@ fn encode(flags: ltx::HeaderFlags) {
         &ltx::Header {
             flags,
             page_size: test_db.page_size,
-            commit: test_db.page_count,
+            commit: Some(test_db.page_count),
             min_txid: ltx::TXID::ONE,
             max_txid: ltx::TXID::ONE,
             timestamp: time::SystemTime::now(),
             pre_apply_checksum: None,
+            node_id: 0,
+            wal: None,
+            app_data: None,
+            page_filter: None,
+            key_id: None,
+            dict_id: None,
         },
     )
     .expect("create LTX encoder");
@@ -67,6 +73,94 @@ fn encode(flags: ltx::HeaderFlags) {
     common::compare_files(&test_db.path, &db_out);
 }
 
+#[test]
+#[cfg_attr(not(feature = "compat"), ignore)]
+fn encode_snapshot_uncompressed() {
+    encode_snapshot(litetx::HeaderFlags::empty());
+}
+
+#[test]
+#[cfg_attr(not(feature = "compat"), ignore)]
+fn encode_snapshot_compressed() {
+    encode_snapshot(litetx::HeaderFlags::COMPRESS_LZ4);
+}
+
+#[cfg_attr(not(feature = "compat"), ignore)]
+fn encode_snapshot(flags: ltx::HeaderFlags) {
+    // Setup test DB
+    let test_db = common::setup_test_db();
+    let ltx_out = common::temp_file();
+    let db_out = common::temp_file();
+
+    // Create an LTX file from the DB file
+    let w = fs::File::create(&ltx_out).expect("create LTX file");
+    let r = fs::File::open(&test_db.path).expect("open DB file");
+    ltx::encode_db_snapshot(
+        r,
+        &w,
+        ltx::SnapshotOptions {
+            flags,
+            ..Default::default()
+        },
+    )
+    .expect("encode DB snapshot");
+    w.sync_all().expect("sync LTX file");
+    mem::drop(w);
+
+    let header = ltx::read_header_from_path(&ltx_out).expect("read LTX header");
+    assert_eq!(Some(test_db.page_count), header.commit);
+
+    // Decode using Go's decoder
+    common::run_ltx(&[
+        "apply",
+        "-db",
+        &db_out.to_string_lossy(),
+        &ltx_out.to_string_lossy(),
+    ]);
+
+    common::compare_files(&test_db.path, &db_out);
+}
+
+#[test]
+#[cfg_attr(not(feature = "compat"), ignore)]
+fn encode_app_data() {
+    // Setup test DB
+    let test_db = common::setup_test_db();
+    let ltx_out = common::temp_file();
+    let db_out = common::temp_file();
+
+    // Re-encode a snapshot with application data in the header
+    let r = fs::File::open(&test_db.path).expect("open DB file");
+    let mut buf = Vec::new();
+    ltx::encode_db_snapshot(r, &mut buf, Default::default()).expect("encode DB snapshot");
+
+    let (mut dec, mut header) = ltx::Decoder::new(buf.as_slice()).expect("create LTX decoder");
+    header.app_data = Some(*b"compat-test-site");
+    let w = fs::File::create(&ltx_out).expect("create LTX file");
+    let mut enc = ltx::Encoder::new(&w, &header).expect("create LTX encoder");
+    let mut page = vec![0; test_db.page_size.into_inner() as usize];
+    while let Some(pgno) = dec.decode_page(&mut page).expect("decode DB page") {
+        enc.encode_page(pgno, &page).expect("encode DB page");
+    }
+    dec.finish().expect("finish LTX decoder");
+    enc.finish_auto().expect("finish LTX encoder");
+    w.sync_all().expect("sync LTX file");
+    mem::drop(w);
+
+    let header = ltx::read_header_from_path(&ltx_out).expect("read LTX header");
+    assert_eq!(Some(*b"compat-test-site"), header.app_data);
+
+    // Decode using Go's decoder, which ignores the application data
+    common::run_ltx(&[
+        "apply",
+        "-db",
+        &db_out.to_string_lossy(),
+        &ltx_out.to_string_lossy(),
+    ]);
+
+    common::compare_files(&test_db.path, &db_out);
+}
+
 #[test]
 #[cfg_attr(not(feature = "compat"), ignore)]
 fn decode_uncompressed() {
@@ -107,7 +201,7 @@ fn decode(compressed: bool) {
     }
     let trailer = dec.finish().expect("finish LTX decoder");
 
-    assert_eq!(checksum, trailer.post_apply_checksum);
+    assert_eq!(Some(checksum), trailer.post_apply_checksum);
 
     common::compare_files(&test_db.path, &db_out);
 }